@@ -0,0 +1,178 @@
+//!
+//! Threshold alarms with hysteresis
+//!
+
+use heapless::Vec;
+
+use crate::events::{Alarm, AlarmKind, Event, Quantity};
+
+/// Max count of tracked alarms and faulted sensors
+pub const MAX_ACTIVE: usize = 4;
+
+/// Alarm thresholds for one quantity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    /// Alarm triggers when value is above
+    pub high: Option<f32>,
+    /// Alarm triggers when value is below
+    pub low: Option<f32>,
+    /// The value must return by this amount inside thresholds to clear alarm
+    pub hysteresis: f32,
+}
+
+impl Thresholds {
+    pub const fn disabled() -> Self {
+        Self {
+            high: None,
+            low: None,
+            hysteresis: 0.0,
+        }
+    }
+
+    /// Checks value against thresholds without hysteresis, e.g. for a single reading
+    pub fn is_exceeded(&self, value: f32) -> bool {
+        self.high.is_some_and(|high| high < value) || self.low.is_some_and(|low| value < low)
+    }
+}
+
+/// Watches values of one quantity and reports threshold crossings
+pub struct ThresholdAlarm {
+    quantity: Quantity,
+    thresholds: Thresholds,
+    active: Option<AlarmKind>,
+}
+
+impl ThresholdAlarm {
+    pub fn new(quantity: Quantity, thresholds: Thresholds) -> Self {
+        Self {
+            quantity,
+            thresholds,
+            active: None,
+        }
+    }
+
+    pub fn thresholds(&self) -> &Thresholds {
+        &self.thresholds
+    }
+
+    /// Replaces thresholds. Active alarm will be re-evaluated on next update
+    pub fn set_thresholds(&mut self, thresholds: Thresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// Checks whatever alarm is active now
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Feeds new value
+    ///
+    /// # Returns
+    /// - Some(event), if alarm triggered or cleared by this value
+    /// - None, if alarm state not changed
+    pub fn update(&mut self, value: f32) -> Option<Event> {
+        let Thresholds {
+            high,
+            low,
+            hysteresis,
+        } = self.thresholds;
+
+        match self.active {
+            None => {
+                if let Some(high) = high.filter(|high| *high < value) {
+                    return Some(self.trigger(AlarmKind::High, value, high));
+                }
+
+                if let Some(low) = low.filter(|low| value < *low) {
+                    return Some(self.trigger(AlarmKind::Low, value, low));
+                }
+
+                None
+            }
+            Some(kind) => {
+                let threshold = match kind {
+                    AlarmKind::High => high,
+                    AlarmKind::Low => low,
+                };
+
+                let clear = match (kind, threshold) {
+                    // Threshold removed while active
+                    (_, None) => true,
+                    (AlarmKind::High, Some(high)) => value <= high - hysteresis,
+                    (AlarmKind::Low, Some(low)) => low + hysteresis <= value,
+                };
+
+                if !clear {
+                    return None;
+                }
+
+                self.active = None;
+                Some(Event::AlarmCleared(Alarm {
+                    quantity: self.quantity,
+                    kind,
+                    value,
+                    threshold: threshold.unwrap_or(value),
+                }))
+            }
+        }
+    }
+
+    fn trigger(&mut self, kind: AlarmKind, value: f32, threshold: f32) -> Event {
+        self.active = Some(kind);
+        Event::AlarmTriggered(Alarm {
+            quantity: self.quantity,
+            kind,
+            value,
+            threshold,
+        })
+    }
+}
+
+/// Active alarms and faulted sensors collected from events
+#[derive(Debug, Default, Clone)]
+pub struct ActiveAlarms {
+    alarms: Vec<Alarm, MAX_ACTIVE>,
+    faults: Vec<&'static str, MAX_ACTIVE>,
+}
+
+impl ActiveAlarms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates state by event, unrelated events are ignored
+    pub fn apply(&mut self, event: &Event) {
+        match event {
+            Event::AlarmTriggered(alarm) => {
+                self.remove_alarm(alarm);
+                if self.alarms.is_full() {
+                    self.alarms.remove(0);
+                }
+                self.alarms.push(*alarm).ok();
+            }
+            Event::AlarmCleared(alarm) => self.remove_alarm(alarm),
+            Event::SensorFault(sensor) => {
+                if !self.faults.contains(sensor) && self.faults.push(sensor).is_err() {
+                    self.faults.remove(0);
+                    self.faults.push(sensor).ok();
+                }
+            }
+            Event::SensorRecovered(sensor) => self.faults.retain(|faulted| faulted != sensor),
+            Event::Boot | Event::SelfHeatingStarted(_) | Event::SelfHeatingStopped(_) => {}
+        }
+    }
+
+    pub fn alarms(&self) -> &[Alarm] {
+        &self.alarms
+    }
+
+    /// Names of sensors not responding
+    pub fn faults(&self) -> &[&'static str] {
+        &self.faults
+    }
+
+    fn remove_alarm(&mut self, alarm: &Alarm) {
+        self.alarms
+            .retain(|active| !(active.quantity == alarm.quantity && active.kind == alarm.kind));
+    }
+}
@@ -0,0 +1,460 @@
+//!
+//! Admin credentials check
+//!
+//! Requests carry admin credentials by HTTP Basic or a bearer token. Only
+//! salted digests of credentials are kept, so settings never hold a password.
+//! Repeated failures lock the failing client out for a growing period.
+//!
+
+use core::net::IpAddr;
+
+use heapless::LinearMap;
+
+use crate::settings::Credentials;
+
+/// Failed attempts allowed before lockout
+const FREE_ATTEMPTS: u32 = 5;
+/// Lockout after the first extra failure, doubled by each next one
+const BASE_LOCKOUT_MS: u64 = 1000;
+const MAX_LOCKOUT_MS: u64 = 5 * 60 * 1000;
+/// How long BOOT button lets anyone set the first credentials
+pub const SETUP_WINDOW_MS: u64 = 5 * 60 * 1000;
+/// Clients whose failures are tracked at once
+const MAX_CLIENTS: usize = 8;
+
+/// Longest `user:password` accepted by Basic authentication
+pub const MAX_BASIC_LEN: usize = 96;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Access {
+    Public,
+    Admin,
+    /// Admin, or anyone while there are no credentials and setup is open, so the
+    /// first ones can be set. Setup is opened by BOOT button, so only someone with
+    /// physical access to the device sets them
+    Setup,
+}
+
+/// Access required by route, reads are `GET` and `HEAD` requests, writes are the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Policy {
+    pub read: Access,
+    pub write: Access,
+}
+
+impl Policy {
+    /// Anyone reads, admin writes
+    pub const PUBLIC_READ: Self = Self {
+        read: Access::Public,
+        write: Access::Admin,
+    };
+    pub const ADMIN: Self = Self {
+        read: Access::Admin,
+        write: Access::Admin,
+    };
+    /// Admin only, but anyone writes the first credentials while setup is open
+    pub const SETUP: Self = Self {
+        read: Access::Admin,
+        write: Access::Setup,
+    };
+
+    pub fn required(&self, method: &str) -> Access {
+        match method {
+            "GET" | "HEAD" => self.read,
+            _ => self.write,
+        }
+    }
+}
+
+impl Credentials {
+    /// Checks value of `Authorization` header
+    pub fn verify(&self, authorization: &[u8]) -> bool {
+        if let Some(encoded) = strip_scheme(authorization, b"Basic") {
+            let mut decoded = [0_u8; MAX_BASIC_LEN];
+            match (self.password, decode_base64(encoded, &mut decoded)) {
+                (Some(password), Some(len)) => password.matches(&decoded[..len]),
+                _ => false,
+            }
+        } else if let Some(token) = strip_scheme(authorization, b"Bearer") {
+            self.token.is_some_and(|expected| expected.matches(token))
+        } else {
+            false
+        }
+    }
+}
+
+/// Strips case-insensitive scheme and spaces after it
+fn strip_scheme<'a>(authorization: &'a [u8], scheme: &[u8]) -> Option<&'a [u8]> {
+    let (name, rest) = authorization.split_at_checked(scheme.len())?;
+    if !name.eq_ignore_ascii_case(scheme) || rest.first() != Some(&b' ') {
+        return None;
+    }
+    Some(rest.trim_ascii())
+}
+
+/// Decodes standard base64 with optional padding
+///
+/// # Returns
+/// Decoded length, None if input is not base64 or does not fit
+pub(crate) fn decode_base64(encoded: &[u8], out: &mut [u8]) -> Option<usize> {
+    let symbols = encoded
+        .strip_suffix(b"==")
+        .or_else(|| encoded.strip_suffix(b"="))
+        .unwrap_or(encoded);
+    // Padded input comes in whole quads, a lone symbol carries less than a byte
+    if (symbols.len() != encoded.len() && encoded.len() % 4 != 0) || symbols.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut len = 0;
+    let mut bits = 0_u32;
+    let mut count = 0;
+    for symbol in symbols {
+        let value = match symbol {
+            b'A'..=b'Z' => symbol - b'A',
+            b'a'..=b'z' => symbol - b'a' + 26,
+            b'0'..=b'9' => symbol - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | u32::from(value);
+        count += 6;
+        if 8 <= count {
+            count -= 8;
+            *out.get_mut(len)? = (bits >> count) as u8;
+            len += 1;
+        }
+    }
+
+    Some(len)
+}
+
+/// Failed attempts of one client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Failures {
+    count: u32,
+    /// In ms since boot
+    locked_until: u64,
+}
+
+impl Failures {
+    fn fail(&mut self, now_ms: u64) {
+        self.count = self.count.saturating_add(1);
+        if let Some(extra) = self.count.checked_sub(FREE_ATTEMPTS) {
+            self.locked_until = now_ms + lockout_ms(extra);
+        }
+    }
+}
+
+/// Locks authentication out after repeated failures, separately for each client
+///
+/// So a client guessing credentials does not lock out the others. Clients are
+/// forgotten on success, or to make room for a new one, the one with the fewest
+/// failures first.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    clients: LinearMap<IpAddr, Failures, MAX_CLIENTS>,
+}
+
+impl Throttle {
+    pub fn is_locked(&self, client: IpAddr, now_ms: u64) -> bool {
+        self.clients
+            .get(&client)
+            .is_some_and(|failures| now_ms < failures.locked_until)
+    }
+
+    pub fn fail(&mut self, client: IpAddr, now_ms: u64) {
+        if !self.clients.contains_key(&client) && self.clients.len() == MAX_CLIENTS {
+            let fewest = self
+                .clients
+                .iter()
+                .min_by_key(|(_, failures)| failures.count)
+                .map(|(client, _)| *client);
+            if let Some(fewest) = fewest {
+                self.clients.remove(&fewest);
+            }
+        }
+
+        if let Some(failures) = self.clients.get_mut(&client) {
+            failures.fail(now_ms);
+        } else {
+            let mut failures = Failures::default();
+            failures.fail(now_ms);
+            // Room is made above
+            self.clients.insert(client, failures).ok();
+        }
+    }
+
+    pub fn succeed(&mut self, client: IpAddr) {
+        self.clients.remove(&client);
+    }
+}
+
+/// Lockout after `extra` failures over the free ones
+fn lockout_ms(extra: u32) -> u64 {
+    // Already over the cap at 2^9 s, larger shifts would drop bits
+    (BASE_LOCKOUT_MS << extra.min(9)).min(MAX_LOCKOUT_MS)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Verdict {
+    Granted,
+    /// Missing or wrong credentials
+    Denied,
+    /// Too many failed attempts
+    Locked,
+    /// There are no credentials to authenticate against
+    Disabled,
+    /// There are no credentials, but the first ones can be set
+    Setup,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    pub credentials: Credentials,
+    throttle: Throttle,
+    /// Setup is open before, in ms since boot
+    setup_until: u64,
+}
+
+impl Auth {
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            credentials,
+            throttle: Throttle::default(),
+            setup_until: 0,
+        }
+    }
+
+    /// Lets anyone set the first credentials for [`SETUP_WINDOW_MS`]
+    ///
+    /// # Returns
+    /// false, if credentials are set already and setup is not needed
+    pub fn open_setup(&mut self, now_ms: u64) -> bool {
+        if self.credentials.is_configured() {
+            return false;
+        }
+        self.setup_until = now_ms + SETUP_WINDOW_MS;
+        true
+    }
+
+    /// Checks `Authorization` header value and counts failures of client
+    pub fn check(&mut self, authorization: Option<&[u8]>, client: IpAddr, now_ms: u64) -> Verdict {
+        if !self.credentials.is_configured() {
+            return if now_ms < self.setup_until {
+                Verdict::Setup
+            } else {
+                Verdict::Disabled
+            };
+        }
+        if self.throttle.is_locked(client, now_ms) {
+            return Verdict::Locked;
+        }
+
+        match authorization {
+            Some(authorization) if self.credentials.verify(authorization) => {
+                self.throttle.succeed(client);
+                Verdict::Granted
+            }
+            // Browser asks first without credentials, it is not an attack
+            None => Verdict::Denied,
+            Some(_) => {
+                self.throttle.fail(client, now_ms);
+                Verdict::Denied
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256::{SaltedDigest, SALT_LEN};
+
+    fn credentials() -> Credentials {
+        Credentials {
+            password: Some(SaltedDigest::new([1; SALT_LEN], b"admin:secret")),
+            token: Some(SaltedDigest::new([2; SALT_LEN], b"token")),
+        }
+    }
+
+    fn decoded(encoded: &[u8]) -> Option<heapless::Vec<u8, 8>> {
+        let mut out = [0_u8; 8];
+        let len = decode_base64(encoded, &mut out)?;
+        Some(heapless::Vec::from_slice(&out[..len]).unwrap())
+    }
+
+    #[test]
+    fn decodes_base64_with_and_without_padding() {
+        assert_eq!(decoded(b"YQ==").as_deref(), Some(&b"a"[..]));
+        assert_eq!(decoded(b"YWI=").as_deref(), Some(&b"ab"[..]));
+        assert_eq!(decoded(b"YWJj").as_deref(), Some(&b"abc"[..]));
+        assert_eq!(decoded(b"YWI").as_deref(), Some(&b"ab"[..]));
+        assert_eq!(decoded(b"").as_deref(), Some(&b""[..]));
+    }
+
+    #[test]
+    fn rejects_bad_base64() {
+        // Padding of wrong length
+        assert_eq!(decoded(b"YQ="), None);
+        assert_eq!(decoded(b"YWI=="), None);
+        assert_eq!(decoded(b"YQ==="), None);
+        // Padding inside
+        assert_eq!(decoded(b"Y=Q="), None);
+        // Lone symbol
+        assert_eq!(decoded(b"YWJjZ"), None);
+        assert_eq!(decoded(b"YW-j"), None);
+        // Does not fit
+        assert_eq!(decoded(b"YWJjZGVmZ2hp"), None);
+    }
+
+    #[test]
+    fn verifies_basic_and_bearer() {
+        let credentials = credentials();
+        assert!(credentials.verify(b"Basic YWRtaW46c2VjcmV0"));
+        assert!(credentials.verify(b"basic  YWRtaW46c2VjcmV0 "));
+        assert!(credentials.verify(b"Bearer token"));
+
+        assert!(!credentials.verify(b"Basic YWRtaW46d3Jvbmc="));
+        assert!(!credentials.verify(b"Basic YWRtaW46c2VjcmV0="));
+        assert!(!credentials.verify(b"Bearer wrong"));
+        assert!(!credentials.verify(b"BasicYWRtaW46c2VjcmV0"));
+        assert!(!credentials.verify(b"Digest YWRtaW46c2VjcmV0"));
+        assert!(!Credentials::default().verify(b"Bearer token"));
+    }
+
+    #[test]
+    fn lockout_doubles_up_to_cap() {
+        assert_eq!(lockout_ms(0), BASE_LOCKOUT_MS);
+        assert_eq!(lockout_ms(1), 2 * BASE_LOCKOUT_MS);
+        assert_eq!(lockout_ms(8), 256 * BASE_LOCKOUT_MS);
+        for extra in [9, 10, 61, 62, 63, 64, 100, u32::MAX] {
+            assert_eq!(lockout_ms(extra), MAX_LOCKOUT_MS);
+        }
+    }
+
+    fn client(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 1, last])
+    }
+
+    #[test]
+    fn throttle_locks_after_free_attempts() {
+        let mut throttle = Throttle::default();
+        for _ in 0..FREE_ATTEMPTS - 1 {
+            throttle.fail(client(1), 0);
+            assert!(!throttle.is_locked(client(1), 0));
+        }
+        throttle.fail(client(1), 0);
+        assert!(throttle.is_locked(client(1), BASE_LOCKOUT_MS - 1));
+        assert!(!throttle.is_locked(client(1), BASE_LOCKOUT_MS));
+
+        throttle.fail(client(1), 10_000);
+        assert!(throttle.is_locked(client(1), 10_000 + 2 * BASE_LOCKOUT_MS - 1));
+        assert!(!throttle.is_locked(client(1), 10_000 + 2 * BASE_LOCKOUT_MS));
+    }
+
+    #[test]
+    fn throttle_stays_locked_after_many_failures() {
+        let mut throttle = Throttle::default();
+        for attempt in 0..200 {
+            throttle.fail(client(1), attempt);
+        }
+        assert!(throttle.is_locked(client(1), 200 + MAX_LOCKOUT_MS - 2));
+        assert!(!throttle.is_locked(client(1), 200 + MAX_LOCKOUT_MS));
+    }
+
+    #[test]
+    fn throttle_evicts_client_with_fewest_failures() {
+        let mut throttle = Throttle::default();
+        for last in 0..MAX_CLIENTS as u8 {
+            for _ in 0..FREE_ATTEMPTS + u32::from(last) {
+                throttle.fail(client(last), 0);
+            }
+        }
+        throttle.fail(client(100), 0);
+
+        assert_eq!(throttle.clients.len(), MAX_CLIENTS);
+        assert!(!throttle.clients.contains_key(&client(0)));
+        assert!(throttle.clients.contains_key(&client(100)));
+        assert!(throttle.is_locked(client(MAX_CLIENTS as u8 - 1), 0));
+    }
+
+    #[test]
+    fn auth_counts_failures_and_resets_on_success() {
+        let mut auth = Auth::new(credentials());
+        let wrong = Some(&b"Bearer wrong"[..]);
+        let right = Some(&b"Bearer token"[..]);
+
+        assert_eq!(auth.check(None, client(1), 0), Verdict::Denied);
+        for _ in 0..FREE_ATTEMPTS - 1 {
+            assert_eq!(auth.check(wrong, client(1), 0), Verdict::Denied);
+        }
+        assert_eq!(auth.check(right, client(1), 0), Verdict::Granted);
+
+        // Success forgot the failures
+        for _ in 0..FREE_ATTEMPTS {
+            assert_eq!(auth.check(wrong, client(1), 0), Verdict::Denied);
+        }
+        assert_eq!(auth.check(right, client(1), 0), Verdict::Locked);
+        assert_eq!(
+            auth.check(right, client(1), BASE_LOCKOUT_MS),
+            Verdict::Granted
+        );
+    }
+
+    #[test]
+    fn locked_client_does_not_lock_out_others() {
+        let mut auth = Auth::new(credentials());
+        for _ in 0..2 * FREE_ATTEMPTS {
+            auth.check(Some(b"Bearer wrong"), client(66), 0);
+        }
+        assert_eq!(
+            auth.check(Some(b"Bearer token"), client(66), 0),
+            Verdict::Locked
+        );
+        assert_eq!(
+            auth.check(Some(b"Bearer token"), client(2), 0),
+            Verdict::Granted
+        );
+    }
+
+    #[test]
+    fn auth_without_credentials_is_disabled() {
+        let mut auth = Auth::new(Credentials::default());
+        assert_eq!(
+            auth.check(Some(b"Bearer token"), client(1), 0),
+            Verdict::Disabled
+        );
+    }
+
+    #[test]
+    fn setup_opens_for_window_without_credentials() {
+        let mut auth = Auth::new(Credentials::default());
+        assert_eq!(auth.check(None, client(1), 1000), Verdict::Disabled);
+
+        assert!(auth.open_setup(1000));
+        assert_eq!(auth.check(None, client(1), 1000), Verdict::Setup);
+        assert_eq!(
+            auth.check(None, client(1), 1000 + SETUP_WINDOW_MS - 1),
+            Verdict::Setup
+        );
+        assert_eq!(
+            auth.check(None, client(1), 1000 + SETUP_WINDOW_MS),
+            Verdict::Disabled
+        );
+
+        let mut auth = Auth::new(credentials());
+        assert!(!auth.open_setup(1000));
+        assert_eq!(auth.check(None, client(1), 1000), Verdict::Denied);
+    }
+
+    #[test]
+    fn policy_splits_reads_and_writes() {
+        assert_eq!(Policy::PUBLIC_READ.required("GET"), Access::Public);
+        assert_eq!(Policy::PUBLIC_READ.required("HEAD"), Access::Public);
+        assert_eq!(Policy::PUBLIC_READ.required("POST"), Access::Admin);
+        assert_eq!(Policy::SETUP.required("GET"), Access::Admin);
+        assert_eq!(Policy::SETUP.required("POST"), Access::Setup);
+    }
+}
@@ -0,0 +1,185 @@
+//!
+//! Battery supply monitoring
+//!
+//! Battery is measured through a resistor divider, as its voltage is above ADC range.
+//! Raw readings are oversampled, averaged over time and mapped to state of charge
+//! by a discharge curve.
+//!
+
+use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
+use serde::Serialize;
+
+/// Point of discharge curve: voltage in mV and state of charge in %
+pub type CurvePoint = (u16, u8);
+
+/// Typical Li-ion cell under light load, from full to empty
+pub const LI_ION_CURVE: &[CurvePoint] = &[
+    (4200, 100),
+    (4100, 90),
+    (4000, 80),
+    (3900, 65),
+    (3800, 50),
+    (3750, 40),
+    (3700, 30),
+    (3650, 20),
+    (3600, 12),
+    (3500, 5),
+    (3300, 0),
+];
+
+/// Battery state published to API
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, defmt::Format)]
+pub struct BatteryStatus {
+    /// In V
+    pub voltage: f32,
+    /// State of charge in %
+    pub percentage: u8,
+    /// Low battery alarm is active
+    pub low: bool,
+}
+
+/// Interpolates state of charge linearly between curve points
+///
+/// # Arguments
+/// - `curve` - points sorted by voltage from the highest
+/// - `millivolts` - battery voltage
+///
+/// # Returns
+/// State of charge in %, limited by the first and the last points
+pub fn state_of_charge(curve: &[CurvePoint], millivolts: u16) -> u8 {
+    let (Some(first), Some(last)) = (curve.first(), curve.last()) else {
+        return 0;
+    };
+    if first.0 <= millivolts {
+        return first.1;
+    }
+    if millivolts <= last.0 {
+        return last.1;
+    }
+
+    for pair in curve.windows(2) {
+        let (high, low) = (pair[0], pair[1]);
+        if low.0 <= millivolts && millivolts <= high.0 {
+            let span = u32::from(high.0 - low.0);
+            if span == 0 {
+                return high.1;
+            }
+            let offset = u32::from(millivolts - low.0);
+            let percents = u32::from(high.1 - low.1);
+            return low.1 + ((offset * percents + span / 2) / span) as u8;
+        }
+    }
+
+    last.1
+}
+
+/// Averages burst of readings, dropping the lowest and the highest ones as spikes
+///
+/// # Returns
+/// None, if there are no readings
+pub fn oversample(readings: &[u16]) -> Option<u16> {
+    let count = readings.len();
+    let sum: u32 = readings.iter().map(|reading| u32::from(*reading)).sum();
+
+    let (sum, count) = if 2 < count {
+        let min = readings.iter().min().copied().unwrap_or_default();
+        let max = readings.iter().max().copied().unwrap_or_default();
+        (sum - u32::from(min) - u32::from(max), count - 2)
+    } else {
+        (sum, count)
+    };
+
+    (0 < count).then(|| ((sum + count as u32 / 2) / count as u32) as u16)
+}
+
+/// Average of the last `N` values, smoothing load spikes like WiFi transmits
+#[derive(Debug, Default)]
+pub struct MovingAverage<const N: usize> {
+    values: ConstGenericRingBuffer<u16, N>,
+}
+
+impl<const N: usize> MovingAverage<N> {
+    pub fn new() -> Self {
+        Self {
+            values: ConstGenericRingBuffer::new(),
+        }
+    }
+
+    /// Adds value, the oldest one is dropped when full
+    ///
+    /// # Returns
+    /// Average of stored values
+    pub fn add(&mut self, value: u16) -> u16 {
+        self.values.push(value);
+        self.average().unwrap_or(value)
+    }
+
+    pub fn average(&self) -> Option<u16> {
+        let count = self.values.len() as u32;
+        let sum: u32 = self.values.iter().map(|value| u32::from(*value)).sum();
+        (0 < count).then(|| ((sum + count / 2) / count) as u16)
+    }
+}
+
+/// Converts ADC pin voltage to battery voltage
+///
+/// # Arguments
+/// - `ratio` - battery voltage divided by pin voltage, e.g. 2 for two equal resistors
+pub fn battery_millivolts(pin_millivolts: u16, ratio: f32) -> u16 {
+    (f32::from(pin_millivolts) * ratio + 0.5).clamp(0.0, f32::from(u16::MAX)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_of_charge_matches_curve_points() {
+        for &(millivolts, percentage) in LI_ION_CURVE {
+            assert_eq!(state_of_charge(LI_ION_CURVE, millivolts), percentage);
+        }
+    }
+
+    #[test]
+    fn state_of_charge_interpolates_between_points() {
+        assert_eq!(state_of_charge(LI_ION_CURVE, 4150), 95);
+        assert_eq!(state_of_charge(LI_ION_CURVE, 3850), 58);
+        assert_eq!(state_of_charge(LI_ION_CURVE, 3725), 35);
+        assert_eq!(state_of_charge(LI_ION_CURVE, 3400), 3);
+    }
+
+    #[test]
+    fn state_of_charge_clamps_outside_curve() {
+        assert_eq!(state_of_charge(LI_ION_CURVE, 4350), 100);
+        assert_eq!(state_of_charge(LI_ION_CURVE, 2800), 0);
+        assert_eq!(state_of_charge(LI_ION_CURVE, 0), 0);
+        assert_eq!(state_of_charge(&[], 3700), 0);
+    }
+
+    #[test]
+    fn oversample_drops_spikes() {
+        assert_eq!(oversample(&[1000, 1002, 3000, 1004, 10]), Some(1002));
+        assert_eq!(oversample(&[1000, 1001]), Some(1001));
+        assert_eq!(oversample(&[1000]), Some(1000));
+        assert_eq!(oversample(&[]), None);
+    }
+
+    #[test]
+    fn moving_average_drops_oldest() {
+        let mut average = MovingAverage::<4>::new();
+        assert_eq!(average.average(), None);
+        assert_eq!(average.add(4000), 4000);
+        assert_eq!(average.add(3900), 3950);
+        assert_eq!(average.add(3800), 3900);
+        assert_eq!(average.add(3700), 3850);
+        assert_eq!(average.add(3600), 3750);
+        assert_eq!(average.average(), Some(3750));
+    }
+
+    #[test]
+    fn divider_scales_pin_voltage() {
+        assert_eq!(battery_millivolts(1850, 2.0), 3700);
+        assert_eq!(battery_millivolts(1000, 1.5), 1500);
+        assert_eq!(battery_millivolts(u16::MAX, 2.0), u16::MAX);
+    }
+}
@@ -0,0 +1,312 @@
+//!
+//! Duty-cycled battery mode
+//!
+//! One wake measures, buffers the reading, uploads the buffer if due and sleeps.
+//!
+
+use defmt::{error, info, warn};
+use embassy_executor::Spawner;
+use embassy_net::Stack;
+use embassy_time::{Duration, Timer};
+use esp_hal::peripherals::{ADC1, GPIO2, GPIO4, GPIO6, GPIO7, I2C0, LPWR, RNG, TIMG0, WIFI};
+use esp_hal::rng::Rng;
+use esp_hal::timer::timg::TimerGroup;
+use esp_temperature::alarm::Thresholds;
+use esp_temperature::battery::{
+    battery_millivolts, state_of_charge, BatteryStatus, MovingAverage, LI_ION_CURVE,
+};
+use esp_temperature::boards::esp32::esp32_c6::{
+    deep_sleep, init_i2c, start_wifi, with_rtc_state, BatteryAdc,
+};
+use esp_temperature::calibration::calibrate;
+use esp_temperature::discovery;
+use esp_temperature::drivers::i2c::stats::{I2cStats, SharedI2cStats};
+use esp_temperature::drivers::sensors::dht22::Dht22Esp32;
+use esp_temperature::duty_cycle::{Sample, Schedule, MAX_BATCH_LEN};
+use esp_temperature::events::Quantity;
+use esp_temperature::net::http::{HttpClient, Url};
+use esp_temperature::settings::Settings;
+use esp_temperature::sync::mutex::AtomicMutex;
+use esp_temperature::units::TemperatureUnit;
+use esp_wifi::EspWifiController;
+
+use crate::environment::EnvironmentSensor;
+use crate::led::STATUS_INDICATOR;
+
+/// One wake of battery mode: measures, buffers reading, uploads buffer if due and sleeps
+#[allow(clippy::too_many_arguments)]
+pub async fn battery_cycle(
+    spawner: Spawner,
+    settings: &'static Settings,
+    lpwr: LPWR<'static>,
+    i2c0: I2C0<'static>,
+    scl: GPIO7<'static>,
+    sda: GPIO6<'static>,
+    dht_pin: GPIO4<'static>,
+    adc: ADC1<'static>,
+    battery_pin: GPIO2<'static>,
+    timg0: TIMG0<'static>,
+    rng: RNG<'static>,
+    wifi: WIFI<'static>,
+) -> ! {
+    let power = &settings.power;
+    let interval = Duration::from_secs(power.sleep_interval_s.into());
+    let schedule = Schedule {
+        upload_every: power.upload_every,
+    };
+
+    let wake = with_rtc_state(|state| {
+        if !state.validate() {
+            info!("battery: RTC state reset");
+        }
+        state.wake()
+    });
+    info!("battery: wake {}", wake);
+
+    let i2c_stats = mk_static!(AtomicMutex<I2cStats>, AtomicMutex::new(I2cStats::default()));
+    let i2c = init_i2c(i2c0, scl, sda, SharedI2cStats::new(i2c_stats), spawner).await;
+    let inventory = discovery::discover(&i2c).await;
+
+    let (sensor, reading) = match EnvironmentSensor::from_inventory(&inventory, &i2c) {
+        Some(mut sensor) => {
+            let reading = if sensor.init().await {
+                sensor
+                    .read()
+                    .await
+                    .and_then(|reading| Some((reading.temperature, reading.humidity?)))
+            } else {
+                None
+            };
+            (sensor.name(), reading)
+        }
+        None => (
+            "dht22",
+            read_dht_once(Dht22Esp32::new(esp_hal::gpio::Flex::new(dht_pin))).await,
+        ),
+    };
+
+    let reading = reading.map(|(temperature, humidity)| {
+        let calibration = &settings.calibration;
+        (
+            calibrate(calibration, sensor, Quantity::Temperature, temperature),
+            calibrate(calibration, sensor, Quantity::Humidity, humidity).clamp(0.0, 100.0),
+        )
+    });
+    let temperature_thresholds = Thresholds {
+        high: settings.alarms.temperature_high,
+        low: settings.alarms.temperature_low,
+        hysteresis: 0.0,
+    };
+    let humidity_thresholds = Thresholds {
+        high: settings.alarms.humidity_high,
+        low: settings.alarms.humidity_low,
+        hysteresis: 0.0,
+    };
+
+    // Measured before WiFi is up, its transmits pull the voltage down
+    let battery = match settings.hardware.battery_divider {
+        Some(ratio) => Some(
+            read_battery_once(
+                BatteryAdc::new(adc, battery_pin),
+                ratio,
+                settings.alarms.battery_low,
+            )
+            .await,
+        ),
+        None => None,
+    };
+    if let Some(battery) = battery {
+        info!("battery: {}", battery);
+    }
+
+    let upload = with_rtc_state(|state| {
+        let battery_low = battery.is_some_and(|battery| battery.low);
+        let alarm_changed = match reading {
+            Some((temperature, humidity)) => {
+                state.push(Sample {
+                    wake,
+                    temperature,
+                    humidity,
+                });
+                state.set_alarm(
+                    temperature_thresholds.is_exceeded(temperature)
+                        || humidity_thresholds.is_exceeded(humidity)
+                        || battery_low,
+                )
+            }
+            None => {
+                error!("battery: failed to get environment data");
+                state.set_alarm(battery_low)
+            }
+        };
+        schedule.should_upload(state, alarm_changed)
+    });
+
+    if upload {
+        let rng = Rng::new(rng);
+        let esp32_wifi_ctrl = &*mk_static!(
+            EspWifiController<'static>,
+            esp_wifi::init(TimerGroup::new(timg0).timer0, rng)
+                .expect("failed to init esp radio ctrl")
+        );
+        let network = &settings.network;
+        let stack = start_wifi(
+            esp32_wifi_ctrl,
+            wifi,
+            rng,
+            &STATUS_INDICATOR,
+            &network.ssid,
+            network.password.as_deref().unwrap_or(""),
+            spawner,
+        )
+        .await;
+
+        if embassy_time::with_timeout(Duration::from_secs(30), stack.wait_config_up())
+            .await
+            .is_err()
+        {
+            error!("battery: no network, upload postponed");
+        } else if !upload_samples(
+            stack,
+            power.upload_url.as_deref(),
+            interval,
+            battery,
+            settings.unit,
+        )
+        .await
+        {
+            info!("battery: upload postponed");
+        }
+    }
+
+    with_rtc_state(|state| state.seal());
+    info!("battery: sleeping for {} s", interval.as_secs());
+    deep_sleep(lpwr, interval)
+}
+
+/// Reads DHT22 with a few retries, it often misses the first read after wake
+async fn read_dht_once(mut dht: Dht22Esp32) -> Option<(f32, f32)> {
+    for _ in 0..3 {
+        // Sensor needs 2 seconds between reads
+        Timer::after_secs(2).await;
+
+        if embassy_time::with_timeout(Duration::from_millis(1500), dht.read())
+            .await
+            .is_ok()
+        {
+            return Some((dht.temperature(), dht.humidity()));
+        }
+        dht.reset().await;
+    }
+
+    None
+}
+
+/// Averages a few battery readings, there is no history to smooth them over in one wake
+///
+/// # Arguments
+/// - `alarm_low` - state of charge in % the battery is low below
+async fn read_battery_once(
+    mut adc: BatteryAdc,
+    ratio: f32,
+    alarm_low: Option<f32>,
+) -> BatteryStatus {
+    let mut average = MovingAverage::<4>::new();
+    let mut millivolts = 0;
+    for _ in 0..4 {
+        millivolts = average.add(battery_millivolts(adc.read_millivolts().await, ratio));
+    }
+    let percentage = state_of_charge(LI_ION_CURVE, millivolts);
+    let low = Thresholds {
+        high: None,
+        low: alarm_low,
+        hysteresis: 0.0,
+    };
+
+    BatteryStatus {
+        voltage: f32::from(millivolts) / 1000.0,
+        percentage,
+        low: low.is_exceeded(f32::from(percentage)),
+    }
+}
+
+/// POSTs buffered samples to upload URL in chunks, removing every accepted one
+///
+/// A chunk rejected by server with a client error is dropped, as it would be rejected
+/// again on every wake. Other failures keep the rest of the buffer for the next wake.
+///
+/// # Arguments
+/// - `battery` - state measured on this wake, if battery is monitored
+/// - `unit` - of uploaded temperatures
+///
+/// # Returns
+/// - true, if the whole buffer left the device
+async fn upload_samples(
+    stack: Stack<'static>,
+    upload_url: Option<&str>,
+    interval: Duration,
+    battery: Option<BatteryStatus>,
+    unit: TemperatureUnit,
+) -> bool {
+    let url = match upload_url.map(Url::parse) {
+        Some(Ok(url)) => url,
+        Some(Err(err)) => {
+            error!("battery: invalid upload url: {}", err);
+            return false;
+        }
+        None => {
+            error!("battery: upload url not set");
+            return false;
+        }
+    };
+
+    let mut body = [0_u8; MAX_BATCH_LEN];
+    let mut rx_buffer = [0_u8; 256];
+    let mut tx_buffer = [0_u8; 512];
+    let mut client = HttpClient::new(
+        stack,
+        &mut rx_buffer,
+        &mut tx_buffer,
+        Duration::from_secs(10),
+    );
+
+    // At least one request, it carries battery state even without samples
+    loop {
+        let Ok((len, count)) = with_rtc_state(|state| {
+            let batch = state.next_batch(env!("CARGO_PKG_NAME"), interval.as_secs(), battery, unit);
+            serde_json_core::to_slice(&batch, &mut body).map(|len| (len, batch.samples.len()))
+        }) else {
+            // Body is sized for the largest batch
+            error!("battery: batch too big");
+            return false;
+        };
+
+        match client.post_json(&url, &body[..len]).await {
+            Ok(code) if (200..300).contains(&code) => {
+                info!("battery: uploaded {} samples to {}", count, url.host);
+            }
+            Ok(code) if (400..500).contains(&code) && code != 408 && code != 429 => {
+                error!(
+                    "battery: {} rejected {} samples with {}, dropped",
+                    url.host, count, code
+                );
+            }
+            Ok(code) => {
+                warn!("battery: {} responded {}", url.host, code);
+                return false;
+            }
+            Err(err) => {
+                warn!("battery: upload to {} failed: {}", url.host, err);
+                return false;
+            }
+        }
+
+        let done = with_rtc_state(|state| {
+            state.remove_uploaded(count);
+            state.samples().is_empty()
+        });
+        if done {
+            return true;
+        }
+    }
+}
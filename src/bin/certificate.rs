@@ -0,0 +1,65 @@
+//!
+//! Certificate of HTTPS
+//!
+//! Loaded from flash, or a self-signed one is generated on the first boot with
+//! HTTPS and saved, so its fingerprint stays the same across restarts.
+//!
+
+use defmt::{error, info};
+use esp_hal::rng::Rng;
+use esp_temperature::boards::esp32::esp32_c6::Flash;
+use esp_temperature::tls::identity::{Identity, IdentityError, IdentityStore};
+
+/// Common and DNS name of self-signed certificate
+const NAME: &str = "esp-temperature";
+
+/// Loads certificate, or generates a self-signed one
+///
+/// RNG is only truly random while the radio is on, so this goes after Wi-Fi is started.
+///
+/// # Returns
+/// None, if there is no `tls` partition
+pub fn load_identity(flash: &mut Flash, mut rng: Rng) -> Option<Identity> {
+    let store = match IdentityStore::open(flash) {
+        Ok(store) => store,
+        Err(err) => {
+            error!("tls: {}", err);
+            return None;
+        }
+    };
+
+    let identity = match store.load(flash) {
+        Some(identity) => {
+            info!("tls: certificate loaded");
+            identity
+        }
+        None => {
+            let identity = loop {
+                let mut key = [0; 32];
+                let mut serial = [0; 16];
+                rng.read(&mut key);
+                rng.read(&mut serial);
+                match Identity::self_signed(key, serial, NAME) {
+                    Ok(identity) => break identity,
+                    // Scalar is zero or not below curve order, vanishingly rare
+                    Err(IdentityError::InvalidKey) => continue,
+                    Err(err) => {
+                        error!("tls: {}", err);
+                        return None;
+                    }
+                }
+            };
+            info!("tls: generated self-signed certificate");
+            if let Err(err) = store.save(flash, &identity) {
+                error!("tls: saving certificate failed: {}", err);
+            }
+            identity
+        }
+    };
+
+    info!(
+        "tls: certificate fingerprint {:02X}",
+        identity.fingerprint()
+    );
+    Some(identity)
+}
@@ -0,0 +1,226 @@
+//!
+//! Settings of the device
+//!
+//! Build environment gives defaults of settings, used until settings are saved
+//! from web. Everything the firmware reads at runtime comes from settings.
+//!
+
+use defmt::{error, info, warn};
+use esp_temperature::boards::esp32::esp32_c6::Flash;
+use esp_temperature::net::webhook;
+use esp_temperature::settings::{
+    AlarmSettings, Credentials, DisplayController, GradientKind, GradientRange, HardwareSettings,
+    LedMode, LedSettings, NetworkSettings, OtaSettings, PowerMode, PowerSettings,
+    SelfHeatingSettings, Settings, SettingsStore, StripChip, StripColorOrder, StripEffect,
+};
+use esp_temperature::units::TemperatureUnit;
+
+/// WiFi network to connect to
+const SSID: Option<&str> = option_env!("SSID");
+/// Password of `SSID`, empty or not set for open network
+const PASSWORD: Option<&str> = option_env!("PASSWORD");
+/// Set to `true` to serve web interface on HTTPS with a self-signed certificate
+const HTTPS: Option<&str> = option_env!("HTTPS");
+/// Comma-separated webhook URLs
+const WEBHOOK_URLS: Option<&str> = option_env!("WEBHOOK_URLS");
+/// Seconds between environment readings, 2 by default
+const SAMPLE_INTERVAL: Option<&str> = option_env!("SAMPLE_INTERVAL");
+/// Unit of shown temperatures, `celsius`, `fahrenheit` or `kelvin`
+const TEMPERATURE_UNIT: Option<&str> = option_env!("TEMPERATURE_UNIT");
+
+/// What RGB Led shows besides device status:
+/// - `load` - CPU load instead of device status
+/// - `temperature` or `humidity` - gradient color of value instead of normal status
+const LED_MODE: Option<&str> = option_env!("LED_MODE");
+/// Brightness of RGB Led, 0-255
+const LED_BRIGHTNESS: Option<&str> = option_env!("LED_BRIGHTNESS");
+/// Max sum of RGB Led channels in % of full white, 10-100, 100 by default
+const LED_CURRENT_LIMIT: Option<&str> = option_env!("LED_CURRENT_LIMIT");
+/// Set to `kelvin` to show gradient through color temperatures instead of cold-warm colors
+const LED_GRADIENT: Option<&str> = option_env!("LED_GRADIENT");
+/// Temperatures in °C shown by the ends of gradient and strip bar, 10 and 30 by default
+const LED_TEMPERATURE_LOW: Option<&str> = option_env!("LED_TEMPERATURE_LOW");
+const LED_TEMPERATURE_HIGH: Option<&str> = option_env!("LED_TEMPERATURE_HIGH");
+/// Humidities in % shown by the ends of gradient, 20 and 80 by default
+const LED_HUMIDITY_LOW: Option<&str> = option_env!("LED_HUMIDITY_LOW");
+const LED_HUMIDITY_HIGH: Option<&str> = option_env!("LED_HUMIDITY_HIGH");
+/// Effect of LED strip: `solid`, `bar`, `breathe` or `chase`. Strip is not driven if not set
+const LED_STRIP_EFFECT: Option<&str> = option_env!("LED_STRIP_EFFECT");
+/// LEDs of strip: `ws2812`, `ws2812b` or `sk6812`, `ws2812b` by default
+const LED_STRIP_CHIP: Option<&str> = option_env!("LED_STRIP_CHIP");
+/// Color order of strip LEDs, e.g. `rgb`, `grb` by default
+const LED_STRIP_ORDER: Option<&str> = option_env!("LED_STRIP_ORDER");
+/// Set to `sh1106` if display uses SH1106 controller instead of SSD1306
+const DISPLAY: Option<&str> = option_env!("DISPLAY");
+
+/// Alarm thresholds
+const TEMPERATURE_ALARM_HIGH: Option<&str> = option_env!("TEMPERATURE_ALARM_HIGH");
+const TEMPERATURE_ALARM_LOW: Option<&str> = option_env!("TEMPERATURE_ALARM_LOW");
+const HUMIDITY_ALARM_HIGH: Option<&str> = option_env!("HUMIDITY_ALARM_HIGH");
+const HUMIDITY_ALARM_LOW: Option<&str> = option_env!("HUMIDITY_ALARM_LOW");
+/// State of charge in % to trigger low battery alarm at, 15 by default
+const BATTERY_ALARM_LOW: Option<&str> = option_env!("BATTERY_ALARM_LOW");
+/// Difference of chip and ambient temperatures in °C to warn about, 25 by default
+const SELF_HEATING_WARNING: Option<&str> = option_env!("SELF_HEATING_WARNING");
+/// Share of chip-ambient difference subtracted from ambient temperature, 0-1. Disabled if not set
+const SELF_HEATING_COMPENSATION: Option<&str> = option_env!("SELF_HEATING_COMPENSATION");
+
+/// Ratio of battery voltage divider on GPIO2, e.g. `2` for two equal resistors.
+/// Battery is not monitored if not set
+const BATTERY_DIVIDER: Option<&str> = option_env!("BATTERY_DIVIDER");
+/// Set to `battery` to sleep between measurements and upload them in batches to `UPLOAD_URL`
+const POWER_MODE: Option<&str> = option_env!("POWER_MODE");
+/// Where battery mode POSTs buffered readings, plain `http://` only
+const UPLOAD_URL: Option<&str> = option_env!("UPLOAD_URL");
+/// Seconds of deep sleep between measurements in battery mode, 300 by default
+const SLEEP_INTERVAL: Option<&str> = option_env!("SLEEP_INTERVAL");
+/// Upload every this count of wakes in battery mode, 12 by default
+const UPLOAD_EVERY: Option<&str> = option_env!("UPLOAD_EVERY");
+
+/// Shared key of HMAC-SHA256 signatures of OTA images, unsigned images are accepted if not set
+const OTA_KEY: Option<&str> = option_env!("OTA_KEY");
+/// Seconds new firmware has to get network and sensor reading in, or it is rolled back.
+/// 120 by default
+const OTA_HEALTH_TIMEOUT: Option<&str> = option_env!("OTA_HEALTH_TIMEOUT");
+
+/// Settings built from environment, used until settings are saved from web
+fn default_settings() -> Settings {
+    let led_mode = match LED_MODE {
+        Some("load") => LedMode::Load,
+        Some("temperature") => LedMode::Temperature,
+        Some("humidity") => LedMode::Humidity,
+        _ => LedMode::Status,
+    };
+    let strip = match LED_STRIP_EFFECT {
+        None => None,
+        Some("solid") => Some(StripEffect::Solid),
+        Some("bar") => Some(StripEffect::Bar),
+        Some("breathe") => Some(StripEffect::Breathe),
+        Some("chase") => Some(StripEffect::Chase),
+        Some(other) => {
+            error!("unknown LED strip effect {}", other);
+            None
+        }
+    };
+    let strip_chip = match LED_STRIP_CHIP {
+        Some("ws2812") => StripChip::Ws2812,
+        Some("sk6812") => StripChip::Sk6812,
+        _ => StripChip::Ws2812b,
+    };
+    let strip_order = match LED_STRIP_ORDER {
+        Some("rgb") => StripColorOrder::Rgb,
+        Some("rbg") => StripColorOrder::Rbg,
+        Some("gbr") => StripColorOrder::Gbr,
+        Some("brg") => StripColorOrder::Brg,
+        Some("bgr") => StripColorOrder::Bgr,
+        _ => StripColorOrder::Grb,
+    };
+    let power = PowerSettings::default();
+    let ota = OtaSettings::default();
+
+    Settings {
+        sample_interval_s: parse(SAMPLE_INTERVAL).unwrap_or(2),
+        unit: match TEMPERATURE_UNIT {
+            Some("fahrenheit") => TemperatureUnit::Fahrenheit,
+            Some("kelvin") => TemperatureUnit::Kelvin,
+            _ => TemperatureUnit::Celsius,
+        },
+        alarms: AlarmSettings {
+            temperature_high: parse(TEMPERATURE_ALARM_HIGH),
+            temperature_low: parse(TEMPERATURE_ALARM_LOW),
+            humidity_high: parse(HUMIDITY_ALARM_HIGH),
+            humidity_low: parse(HUMIDITY_ALARM_LOW),
+            battery_low: Some(parse(BATTERY_ALARM_LOW).unwrap_or(15.0)),
+        },
+        network: NetworkSettings {
+            ssid: SSID
+                .unwrap_or("example_wifi_ssid")
+                .try_into()
+                .expect("SSID is too long"),
+            password: Some(
+                PASSWORD
+                    .unwrap_or("")
+                    .try_into()
+                    .expect("PASSWORD is too long"),
+            ),
+            https: parse(HTTPS).unwrap_or(false),
+        },
+        webhooks: webhook::split_urls(WEBHOOK_URLS.unwrap_or("")),
+        led: LedSettings {
+            mode: led_mode,
+            brightness: parse(LED_BRIGHTNESS).unwrap_or(255),
+            current_limit: parse(LED_CURRENT_LIMIT).unwrap_or(100),
+            gradient: match LED_GRADIENT {
+                Some("kelvin") => GradientKind::Kelvin,
+                _ => GradientKind::Colors,
+            },
+            temperature_range: GradientRange {
+                low: parse(LED_TEMPERATURE_LOW).unwrap_or(GradientRange::TEMPERATURE.low),
+                high: parse(LED_TEMPERATURE_HIGH).unwrap_or(GradientRange::TEMPERATURE.high),
+            },
+            humidity_range: GradientRange {
+                low: parse(LED_HUMIDITY_LOW).unwrap_or(GradientRange::HUMIDITY.low),
+                high: parse(LED_HUMIDITY_HIGH).unwrap_or(GradientRange::HUMIDITY.high),
+            },
+            strip,
+            strip_chip,
+            strip_order,
+        },
+        calibration: heapless::Vec::new(),
+        self_heating: SelfHeatingSettings {
+            warning: parse(SELF_HEATING_WARNING).unwrap_or(25.0),
+            compensation: parse(SELF_HEATING_COMPENSATION).unwrap_or(0.0),
+        },
+        hardware: HardwareSettings {
+            display: match DISPLAY {
+                Some("sh1106") => DisplayController::Sh1106,
+                _ => DisplayController::Ssd1306,
+            },
+            battery_divider: parse(BATTERY_DIVIDER),
+        },
+        power: PowerSettings {
+            mode: match POWER_MODE {
+                Some("battery") => PowerMode::Battery,
+                _ => PowerMode::Live,
+            },
+            upload_url: UPLOAD_URL.map(|url| url.try_into().expect("UPLOAD_URL is too long")),
+            sleep_interval_s: parse(SLEEP_INTERVAL).unwrap_or(power.sleep_interval_s),
+            upload_every: parse(UPLOAD_EVERY).unwrap_or(power.upload_every),
+        },
+        ota: OtaSettings {
+            key: OTA_KEY.map(|key| key.try_into().expect("OTA_KEY is too long")),
+            health_timeout_s: parse(OTA_HEALTH_TIMEOUT).unwrap_or(ota.health_timeout_s),
+        },
+        // The first ones are set from web
+        credentials: Credentials::default(),
+    }
+}
+
+/// Loads settings saved from web, or defaults if nothing valid is saved
+pub fn load_settings(flash: &mut Flash) -> Settings {
+    let saved = match SettingsStore::open(flash) {
+        Ok(store) => store.load(flash),
+        Err(err) => {
+            error!("settings: {}", err);
+            None
+        }
+    };
+
+    match saved {
+        Some(settings) => {
+            info!("settings: loaded");
+            settings
+        }
+        None => {
+            let settings = default_settings();
+            if let Err(err) = settings.validate() {
+                warn!("settings: invalid default {}", err);
+            }
+            settings
+        }
+    }
+}
+
+fn parse<T: core::str::FromStr>(value: Option<&str>) -> Option<T> {
+    value.and_then(|value| value.parse().ok())
+}
@@ -0,0 +1,139 @@
+//!
+//! Pages on the display
+//!
+
+use core::sync::atomic::Ordering;
+
+use defmt::{error, Debug2Format};
+use embassy_net::Stack;
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::gpio::Output;
+use esp_temperature::alarm::ActiveAlarms;
+use esp_temperature::boards::esp32::esp32_c6::{
+    Flash, HumiditySensorStore, I2c, TemperatureSensorStore, SENSOR_STORE_CAP,
+};
+use esp_temperature::drivers::display::ssd1306::{DisplayFramebuffer, Ssd1306};
+use esp_temperature::events::EventSubscriber;
+use esp_temperature::net::webhook::SharedWebhookStatus;
+use esp_temperature::sensor_data::{Filter, SensorDataStore};
+use esp_temperature::settings::SharedSettings;
+use esp_temperature::status_indicator::Status;
+use esp_temperature::sync::mutex::AtomicMutex;
+use esp_temperature::ui::{self, Diagnostics, NetworkInfo, Page, Readings, Screen, WifiState};
+use esp_temperature::web::{SharedChipTemp, SharedHumidity, SharedTemp};
+
+use crate::led::STATUS_INDICATOR;
+use crate::CPU_LOAD_THREADING;
+
+/// Time each display page is shown before switching to the next one
+const DISPLAY_PAGE_TIME: Duration = Duration::from_secs(5);
+
+/// Shows rotating pages with readings, history, network, alarms and diagnostics on the display
+///
+/// # Arguments
+/// - `_power` - display power pin, kept high while task runs
+#[allow(clippy::too_many_arguments)]
+#[embassy_executor::task]
+pub async fn show_pages(
+    mut display: Ssd1306<I2c>,
+    _power: Output<'static>,
+    temp: SharedTemp,
+    humidity: SharedHumidity,
+    temperature_history: &'static AtomicMutex<TemperatureSensorStore>,
+    humidity_history: &'static AtomicMutex<HumiditySensorStore>,
+    mut events: EventSubscriber,
+    webhooks: SharedWebhookStatus,
+    chip_temp: SharedChipTemp,
+    settings: SharedSettings<Flash>,
+    stack: Stack<'static>,
+) {
+    if let Err(err) = display.init().await {
+        error!("display: init failed: {}", Debug2Format(&err));
+        return;
+    }
+
+    // New framebuffer is all dirty, so the first flush sends everything
+    let mut framebuffer = DisplayFramebuffer::new();
+    let mut active_alarms = ActiveAlarms::new();
+    let mut page = Page::Readings;
+    let mut page_shown = Instant::now();
+
+    let mut temperatures = [0.0_f32; SENSOR_STORE_CAP];
+    let mut humidities = [0.0_f32; SENSOR_STORE_CAP];
+
+    loop {
+        while let Some(event) = events.try_next_message_pure() {
+            active_alarms.apply(&event);
+        }
+
+        if DISPLAY_PAGE_TIME <= page_shown.elapsed() {
+            page = page.next();
+            page_shown = Instant::now();
+        }
+
+        let wifi = if STATUS_INDICATOR.is_set(Status::WifiConnecting) {
+            WifiState::Connecting
+        } else if STATUS_INDICATOR.is_set(Status::NoIp) {
+            WifiState::NoIp
+        } else {
+            WifiState::Connected
+        };
+        let config = stack.config_v4();
+
+        let temperatures_len = copy_history(&*temperature_history.lock().await, &mut temperatures);
+        let humidities_len = copy_history(&*humidity_history.lock().await, &mut humidities);
+        let webhooks = webhooks.get().await;
+
+        let screen = Screen {
+            unit: settings.get().await.unit,
+            readings: Readings {
+                temperature: temp.get().await,
+                humidity: humidity.get().await,
+            },
+            temperature_history: &temperatures[..temperatures_len],
+            humidity_history: &humidities[..humidities_len],
+            network: NetworkInfo {
+                wifi,
+                ip: config.as_ref().map(|config| config.address.address()),
+                gateway: config.and_then(|config| config.gateway),
+            },
+            alarms: active_alarms.alarms(),
+            faults: active_alarms.faults(),
+            diagnostics: Diagnostics {
+                uptime_s: Instant::now().as_secs(),
+                cpu_load: CPU_LOAD_THREADING.load(Ordering::SeqCst),
+                chip_temperature: chip_temp.get().await,
+                heap_used: esp_alloc::HEAP.used(),
+                heap_free: esp_alloc::HEAP.free(),
+                webhooks_delivered: webhooks.delivered,
+                webhooks_failed: webhooks.failed,
+            },
+        };
+
+        ui::render(&mut framebuffer, page, &screen);
+        if let Err(err) = display.flush_dirty(&mut framebuffer).await {
+            error!("display: flush failed: {}", Debug2Format(&err));
+            // Display content is unknown now
+            framebuffer.mark_all_dirty();
+        }
+
+        Timer::after_secs(1).await;
+    }
+}
+
+/// Copies stored values into buffer
+///
+/// # Returns
+/// Count of copied values
+fn copy_history<F, const N: usize>(store: &SensorDataStore<f32, F, N>, out: &mut [f32]) -> usize
+where
+    F: Filter<Item = f32>,
+{
+    let mut len = 0;
+    for (slot, data) in out.iter_mut().zip(store.iter()) {
+        *slot = *data.get();
+        len += 1;
+    }
+
+    len
+}
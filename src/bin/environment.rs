@@ -0,0 +1,409 @@
+//!
+//! Temperature and humidity of environment
+//!
+
+use core::sync::atomic::Ordering;
+
+use defmt::{error, info, warn};
+use embassy_time::{Duration, Timer};
+use esp_temperature::alarm::{ThresholdAlarm, Thresholds};
+use esp_temperature::boards::esp32::esp32_c6::{
+    Flash, HumiditySensorStore, I2c, TemperatureSensorStore,
+};
+use esp_temperature::calibration::SharedCalibratedReadings;
+use esp_temperature::discovery::{Chip, Inventory};
+use esp_temperature::drivers::sensors::bme280::{self, Bme280};
+use esp_temperature::drivers::sensors::dht22::Dht22Esp32;
+use esp_temperature::drivers::sensors::lm75b::Lm75B;
+use esp_temperature::drivers::sensors::sht3x::Sht3x;
+use esp_temperature::drivers::sensors::sht4x::Sht4x;
+use esp_temperature::drivers::sensors::temperature::TemperatureSensorAsync;
+use esp_temperature::events::{Event, EventPublisher, Heating, Quantity};
+use esp_temperature::self_heating::SelfHeating;
+use esp_temperature::settings::{SelfHeatingSettings, Settings, SharedSettings};
+use esp_temperature::status_indicator::Status;
+use esp_temperature::sync::mutex::AtomicMutex;
+use esp_temperature::web::{SharedChipTemp, SharedHumidity, SharedPressure, SharedTemp};
+
+use crate::firmware::SENSOR_READ;
+use crate::led::{LED_HUMIDITY, STATUS_INDICATOR};
+
+/// Publishes environment readings to web, history, LED and alarms
+pub struct EnvironmentPublisher {
+    sensor: &'static str,
+    out_temp: SharedTemp,
+    out_humidity: SharedHumidity,
+    temperature_history: &'static AtomicMutex<TemperatureSensorStore>,
+    humidity_history: &'static AtomicMutex<HumiditySensorStore>,
+    events: EventPublisher,
+    chip_temp: SharedChipTemp,
+    settings: SharedSettings<Flash>,
+    calibrated: SharedCalibratedReadings,
+    self_heating: SelfHeating,
+    temp_alarm: ThresholdAlarm,
+    humidity_alarm: ThresholdAlarm,
+    faulted: bool,
+}
+
+impl EnvironmentPublisher {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sensor: &'static str,
+        out_temp: SharedTemp,
+        out_humidity: SharedHumidity,
+        temperature_history: &'static AtomicMutex<TemperatureSensorStore>,
+        humidity_history: &'static AtomicMutex<HumiditySensorStore>,
+        events: EventPublisher,
+        chip_temp: SharedChipTemp,
+        settings: SharedSettings<Flash>,
+        calibrated: SharedCalibratedReadings,
+    ) -> Self {
+        Self {
+            sensor,
+            out_temp,
+            out_humidity,
+            temperature_history,
+            humidity_history,
+            events,
+            chip_temp,
+            settings,
+            calibrated,
+            // Levels are taken from settings on every reading
+            self_heating: SelfHeating::new(
+                SelfHeatingSettings::default().warning,
+                SelfHeatingSettings::default().compensation,
+            ),
+            // Thresholds are taken from settings on every reading
+            temp_alarm: ThresholdAlarm::new(
+                Quantity::Temperature,
+                Thresholds {
+                    hysteresis: 0.5,
+                    ..Thresholds::disabled()
+                },
+            ),
+            humidity_alarm: ThresholdAlarm::new(
+                Quantity::Humidity,
+                Thresholds {
+                    hysteresis: 2.0,
+                    ..Thresholds::disabled()
+                },
+            ),
+            faulted: false,
+        }
+    }
+
+    /// Time to wait before next reading
+    async fn sample_interval(&self) -> Duration {
+        Duration::from_secs(self.settings.get().await.sample_interval_s.into())
+    }
+
+    /// Reports failed read, only the first one of series is published
+    fn fault(&mut self) {
+        if !self.faulted {
+            self.faulted = true;
+            STATUS_INDICATOR.set(Status::SensorFault, true);
+            self.events
+                .publish_immediate(Event::SensorFault(self.sensor));
+        }
+    }
+
+    /// Corrects raw value by calibration of sensor quantity and records both for audit
+    async fn calibrate(&self, settings: &Settings, quantity: Quantity, raw: f32) -> f32 {
+        self.calibrated
+            .calibrate(&settings.calibration, self.sensor, quantity, raw)
+            .await
+    }
+
+    /// # Arguments
+    /// - `humi` - None, if sensor measures temperature only
+    async fn publish(&mut self, temp: f32, humi: Option<f32>) {
+        SENSOR_READ.store(true, Ordering::Relaxed);
+        if self.faulted {
+            self.faulted = false;
+            STATUS_INDICATOR.set(Status::SensorFault, false);
+            self.events
+                .publish_immediate(Event::SensorRecovered(self.sensor));
+        }
+
+        let settings = self.settings.get().await;
+        let mut temp = self.calibrate(&settings, Quantity::Temperature, temp).await;
+        let humi = match humi {
+            Some(humi) => Some(
+                self.calibrate(&settings, Quantity::Humidity, humi)
+                    .await
+                    .clamp(0.0, 100.0),
+            ),
+            None => None,
+        };
+
+        if let Some(chip) = self.chip_temp.get().await {
+            let levels = settings.self_heating;
+            self.self_heating
+                .set_levels(levels.warning, levels.compensation);
+            let heating = Heating {
+                sensor: self.sensor,
+                difference: chip - temp,
+                warning: levels.warning,
+            };
+            match self.self_heating.update(chip, temp) {
+                Some(true) => {
+                    warn!(
+                        "{}: chip at {} °C heats sensor at {} °C",
+                        self.sensor, chip, temp
+                    );
+                    self.events
+                        .publish_immediate(Event::SelfHeatingStarted(heating));
+                }
+                Some(false) => {
+                    info!("{}: chip heating is back to normal", self.sensor);
+                    self.events
+                        .publish_immediate(Event::SelfHeatingStopped(heating));
+                }
+                None => {}
+            }
+            temp = self.self_heating.compensate(chip, temp);
+        }
+
+        self.out_temp.set(temp).await;
+        self.temperature_history.lock().await.add(temp);
+        if let Some(humi) = humi {
+            self.out_humidity.set(humi).await;
+            self.humidity_history.lock().await.add(humi);
+        }
+
+        STATUS_INDICATOR.set_gradient_value(match humi {
+            Some(humi) if LED_HUMIDITY.load(Ordering::Relaxed) => humi,
+            _ => temp,
+        });
+
+        let alarms = settings.alarms;
+        self.temp_alarm.set_thresholds(Thresholds {
+            high: alarms.temperature_high,
+            low: alarms.temperature_low,
+            ..*self.temp_alarm.thresholds()
+        });
+        self.humidity_alarm.set_thresholds(Thresholds {
+            high: alarms.humidity_high,
+            low: alarms.humidity_low,
+            ..*self.humidity_alarm.thresholds()
+        });
+
+        for event in [
+            self.temp_alarm.update(temp),
+            humi.and_then(|humi| self.humidity_alarm.update(humi)),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            info!("{}", event);
+            self.events.publish_immediate(event);
+        }
+        STATUS_INDICATOR.set(
+            Status::AlarmActive,
+            self.temp_alarm.is_active() || self.humidity_alarm.is_active(),
+        );
+    }
+}
+
+#[embassy_executor::task]
+pub async fn publish_web_environment(mut dht: Dht22Esp32, mut publisher: EnvironmentPublisher) {
+    loop {
+        Timer::after(publisher.sample_interval().await).await;
+
+        if embassy_time::with_timeout(Duration::from_millis(1500), dht.read())
+            .await
+            .is_err()
+        {
+            error!("failed to get environment data");
+            dht.reset().await;
+            publisher.fault();
+            continue;
+        }
+
+        publisher
+            .publish(dht.temperature(), Some(dht.humidity()))
+            .await;
+    }
+}
+
+/// Reading of [`EnvironmentSensor`]
+pub struct EnvironmentReading {
+    /// In °C
+    pub temperature: f32,
+    /// In %, None if sensor measures temperature only
+    pub humidity: Option<f32>,
+    /// In hPa, None if sensor has no barometer
+    pub pressure: Option<f32>,
+}
+
+/// Temperature and humidity sensor found by discovery
+pub enum EnvironmentSensor {
+    Sht4x(Sht4x<I2c>),
+    Sht3x(Sht3x<I2c>),
+    Bme280(Bme280<I2c>),
+    /// Temperature only
+    Lm75b(Lm75B<I2c>),
+}
+
+impl EnvironmentSensor {
+    /// Picks the most accurate of discovered sensors, sensors with humidity first
+    ///
+    /// # Returns
+    /// - None, if there is no I2C temperature sensor
+    pub fn from_inventory(inventory: &Inventory, i2c: &I2c) -> Option<Self> {
+        if let Some(address) = inventory.find(Chip::Sht4x) {
+            Some(Self::Sht4x(Sht4x::new(i2c.clone(), address)))
+        } else if let Some(address) = inventory.find(Chip::Sht3x) {
+            Some(Self::Sht3x(Sht3x::new(i2c.clone(), address)))
+        } else if let Some(address) = inventory.find(Chip::Bme280) {
+            Some(Self::Bme280(Bme280::new(i2c.clone(), address)))
+        } else {
+            inventory
+                .find(Chip::Lm75b)
+                .map(|address| Self::Lm75b(Lm75B::new(i2c.clone(), address)))
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sht4x(_) => "sht4x",
+            Self::Sht3x(_) => "sht3x",
+            Self::Bme280(_) => "bme280",
+            Self::Lm75b(_) => "lm75b",
+        }
+    }
+
+    pub async fn init(&mut self) -> bool {
+        match self {
+            Self::Bme280(bme280) => {
+                if let Err(err) = bme280.init().await {
+                    error!("bme280: init failed: {}", err);
+                    return false;
+                }
+                bme280
+                    .configure(bme280::Config::default(), bme280::Mode::Sleep)
+                    .await
+                    .inspect_err(|err| error!("bme280: configure failed: {}", err))
+                    .is_ok()
+            }
+            // Shutdown mode survives MCU reset
+            Self::Lm75b(lm75b) => lm75b
+                .set_shutdown(false)
+                .await
+                .inspect_err(|err| error!("lm75b: init failed: {}", err))
+                .is_ok(),
+            Self::Sht4x(_) | Self::Sht3x(_) => true,
+        }
+    }
+
+    pub async fn read(&mut self) -> Option<EnvironmentReading> {
+        match self {
+            Self::Sht4x(sht4x) => sht4x
+                .measure()
+                .await
+                .inspect_err(|err| error!("sht4x: read failed: {}", err))
+                .ok()
+                .map(|measurement| EnvironmentReading {
+                    temperature: measurement.temperature,
+                    humidity: Some(measurement.humidity),
+                    pressure: None,
+                }),
+            Self::Sht3x(sht3x) => sht3x
+                .measure()
+                .await
+                .inspect_err(|err| error!("sht3x: read failed: {}", err))
+                .ok()
+                .map(|measurement| EnvironmentReading {
+                    temperature: measurement.temperature,
+                    humidity: Some(measurement.humidity),
+                    pressure: None,
+                }),
+            Self::Bme280(bme280) => match bme280.measure().await {
+                Ok(measurement) => measurement.and_then(|measurement| {
+                    Some(EnvironmentReading {
+                        temperature: measurement.temperature,
+                        humidity: Some(measurement.humidity?),
+                        pressure: measurement.pressure,
+                    })
+                }),
+                Err(err) => {
+                    error!("bme280: read failed: {}", err);
+                    None
+                }
+            },
+            Self::Lm75b(lm75b) => lm75b
+                .read_temperature()
+                .await
+                .inspect_err(|err| error!("lm75b: read failed: {}", err))
+                .ok()
+                .map(|temperature| EnvironmentReading {
+                    temperature,
+                    humidity: None,
+                    pressure: None,
+                }),
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn publish_i2c_environment(
+    mut sensor: EnvironmentSensor,
+    mut publisher: EnvironmentPublisher,
+    out_pressure: SharedPressure,
+) {
+    if !sensor.init().await {
+        publisher.fault();
+        return;
+    }
+
+    loop {
+        Timer::after(publisher.sample_interval().await).await;
+
+        match sensor.read().await {
+            Some(reading) => {
+                publisher
+                    .publish(reading.temperature, reading.humidity)
+                    .await;
+                if reading.pressure.is_some() {
+                    out_pressure.set(reading.pressure).await;
+                }
+            }
+            None => publisher.fault(),
+        }
+    }
+}
+
+/// Reads pressure of barometer which is not the environment sensor
+#[embassy_executor::task]
+pub async fn publish_pressure(
+    mut barometer: Bme280<I2c>,
+    out_pressure: SharedPressure,
+    settings: SharedSettings<Flash>,
+) {
+    if let Err(err) = barometer.init().await {
+        error!("barometer: init failed: {}", err);
+        return;
+    }
+    // Temperature is still measured, pressure compensation needs it
+    let config = bme280::Config {
+        humidity: bme280::Oversampling::Skip,
+        ..Default::default()
+    };
+    if let Err(err) = barometer.configure(config, bme280::Mode::Sleep).await {
+        error!("barometer: configure failed: {}", err);
+        return;
+    }
+
+    loop {
+        Timer::after_secs(settings.get().await.sample_interval_s.into()).await;
+
+        match barometer.measure().await {
+            Ok(measurement) => {
+                if let Some(pressure) = measurement.and_then(|measurement| measurement.pressure) {
+                    out_pressure.set(Some(pressure)).await;
+                }
+            }
+            Err(err) => error!("barometer: read failed: {}", err),
+        }
+    }
+}
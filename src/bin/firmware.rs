@@ -0,0 +1,65 @@
+//!
+//! Health check of updated firmware
+//!
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::{error, info, warn};
+use embassy_net::Stack;
+use embassy_time::{Duration, Instant, Timer};
+use esp_temperature::boards::esp32::esp32_c6::Flash;
+use esp_temperature::ota::{self, BootState, SharedOta};
+
+/// Set by the first successful environment reading, new firmware image is
+/// confirmed only after it
+pub static SENSOR_READ: AtomicBool = AtomicBool::new(false);
+
+/// Confirms freshly updated firmware once it gets network and a sensor reading,
+/// or rejects it and reboots into the previous one
+///
+/// # Arguments
+/// - `timeout` - time the firmware has to get healthy in
+#[embassy_executor::task]
+pub async fn confirm_firmware(ota: SharedOta<Flash>, stack: Stack<'static>, timeout: Duration) {
+    let boot = {
+        let mut flash = ota.flash().lock().await;
+        BootState::read(&mut *flash)
+    };
+    match boot {
+        Ok(boot) if boot.needs_confirmation() => {}
+        Ok(_) => return,
+        Err(err) => {
+            warn!("ota: no OTA data: {}", err);
+            return;
+        }
+    }
+
+    let deadline = Instant::now() + timeout;
+    info!("ota: new firmware, confirming in {} s", timeout.as_secs());
+    while Instant::now() < deadline {
+        if stack.is_config_up() && SENSOR_READ.load(Ordering::Relaxed) {
+            let mut flash = ota.flash().lock().await;
+            match ota::confirm(&mut *flash) {
+                Ok(()) => info!("ota: firmware confirmed"),
+                Err(err) => error!("ota: failed to confirm firmware: {}", err),
+            }
+            return;
+        }
+        Timer::after_secs(1).await;
+    }
+
+    error!("ota: firmware is not healthy, rolling back");
+    if let Err(err) = ota::reject(&mut *ota.flash().lock().await) {
+        error!("ota: failed to reject firmware: {}", err);
+    }
+    esp_hal::system::software_reset();
+}
+
+/// Reboots into activated image, after upload response is sent
+#[embassy_executor::task]
+pub async fn reboot_after_update(ota: SharedOta<Flash>) {
+    let slot = ota.wait_reboot().await;
+    info!("ota: rebooting into slot {}", slot.number());
+    Timer::after_secs(1).await;
+    esp_hal::system::software_reset();
+}
@@ -0,0 +1,176 @@
+//!
+//! RGB Led showing device status and LED strip effects
+//!
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use defmt::trace;
+use embassy_time::{Duration, Instant, Timer};
+use esp_temperature::boards::esp32::esp32_c6::{Flash, LedStrip, RgbLed, LED_STRIP_LEN};
+use esp_temperature::color_gradient::{Gradient, COLD_TO_WARM, DRY_TO_WET};
+use esp_temperature::drivers::led::{
+    smooth::SmoothLed,
+    ws2812::{ColorOrder, Timing},
+    LedStripAsync,
+};
+use esp_temperature::led_effects::Effect;
+use esp_temperature::settings::{
+    GradientKind, GradientRange, LedMode, LedSettings, SharedSettings, StripChip, StripColorOrder,
+    StripEffect,
+};
+use esp_temperature::status_indicator::{IndicatorMode, StatusIndicator};
+use esp_temperature::web::SharedTemp;
+
+use crate::CPU_LOAD_THREADING;
+
+/// Device status shown by RGB Led
+pub static STATUS_INDICATOR: StatusIndicator = StatusIndicator::new();
+/// RGB Led gradient shows humidity instead of temperature
+pub static LED_HUMIDITY: AtomicBool = AtomicBool::new(false);
+/// RGB Led gradient goes through color temperatures
+static LED_KELVIN: AtomicBool = AtomicBool::new(false);
+/// Max sum of RGB Led channels in % of full white
+static LED_CURRENT_LIMIT: AtomicU8 = AtomicU8::new(100);
+/// f32 bits of values shown by the ends of RGB Led gradient
+static LED_GRADIENT_LOW: AtomicU32 = AtomicU32::new(0);
+static LED_GRADIENT_HIGH: AtomicU32 = AtomicU32::new(0);
+
+/// Indicates device status or CPU load with the RGB Led
+#[embassy_executor::task]
+pub async fn indicate_status(mut led: SmoothLed<RgbLed>) {
+    let start = Instant::now();
+
+    loop {
+        let load = CPU_LOAD_THREADING.load(Ordering::SeqCst);
+        trace!("Load {}", load);
+
+        let elapsed = start.elapsed().as_millis();
+        let gradient = led_gradient(
+            LED_HUMIDITY.load(Ordering::Relaxed),
+            LED_KELVIN.load(Ordering::Relaxed),
+            GradientRange {
+                low: f32::from_bits(LED_GRADIENT_LOW.load(Ordering::Relaxed)),
+                high: f32::from_bits(LED_GRADIENT_HIGH.load(Ordering::Relaxed)),
+            },
+        );
+        let color = STATUS_INDICATOR.color(elapsed, load, &gradient);
+        led.set_brightness(STATUS_INDICATOR.brightness());
+        led.set_current_limit(LED_CURRENT_LIMIT.load(Ordering::Relaxed));
+        // Fade takes the frame time, smoothing jumps between frames
+        led.fade_to(color, Duration::from_millis(60)).await;
+    }
+}
+
+/// Runs effect on LED strip
+#[embassy_executor::task]
+pub async fn run_led_strip(mut strip: LedStrip, effect: Effect, temp: SharedTemp) {
+    let start = Instant::now();
+    let mut pixels = [(0_u8, 0_u8, 0_u8); LED_STRIP_LEN];
+
+    loop {
+        let elapsed = start.elapsed().as_millis();
+        effect.render(elapsed, temp.get().await, &mut pixels);
+        strip.write(&pixels).await;
+
+        Timer::after_millis(30).await
+    }
+}
+
+pub fn strip_effect(effect: StripEffect, led: &LedSettings) -> Effect {
+    let GradientRange { low, high } = led.temperature_range;
+    match effect {
+        StripEffect::Solid => Effect::Solid((255, 255, 255)),
+        StripEffect::Bar => Effect::BarGraph {
+            gradient: Gradient::Stops {
+                stops: COLD_TO_WARM,
+                low,
+                high,
+            },
+            min: low,
+            max: high,
+        },
+        StripEffect::Breathe => Effect::Breathe {
+            color: (255, 128, 0),
+            period_ms: 4000,
+        },
+        StripEffect::Chase => Effect::Chase {
+            color: (0, 0, 255),
+            step_ms: 80,
+            tail: 4,
+        },
+    }
+}
+
+pub fn strip_timing(chip: StripChip) -> Timing {
+    match chip {
+        StripChip::Ws2812 => Timing::WS2812,
+        StripChip::Ws2812b => Timing::WS2812B,
+        StripChip::Sk6812 => Timing::SK6812,
+    }
+}
+
+pub fn strip_order(order: StripColorOrder) -> ColorOrder {
+    match order {
+        StripColorOrder::Rgb => ColorOrder::Rgb,
+        StripColorOrder::Rbg => ColorOrder::Rbg,
+        StripColorOrder::Grb => ColorOrder::Grb,
+        StripColorOrder::Gbr => ColorOrder::Gbr,
+        StripColorOrder::Brg => ColorOrder::Brg,
+        StripColorOrder::Bgr => ColorOrder::Bgr,
+    }
+}
+
+fn led_gradient(
+    humidity: bool,
+    kelvin: bool,
+    GradientRange { low, high }: GradientRange,
+) -> Gradient {
+    match (humidity, kelvin) {
+        // Wet is cold blue, dry is warm red
+        (true, true) => Gradient::Kelvin {
+            cold: high,
+            warm: low,
+        },
+        (true, false) => Gradient::Stops {
+            stops: DRY_TO_WET,
+            low,
+            high,
+        },
+        (false, true) => Gradient::Kelvin {
+            cold: low,
+            warm: high,
+        },
+        (false, false) => Gradient::Stops {
+            stops: COLD_TO_WARM,
+            low,
+            high,
+        },
+    }
+}
+
+pub fn apply_led(led: &LedSettings) {
+    STATUS_INDICATOR.set_mode(match led.mode {
+        LedMode::Status => IndicatorMode::Status,
+        LedMode::Load => IndicatorMode::CpuLoad,
+        LedMode::Temperature | LedMode::Humidity => IndicatorMode::Gradient,
+    });
+    LED_HUMIDITY.store(led.mode == LedMode::Humidity, Ordering::Relaxed);
+    LED_KELVIN.store(led.gradient == GradientKind::Kelvin, Ordering::Relaxed);
+    let range = match led.mode {
+        LedMode::Humidity => led.humidity_range,
+        _ => led.temperature_range,
+    };
+    LED_GRADIENT_LOW.store(range.low.to_bits(), Ordering::Relaxed);
+    LED_GRADIENT_HIGH.store(range.high.to_bits(), Ordering::Relaxed);
+    STATUS_INDICATOR.set_brightness(led.brightness);
+    LED_CURRENT_LIMIT.store(led.current_limit, Ordering::Relaxed);
+}
+
+/// Applies settings saved from web which are not read by other tasks
+#[embassy_executor::task]
+pub async fn apply_settings(settings: SharedSettings<Flash>) {
+    loop {
+        settings.wait_changed().await;
+        apply_led(&settings.get().await.led);
+    }
+}
@@ -8,16 +8,14 @@
 #![feature(impl_trait_in_assoc_type)]
 
 use core::mem::transmute;
-use core::sync::atomic::{AtomicU8, Ordering};
+use core::sync::atomic::AtomicU8;
 
-use defmt::{error, info, trace};
+use defmt::{info, warn};
 use embassy_executor::Spawner;
-
-use embassy_time::{Duration, Timer};
-use embedded_hal_async::i2c::I2c;
+use embassy_time::Duration;
 use esp_hal::clock::CpuClock;
 
-use esp_hal::gpio::Output;
+use esp_hal::gpio::{Input, InputConfig, Output, Pull};
 use esp_hal::interrupt::software::SoftwareInterruptControl;
 use esp_hal::interrupt::Priority;
 use esp_hal::rmt::Rmt;
@@ -27,16 +25,46 @@ use esp_hal::timer::systimer::SystemTimer;
 
 use esp_hal::timer::timg::TimerGroup;
 use esp_hal_embassy::InterruptExecutor;
+use esp_temperature::auth::Auth;
+use esp_temperature::calibration::{CalibratedReading, SharedCalibratedReadings, MAX_CALIBRATIONS};
+use esp_temperature::discovery::{self, Chip, Inventory, SharedInventory};
+use esp_temperature::drivers::onewire::OneWireBus;
+use esp_temperature::drivers::sensors::bme280::Bme280;
 use esp_temperature::drivers::sensors::dht22::Dht22Esp32;
+use esp_temperature::drivers::sensors::scd4x::Scd4x;
+use esp_temperature::drivers::sensors::tsens::Tsens;
+use esp_temperature::events::{Event, EventBus};
 use esp_temperature::load_indicator::LoadExecutorHook;
+use esp_temperature::net::redirect::redirect_task;
+use esp_temperature::net::webhook::{self, SharedWebhookStatus, WebhookStatus};
+use esp_temperature::ota::{OtaStatus, SharedOta};
+use esp_temperature::sensor_data::filter::NoopFilter;
+use esp_temperature::sensor_data::SensorDataStore;
+use esp_temperature::settings::{
+    DisplayController, NetworkSettings, PowerMode, Settings, SharedSettings, MAX_OTA_KEY_LEN,
+    MAX_URL_LEN,
+};
+use esp_temperature::status_indicator::Status;
 use esp_temperature::sync::mutex::AtomicMutex;
-use esp_temperature::web::{SharedHumidity, SharedTemp};
+use esp_temperature::tls::identity::{Identity, PemDecoder};
+use esp_temperature::web::auth::SharedAuth;
+use esp_temperature::web::{
+    ProbeReading, SharedBattery, SharedChipTemp, SharedCo2, SharedHumidity, SharedPressure,
+    SharedProbes, SharedTemp, SharedTls, WebBuffers, MAX_PROBES, WEB_TASK_POOL_SIZE,
+};
 use esp_wifi::EspWifiController;
+use static_cell::ConstStaticCell;
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use esp_bootloader_esp_idf::ota::Slot;
 
 use {esp_backtrace as _, esp_println as _};
 
+use esp_temperature::battery::BatteryStatus;
 use esp_temperature::boards::esp32::esp32_c6::*;
-use esp_temperature::drivers::led::RgbLedAsync as _;
+use esp_temperature::drivers::display::ssd1306::{Controller, Ssd1306};
+use esp_temperature::drivers::i2c::stats::{I2cStats, SharedI2cStats};
+use esp_temperature::drivers::led::smooth::SmoothLed;
 
 extern crate alloc;
 
@@ -47,6 +75,12 @@ esp_bootloader_esp_idf::esp_app_desc!();
 /// Load from main executor
 static CPU_LOAD_THREADING: AtomicU8 = AtomicU8::new(100);
 
+/// Certificate uploaded from web, decoded as it streams in
+static TLS_UPLOAD: AtomicMutex<PemDecoder> = AtomicMutex::new(PemDecoder::new());
+/// Too large to be built on the stack and moved
+static WEB_BUFFERS: ConstStaticCell<[WebBuffers; WEB_TASK_POOL_SIZE]> =
+    ConstStaticCell::new([const { WebBuffers::new() }; WEB_TASK_POOL_SIZE]);
+
 macro_rules! mk_static {
     ($t:ty,$val:expr) => {{
         static STATIC_CELL: static_cell::StaticCell<$t> = static_cell::StaticCell::new();
@@ -56,6 +90,28 @@ macro_rules! mk_static {
     }};
 }
 
+mod battery_mode;
+mod certificate;
+mod config;
+mod display;
+mod environment;
+mod firmware;
+mod led;
+mod sensors;
+mod setup;
+
+use battery_mode::battery_cycle;
+use certificate::load_identity;
+use config::load_settings;
+use display::show_pages;
+use environment::{
+    publish_i2c_environment, publish_pressure, publish_web_environment, EnvironmentPublisher,
+    EnvironmentSensor,
+};
+use firmware::{confirm_firmware, reboot_after_update};
+use led::{apply_led, apply_settings, indicate_status, run_led_strip, STATUS_INDICATOR};
+use sensors::{publish_battery, publish_chip_temperature, publish_co2, publish_probes};
+
 #[esp_hal::main]
 fn main() -> ! {
     let mut executor = ::esp_hal_embassy::Executor::new();
@@ -73,33 +129,21 @@ fn main() -> ! {
     )
 }
 
-/// Indicates load of CPU by brightness of the RGB Led
-#[embassy_executor::task]
-async fn indicate_load(mut led: RgbLed) {
-    loop {
-        let load = CPU_LOAD_THREADING.load(Ordering::SeqCst);
-
-        let load_normalized = (load as u16) * 255 / 100;
-        let load_color = (
-            load_normalized as u8,
-            load_normalized as u8,
-            load_normalized as u8,
-        );
-
-        trace!("Load {}", load);
-
-        led.set_color(load_color.0, load_color.1, load_color.2)
-            .await;
-
-        Timer::after_millis(250).await
-    }
-}
-
 #[embassy_executor::task]
 async fn embassy_main(spawner: Spawner) {
     // generator version: 0.5.0
 
-    let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
+    // Read before init, power mode decides CPU clock
+    let mut flash = Flash::new();
+    let settings = load_settings(&mut flash);
+
+    let battery = settings.power.mode == PowerMode::Battery;
+    let cpu_clock = if battery {
+        CpuClock::_80MHz
+    } else {
+        CpuClock::max()
+    };
+    let config = esp_hal::Config::default().with_cpu_clock(cpu_clock);
     let peripherals = esp_hal::init(config);
 
     esp_alloc::heap_allocator!(size: 64 * 1024);
@@ -107,6 +151,24 @@ async fn embassy_main(spawner: Spawner) {
     let timer0 = SystemTimer::new(peripherals.SYSTIMER);
     esp_hal_embassy::init(timer0.alarm0);
 
+    if battery {
+        battery_cycle(
+            spawner,
+            mk_static!(Settings, settings),
+            peripherals.LPWR,
+            peripherals.I2C0,
+            peripherals.GPIO7,
+            peripherals.GPIO6,
+            peripherals.GPIO4,
+            peripherals.ADC1,
+            peripherals.GPIO2,
+            peripherals.TIMG0,
+            peripherals.RNG,
+            peripherals.WIFI,
+        )
+        .await;
+    }
+
     let sw_ints = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
     let executor_medium = mk_static!(
         InterruptExecutor<0>,
@@ -117,13 +179,41 @@ async fn embassy_main(spawner: Spawner) {
 
     info!("Embassy initialized!");
 
+    let events = &*mk_static!(EventBus, EventBus::new());
+    // Subscribe before publishing anything, so boot event is not lost
+    let webhook_events = events
+        .subscriber()
+        .expect("failed to subscribe webhooks to events");
+    let display_events = events
+        .subscriber()
+        .expect("failed to subscribe display to events");
+    events.immediate_publisher().publish_immediate(Event::Boot);
+
     let freq = Rate::from_mhz(80);
-    let rmt = Rmt::new(peripherals.RMT, freq)
-        .expect("failed to init RMT")
-        .into_async();
+    // Blocking, so strip frames longer than channel memory are sent without gaps
+    let rmt = Rmt::new(peripherals.RMT, freq).expect("failed to init RMT");
+
+    let flash = mk_static!(AtomicMutex<Flash>, AtomicMutex::new(flash));
+    apply_led(&settings.led);
+    let credentials = settings.credentials;
+    // Set up once, changes apply after restart
+    let hardware = settings.hardware;
+    let led_settings = settings.led;
+    let ota_settings = settings.ota.clone();
+    let network = &*mk_static!(NetworkSettings, settings.network.clone());
+    let webhook_urls = &*mk_static!(
+        heapless::Vec<heapless::String<MAX_URL_LEN>, { webhook::MAX_WEBHOOKS }>,
+        settings.webhooks.clone()
+    );
+    let shared_settings = SharedSettings::new(
+        mk_static!(AtomicMutex<Settings>, AtomicMutex::new(settings)),
+        flash,
+        mk_static!(Signal<CriticalSectionRawMutex, ()>, Signal::new()),
+    );
+    spawner.must_spawn(apply_settings(shared_settings.clone()));
 
     let rgb_led = init_rgb_led(rmt.channel0, freq, peripherals.GPIO8.into()).await;
-    spawner.must_spawn(indicate_load(rgb_led));
+    spawner.must_spawn(indicate_status(SmoothLed::new(rgb_led)));
 
     let rng = Rng::new(peripherals.RNG);
     let timg0 = TimerGroup::new(peripherals.TIMG0);
@@ -132,86 +222,286 @@ async fn embassy_main(spawner: Spawner) {
         esp_wifi::init(timg0.timer0, rng).expect("failed to init esp radio ctrl")
     );
 
-    let stack = start_wifi(esp32_wifi_ctrl, peripherals.WIFI, rng, spawner).await;
+    let stack = start_wifi(
+        esp32_wifi_ctrl,
+        peripherals.WIFI,
+        rng,
+        &STATUS_INDICATOR,
+        &network.ssid,
+        network.password.as_deref().unwrap_or(""),
+        spawner,
+    )
+    .await;
+
+    let identity = if network.https {
+        load_identity(&mut *flash.lock().await, rng)
+            .map(|identity| &*mk_static!(Identity, identity))
+    } else {
+        None
+    };
+    if identity.is_some() {
+        spawner.must_spawn(redirect_task(stack));
+    }
+    let shared_tls = SharedTls::new(identity, &TLS_UPLOAD, flash);
 
     let web_temperature = mk_static!(AtomicMutex<f32>, AtomicMutex::new(0.0_f32));
     let shared_temperature = SharedTemp::new(web_temperature);
     let web_humidity = mk_static!(AtomicMutex<f32>, AtomicMutex::new(0.0_f32));
     let shared_humidity = SharedHumidity::new(web_humidity);
 
+    let web_co2 = mk_static!(AtomicMutex<Option<u16>>, AtomicMutex::new(None));
+    let shared_co2 = SharedCo2::new(web_co2);
+
+    let web_pressure = mk_static!(AtomicMutex<Option<f32>>, AtomicMutex::new(None));
+    let shared_pressure = SharedPressure::new(web_pressure);
+
+    let web_chip_temp = mk_static!(AtomicMutex<Option<f32>>, AtomicMutex::new(None));
+    let shared_chip_temp = SharedChipTemp::new(web_chip_temp);
+    spawner.must_spawn(publish_chip_temperature(
+        Tsens::new(peripherals.TSENS),
+        shared_chip_temp.clone(),
+    ));
+
+    let web_battery = mk_static!(AtomicMutex<Option<BatteryStatus>>, AtomicMutex::new(None));
+    let shared_battery = SharedBattery::new(web_battery);
+    if let Some(ratio) = hardware.battery_divider {
+        spawner.must_spawn(publish_battery(
+            BatteryAdc::new(peripherals.ADC1, peripherals.GPIO2),
+            ratio,
+            shared_battery.clone(),
+            events.immediate_publisher(),
+            shared_settings.clone(),
+        ));
+    }
+
+    let web_probes = mk_static!(
+        AtomicMutex<heapless::Vec<ProbeReading, MAX_PROBES>>,
+        AtomicMutex::new(heapless::Vec::new())
+    );
+    let shared_probes = SharedProbes::new(web_probes);
+
+    let temperature_history = &*mk_static!(
+        AtomicMutex<TemperatureSensorStore>,
+        AtomicMutex::new(SensorDataStore::new(
+            SENSOR_STORE_WINDOW,
+            NoopFilter::default()
+        ))
+    );
+    let humidity_history = &*mk_static!(
+        AtomicMutex<HumiditySensorStore>,
+        AtomicMutex::new(SensorDataStore::new(
+            SENSOR_STORE_WINDOW,
+            NoopFilter::default()
+        ))
+    );
+
+    if let Some(effect) = led_settings.strip {
+        let strip = init_led_strip(
+            rmt.channel1,
+            freq,
+            peripherals.GPIO18.into(),
+            led::strip_timing(led_settings.strip_chip),
+            led::strip_order(led_settings.strip_order),
+        )
+        .await;
+        spawner.must_spawn(run_led_strip(
+            strip,
+            led::strip_effect(effect, &led_settings),
+            shared_temperature.clone(),
+        ));
+    }
+
+    let webhook_status = mk_static!(
+        AtomicMutex<WebhookStatus>,
+        AtomicMutex::new(WebhookStatus::default())
+    );
+    let shared_webhook_status = SharedWebhookStatus::new(webhook_status);
+    spawner.must_spawn(esp_temperature::net::webhook::webhook_task(
+        stack,
+        webhook_events,
+        shared_webhook_status.clone(),
+        webhook_urls,
+    ));
+
+    let inventory = mk_static!(
+        AtomicMutex<Inventory>,
+        AtomicMutex::new(Inventory::default())
+    );
+    let shared_inventory = SharedInventory::new(inventory);
+
+    let i2c_stats = mk_static!(AtomicMutex<I2cStats>, AtomicMutex::new(I2cStats::default()));
+    let shared_i2c_stats = SharedI2cStats::new(i2c_stats);
+
+    let ota_status = mk_static!(
+        AtomicMutex<OtaStatus>,
+        AtomicMutex::new(OtaStatus::default())
+    );
+    let ota_activated = mk_static!(
+        Signal<CriticalSectionRawMutex, Slot>,
+        Signal::new()
+    );
+    let ota_key = &*mk_static!(Option<heapless::String<MAX_OTA_KEY_LEN>>, ota_settings.key);
+    let ota_key = ota_key
+        .as_deref()
+        .filter(|key| !key.is_empty())
+        .map(str::as_bytes);
+    let shared_ota = SharedOta::new(flash, ota_status, ota_activated, ota_key);
+    spawner.must_spawn(confirm_firmware(
+        shared_ota.clone(),
+        stack,
+        Duration::from_secs(ota_settings.health_timeout_s.into()),
+    ));
+    spawner.must_spawn(reboot_after_update(shared_ota.clone()));
+
+    let calibrated = mk_static!(
+        AtomicMutex<heapless::Vec<CalibratedReading, MAX_CALIBRATIONS>>,
+        AtomicMutex::new(heapless::Vec::new())
+    );
+    let shared_calibrated = SharedCalibratedReadings::new(calibrated);
+
+    if !credentials.is_configured() {
+        warn!("web: no admin credentials, press BOOT button and set them at /api/credentials");
+    }
+    let auth = SharedAuth::new(mk_static!(
+        AtomicMutex<Auth>,
+        AtomicMutex::new(Auth::new(credentials))
+    ));
+    let boot_button = Input::new(
+        peripherals.GPIO9,
+        InputConfig::default().with_pull(Pull::Up),
+    );
+    spawner.must_spawn(setup::open_setup_on_button(boot_button, auth.clone()));
+
     let web_app_state = mk_static!(
         esp_temperature::web::AppState,
         esp_temperature::web::AppState {
             temp: shared_temperature.clone(),
             humidity: shared_humidity.clone(),
+            co2: shared_co2.clone(),
+            pressure: shared_pressure.clone(),
+            chip_temp: shared_chip_temp.clone(),
+            battery: shared_battery.clone(),
+            probes: shared_probes.clone(),
+            webhooks: shared_webhook_status.clone(),
+            i2c: shared_i2c_stats.clone(),
+            devices: shared_inventory.clone(),
+            ota: shared_ota,
+            auth,
+            settings: shared_settings.clone(),
+            calibrated: shared_calibrated.clone(),
+            tls: shared_tls,
+            rng,
+            client: core::net::Ipv4Addr::UNSPECIFIED.into(),
         }
     );
 
     let web_app = esp_temperature::web::WebApp::default();
-    for id in 0..esp_temperature::web::WEB_TASK_POOL_SIZE {
+    for (id, buffers) in WEB_BUFFERS.take().iter_mut().enumerate() {
         spawner.must_spawn(esp_temperature::web::web_task(
             id,
             stack,
             web_app.router,
             web_app.config,
             web_app_state,
+            buffers,
         ));
     }
 
-    let dht_pin = esp_hal::gpio::Flex::new(peripherals.GPIO4);
-
-    let dht = Dht22Esp32::new(dht_pin);
-    spawner_medium.must_spawn(publish_web_environment(
-        dht,
-        shared_temperature,
-        shared_humidity,
+    let onewire = OneWireBus::new(esp_hal::gpio::Flex::new(peripherals.GPIO19));
+    spawner.must_spawn(publish_probes(
+        onewire,
+        shared_probes,
+        shared_settings.clone(),
+        shared_calibrated.clone(),
     ));
 
-    let mut i2c = init_i2c(
+    let i2c = init_i2c(
         peripherals.I2C0,
         peripherals.GPIO7,
         peripherals.GPIO6,
+        shared_i2c_stats.clone(),
         spawner,
     )
     .await;
 
-    let _display_pin = Output::new(
+    let display_pin = Output::new(
         peripherals.GPIO5,
         esp_hal::gpio::Level::High,
         Default::default(),
     );
 
-    for i in 1..127 {
-        if i2c.write(i, &[0x00]).await.is_ok() {
-            info!("I2C: dev@{:x}", i)
+    let inventory = discovery::discover(&i2c).await;
+    shared_inventory.set(inventory.clone()).await;
+
+    let environment = EnvironmentSensor::from_inventory(&inventory, &i2c);
+    // BME280 gives pressure along with humidity, otherwise barometer is read on its own
+    if !matches!(environment, Some(EnvironmentSensor::Bme280(_))) {
+        let barometer = inventory
+            .find(Chip::Bme280)
+            .or_else(|| inventory.find(Chip::Bmp280));
+        if let Some(address) = barometer {
+            spawner.must_spawn(publish_pressure(
+                Bme280::new(i2c.clone(), address),
+                shared_pressure.clone(),
+                shared_settings.clone(),
+            ));
         }
     }
-
-    // for inspiration have a look at the examples at https://github.com/esp-rs/esp-hal/tree/esp-hal-v1.0.0-rc.0/examples/src/bin
-}
-
-#[embassy_executor::task]
-async fn publish_web_environment(
-    mut dht: Dht22Esp32,
-    out_temp: SharedTemp,
-    out_humidity: SharedHumidity,
-) {
-    loop {
-        Timer::after_secs(2).await;
-
-        if embassy_time::with_timeout(Duration::from_millis(1500), dht.read())
-            .await
-            .is_err()
-        {
-            error!("failed to get environment data");
-            dht.reset().await;
-            continue;
+    let publisher = EnvironmentPublisher::new(
+        environment
+            .as_ref()
+            .map_or("dht22", EnvironmentSensor::name),
+        shared_temperature.clone(),
+        shared_humidity.clone(),
+        temperature_history,
+        humidity_history,
+        events.immediate_publisher(),
+        shared_chip_temp.clone(),
+        shared_settings.clone(),
+        shared_calibrated.clone(),
+    );
+    match environment {
+        Some(sensor) => {
+            info!("environment: {}", sensor.name());
+            spawner.must_spawn(publish_i2c_environment(sensor, publisher, shared_pressure));
+        }
+        None => {
+            info!("environment: dht22");
+            let dht = Dht22Esp32::new(esp_hal::gpio::Flex::new(peripherals.GPIO4));
+            spawner_medium.must_spawn(publish_web_environment(dht, publisher));
         }
+    }
 
-        let temp = dht.temperature();
-        let humi = dht.humidity();
+    if let Some(address) = inventory.find(Chip::Scd4x) {
+        spawner.must_spawn(publish_co2(
+            Scd4x::new(i2c.clone(), address),
+            shared_co2,
+            shared_settings.clone(),
+            shared_calibrated.clone(),
+        ));
+    }
 
-        out_temp.set(temp).await;
-        out_humidity.set(humi).await;
+    if let Some(address) = inventory.find(Chip::Ssd1306) {
+        let controller = match hardware.display {
+            DisplayController::Ssd1306 => Controller::Ssd1306,
+            DisplayController::Sh1106 => Controller::Sh1106,
+        };
+        spawner.must_spawn(show_pages(
+            Ssd1306::new(i2c.clone(), address, controller),
+            display_pin,
+            shared_temperature,
+            shared_humidity,
+            temperature_history,
+            humidity_history,
+            display_events,
+            shared_webhook_status,
+            shared_chip_temp,
+            shared_settings,
+            stack,
+        ));
     }
+
+    STATUS_INDICATOR.set(Status::Booting, false);
+
+    // for inspiration have a look at the examples at https://github.com/esp-rs/esp-hal/tree/esp-hal-v1.0.0-rc.0/examples/src/bin
 }
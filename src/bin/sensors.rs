@@ -0,0 +1,225 @@
+//!
+//! Sensors besides the environment one
+//!
+
+use defmt::{error, info};
+use embassy_futures::yield_now;
+use embassy_time::Timer;
+use esp_temperature::alarm::{ThresholdAlarm, Thresholds};
+use esp_temperature::battery::{
+    battery_millivolts, state_of_charge, BatteryStatus, MovingAverage, LI_ION_CURVE,
+};
+use esp_temperature::boards::esp32::esp32_c6::{BatteryAdc, Flash, I2c};
+use esp_temperature::calibration::SharedCalibratedReadings;
+use esp_temperature::drivers::onewire::{OneWire, OneWireBus, Rom, SearchState};
+use esp_temperature::drivers::sensors::ds18b20::{self, Ds18b20};
+use esp_temperature::drivers::sensors::scd4x::Scd4x;
+use esp_temperature::drivers::sensors::temperature::TemperatureSensorAsync;
+use esp_temperature::drivers::sensors::tsens::Tsens;
+use esp_temperature::events::{EventPublisher, Quantity};
+use esp_temperature::settings::SharedSettings;
+use esp_temperature::web::{
+    ProbeReading, SharedBattery, SharedChipTemp, SharedCo2, SharedProbes, MAX_PROBES,
+};
+
+/// Measures DS18B20 probes found on 1-Wire bus
+///
+/// Bus calls are blocking bit-bang, about 10 ms to read one probe and 15 ms to find
+/// one in search. The task yields between probes, so other tasks on the executor wait
+/// for one probe at most.
+#[embassy_executor::task]
+pub async fn publish_probes(
+    mut bus: OneWireBus,
+    out_probes: SharedProbes,
+    settings: SharedSettings<Flash>,
+    calibrated: SharedCalibratedReadings,
+) {
+    let mut roms: heapless::Vec<Rom, MAX_PROBES> = heapless::Vec::new();
+    let mut search = SearchState::default();
+    loop {
+        match bus.search_next(&mut search) {
+            Ok(Some(rom)) => {
+                if roms.push(rom).is_err() {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(err) => {
+                error!("1-wire: search failed: {}", err);
+                return;
+            }
+        }
+        yield_now().await;
+    }
+    let parasite = ds18b20::any_parasite_powered(&mut bus).unwrap_or(false);
+
+    let mut probes: heapless::Vec<Ds18b20, MAX_PROBES> = roms
+        .iter()
+        .filter(|rom| rom.family() == ds18b20::FAMILY_CODE)
+        .map(|rom| Ds18b20::new(*rom, parasite))
+        .collect();
+    for probe in &mut probes {
+        info!("1-wire: DS18B20 {}", probe.rom());
+        // Learn resolution
+        probe.read_scratchpad(&mut bus).ok();
+        yield_now().await;
+    }
+    if probes.is_empty() {
+        info!("1-wire: no DS18B20 probes");
+        return;
+    }
+
+    let conversion_ms = probes
+        .iter()
+        .map(|probe| probe.resolution().conversion_ms())
+        .max()
+        .unwrap_or_default();
+
+    loop {
+        if let Err(err) = ds18b20::start_conversion_all(&mut bus, parasite) {
+            error!("1-wire: failed to start conversion: {}", err);
+        }
+        Timer::after_millis(conversion_ms).await;
+
+        let calibration = settings.get().await.calibration;
+        let mut readings = heapless::Vec::new();
+        for probe in &mut probes {
+            let temperature = match probe.read_temperature(&mut bus) {
+                // Every probe is calibrated on its own, by ROM id as sensor name
+                Ok(raw) => Some(
+                    calibrated
+                        .calibrate(
+                            &calibration,
+                            &probe.rom().to_hex(),
+                            Quantity::Temperature,
+                            raw,
+                        )
+                        .await,
+                ),
+                Err(err) => {
+                    error!("1-wire: {} read failed: {}", probe.rom(), err);
+                    None
+                }
+            };
+            // Same capacity as probes
+            readings
+                .push(ProbeReading {
+                    rom: probe.rom(),
+                    temperature,
+                })
+                .ok();
+            yield_now().await;
+        }
+        out_probes.set(readings).await;
+
+        Timer::after_secs(10).await;
+    }
+}
+
+/// Measures temperature of the MCU die
+#[embassy_executor::task]
+pub async fn publish_chip_temperature(mut tsens: Tsens, out_chip_temp: SharedChipTemp) {
+    loop {
+        out_chip_temp
+            .set(Some(tsens.read_temperature().await))
+            .await;
+
+        Timer::after_secs(5).await;
+    }
+}
+
+/// Measures battery voltage and raises low battery alarm
+///
+/// # Arguments
+/// - `ratio` - battery voltage divided by ADC pin voltage
+#[embassy_executor::task]
+pub async fn publish_battery(
+    mut adc: BatteryAdc,
+    ratio: f32,
+    out_battery: SharedBattery,
+    events: EventPublisher,
+    settings: SharedSettings<Flash>,
+) {
+    let mut average = MovingAverage::<8>::new();
+    // Threshold is taken from settings on every reading
+    let mut alarm = ThresholdAlarm::new(
+        Quantity::Battery,
+        Thresholds {
+            hysteresis: 5.0,
+            ..Thresholds::disabled()
+        },
+    );
+
+    loop {
+        let millivolts = average.add(battery_millivolts(adc.read_millivolts().await, ratio));
+        let percentage = state_of_charge(LI_ION_CURVE, millivolts);
+
+        alarm.set_thresholds(Thresholds {
+            low: settings.get().await.alarms.battery_low,
+            ..*alarm.thresholds()
+        });
+        if let Some(event) = alarm.update(f32::from(percentage)) {
+            info!("{}", event);
+            events.publish_immediate(event);
+        }
+
+        out_battery
+            .set(Some(BatteryStatus {
+                voltage: f32::from(millivolts) / 1000.0,
+                percentage,
+                low: alarm.is_active(),
+            }))
+            .await;
+
+        Timer::after_secs(30).await;
+    }
+}
+
+/// Measures CO2 with SCD4x, if it is connected
+#[embassy_executor::task]
+pub async fn publish_co2(
+    mut scd4x: Scd4x<I2c>,
+    out_co2: SharedCo2,
+    settings: SharedSettings<Flash>,
+    calibrated: SharedCalibratedReadings,
+) {
+    // Sensor keeps measuring over MCU reset, and ignores other commands while measuring
+    if let Err(err) = scd4x.stop_periodic_measurement().await {
+        info!("scd4x: not found: {}", err);
+        return;
+    }
+    if let Err(err) = scd4x.start_periodic_measurement().await {
+        error!("scd4x: failed to start measurement: {}", err);
+        return;
+    }
+
+    loop {
+        // New measurement is ready every 5 seconds
+        Timer::after_secs(5).await;
+
+        match scd4x.data_ready().await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => {
+                error!("scd4x: failed to get data ready status: {}", err);
+                continue;
+            }
+        }
+
+        match scd4x.read_measurement().await {
+            Ok(measurement) => {
+                let co2 = calibrated
+                    .calibrate(
+                        &settings.get().await.calibration,
+                        "scd4x",
+                        Quantity::Co2,
+                        measurement.co2.into(),
+                    )
+                    .await;
+                // Saturates, calibration cannot make concentration negative
+                out_co2.set(Some(libm::roundf(co2) as u16)).await;
+            }
+            Err(err) => error!("scd4x: failed to read measurement: {}", err),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//!
+//! BOOT button opening setup of the first admin credentials
+//!
+
+use defmt::{info, warn};
+use esp_hal::gpio::Input;
+use esp_temperature::auth::SETUP_WINDOW_MS;
+use esp_temperature::web::auth::SharedAuth;
+
+/// Lets anyone set the first admin credentials for a while after BOOT button is pressed
+#[embassy_executor::task]
+pub async fn open_setup_on_button(mut button: Input<'static>, auth: SharedAuth) {
+    loop {
+        button.wait_for_falling_edge().await;
+        if auth.open_setup().await {
+            warn!(
+                "web: admin credentials can be set for {} s",
+                SETUP_WINDOW_MS / 1000
+            );
+        } else {
+            info!("web: admin credentials are set, setup stays closed");
+        }
+    }
+}
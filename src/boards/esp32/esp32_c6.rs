@@ -1,9 +1,20 @@
+mod battery;
+mod flash;
 mod i2c;
 mod rgb;
 mod sensors;
+mod sleep;
+mod strip;
 mod wifi;
 
+pub use battery::BatteryAdc;
+pub use flash::{Flash, FlashError};
 pub use rgb::{init_rgb_led, RgbLed};
+pub use sensors::{
+    HumiditySensorStore, TemperatureSensorStore, SENSOR_STORE_CAP, SENSOR_STORE_WINDOW,
+};
+pub use sleep::{deep_sleep, with_rtc_state};
+pub use strip::{init_led_strip, LedStrip, LED_STRIP_LEN};
 
 pub use i2c::{init_i2c, I2c};
 
@@ -0,0 +1,42 @@
+//!
+//! Battery voltage divider on ADC1
+//!
+
+use esp_hal::{
+    analog::adc::{Adc, AdcCalCurve, AdcConfig, AdcPin, Attenuation},
+    peripherals::{ADC1, GPIO2},
+    Async,
+};
+
+use crate::battery::oversample;
+
+/// Count of conversions averaged into one reading
+const OVERSAMPLING: usize = 16;
+
+pub struct BatteryAdc {
+    adc: Adc<'static, ADC1<'static>, Async>,
+    pin: AdcPin<GPIO2<'static>, ADC1<'static>, AdcCalCurve<ADC1<'static>>>,
+}
+
+impl BatteryAdc {
+    /// Uses full range attenuation, up to ~2.9 V on pin
+    pub fn new(adc: ADC1<'static>, pin: GPIO2<'static>) -> Self {
+        let mut config = AdcConfig::new();
+        let pin = config.enable_pin_with_cal(pin, Attenuation::_11dB);
+
+        Self {
+            adc: Adc::new(adc, config).into_async(),
+            pin,
+        }
+    }
+
+    /// Reads calibrated pin voltage in mV
+    pub async fn read_millivolts(&mut self) -> u16 {
+        let mut readings = [0_u16; OVERSAMPLING];
+        for reading in &mut readings {
+            *reading = self.adc.read_oneshot(&mut self.pin).await;
+        }
+
+        oversample(&readings).unwrap_or_default()
+    }
+}
@@ -0,0 +1,227 @@
+//!
+//! SPI flash through ROM routines
+//!
+//! Used for partition table, OTA data and OTA slots. Flash is not readable
+//! through cache while it is erased or written, so every operation runs from
+//! RAM inside a critical section.
+//!
+
+use embedded_storage::{
+    nor_flash::{ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash},
+    ReadStorage, Storage,
+};
+use esp_hal::ram;
+use esp_rom_sys::rom::spiflash::{
+    esp_rom_spiflash_erase_sector, esp_rom_spiflash_read, esp_rom_spiflash_unlock,
+    esp_rom_spiflash_write, ESP_ROM_SPIFLASH_RESULT_OK,
+};
+
+const SECTOR_SIZE: u32 = 4096;
+/// Flash size of the smallest ESP32-C6 modules
+const FLASH_SIZE: u32 = 4 * 1024 * 1024;
+/// Words moved by one ROM call, ROM routines require word aligned buffers
+const CHUNK_WORDS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum FlashError {
+    OutOfBounds,
+    NotAligned,
+    /// ROM routine returned error or timeout
+    Rom(i32),
+}
+
+impl NorFlashError for FlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            FlashError::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            FlashError::NotAligned => NorFlashErrorKind::NotAligned,
+            FlashError::Rom(_) => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+pub struct Flash {
+    /// Sector buffer of read-modify-write by [`Storage::write`]
+    sector: [u8; SECTOR_SIZE as usize],
+}
+
+impl Default for Flash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Flash {
+    pub fn new() -> Self {
+        // Status register may be left write protected by bootloader
+        critical_section::with(|_| unlock());
+        Self {
+            sector: [0; SECTOR_SIZE as usize],
+        }
+    }
+
+    fn check_bounds(offset: u32, len: usize) -> Result<(), FlashError> {
+        match offset.checked_add(len as u32) {
+            Some(end) if end <= FLASH_SIZE => Ok(()),
+            _ => Err(FlashError::OutOfBounds),
+        }
+    }
+
+    /// Reads any range, unaligned ends are read as whole words
+    fn read_bytes(offset: u32, bytes: &mut [u8]) -> Result<(), FlashError> {
+        Self::check_bounds(offset, bytes.len())?;
+
+        let mut words = [0_u32; CHUNK_WORDS];
+        let mut done = 0;
+        while done < bytes.len() {
+            let address = offset + done as u32;
+            let aligned = address & !3;
+            let skip = (address - aligned) as usize;
+            let count = (bytes.len() - done).min(CHUNK_WORDS * 4 - skip);
+            let len = (skip + count).next_multiple_of(4);
+
+            critical_section::with(|_| read(aligned, &mut words[..len / 4]))?;
+
+            let chunk = words[..len / 4].iter().flat_map(|word| word.to_le_bytes());
+            for (byte, value) in bytes[done..done + count].iter_mut().zip(chunk.skip(skip)) {
+                *byte = value;
+            }
+            done += count;
+        }
+
+        Ok(())
+    }
+
+    /// Writes erased flash, offset and length must be word aligned
+    fn write_words(offset: u32, bytes: &[u8]) -> Result<(), FlashError> {
+        Self::check_bounds(offset, bytes.len())?;
+        if offset % 4 != 0 || bytes.len() % 4 != 0 {
+            return Err(FlashError::NotAligned);
+        }
+
+        let mut words = [0_u32; CHUNK_WORDS];
+        for (index, chunk) in bytes.chunks(CHUNK_WORDS * 4).enumerate() {
+            let count = chunk.len() / 4;
+            for (word, bytes) in words.iter_mut().zip(chunk.chunks_exact(4)) {
+                *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+            let address = offset + (index * CHUNK_WORDS * 4) as u32;
+            critical_section::with(|_| write(address, &words[..count]))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ReadStorage for Flash {
+    type Error = FlashError;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        Self::read_bytes(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_SIZE as usize
+    }
+}
+
+impl Storage for Flash {
+    /// Writes any range, sectors are erased and rewritten when bits must be set
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        Self::check_bounds(offset, bytes.len())?;
+
+        let mut done = 0;
+        while done < bytes.len() {
+            let address = offset + done as u32;
+            let sector_start = address - address % SECTOR_SIZE;
+            let start = (address - sector_start) as usize;
+            let count = (bytes.len() - done).min(SECTOR_SIZE as usize - start);
+            let data = &bytes[done..done + count];
+
+            Self::read_bytes(sector_start, &mut self.sector)?;
+            let current = &mut self.sector[start..start + count];
+            // Programming only clears bits, erase is needed otherwise
+            if current.iter().zip(data).all(|(old, new)| old & new == *new) {
+                current.copy_from_slice(data);
+                let first = start - start % 4;
+                let last = (start + count).next_multiple_of(4);
+                Self::write_words(sector_start + first as u32, &self.sector[first..last])?;
+            } else {
+                current.copy_from_slice(data);
+                critical_section::with(|_| erase(sector_start))?;
+                Self::write_words(sector_start, &self.sector)?;
+            }
+            done += count;
+        }
+
+        Ok(())
+    }
+}
+
+impl ErrorType for Flash {
+    type Error = FlashError;
+}
+
+impl ReadNorFlash for Flash {
+    const READ_SIZE: usize = 4;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        Self::read_bytes(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_SIZE as usize
+    }
+}
+
+impl NorFlash for Flash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from % SECTOR_SIZE != 0 || to % SECTOR_SIZE != 0 {
+            return Err(FlashError::NotAligned);
+        }
+        Self::check_bounds(from, to.saturating_sub(from) as usize)?;
+
+        for sector in (from..to).step_by(SECTOR_SIZE as usize) {
+            critical_section::with(|_| erase(sector))?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        Self::write_words(offset, bytes)
+    }
+}
+
+fn result(code: i32) -> Result<(), FlashError> {
+    match code {
+        ESP_ROM_SPIFLASH_RESULT_OK => Ok(()),
+        code => Err(FlashError::Rom(code)),
+    }
+}
+
+#[ram]
+fn unlock() {
+    // Safety: called inside critical section, nothing runs from flash meanwhile
+    unsafe { esp_rom_spiflash_unlock() };
+}
+
+#[ram]
+fn read(address: u32, words: &mut [u32]) -> Result<(), FlashError> {
+    // Safety: buffer is word aligned and holds `words.len()` words
+    result(unsafe { esp_rom_spiflash_read(address, words.as_mut_ptr(), (words.len() * 4) as u32) })
+}
+
+#[ram]
+fn write(address: u32, words: &[u32]) -> Result<(), FlashError> {
+    // Safety: buffer is word aligned and holds `words.len()` words
+    result(unsafe { esp_rom_spiflash_write(address, words.as_ptr(), (words.len() * 4) as u32) })
+}
+
+#[ram]
+fn erase(address: u32) -> Result<(), FlashError> {
+    // Safety: called inside critical section, nothing runs from flash meanwhile
+    result(unsafe { esp_rom_spiflash_erase_sector(address / SECTOR_SIZE) })
+}
@@ -1,31 +1,109 @@
 use embassy_executor::Spawner;
-use esp_hal::{i2c::master::Config, Async};
+use esp_hal::{
+    gpio::{DriveMode, Flex, InputConfig, OutputConfig, Pull},
+    i2c::master::Config,
+    peripherals::{GPIO6, GPIO7, I2C0},
+    Async,
+};
+
+use crate::drivers::i2c::{
+    ector::{I2cActor, ReplySlot},
+    recovery::{clear_bus, RecoverI2c},
+    stats::SharedI2cStats,
+};
 
 type RawI2c = esp_hal::i2c::master::I2c<'static, Async>;
 pub type I2c = crate::drivers::i2c::ector::EctorI2c;
 
+/// I2C0 on GPIO7 (SCL) and GPIO6 (SDA), which can recover the bus
+pub struct RecoverableI2c {
+    /// None only while recovering
+    i2c: Option<RawI2c>,
+}
+
+impl RecoverableI2c {
+    fn new(i2c: I2C0<'static>, scl: GPIO7<'static>, sda: GPIO6<'static>) -> Self {
+        let i2c = esp_hal::i2c::master::I2c::new(i2c, Config::default())
+            .unwrap()
+            .with_scl(scl)
+            .with_sda(sda)
+            .into_async();
+
+        Self { i2c: Some(i2c) }
+    }
+}
+
+impl RecoverI2c for RecoverableI2c {
+    async fn recover(&mut self) {
+        // Release peripheral and pins
+        self.i2c = None;
+
+        // Safety: the driver owning them is dropped and a new one is created only below
+        let (i2c, mut scl, mut sda) = unsafe { (I2C0::steal(), GPIO7::steal(), GPIO6::steal()) };
+
+        let released = {
+            let mut scl = open_drain(scl.reborrow());
+            let mut sda = open_drain(sda.reborrow());
+            clear_bus(&mut scl, &mut sda, &mut embassy_time::Delay).await
+        };
+        if !released {
+            defmt::error!("i2c: SDA is still held low");
+        }
+
+        *self = Self::new(i2c, scl, sda);
+    }
+}
+
+fn open_drain<'d>(pin: impl esp_hal::gpio::Pin + 'd) -> Flex<'d> {
+    let mut pin = Flex::new(pin);
+    pin.apply_output_config(
+        &OutputConfig::default()
+            .with_drive_mode(DriveMode::OpenDrain)
+            .with_pull(Pull::Up),
+    );
+    pin.apply_input_config(&InputConfig::default().with_pull(Pull::Up));
+    pin.set_high();
+    pin.set_output_enable(true);
+    pin.set_input_enable(true);
+    pin
+}
+
+impl embedded_hal_async::i2c::ErrorType for RecoverableI2c {
+    type Error = esp_hal::i2c::master::Error;
+}
+
+impl embedded_hal_async::i2c::I2c for RecoverableI2c {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        // Always set outside of recovery
+        let Some(i2c) = self.i2c.as_mut() else {
+            return Err(esp_hal::i2c::master::Error::ExecutionIncomplete);
+        };
+
+        embedded_hal_async::i2c::I2c::transaction(i2c, address, operations).await
+    }
+}
+
 pub async fn init_i2c(
-    i2c: esp_hal::peripherals::I2C0<'static>,
-    scl: esp_hal::peripherals::GPIO7<'static>,
-    sda: esp_hal::peripherals::GPIO6<'static>,
+    i2c: I2C0<'static>,
+    scl: GPIO7<'static>,
+    sda: GPIO6<'static>,
+    stats: SharedI2cStats,
     spawner: Spawner,
 ) -> I2c {
-    let i2c = esp_hal::i2c::master::I2c::new(i2c, Config::default())
-        .unwrap()
-        .with_scl(scl)
-        .with_sda(sda)
-        .into_async();
+    let i2c = RecoverableI2c::new(i2c, scl, sda);
+
+    static REPLIES: ReplySlot = ReplySlot::new();
 
     let addr = ector::actor!(
         spawner,
         i2c0_ector_task,
-        crate::drivers::i2c::ector::I2cActor<RawI2c>,
-        crate::drivers::i2c::ector::I2cActor { i2c }
+        I2cActor<RecoverableI2c>,
+        I2cActor::new(i2c, &REPLIES, stats)
     );
 
-    let driver = I2c {
-        address: addr.into(),
-    };
-
-    driver
+    I2c::new(addr.into(), &REPLIES)
 }
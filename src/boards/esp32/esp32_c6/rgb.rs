@@ -2,14 +2,13 @@ use esp_hal::{
     gpio::Level,
     rmt::{TxChannelConfig, TxChannelCreator},
     time::Rate,
-    Async,
+    Blocking,
 };
 
-type RgbLedChannelCreator = esp_hal::rmt::ChannelCreator<Async, 0>;
-type RgbLedChannel =
-    esp_hal::rmt::Channel<Async, <RgbLedChannelCreator as TxChannelCreator<'static, Async>>::Raw>;
+type RgbLedChannelCreator = esp_hal::rmt::ChannelCreator<Blocking, 0>;
+type RgbLedChannelRaw = <RgbLedChannelCreator as TxChannelCreator<'static, Blocking>>::Raw;
 
-pub type RgbLed = crate::drivers::led::ws2812::WS2812<RgbLedChannel>;
+pub type RgbLed = crate::drivers::led::ws2812::WS2812<RgbLedChannelRaw>;
 
 pub async fn init_rgb_led(
     rmt_ch: RgbLedChannelCreator,
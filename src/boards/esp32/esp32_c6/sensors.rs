@@ -1,6 +1,11 @@
+use embassy_time::Duration;
+
 use crate::sensor_data::{filter::NoopFilter, SensorDataStore};
 
-pub const SENSOR_STORE_CAP: usize = 32;
+pub const SENSOR_STORE_CAP: usize = 96;
+
+/// Time covered by one store cell, so store keeps 24 hours of history
+pub const SENSOR_STORE_WINDOW: Duration = Duration::from_secs(15 * 60);
 
 pub type TemperatureSensorStore = SensorDataStore<f32, NoopFilter<f32>, SENSOR_STORE_CAP>;
 
@@ -0,0 +1,35 @@
+//!
+//! Deep sleep with state kept in RTC fast memory
+//!
+
+use esp_hal::{
+    peripherals::LPWR,
+    ram,
+    rtc_cntl::{sleep::TimerWakeupSource, Rtc},
+};
+
+use crate::duty_cycle::{RtcState, Sample};
+
+// Safety: plain integers and floats, any bit pattern is valid, and RtcState::validate
+// rejects garbage left after power loss
+unsafe impl esp_hal::Persistable for Sample {}
+unsafe impl esp_hal::Persistable for RtcState {}
+
+#[ram(rtc_fast, persistent)]
+static mut RTC_STATE: RtcState = RtcState::new();
+
+/// Gives access to state kept over deep sleep
+pub fn with_rtc_state<R>(f: impl FnOnce(&mut RtcState) -> R) -> R {
+    critical_section::with(|_| {
+        // Safety: accessed only inside critical section
+        let state = unsafe { &mut *core::ptr::addr_of_mut!(RTC_STATE) };
+        f(state)
+    })
+}
+
+/// Powers everything down except RTC, the device boots from start after `duration`
+pub fn deep_sleep(lpwr: LPWR<'static>, duration: embassy_time::Duration) -> ! {
+    let mut rtc = Rtc::new(lpwr);
+    let timer = TimerWakeupSource::new(core::time::Duration::from_millis(duration.as_millis()));
+    rtc.sleep_deep(&[&timer])
+}
@@ -0,0 +1,49 @@
+use esp_hal::{
+    gpio::Level,
+    rmt::{TxChannelConfig, TxChannelCreator},
+    time::Rate,
+    Blocking,
+};
+
+use crate::drivers::led::ws2812::{ColorOrder, Timing};
+
+/// Count of LEDs in strip, `LED_STRIP_LEN` set at build time or 16. Frames are sized
+/// at compile time, so it cannot be changed from settings. It is capped by
+/// [`MAX_STRIP_LEN`](crate::drivers::led::ws2812::MAX_STRIP_LEN)
+pub const LED_STRIP_LEN: usize = match option_env!("LED_STRIP_LEN") {
+    Some(len) => match usize::from_str_radix(len, 10) {
+        Ok(len) => len,
+        Err(_) => panic!("LED_STRIP_LEN is not a number"),
+    },
+    None => 16,
+};
+
+type LedStripChannelCreator = esp_hal::rmt::ChannelCreator<Blocking, 1>;
+type LedStripChannelRaw = <LedStripChannelCreator as TxChannelCreator<'static, Blocking>>::Raw;
+
+pub type LedStrip = crate::drivers::led::ws2812::WS2812Strip<LedStripChannelRaw, LED_STRIP_LEN>;
+
+/// # Arguments
+/// - `timing`, `order` - of LEDs in strip
+pub async fn init_led_strip(
+    rmt_ch: LedStripChannelCreator,
+    input_freq: Rate,
+    gpio: esp_hal::gpio::AnyPin<'static>,
+    timing: Timing,
+    order: ColorOrder,
+) -> LedStrip {
+    const DIV: u8 = 2;
+    // Channel 1 takes memory of unused RX channels, so memory is refilled less often
+    const MEMORY_BLOCKS: u8 = 3;
+
+    let tx_rmt_cfg = TxChannelConfig::default()
+        .with_clk_divider(DIV)
+        .with_idle_output(true)
+        .with_idle_output_level(Level::Low)
+        .with_memsize(MEMORY_BLOCKS);
+    let tx_rmt_chan = rmt_ch
+        .configure_tx(gpio, tx_rmt_cfg)
+        .expect("failed to configure channel for LED strip");
+
+    crate::drivers::led::ws2812::init_strip(tx_rmt_chan, input_freq / (DIV as u32), timing, order)
+}
@@ -10,33 +10,41 @@ use esp_wifi::{
     EspWifiController,
 };
 
-use crate::mk_static;
-
-const SSID: Option<&str> = option_env!("SSID");
-const PASSWORD: Option<&str> = option_env!("PASSWORD");
+use crate::{
+    mk_static,
+    status_indicator::{Status, StatusIndicator},
+};
 
+/// Starts network stack and connection to access point
+///
+/// # Arguments
+/// - `password` - empty for open network
 pub async fn start_wifi(
     esp_wifi_ctrl: &'static EspWifiController<'static>,
     wifi: esp_hal::peripherals::WIFI<'static>,
     mut rng: esp_hal::rng::Rng,
+    indicator: &'static StatusIndicator,
+    ssid: &'static str,
+    password: &'static str,
     spawner: Spawner,
 ) -> Stack<'static> {
-    let (controller, interfaces) = esp_wifi::wifi::new(&esp_wifi_ctrl, wifi).unwrap();
+    let (controller, interfaces) = esp_wifi::wifi::new(esp_wifi_ctrl, wifi).unwrap();
     let wifi_interface = interfaces.sta;
     let net_seed = rng.random() as u64 | ((rng.random() as u64) << 32);
 
     let net_config = embassy_net::Config::dhcpv4(Default::default());
 
+    // DHCP, DNS, two web tasks, HTTP redirect and webhook client
     let (stack, runner) = embassy_net::new(
         wifi_interface,
         net_config,
-        mk_static!(StackResources<3>, StackResources::<3>::new()),
+        mk_static!(StackResources<6>, StackResources::<6>::new()),
         net_seed,
     );
 
     spawner.must_spawn(net_task(runner));
-    spawner.must_spawn(connection(controller));
-    spawner.must_spawn(ipv4_watcher(stack));
+    spawner.must_spawn(connection(controller, indicator, ssid, password));
+    spawner.must_spawn(ipv4_watcher(stack, indicator));
 
     // stack.wait_config_up().await;
 
@@ -44,15 +52,19 @@ pub async fn start_wifi(
 }
 
 #[embassy_executor::task]
-async fn ipv4_watcher(stack: Stack<'static>) {
+async fn ipv4_watcher(stack: Stack<'static>, indicator: &'static StatusIndicator) {
+    indicator.set(Status::NoIp, true);
+
     loop {
         stack.wait_config_up().await;
         if let Some(config) = stack.config_v4() {
             info!("Got IP: {}", config.address);
-            break;
         }
+        indicator.set(Status::NoIp, false);
+
         stack.wait_config_down().await;
         error!("WiFi config down");
+        indicator.set(Status::NoIp, true);
     }
 }
 
@@ -62,29 +74,32 @@ async fn net_task(mut runner: Runner<'static, WifiDevice<'static>>) {
 }
 
 #[embassy_executor::task]
-async fn connection(mut controller: WifiController<'static>) {
+async fn connection(
+    mut controller: WifiController<'static>,
+    indicator: &'static StatusIndicator,
+    ssid: &'static str,
+    password: &'static str,
+) {
     info!("start connection task");
 
     loop {
-        match esp_wifi::wifi::wifi_state() {
-            WifiState::StaConnected => {
-                // wait until we're no longer connected
+        if esp_wifi::wifi::wifi_state() == WifiState::StaConnected {
+            // wait until we're no longer connected
 
-                controller.wait_for_event(WifiEvent::StaDisconnected).await;
-                controller.stop_async().await.ok();
-                Timer::after(Duration::from_millis(5000)).await;
-            }
-            _ => {}
+            controller.wait_for_event(WifiEvent::StaDisconnected).await;
+            indicator.set(Status::WifiConnecting, true);
+            controller.stop_async().await.ok();
+            Timer::after(Duration::from_millis(5000)).await;
         }
+        indicator.set(Status::WifiConnecting, true);
+
         if !matches!(controller.is_started(), Ok(true)) {
-            let ssid: &'static str = SSID.unwrap_or("example_wifi_ssid").into();
             info!("Trying to connect to {}", ssid);
 
-            let client_config = Configuration::Client({
-                let mut config = ClientConfiguration::default();
-                config.ssid = ssid.into();
-                config.password = PASSWORD.unwrap_or("").into();
-                config
+            let client_config = Configuration::Client(ClientConfiguration {
+                ssid: ssid.into(),
+                password: password.into(),
+                ..Default::default()
             });
 
             controller
@@ -103,7 +118,10 @@ async fn connection(mut controller: WifiController<'static>) {
         }
 
         match controller.connect_async().await {
-            Ok(_) => info!("Wifi connected!"),
+            Ok(_) => {
+                info!("Wifi connected!");
+                indicator.set(Status::WifiConnecting, false);
+            }
             Err(e) => {
                 info!("Failed to connect to wifi: {:?}", e);
                 Timer::after(Duration::from_millis(5000)).await
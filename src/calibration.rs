@@ -0,0 +1,357 @@
+//!
+//! Calibration of sensor readings against a reference
+//!
+//! Raw driver values are corrected per sensor and quantity before they reach
+//! history, alarms and web. Calibrations are persisted with settings, the last
+//! raw and calibrated values are kept for audit.
+//!
+
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+
+use crate::{events::Quantity, sync::mutex::AtomicMutex};
+
+/// Max count of calibrated sensor quantities
+pub const MAX_CALIBRATIONS: usize = 8;
+pub const MAX_SENSOR_NAME_LEN: usize = 16;
+
+/// Reference points closer than this cannot define a line
+const MIN_POINT_DISTANCE: f32 = 0.1;
+
+/// Correction of raw value, temperatures in °C regardless of settings unit
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Correction {
+    /// `raw + offset`
+    Offset { offset: f32 },
+    /// `raw * gain + offset`
+    Linear { gain: f32, offset: f32 },
+    /// Line through two pairs of raw and reference values
+    TwoPoint {
+        raw_low: f32,
+        reference_low: f32,
+        raw_high: f32,
+        reference_high: f32,
+    },
+}
+
+impl Correction {
+    pub fn apply(&self, raw: f32) -> f32 {
+        match *self {
+            Correction::Offset { offset } => raw + offset,
+            Correction::Linear { gain, offset } => raw * gain + offset,
+            Correction::TwoPoint {
+                raw_low,
+                reference_low,
+                raw_high,
+                reference_high,
+            } => {
+                let gain = (reference_high - reference_low) / (raw_high - raw_low);
+                reference_low + (raw - raw_low) * gain
+            }
+        }
+    }
+
+    /// Checks that correction maps every finite value to a finite one
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Correction::Offset { offset } => offset.is_finite(),
+            Correction::Linear { gain, offset } => {
+                gain.is_finite() && gain != 0.0 && offset.is_finite()
+            }
+            Correction::TwoPoint {
+                raw_low,
+                reference_low,
+                raw_high,
+                reference_high,
+            } => {
+                [raw_low, reference_low, raw_high, reference_high]
+                    .iter()
+                    .all(|value| value.is_finite())
+                    && MIN_POINT_DISTANCE <= (raw_high - raw_low).abs()
+                    && MIN_POINT_DISTANCE <= (reference_high - reference_low).abs()
+            }
+        }
+    }
+}
+
+/// Correction of one quantity of one sensor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    /// Sensor name, e.g. `dht22` or `sht4x`
+    pub sensor: String<MAX_SENSOR_NAME_LEN>,
+    pub quantity: Quantity,
+    pub correction: Correction,
+}
+
+pub type Calibrations = Vec<Calibration, MAX_CALIBRATIONS>;
+
+/// Finds calibration of sensor quantity
+///
+/// # Returns
+/// Calibrated value, raw value if sensor quantity is not calibrated
+pub fn calibrate(calibrations: &[Calibration], sensor: &str, quantity: Quantity, raw: f32) -> f32 {
+    calibrations
+        .iter()
+        .find(|calibration| calibration.sensor == sensor && calibration.quantity == quantity)
+        .map_or(raw, |calibration| calibration.correction.apply(raw))
+}
+
+/// Checks corrections and that every sensor quantity is calibrated once
+///
+/// # Returns
+/// Index of the first invalid calibration
+pub fn validate(calibrations: &[Calibration]) -> Result<(), usize> {
+    for (index, calibration) in calibrations.iter().enumerate() {
+        let duplicated = calibrations[..index].iter().any(|other| {
+            other.sensor == calibration.sensor && other.quantity == calibration.quantity
+        });
+        if duplicated || calibration.sensor.is_empty() || !calibration.correction.is_valid() {
+            return Err(index);
+        }
+    }
+    Ok(())
+}
+
+/// The last reading of sensor quantity before and after calibration, temperatures in °C
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CalibratedReading {
+    pub sensor: String<MAX_SENSOR_NAME_LEN>,
+    pub quantity: Quantity,
+    pub raw: f32,
+    pub calibrated: f32,
+}
+
+#[derive(Clone)]
+pub struct SharedCalibratedReadings(&'static AtomicMutex<Vec<CalibratedReading, MAX_CALIBRATIONS>>);
+
+impl SharedCalibratedReadings {
+    pub fn new(m: &'static AtomicMutex<Vec<CalibratedReading, MAX_CALIBRATIONS>>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> Vec<CalibratedReading, MAX_CALIBRATIONS> {
+        self.0.lock().await.clone()
+    }
+
+    /// Corrects raw value by calibration of sensor quantity and records both for audit
+    pub async fn calibrate(
+        &self,
+        calibrations: &[Calibration],
+        sensor: &str,
+        quantity: Quantity,
+        raw: f32,
+    ) -> f32 {
+        let calibrated = calibrate(calibrations, sensor, quantity, raw);
+        self.record(CalibratedReading {
+            // Names of calibrated sensors fit
+            sensor: String::try_from(sensor).unwrap_or_default(),
+            quantity,
+            raw,
+            calibrated,
+        })
+        .await;
+        calibrated
+    }
+
+    /// Replaces the last reading of the same sensor quantity
+    pub async fn record(&self, reading: CalibratedReading) {
+        let mut readings = self.0.lock().await;
+        match readings
+            .iter_mut()
+            .find(|last| last.sensor == reading.sensor && last.quantity == reading.quantity)
+        {
+            Some(last) => *last = reading,
+            None => {
+                // Readings of sensors over capacity are not audited
+                readings.push(reading).ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{actual} is not close to {expected}"
+        );
+    }
+
+    fn calibration(sensor: &str, quantity: Quantity, correction: Correction) -> Calibration {
+        Calibration {
+            sensor: String::try_from(sensor).unwrap(),
+            quantity,
+            correction,
+        }
+    }
+
+    const OFFSET: Correction = Correction::Offset { offset: -0.8 };
+    const TWO_POINT: Correction = Correction::TwoPoint {
+        raw_low: 0.5,
+        reference_low: 0.0,
+        raw_high: 99.0,
+        reference_high: 100.0,
+    };
+
+    #[test]
+    fn offset_shifts_value() {
+        assert_close(OFFSET.apply(21.3), 20.5);
+    }
+
+    #[test]
+    fn linear_scales_and_shifts_value() {
+        let correction = Correction::Linear {
+            gain: 1.02,
+            offset: -0.5,
+        };
+        assert_close(correction.apply(50.0), 50.5);
+        assert_close(correction.apply(0.0), -0.5);
+    }
+
+    #[test]
+    fn two_point_goes_through_both_points() {
+        assert_close(TWO_POINT.apply(0.5), 0.0);
+        assert_close(TWO_POINT.apply(99.0), 100.0);
+        assert_close(TWO_POINT.apply(49.75), 50.0);
+        // Extrapolated outside of points
+        assert_close(TWO_POINT.apply(-0.485), -1.0);
+    }
+
+    #[test]
+    fn two_point_with_points_swapped_is_the_same_line() {
+        let swapped = Correction::TwoPoint {
+            raw_low: 99.0,
+            reference_low: 100.0,
+            raw_high: 0.5,
+            reference_high: 0.0,
+        };
+        assert!(swapped.is_valid());
+        assert_close(swapped.apply(49.75), TWO_POINT.apply(49.75));
+    }
+
+    #[test]
+    fn degenerate_two_point_is_invalid() {
+        let same_raw = Correction::TwoPoint {
+            raw_low: 20.0,
+            reference_low: 19.0,
+            raw_high: 20.0,
+            reference_high: 25.0,
+        };
+        assert!(!same_raw.is_valid());
+        // It would divide by zero
+        assert!(!same_raw.apply(21.0).is_finite());
+
+        let close_raw = Correction::TwoPoint {
+            raw_low: 20.0,
+            reference_low: 19.0,
+            raw_high: 20.05,
+            reference_high: 25.0,
+        };
+        assert!(!close_raw.is_valid());
+
+        let same_reference = Correction::TwoPoint {
+            raw_low: 20.0,
+            reference_low: 19.0,
+            raw_high: 25.0,
+            reference_high: 19.0,
+        };
+        assert!(!same_reference.is_valid());
+    }
+
+    #[test]
+    fn non_finite_corrections_are_invalid() {
+        assert!(OFFSET.is_valid());
+        assert!(TWO_POINT.is_valid());
+        assert!(!Correction::Offset { offset: f32::NAN }.is_valid());
+        assert!(!Correction::Linear {
+            gain: f32::INFINITY,
+            offset: 0.0
+        }
+        .is_valid());
+        assert!(!Correction::Linear {
+            gain: 0.0,
+            offset: 0.0
+        }
+        .is_valid());
+        assert!(!Correction::TwoPoint {
+            raw_low: f32::NAN,
+            reference_low: 0.0,
+            raw_high: 10.0,
+            reference_high: 10.0,
+        }
+        .is_valid());
+    }
+
+    #[test]
+    fn calibrates_only_matching_sensor_quantity() {
+        let calibrations = [
+            calibration("dht22", Quantity::Temperature, OFFSET),
+            calibration("dht22", Quantity::Humidity, TWO_POINT),
+        ];
+
+        assert_close(
+            calibrate(&calibrations, "dht22", Quantity::Temperature, 21.3),
+            20.5,
+        );
+        assert_close(
+            calibrate(&calibrations, "dht22", Quantity::Humidity, 49.75),
+            50.0,
+        );
+        assert_eq!(
+            calibrate(&calibrations, "sht4x", Quantity::Temperature, 21.3),
+            21.3
+        );
+        assert_eq!(
+            calibrate(&calibrations, "dht22", Quantity::Co2, 800.0),
+            800.0
+        );
+    }
+
+    #[test]
+    fn validate_accepts_distinct_sensor_quantities() {
+        assert_eq!(validate(&[]), Ok(()));
+        assert_eq!(
+            validate(&[
+                calibration("dht22", Quantity::Temperature, OFFSET),
+                calibration("dht22", Quantity::Humidity, TWO_POINT),
+                calibration("lm75b", Quantity::Temperature, OFFSET),
+            ]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn validate_points_at_first_invalid_calibration() {
+        assert_eq!(
+            validate(&[
+                calibration("dht22", Quantity::Temperature, OFFSET),
+                calibration("dht22", Quantity::Temperature, TWO_POINT),
+            ]),
+            Err(1)
+        );
+        assert_eq!(
+            validate(&[
+                calibration("dht22", Quantity::Temperature, OFFSET),
+                calibration("", Quantity::Humidity, OFFSET),
+            ]),
+            Err(1)
+        );
+        assert_eq!(
+            validate(&[
+                calibration(
+                    "lm75b",
+                    Quantity::Temperature,
+                    Correction::Offset {
+                        offset: f32::INFINITY
+                    }
+                ),
+                calibration("", Quantity::Humidity, OFFSET),
+            ]),
+            Err(0)
+        );
+    }
+}
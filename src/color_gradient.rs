@@ -0,0 +1,205 @@
+//!
+//! Mapping of sensor values onto colors
+//!
+
+use crate::color_temp;
+
+/// Color of gradient at given position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientStop {
+    /// From 0 at the low end of gradient range to 1 at the high one
+    pub position: f32,
+    pub color: (u8, u8, u8),
+}
+
+impl GradientStop {
+    pub const fn new(position: f32, r: u8, g: u8, b: u8) -> Self {
+        Self {
+            position,
+            color: (r, g, b),
+        }
+    }
+}
+
+/// Temperature from cold blue to warm red
+pub const COLD_TO_WARM: &[GradientStop] = &[
+    GradientStop::new(0.0, 0, 0, 255),
+    GradientStop::new(0.4, 0, 255, 255),
+    GradientStop::new(0.6, 0, 255, 0),
+    GradientStop::new(0.8, 255, 255, 0),
+    GradientStop::new(1.0, 255, 0, 0),
+];
+
+/// Relative humidity from dry orange through comfortable green to wet blue
+pub const DRY_TO_WET: &[GradientStop] = &[
+    GradientStop::new(0.0, 255, 96, 0),
+    GradientStop::new(1.0 / 3.0, 0, 255, 0),
+    GradientStop::new(2.0 / 3.0, 0, 255, 0),
+    GradientStop::new(1.0, 0, 0, 255),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gradient {
+    /// Linear interpolation between stops sorted by position, spread over `low`-`high` values
+    Stops {
+        stops: &'static [GradientStop],
+        low: f32,
+        high: f32,
+    },
+    /// Goes through Kelvin table: `cold` value shown as 12000K blue, `warm` one as 1000K red
+    Kelvin { cold: f32, warm: f32 },
+}
+
+impl Gradient {
+    /// Gets color of value. Values outside of gradient are clamped to its ends
+    pub fn color_at(&self, value: f32) -> (u8, u8, u8) {
+        match *self {
+            Gradient::Stops { stops, low, high } => {
+                let position = if low == high {
+                    0.0
+                } else {
+                    (value - low) / (high - low)
+                };
+                stops_color_at(stops, position)
+            }
+            Gradient::Kelvin { cold, warm } => {
+                let first = color_temp::KELVIN_TABLE[0].color_temp() as f32;
+                let last = color_temp::KELVIN_TABLE[color_temp::KELVIN_TABLE.len() - 1].color_temp()
+                    as f32;
+
+                let position = if cold == warm {
+                    0.0
+                } else {
+                    ((value - cold) / (warm - cold)).clamp(0.0, 1.0)
+                };
+
+                let kelvin = last + (first - last) * position;
+                color_temp::interpolated_color(kelvin as u16)
+            }
+        }
+    }
+}
+
+fn stops_color_at(stops: &[GradientStop], position: f32) -> (u8, u8, u8) {
+    let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+        return (0, 0, 0);
+    };
+
+    if position.is_nan() || position <= first.position {
+        return first.color;
+    }
+    if last.position <= position {
+        return last.color;
+    }
+
+    for pair in stops.windows(2) {
+        let (lower, upper) = (&pair[0], &pair[1]);
+        if position <= upper.position {
+            let position = (position - lower.position) / (upper.position - lower.position);
+            let lerp =
+                |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * position + 0.5) as u8;
+
+            return (
+                lerp(lower.color.0, upper.color.0),
+                lerp(lower.color.1, upper.color.1),
+                lerp(lower.color.2, upper.color.2),
+            );
+        }
+    }
+
+    last.color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPERATURE: Gradient = Gradient::Stops {
+        stops: COLD_TO_WARM,
+        low: 10.0,
+        high: 30.0,
+    };
+    const HUMIDITY: Gradient = Gradient::Stops {
+        stops: DRY_TO_WET,
+        low: 20.0,
+        high: 80.0,
+    };
+
+    #[test]
+    fn stops_match_exactly() {
+        for (value, stop) in [10.0, 18.0, 22.0, 26.0, 30.0].into_iter().zip(COLD_TO_WARM) {
+            assert_eq!(TEMPERATURE.color_at(value), stop.color);
+        }
+    }
+
+    #[test]
+    fn stops_interpolate_between() {
+        assert_eq!(TEMPERATURE.color_at(14.0), (0, 128, 255));
+        assert_eq!(TEMPERATURE.color_at(25.0), (191, 255, 0));
+        assert_eq!(TEMPERATURE.color_at(29.0), (255, 64, 0));
+    }
+
+    #[test]
+    fn stops_clamp_outside() {
+        assert_eq!(HUMIDITY.color_at(0.0), (255, 96, 0));
+        assert_eq!(HUMIDITY.color_at(100.0), (0, 0, 255));
+        assert_eq!(HUMIDITY.color_at(f32::NAN), (255, 96, 0));
+        assert_eq!(HUMIDITY.color_at(50.0), (0, 255, 0));
+    }
+
+    #[test]
+    fn stops_follow_range() {
+        let gradient = Gradient::Stops {
+            stops: COLD_TO_WARM,
+            low: 50.0,
+            high: 90.0,
+        };
+        assert_eq!(gradient.color_at(50.0), (0, 0, 255));
+        assert_eq!(gradient.color_at(66.0), (0, 255, 255));
+        assert_eq!(gradient.color_at(90.0), (255, 0, 0));
+        assert_eq!(gradient.color_at(30.0), (0, 0, 255));
+    }
+
+    #[test]
+    fn stops_without_range_show_low_end() {
+        let gradient = Gradient::Stops {
+            stops: COLD_TO_WARM,
+            low: 20.0,
+            high: 20.0,
+        };
+        assert_eq!(gradient.color_at(25.0), (0, 0, 255));
+    }
+
+    #[test]
+    fn empty_stops_are_dark() {
+        let gradient = Gradient::Stops {
+            stops: &[],
+            low: 10.0,
+            high: 30.0,
+        };
+        assert_eq!(gradient.color_at(20.0), (0, 0, 0));
+    }
+    #[test]
+    fn kelvin_maps_cold_to_blue_and_warm_to_red() {
+        let gradient = Gradient::Kelvin {
+            cold: 10.0,
+            warm: 30.0,
+        };
+        assert_eq!(gradient.color_at(10.0), (195, 209, 255));
+        assert_eq!(gradient.color_at(30.0), (255, 56, 0));
+        assert_eq!(gradient.color_at(-40.0), (195, 209, 255));
+        assert_eq!(gradient.color_at(60.0), (255, 56, 0));
+
+        let (r, _, b) = gradient.color_at(20.0);
+        assert!(r > 195 && b < 255);
+    }
+
+    #[test]
+    fn kelvin_without_range_is_cold() {
+        let gradient = Gradient::Kelvin {
+            cold: 20.0,
+            warm: 20.0,
+        };
+        assert_eq!(gradient.color_at(25.0), (195, 209, 255));
+    }
+}
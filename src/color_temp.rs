@@ -6,6 +6,12 @@ pub struct ColorTemp {
 }
 
 impl ColorTemp {
+    /// Color temperature in Kelvins
+    #[inline]
+    pub fn color_temp(&self) -> u16 {
+        self.color_temp
+    }
+
     #[inline]
     pub fn r(&self) -> u8 {
         self.r
@@ -701,3 +707,32 @@ pub fn nearest_color(temp: u16) -> &'static ColorTemp {
 
     unreachable!();
 }
+
+/// Gets color of given temperature, interpolated between nearest table entries
+///
+/// Temperatures outside of the table are clamped to its bounds
+pub fn interpolated_color(temp: u16) -> (u8, u8, u8) {
+    let first = &KELVIN_TABLE[0];
+    let last = &KELVIN_TABLE[KELVIN_TABLE.len() - 1];
+    let temp = temp.clamp(first.color_temp, last.color_temp);
+
+    let upper_idx = KELVIN_TABLE
+        .iter()
+        .position(|ct| temp <= ct.color_temp)
+        .unwrap_or(KELVIN_TABLE.len() - 1);
+    let upper = &KELVIN_TABLE[upper_idx];
+    if upper_idx == 0 || upper.color_temp == temp {
+        return (upper.r, upper.g, upper.b);
+    }
+
+    let lower = &KELVIN_TABLE[upper_idx - 1];
+    let span = (upper.color_temp - lower.color_temp) as i32;
+    let offset = (temp - lower.color_temp) as i32;
+    let lerp = |from: u8, to: u8| (from as i32 + (to as i32 - from as i32) * offset / span) as u8;
+
+    (
+        lerp(lower.r, upper.r),
+        lerp(lower.g, upper.g),
+        lerp(lower.b, upper.b),
+    )
+}
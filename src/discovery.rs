@@ -0,0 +1,223 @@
+//!
+//! Discovery of I2C devices at boot
+//!
+//! Known addresses are probed with chip specific reads, so sensor boards can be
+//! swapped without rebuilding firmware
+//!
+
+use defmt::info;
+use embassy_time::Timer;
+use heapless::Vec;
+use serde::Serialize;
+
+use crate::{
+    drivers::{
+        display::ssd1306::{self, Controller, Ssd1306},
+        sensors::{bme280, lm75b, scd4x, sht3x::Sht3x, sht4x::Sht4x},
+    },
+    sync::mutex::AtomicMutex,
+};
+
+/// Max count of discovered devices
+pub const MAX_DEVICES: usize = 8;
+
+const SHT_ADDRESSES: [u8; 3] = [0x44, 0x45, 0x46];
+const BME280_ADDRESSES: [u8; 2] = [bme280::DEFAULT_ADDRESS, 0x77];
+const DISPLAY_ADDRESSES: [u8; 2] = [ssd1306::DEFAULT_ADDRESS, 0x3D];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum Chip {
+    Lm75b,
+    Sht3x,
+    Sht4x,
+    Bme280,
+    Bmp280,
+    Scd4x,
+    /// SSD1306 or SH1106 display, they can not be told apart
+    Ssd1306,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+pub struct Device {
+    pub address: u8,
+    pub chip: Chip,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Inventory {
+    pub devices: Vec<Device, MAX_DEVICES>,
+}
+
+impl Inventory {
+    /// Gets address of the first found chip
+    pub fn find(&self, chip: Chip) -> Option<u8> {
+        self.devices
+            .iter()
+            .find(|device| device.chip == chip)
+            .map(|device| device.address)
+    }
+
+    fn add(&mut self, address: u8, chip: Chip) {
+        info!("discovery: {} at {:x}", chip, address);
+        self.devices.push(Device { address, chip }).ok();
+    }
+}
+
+#[derive(Clone)]
+pub struct SharedInventory(&'static AtomicMutex<Inventory>);
+
+impl SharedInventory {
+    pub fn new(m: &'static AtomicMutex<Inventory>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> Inventory {
+        self.0.lock().await.clone()
+    }
+
+    pub async fn set(&self, inventory: Inventory) {
+        *self.0.lock().await = inventory;
+    }
+}
+
+/// Probes known addresses and identifies chips
+pub async fn discover<I2C>(i2c: &I2C) -> Inventory
+where
+    I2C: embedded_hal_async::i2c::I2c + Clone,
+{
+    let mut inventory = Inventory::default();
+
+    for address in SHT_ADDRESSES {
+        // SHT3x does not answer read after single byte command of SHT4x
+        if Sht4x::new(i2c.clone(), address)
+            .serial_number()
+            .await
+            .is_ok()
+        {
+            inventory.add(address, Chip::Sht4x);
+        } else if Sht3x::new(i2c.clone(), address).status().await.is_ok() {
+            inventory.add(address, Chip::Sht3x);
+        }
+    }
+
+    for address in BME280_ADDRESSES {
+        match bme280::Bme280::new(i2c.clone(), address).init().await {
+            Ok(bme280::Chip::Bme280) => inventory.add(address, Chip::Bme280),
+            Ok(bme280::Chip::Bmp280) => inventory.add(address, Chip::Bmp280),
+            Err(_) => {}
+        }
+    }
+
+    if probe_scd4x(i2c.clone()).await {
+        inventory.add(scd4x::DEFAULT_ADDRESS, Chip::Scd4x);
+    }
+
+    for address in lm75b::DEFAULT_ADDRESS..=0x4F {
+        if probe_lm75b(&mut i2c.clone(), address).await {
+            inventory.add(address, Chip::Lm75b);
+        }
+    }
+
+    for address in DISPLAY_ADDRESSES {
+        if probe_display(i2c.clone(), address).await {
+            inventory.add(address, Chip::Ssd1306);
+        }
+    }
+
+    inventory
+}
+
+async fn probe_scd4x<I2C>(i2c: I2C) -> bool
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let mut scd4x = scd4x::Scd4x::new(i2c, scd4x::DEFAULT_ADDRESS);
+    // Serial number is not readable during periodic measurement, it survives MCU reset
+    scd4x.stop_periodic_measurement().await.is_ok() && scd4x.serial_number().await.is_ok()
+}
+
+/// Display has no ID register, status must report display off after it is turned off
+///
+/// Display is turned on again by its init
+async fn probe_display<I2C>(i2c: I2C, address: u8) -> bool
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let mut display = Ssd1306::new(i2c, address, Controller::Ssd1306);
+    if display.set_on(false).await.is_err() {
+        return false;
+    }
+
+    // Bus without driver reads all ones
+    matches!(
+        display.status().await,
+        Ok(status) if status != 0xFF && status & ssd1306::STATUS_DISPLAY_OFF != 0
+    )
+}
+
+/// LM75B has no ID register, unused bits of configuration and temperature must be zero
+async fn probe_lm75b<I2C>(i2c: &mut I2C, address: u8) -> bool
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    let mut configuration = [0_u8];
+    if i2c
+        .write_read(address, &[0x01], &mut configuration)
+        .await
+        .is_err()
+    {
+        return false;
+    }
+
+    let mut temperature = [0_u8; 2];
+    if i2c
+        .write_read(address, &[0x00], &mut temperature)
+        .await
+        .is_err()
+    {
+        return false;
+    }
+    // Let sensor return to conversions, pointer is left at temperature
+    Timer::after_millis(1).await;
+
+    configuration[0] & 0xE0 == 0 && temperature[1] & 0x1F == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::drivers::i2c::mock::{MockI2c, Transaction};
+
+    const ADDRESS: u8 = ssd1306::DEFAULT_ADDRESS;
+    const DISPLAY_OFF: Transaction = Transaction::write(ADDRESS, &[0x00, 0xAE]);
+
+    fn probe(script: &[Transaction]) -> bool {
+        let mut i2c = MockI2c::new(script);
+        let found = block_on(probe_display(&mut i2c, ADDRESS));
+        i2c.done();
+        found
+    }
+
+    #[test]
+    fn display_reports_off_after_turned_off() {
+        assert!(probe(&[DISPLAY_OFF, Transaction::read(ADDRESS, &[0x43])]));
+    }
+
+    #[test]
+    fn missing_display_is_not_found() {
+        assert!(!probe(&[DISPLAY_OFF.nack()]));
+    }
+
+    #[test]
+    fn device_acknowledging_writes_only_is_not_display() {
+        assert!(!probe(&[DISPLAY_OFF, Transaction::read(ADDRESS, &[0xFF])]));
+        assert!(!probe(&[DISPLAY_OFF, Transaction::read(ADDRESS, &[0x03])]));
+        assert!(!probe(&[
+            DISPLAY_OFF,
+            Transaction::read(ADDRESS, &[0x00]).nack()
+        ]));
+    }
+}
@@ -1,3 +1,5 @@
+pub mod display;
 pub mod i2c;
 pub mod led;
+pub mod onewire;
 pub mod sensors;
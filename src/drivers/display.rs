@@ -0,0 +1 @@
+pub mod ssd1306;
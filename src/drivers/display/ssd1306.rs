@@ -0,0 +1,166 @@
+//!
+//! SSD1306 and SH1106 128x64 OLED displays over I2C
+//!
+
+use crate::graphics::framebuffer::Framebuffer;
+
+pub const WIDTH: usize = 128;
+pub const PAGES: usize = 8;
+
+/// Default I2C address, 0x3D if SA0 pulled high
+pub const DEFAULT_ADDRESS: u8 = 0x3C;
+
+pub type DisplayFramebuffer = Framebuffer<WIDTH, PAGES>;
+
+/// Control byte: following bytes are commands
+const CONTROL_COMMAND: u8 = 0x00;
+/// Control byte: following bytes are display RAM data
+const CONTROL_DATA: u8 = 0x40;
+/// Status bit set while display is off, the same on SSD1306 and SH1106
+pub const STATUS_DISPLAY_OFF: u8 = 0x40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Controller {
+    Ssd1306,
+    /// Has 132 columns RAM with visible area starting at column 2
+    Sh1106,
+}
+
+impl Controller {
+    fn column_offset(&self) -> u8 {
+        match self {
+            Controller::Ssd1306 => 0,
+            Controller::Sh1106 => 2,
+        }
+    }
+}
+
+pub struct Ssd1306<I2C> {
+    i2c: I2C,
+    address: u8,
+    controller: Controller,
+}
+
+impl<I2C> Ssd1306<I2C> {
+    pub fn new(i2c: I2C, address: u8, controller: Controller) -> Self {
+        Self {
+            i2c,
+            address,
+            controller,
+        }
+    }
+
+    pub fn controller(&self) -> Controller {
+        self.controller
+    }
+}
+
+impl<I2C> Ssd1306<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    /// Configures display for 128x64 panel and turns it on
+    pub async fn init(&mut self) -> Result<(), I2C::Error> {
+        self.command(&[0xAE]).await?; // Display off
+        self.command(&[0xD5, 0x80]).await?; // Clock divide ratio
+        self.command(&[0xA8, 0x3F]).await?; // Multiplex ratio: 64
+        self.command(&[0xD3, 0x00]).await?; // Display offset
+        self.command(&[0x40]).await?; // Start line 0
+
+        match self.controller {
+            Controller::Ssd1306 => {
+                self.command(&[0x8D, 0x14]).await?; // Charge pump on
+                self.command(&[0x20, 0x02]).await?; // Page addressing mode
+            }
+            Controller::Sh1106 => {
+                self.command(&[0xAD, 0x8B]).await?; // DC-DC on
+            }
+        }
+
+        self.command(&[0xA1]).await?; // Segment remap: column 127 is SEG0
+        self.command(&[0xC8]).await?; // COM scan from COM63
+        self.command(&[0xDA, 0x12]).await?; // COM pins alternative configuration
+        self.command(&[0x81, 0xCF]).await?; // Contrast
+        self.command(&[0xD9, 0xF1]).await?; // Pre-charge period
+        self.command(&[0xDB, 0x40]).await?; // VCOMH deselect level
+        self.command(&[0xA4]).await?; // Show RAM content
+        self.command(&[0xA6]).await?; // Not inverted
+        self.command(&[0xAF]).await // Display on
+    }
+
+    pub async fn set_on(&mut self, on: bool) -> Result<(), I2C::Error> {
+        self.command(&[if on { 0xAF } else { 0xAE }]).await
+    }
+
+    pub async fn set_contrast(&mut self, contrast: u8) -> Result<(), I2C::Error> {
+        self.command(&[0x81, contrast]).await
+    }
+
+    pub async fn set_inverted(&mut self, inverted: bool) -> Result<(), I2C::Error> {
+        self.command(&[if inverted { 0xA7 } else { 0xA6 }]).await
+    }
+
+    /// Reads status byte, see [`STATUS_DISPLAY_OFF`]
+    pub async fn status(&mut self) -> Result<u8, I2C::Error> {
+        let mut status = [0_u8];
+        self.i2c.read(self.address, &mut status).await?;
+        Ok(status[0])
+    }
+
+    /// Writes whole framebuffer to display
+    pub async fn flush(&mut self, framebuffer: &DisplayFramebuffer) -> Result<(), I2C::Error> {
+        for page in 0..PAGES {
+            self.flush_page(framebuffer, page, 0, WIDTH).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes only changed parts of framebuffer to display and forgets the changes
+    pub async fn flush_dirty(
+        &mut self,
+        framebuffer: &mut DisplayFramebuffer,
+    ) -> Result<(), I2C::Error> {
+        for page in 0..PAGES {
+            if let Some((start, end)) = framebuffer.dirty(page) {
+                self.flush_page(framebuffer, page, start, end).await?;
+            }
+        }
+
+        framebuffer.clear_dirty();
+        Ok(())
+    }
+
+    /// Writes columns `start..end` of one page to display
+    pub async fn flush_page(
+        &mut self,
+        framebuffer: &DisplayFramebuffer,
+        page: usize,
+        start: usize,
+        end: usize,
+    ) -> Result<(), I2C::Error> {
+        let end = end.min(WIDTH);
+        if end <= start {
+            return Ok(());
+        }
+
+        let column = start as u8 + self.controller.column_offset();
+        self.command(&[0xB0 | page as u8, column & 0x0F, 0x10 | (column >> 4)])
+            .await?;
+
+        let mut data = [0_u8; WIDTH + 1];
+        data[0] = CONTROL_DATA;
+        let len = end - start;
+        data[1..=len].copy_from_slice(&framebuffer.page(page)[start..end]);
+
+        self.i2c.write(self.address, &data[..=len]).await
+    }
+
+    async fn command(&mut self, command: &[u8]) -> Result<(), I2C::Error> {
+        let mut data = [0_u8; 4];
+        data[0] = CONTROL_COMMAND;
+        data[1..=command.len()].copy_from_slice(command);
+
+        self.i2c.write(self.address, &data[..=command.len()]).await
+    }
+}
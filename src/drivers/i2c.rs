@@ -1 +1,5 @@
 pub mod ector;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod recovery;
+pub mod stats;
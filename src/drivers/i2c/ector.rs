@@ -1,27 +1,205 @@
 //!
 //! ector-based I2C bus
 //!
+//! Transactions are copied into buffers owned by the message, and the read data is
+//! copied back from the reply, so the actor never touches memory of the caller.
+//! Cancelling a transaction (e.g. with `with_timeout`) is safe: the actor finishes it
+//! on its own buffers and the stale reply is dropped by the next caller.
+//!
+//! Every transaction is limited by [`TRANSACTION_TIMEOUT`]. After a timeout or a bus
+//! error the bus is recovered, and outcomes are counted per address in [`I2cStats`].
+//!
 
-use defmt::info;
-use ector::{Actor, ActorRequest};
-use embedded_hal_async::i2c::{Error, ErrorKind};
+use defmt::{error, info, warn};
+use ector::Actor;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::Duration;
+use embedded_hal_async::i2c::{Error, ErrorKind, Operation};
+use heapless::Vec;
 
-pub struct I2cActor<I2C> {
-    pub i2c: I2C,
+use crate::drivers::i2c::{
+    recovery::{needs_recovery, RecoverI2c},
+    stats::{I2cStats, Outcome, SharedI2cStats},
+};
+
+/// Max count of operations in one transaction
+pub const MAX_OPERATIONS: usize = 4;
+/// Max count of bytes written by one transaction, enough for a full display page
+pub const MAX_WRITE: usize = 132;
+/// Max count of bytes read by one transaction
+pub const MAX_READ: usize = 32;
+/// Max time of one transaction on the bus, a full display page takes about 12ms
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// Read of the given length
+    Read(usize),
+    /// Write of the given length, data follows the previous writes
+    Write(usize),
 }
 
-pub struct Request {
+/// Transaction with own copy of the data
+pub struct Transfer {
     address: u8,
-    operations: &'static mut [embedded_hal_async::i2c::Operation<'static>],
+    ticket: u32,
+    operations: Vec<Op, MAX_OPERATIONS>,
+    write: Vec<u8, MAX_WRITE>,
+}
+
+impl Transfer {
+    /// Copies operations
+    ///
+    /// # Returns
+    /// - None, if operations do not fit into limits
+    fn new(address: u8, ticket: u32, operations: &[Operation<'_>]) -> Option<Self> {
+        let mut transfer = Self {
+            address,
+            ticket,
+            operations: Vec::new(),
+            write: Vec::new(),
+        };
+
+        let mut read_len = 0;
+        for operation in operations {
+            let op = match operation {
+                Operation::Read(buffer) => {
+                    read_len += buffer.len();
+                    Op::Read(buffer.len())
+                }
+                Operation::Write(data) => {
+                    transfer.write.extend_from_slice(data).ok()?;
+                    Op::Write(data.len())
+                }
+            };
+            transfer.operations.push(op).ok()?;
+        }
+
+        (read_len <= MAX_READ).then_some(transfer)
+    }
+}
+
+/// Result of transaction with the read data of all reads one after another
+pub type Response = Result<Vec<u8, MAX_READ>, ErrorKind>;
+
+struct Reply {
+    ticket: u32,
+    response: Response,
+}
+
+/// Where the actor puts replies
+///
+/// Lives forever, so the actor can reply even if the caller has gone
+pub struct ReplySlot {
+    /// Ticket of the last transaction, held by the caller during the whole transaction
+    ticket: Mutex<CriticalSectionRawMutex, u32>,
+    reply: Signal<CriticalSectionRawMutex, Reply>,
+}
+
+impl Default for ReplySlot {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub type Response = Result<(), ErrorKind>;
+impl ReplySlot {
+    pub const fn new() -> Self {
+        Self {
+            ticket: Mutex::new(0),
+            reply: Signal::new(),
+        }
+    }
+}
+
+pub struct I2cActor<I2C> {
+    i2c: I2C,
+    replies: &'static ReplySlot,
+    stats: SharedI2cStats,
+}
+
+impl<I2C> I2cActor<I2C> {
+    pub fn new(i2c: I2C, replies: &'static ReplySlot, stats: SharedI2cStats) -> Self {
+        Self {
+            i2c,
+            replies,
+            stats,
+        }
+    }
+}
+
+impl<I2C> I2cActor<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c + RecoverI2c,
+{
+    /// Runs transaction with timeout, recovering bus if it looks stuck
+    async fn process(&mut self, transfer: &Transfer) -> Response {
+        let (response, outcome) =
+            match embassy_time::with_timeout(TRANSACTION_TIMEOUT, self.transfer(transfer)).await {
+                Ok(Ok(read)) => (Ok(read), Outcome::Ok),
+                Ok(Err(kind)) => (Err(kind), Outcome::from(kind)),
+                Err(_) => (Err(ErrorKind::Other), Outcome::Timeout),
+            };
+
+        let recover = needs_recovery(outcome, response.as_ref().err().copied());
+        if recover {
+            warn!("i2c: {} on {:x}, recovering bus", outcome, transfer.address);
+            self.i2c.recover().await;
+        }
+
+        self.stats
+            .update(|stats: &mut I2cStats| {
+                stats.record(transfer.address, outcome);
+                if recover {
+                    stats.recoveries += 1;
+                }
+            })
+            .await;
+
+        response
+    }
+
+    async fn transfer(&mut self, transfer: &Transfer) -> Response {
+        let mut read = [0_u8; MAX_READ];
+        let mut read_len = 0;
+
+        let mut operations: Vec<Operation<'_>, MAX_OPERATIONS> = Vec::new();
+        let mut write = transfer.write.as_slice();
+        let mut read_rest = read.as_mut_slice();
+        for op in &transfer.operations {
+            let operation = match *op {
+                Op::Read(len) => {
+                    let (buffer, rest) = core::mem::take(&mut read_rest).split_at_mut(len);
+                    read_rest = rest;
+                    read_len += len;
+                    Operation::Read(buffer)
+                }
+                Op::Write(len) => {
+                    let (data, rest) = write.split_at(len);
+                    write = rest;
+                    Operation::Write(data)
+                }
+            };
+            // Same count as in transfer
+            operations.push(operation).ok();
+        }
+
+        let result = self
+            .i2c
+            .transaction(transfer.address, &mut operations)
+            .await;
+        drop(operations);
+        result.map_err(|err| err.kind())?;
+
+        // Fits, checked when transfer created
+        Ok(Vec::from_slice(&read[..read_len]).unwrap_or_default())
+    }
+}
 
 impl<I2C> Actor for I2cActor<I2C>
 where
-    I2C: embedded_hal_async::i2c::I2c,
+    I2C: embedded_hal_async::i2c::I2c + RecoverI2c,
 {
-    type Message = ector::Request<Request, Response>;
+    type Message = Transfer;
 
     async fn on_mount<M>(&mut self, _: ector::DynamicAddress<Self::Message>, mut inbox: M) -> !
     where
@@ -30,30 +208,33 @@ where
         info!("i2c actor started");
 
         loop {
-            let mut input_request = inbox.next().await;
-            let request = input_request.get_mut();
-
-            match self
-                .i2c
-                .transaction(request.address, request.operations)
-                .await
-            {
-                Ok(_) => input_request.reply(Ok(())).await,
-                Err(err) => input_request.reply(Err(err.kind())).await,
-            };
+            let transfer = inbox.next().await;
+            let response = self.process(&transfer).await;
+
+            self.replies.reply.signal(Reply {
+                ticket: transfer.ticket,
+                response,
+            });
         }
     }
 }
 
-/// # Warning
-///
-/// Do not use in Select
-/// This implementation is NOT cancel-safe. It will panic it you try to do so
-///
+/// I2C bus shared through [`I2cActor`]
 ///
+/// Transactions are limited by [`MAX_OPERATIONS`], [`MAX_WRITE`] and [`MAX_READ`],
+/// larger ones fail with [`ErrorKind::Other`]. Transactions can be cancelled.
 #[derive(Clone)]
 pub struct EctorI2c {
-    pub address: ector::DynamicAddress<ector::Request<Request, Response>>,
+    address: ector::DynamicAddress<Transfer>,
+    replies: &'static ReplySlot,
+}
+
+impl EctorI2c {
+    /// # Arguments
+    /// - `replies` - the same slot the actor was created with
+    pub fn new(address: ector::DynamicAddress<Transfer>, replies: &'static ReplySlot) -> Self {
+        Self { address, replies }
+    }
 }
 
 impl embedded_hal_async::i2c::ErrorType for EctorI2c {
@@ -64,20 +245,139 @@ impl embedded_hal_async::i2c::I2c for EctorI2c {
     async fn transaction(
         &mut self,
         address: u8,
-        operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+        operations: &mut [Operation<'_>],
     ) -> Result<(), Self::Error> {
-        // Safety:
-        // - the request completion ensures buffer will not used anymore by actor
-        // - because cancel is forbidden(protected with panic), even if user tries we will stop using buffer y stopping program
-        //
-        let static_operations: &'static mut [embedded_hal_async::i2c::Operation<'static>] =
-            unsafe { core::mem::transmute(operations) };
-
-        self.address
-            .request(Request {
-                address,
-                operations: static_operations,
-            })
-            .await
+        // Only one caller waits for reply at a time
+        let mut ticket = self.replies.ticket.lock().await;
+        *ticket = ticket.wrapping_add(1);
+
+        let Some(transfer) = Transfer::new(address, *ticket, operations) else {
+            error!("i2c: transaction to {:x} is too large", address);
+            return Err(ErrorKind::Other);
+        };
+        self.address.send(transfer).await;
+
+        let read = loop {
+            let reply = self.replies.reply.wait().await;
+            // Other tickets are replies to cancelled transactions
+            if reply.ticket == *ticket {
+                break reply.response?;
+            }
+        };
+
+        let mut read = read.as_slice();
+        for operation in operations {
+            if let Operation::Read(buffer) = operation {
+                let (data, rest) = read.split_at(buffer.len());
+                buffer.copy_from_slice(data);
+                read = rest;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ector::ActorContext;
+    use embassy_futures::{
+        block_on,
+        join::join,
+        select::{select, select3, Either, Either3},
+    };
+    use embedded_hal_async::i2c::I2c as _;
+    use static_cell::StaticCell;
+
+    use super::*;
+    use crate::sync::mutex::AtomicMutex;
+
+    type Event = Signal<CriticalSectionRawMutex, ()>;
+
+    /// Bus which holds every transaction until released, reads return number of transaction
+    struct SlowBus {
+        count: u8,
+        started: &'static Event,
+        release: &'static Event,
+    }
+
+    impl embedded_hal_async::i2c::ErrorType for SlowBus {
+        type Error = ErrorKind;
+    }
+
+    impl embedded_hal_async::i2c::I2c for SlowBus {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            self.count += 1;
+            self.started.signal(());
+            self.release.wait().await;
+
+            for operation in operations {
+                if let Operation::Read(buffer) = operation {
+                    buffer.fill(self.count);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl RecoverI2c for SlowBus {
+        async fn recover(&mut self) {}
+    }
+
+    #[test]
+    fn cancelled_transaction_does_not_leak_into_next() {
+        static CONTEXT: ActorContext<I2cActor<SlowBus>> = ActorContext::new();
+        static REPLIES: ReplySlot = ReplySlot::new();
+        static STATS: StaticCell<AtomicMutex<I2cStats>> = StaticCell::new();
+        static STARTED: Event = Signal::new();
+        static RELEASE: Event = Signal::new();
+
+        let actor = I2cActor::new(
+            SlowBus {
+                count: 0,
+                started: &STARTED,
+                release: &RELEASE,
+            },
+            &REPLIES,
+            SharedI2cStats::new(STATS.init(AtomicMutex::new(I2cStats::default()))),
+        );
+        let mut first = EctorI2c::new(CONTEXT.dyn_address(), &REPLIES);
+        let mut second = first.clone();
+
+        let scenario = async {
+            // Caller gives up while the actor is in the middle of its transaction
+            let mut cancelled = [0_u8; 2];
+            match select(first.read(0x44, &mut cancelled), STARTED.wait()).await {
+                Either::First(_) => panic!("transaction finished before bus released"),
+                Either::Second(()) => {}
+            }
+
+            // Reply to the cancelled transaction arrives while the next one waits
+            let mut read = [0_u8; 2];
+            let bus = async {
+                RELEASE.signal(());
+                STARTED.wait().await;
+                RELEASE.signal(());
+            };
+            let (result, ()) = join(second.read(0x44, &mut read), bus).await;
+            result.unwrap();
+            (cancelled, read)
+        };
+
+        let (cancelled, read) = match block_on(select3(
+            CONTEXT.mount(actor),
+            scenario,
+            embassy_time::Timer::after_secs(1),
+        )) {
+            Either3::Second(result) => result,
+            _ => panic!("scenario did not finish"),
+        };
+
+        assert_eq!(cancelled, [0, 0]);
+        assert_eq!(read, [2, 2]);
     }
 }
@@ -0,0 +1,112 @@
+//!
+//! Scripted I2C bus for host tests
+//!
+//! Every transaction must match the next expected one: the same address and the same
+//! written bytes. Reads are filled from the script, one after another.
+//!
+
+use embedded_hal_async::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use heapless::Vec;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Transaction {
+    address: u8,
+    write: &'static [u8],
+    read: &'static [u8],
+    error: Option<ErrorKind>,
+}
+
+impl Transaction {
+    pub const fn write(address: u8, write: &'static [u8]) -> Self {
+        Self {
+            address,
+            write,
+            read: &[],
+            error: None,
+        }
+    }
+
+    pub const fn read(address: u8, read: &'static [u8]) -> Self {
+        Self {
+            address,
+            write: &[],
+            read,
+            error: None,
+        }
+    }
+
+    pub const fn write_read(address: u8, write: &'static [u8], read: &'static [u8]) -> Self {
+        Self {
+            address,
+            write,
+            read,
+            error: None,
+        }
+    }
+
+    /// Same transaction not acknowledged by the device
+    pub const fn nack(self) -> Self {
+        Self {
+            error: Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+            ..self
+        }
+    }
+}
+
+pub struct MockI2c<'a> {
+    script: &'a [Transaction],
+    next: usize,
+}
+
+impl<'a> MockI2c<'a> {
+    pub fn new(script: &'a [Transaction]) -> Self {
+        Self { script, next: 0 }
+    }
+
+    /// Asserts every scripted transaction happened
+    pub fn done(&self) {
+        assert_eq!(self.next, self.script.len(), "scripted transactions left");
+    }
+}
+
+impl ErrorType for MockI2c<'_> {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockI2c<'_> {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let Some(expected) = self.script.get(self.next) else {
+            panic!("unexpected transaction to {address:#x}");
+        };
+        self.next += 1;
+
+        let mut write: Vec<u8, 256> = Vec::new();
+        for operation in operations.iter() {
+            if let Operation::Write(data) = operation {
+                write.extend_from_slice(data).unwrap();
+            }
+        }
+
+        assert_eq!(address, expected.address, "address");
+        assert_eq!(&write[..], expected.write, "written to {address:#x}");
+        if let Some(error) = expected.error {
+            return Err(error);
+        }
+
+        let mut read = expected.read;
+        for operation in operations {
+            if let Operation::Read(buffer) = operation {
+                let (data, rest) = read.split_at(buffer.len());
+                buffer.copy_from_slice(data);
+                read = rest;
+            }
+        }
+        assert!(read.is_empty(), "scripted data left unread");
+
+        Ok(())
+    }
+}
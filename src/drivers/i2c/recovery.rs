@@ -0,0 +1,236 @@
+//!
+//! Recovery of I2C bus held by a stuck device
+//!
+
+use core::future::Future;
+
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal_async::{delay::DelayNs, i2c::ErrorKind};
+
+use crate::drivers::i2c::stats::Outcome;
+
+/// Max count of SCL clocks, enough to finish any byte and its ACK
+const CLEAR_BUS_CLOCKS: usize = 9;
+/// Half of SCL period at 100kHz
+const HALF_PERIOD_US: u32 = 5;
+
+/// I2C bus which can be brought back after a device hangs it
+pub trait RecoverI2c {
+    /// Releases the bus with [`clear_bus`] and re-initialises the controller
+    fn recover(&mut self) -> impl Future<Output = ()>;
+}
+
+/// Whatever bus looks stuck after transaction
+///
+/// A timeout or lost arbitration on a bus without other masters means a device holds
+/// a line, a bus error means the controller saw a misplaced START or STOP
+pub fn needs_recovery(outcome: Outcome, error: Option<ErrorKind>) -> bool {
+    matches!(outcome, Outcome::Timeout | Outcome::ArbitrationLoss) || error == Some(ErrorKind::Bus)
+}
+
+/// Frees bus from a device stuck in the middle of a byte with SDA held low
+///
+/// Clocks SCL until SDA is released, at most 9 times, then generates STOP.
+/// Both pins must be open-drain with pull-ups and detached from I2C controller.
+///
+/// # Returns
+/// Whatever SDA is released
+pub async fn clear_bus<SCL, SDA, D>(scl: &mut SCL, sda: &mut SDA, delay: &mut D) -> bool
+where
+    SCL: OutputPin,
+    SDA: InputPin + OutputPin,
+    D: DelayNs,
+{
+    sda.set_high().ok();
+    scl.set_high().ok();
+    delay.delay_us(HALF_PERIOD_US).await;
+
+    for _ in 0..CLEAR_BUS_CLOCKS {
+        if sda.is_high().unwrap_or(false) {
+            break;
+        }
+
+        scl.set_low().ok();
+        delay.delay_us(HALF_PERIOD_US).await;
+        scl.set_high().ok();
+        delay.delay_us(HALF_PERIOD_US).await;
+    }
+
+    // STOP: SDA rises while SCL is high
+    scl.set_low().ok();
+    delay.delay_us(HALF_PERIOD_US).await;
+    sda.set_low().ok();
+    delay.delay_us(HALF_PERIOD_US).await;
+    scl.set_high().ok();
+    delay.delay_us(HALF_PERIOD_US).await;
+    sda.set_high().ok();
+    delay.delay_us(HALF_PERIOD_US).await;
+
+    sda.is_high().unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use embassy_futures::block_on;
+    use embedded_hal::digital::{ErrorType, PinState};
+    use embedded_hal_async::i2c::NoAcknowledgeSource;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Lines of bus with a device holding SDA low for some SCL clocks
+    #[derive(Default)]
+    struct Bus {
+        scl: bool,
+        sda: bool,
+        /// Falling SCL edges until device releases SDA
+        held_for: usize,
+        /// Level changes as (SCL, SDA) seen on the lines
+        trace: Vec<(bool, bool)>,
+    }
+
+    impl Bus {
+        fn sda_line(&self) -> bool {
+            self.sda && self.held_for == 0
+        }
+
+        fn set(&mut self, scl: bool, sda: bool) {
+            if self.scl && !scl && self.held_for > 0 {
+                self.held_for -= 1;
+            }
+            self.scl = scl;
+            self.sda = sda;
+
+            let levels = (scl, self.sda_line());
+            if self.trace.last() != Some(&levels) {
+                self.trace.push(levels);
+            }
+        }
+    }
+
+    struct Scl<'a>(&'a RefCell<Bus>);
+    struct Sda<'a>(&'a RefCell<Bus>);
+
+    impl ErrorType for Scl<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl ErrorType for Sda<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl OutputPin for Scl<'_> {
+        fn set_state(&mut self, state: PinState) -> Result<(), Self::Error> {
+            let mut bus = self.0.borrow_mut();
+            let sda = bus.sda;
+            bus.set(state == PinState::High, sda);
+            Ok(())
+        }
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.set_state(PinState::Low)
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.set_state(PinState::High)
+        }
+    }
+
+    impl OutputPin for Sda<'_> {
+        fn set_state(&mut self, state: PinState) -> Result<(), Self::Error> {
+            let mut bus = self.0.borrow_mut();
+            let scl = bus.scl;
+            bus.set(scl, state == PinState::High);
+            Ok(())
+        }
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.set_state(PinState::Low)
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.set_state(PinState::High)
+        }
+    }
+
+    impl InputPin for Sda<'_> {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            Ok(self.0.borrow().sda_line())
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.0.borrow().sda_line())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn clear(held_for: usize) -> (bool, Bus) {
+        let bus = RefCell::new(Bus {
+            held_for,
+            ..Default::default()
+        });
+        let released = block_on(clear_bus(&mut Scl(&bus), &mut Sda(&bus), &mut NoDelay));
+        (released, bus.into_inner())
+    }
+
+    fn clocks(bus: &Bus) -> usize {
+        bus.trace
+            .windows(2)
+            .filter(|pair| pair[0].0 && !pair[1].0)
+            .count()
+    }
+
+    /// Bus ends with STOP: SDA rises while SCL is high
+    fn ends_with_stop(bus: &Bus) -> bool {
+        bus.trace.ends_with(&[(true, false), (true, true)])
+    }
+
+    #[test]
+    fn free_bus_gets_only_stop() {
+        let (released, bus) = clear(0);
+        assert!(released);
+        // The only falling SCL edge is the one before STOP
+        assert_eq!(clocks(&bus), 1);
+        assert!(ends_with_stop(&bus));
+    }
+
+    #[test]
+    fn clocks_stuck_device_until_it_releases_sda() {
+        let (released, bus) = clear(3);
+        assert!(released);
+        assert_eq!(clocks(&bus), 3 + 1);
+        assert!(ends_with_stop(&bus));
+    }
+
+    #[test]
+    fn gives_up_after_nine_clocks() {
+        let (released, bus) = clear(100);
+        assert!(!released);
+        assert_eq!(clocks(&bus), CLEAR_BUS_CLOCKS + 1);
+    }
+
+    #[test]
+    fn recovers_after_stuck_bus_outcomes_only() {
+        assert!(needs_recovery(Outcome::Timeout, Some(ErrorKind::Other)));
+        assert!(needs_recovery(
+            Outcome::ArbitrationLoss,
+            Some(ErrorKind::ArbitrationLoss)
+        ));
+        assert!(needs_recovery(Outcome::Error, Some(ErrorKind::Bus)));
+
+        assert!(!needs_recovery(Outcome::Ok, None));
+        assert!(!needs_recovery(
+            Outcome::Nack,
+            Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data))
+        ));
+        assert!(!needs_recovery(Outcome::Error, Some(ErrorKind::Overrun)));
+    }
+}
@@ -0,0 +1,169 @@
+//!
+//! Per-address I2C bus statistics
+//!
+
+use embedded_hal_async::i2c::ErrorKind;
+use heapless::Vec;
+use serde::Serialize;
+
+use crate::sync::mutex::AtomicMutex;
+
+/// Max count of tracked devices
+pub const MAX_DEVICES: usize = 16;
+
+/// How transaction ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Outcome {
+    Ok,
+    Nack,
+    ArbitrationLoss,
+    /// Bus did not finish transaction in time
+    Timeout,
+    /// Any other error
+    Error,
+}
+
+impl From<ErrorKind> for Outcome {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::NoAcknowledge(_) => Outcome::Nack,
+            ErrorKind::ArbitrationLoss => Outcome::ArbitrationLoss,
+            _ => Outcome::Error,
+        }
+    }
+}
+
+/// Counters of one device
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DeviceStats {
+    pub address: u8,
+    pub transactions: u32,
+    pub nacks: u32,
+    pub arbitration_losses: u32,
+    pub timeouts: u32,
+    pub errors: u32,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct I2cStats {
+    /// Count of bus recoveries
+    pub recoveries: u32,
+    /// Devices acknowledged at least once, so bus scans do not fill the table
+    pub devices: Vec<DeviceStats, MAX_DEVICES>,
+}
+
+impl I2cStats {
+    pub fn record(&mut self, address: u8, outcome: Outcome) {
+        let idx = match self.devices.iter().position(|dev| dev.address == address) {
+            Some(idx) => idx,
+            None if outcome == Outcome::Nack => return,
+            None => {
+                let device = DeviceStats {
+                    address,
+                    ..Default::default()
+                };
+                if self.devices.push(device).is_err() {
+                    return;
+                }
+                self.devices.len() - 1
+            }
+        };
+
+        let device = &mut self.devices[idx];
+        device.transactions += 1;
+        match outcome {
+            Outcome::Ok => {}
+            Outcome::Nack => device.nacks += 1,
+            Outcome::ArbitrationLoss => device.arbitration_losses += 1,
+            Outcome::Timeout => device.timeouts += 1,
+            Outcome::Error => device.errors += 1,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SharedI2cStats(&'static AtomicMutex<I2cStats>);
+
+impl SharedI2cStats {
+    pub fn new(m: &'static AtomicMutex<I2cStats>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> I2cStats {
+        self.0.lock().await.clone()
+    }
+
+    pub(crate) async fn update(&self, f: impl FnOnce(&mut I2cStats)) {
+        f(&mut *self.0.lock().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_async::i2c::NoAcknowledgeSource;
+
+    #[test]
+    fn counts_outcomes_per_address() {
+        let mut stats = I2cStats::default();
+        for (address, outcome) in [
+            (0x44, Outcome::Ok),
+            (0x76, Outcome::Timeout),
+            (0x44, Outcome::Nack),
+            (0x44, Outcome::ArbitrationLoss),
+            (0x76, Outcome::Error),
+            (0x44, Outcome::Ok),
+        ] {
+            stats.record(address, outcome);
+        }
+
+        assert_eq!(
+            stats.devices.as_slice(),
+            [
+                DeviceStats {
+                    address: 0x44,
+                    transactions: 4,
+                    nacks: 1,
+                    arbitration_losses: 1,
+                    ..Default::default()
+                },
+                DeviceStats {
+                    address: 0x76,
+                    transactions: 2,
+                    timeouts: 1,
+                    errors: 1,
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_do_not_fill_table() {
+        let mut stats = I2cStats::default();
+        for address in 0x08..0x78 {
+            stats.record(address, Outcome::Nack);
+        }
+        assert!(stats.devices.is_empty());
+
+        for address in 0..MAX_DEVICES as u8 + 2 {
+            stats.record(address, Outcome::Ok);
+        }
+        assert_eq!(stats.devices.len(), MAX_DEVICES);
+        assert!(stats.devices.iter().all(|device| device.transactions == 1));
+    }
+
+    #[test]
+    fn outcome_of_error() {
+        assert_eq!(
+            Outcome::from(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+            Outcome::Nack
+        );
+        assert_eq!(
+            Outcome::from(ErrorKind::ArbitrationLoss),
+            Outcome::ArbitrationLoss
+        );
+        assert_eq!(Outcome::from(ErrorKind::Bus), Outcome::Error);
+        assert_eq!(Outcome::from(ErrorKind::Overrun), Outcome::Error);
+    }
+}
@@ -1,4 +1,8 @@
 mod rgb;
+mod strip;
 pub use rgb::RgbLedAsync;
+pub use strip::LedStripAsync;
 
+pub mod smooth;
+#[cfg(target_arch = "riscv32")]
 pub mod ws2812;
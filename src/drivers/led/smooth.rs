@@ -0,0 +1,295 @@
+//!
+//! Color corrections and fades over any RGB Led
+//!
+
+use embassy_time::{Duration, Timer};
+
+use crate::drivers::led::RgbLedAsync;
+
+/// Gamma 2.2 correction table: linear brightness to LED duty
+pub const GAMMA_2_2: [u8; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2,
+    3, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10, 10, 11, 11,
+    11, 12, 12, 13, 13, 13, 14, 14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19, 20, 20, 21, 22, 22, 23,
+    23, 24, 25, 25, 26, 26, 27, 28, 28, 29, 30, 30, 31, 32, 33, 33, 34, 35, 35, 36, 37, 38, 39, 39,
+    40, 41, 42, 43, 43, 44, 45, 46, 47, 48, 49, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61,
+    62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 73, 74, 75, 76, 77, 78, 79, 81, 82, 83, 84, 85, 87, 88,
+    89, 90, 91, 93, 94, 95, 97, 98, 99, 100, 102, 103, 105, 106, 107, 109, 110, 111, 113, 114, 116,
+    117, 119, 120, 121, 123, 124, 126, 127, 129, 130, 132, 133, 135, 137, 138, 140, 141, 143, 145,
+    146, 148, 149, 151, 153, 154, 156, 158, 159, 161, 163, 165, 166, 168, 170, 172, 173, 175, 177,
+    179, 181, 182, 184, 186, 188, 190, 192, 194, 196, 197, 199, 201, 203, 205, 207, 209, 211, 213,
+    215, 217, 219, 221, 223, 225, 227, 229, 231, 234, 236, 238, 240, 242, 244, 246, 248, 251, 253,
+    255,
+];
+
+/// Interval between fade steps
+const FADE_STEP: Duration = Duration::from_millis(20);
+
+/// Max sum of channels, i.e. no current limit
+const NO_CURRENT_LIMIT: u16 = 3 * 255;
+
+/// Wraps RGB Led with gamma correction, brightness and current limits and fades
+///
+/// Colors passed in are linear, corrections applied just before writing to Led
+pub struct SmoothLed<L> {
+    led: L,
+    gamma: Option<&'static [u8; 256]>,
+    brightness: u8,
+    /// Max sum of corrected channels. Roughly proportional to Led current
+    max_total: u16,
+    /// Color shown now, before corrections
+    current: (u8, u8, u8),
+}
+
+impl<L> SmoothLed<L>
+where
+    L: RgbLedAsync,
+{
+    /// Wraps Led with [`GAMMA_2_2`], full brightness and no current limit
+    pub fn new(led: L) -> Self {
+        Self {
+            led,
+            gamma: Some(&GAMMA_2_2),
+            brightness: 255,
+            max_total: NO_CURRENT_LIMIT,
+            current: (0, 0, 0),
+        }
+    }
+
+    /// Sets gamma table, `None` disables correction
+    pub fn with_gamma(mut self, gamma: Option<&'static [u8; 256]>) -> Self {
+        self.gamma = gamma;
+        self
+    }
+
+    pub fn with_brightness(mut self, brightness: u8) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    /// Limits sum of corrected channels. Colors above limit are scaled down
+    pub fn with_current_limit(mut self, max_total: u16) -> Self {
+        self.max_total = max_total;
+        self
+    }
+
+    /// Limits sum of corrected channels to `percent` of full white. Applied on next
+    /// color change
+    pub fn set_current_limit(&mut self, percent: u8) {
+        self.max_total = (NO_CURRENT_LIMIT as u32 * percent.min(100) as u32 / 100) as u16;
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Sets global brightness. Applied on next color change
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    /// Gets color shown now, before corrections
+    pub fn color(&self) -> (u8, u8, u8) {
+        self.current
+    }
+
+    /// Sets color immediately from hue (0..360), saturation and value
+    pub async fn set_hsv(&mut self, hue: u16, saturation: u8, value: u8) {
+        self.show(hsv_to_rgb(hue, saturation, value)).await;
+    }
+
+    /// Changes color smoothly within duration
+    ///
+    /// Takes about `duration` to complete. Cancel leaves the Led at intermediate color
+    pub async fn fade_to(&mut self, (r, g, b): (u8, u8, u8), duration: Duration) {
+        let steps = (duration.as_ticks() / FADE_STEP.as_ticks()).max(1) as i32;
+        let (from_r, from_g, from_b) = self.current;
+        let lerp = |from: u8, to: u8, step: i32| {
+            (from as i32 + (to as i32 - from as i32) * step / steps) as u8
+        };
+
+        for step in 1..=steps {
+            self.show((
+                lerp(from_r, r, step),
+                lerp(from_g, g, step),
+                lerp(from_b, b, step),
+            ))
+            .await;
+            Timer::after(FADE_STEP).await;
+        }
+    }
+
+    /// Changes color smoothly to hue (0..360), saturation and value within duration
+    pub async fn fade_to_hsv(&mut self, hue: u16, saturation: u8, value: u8, duration: Duration) {
+        self.fade_to(hsv_to_rgb(hue, saturation, value), duration)
+            .await;
+    }
+
+    /// Applies corrections to linear color
+    fn corrected(&self, (r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+        let correct = |channel: u8| {
+            let scaled = ((channel as u16) * (self.brightness as u16) / 255) as u8;
+            match self.gamma {
+                Some(gamma) => gamma[scaled as usize],
+                None => scaled,
+            }
+        };
+        let (r, g, b) = (correct(r), correct(g), correct(b));
+
+        let total = r as u32 + g as u32 + b as u32;
+        let max_total = self.max_total as u32;
+        if total <= max_total {
+            return (r, g, b);
+        }
+
+        let limit = |channel: u8| (channel as u32 * max_total / total) as u8;
+        (limit(r), limit(g), limit(b))
+    }
+
+    async fn show(&mut self, color: (u8, u8, u8)) {
+        self.current = color;
+        let (r, g, b) = self.corrected(color);
+        self.led.set_color(r, g, b).await;
+    }
+}
+
+impl<L> RgbLedAsync for SmoothLed<L>
+where
+    L: RgbLedAsync,
+{
+    /// Sets linear color immediately with corrections applied
+    async fn set_color(&mut self, r: u8, g: u8, b: u8) {
+        self.show((r, g, b)).await;
+    }
+}
+
+/// Converts hue (0..360), saturation and value into RGB
+pub fn hsv_to_rgb(hue: u16, saturation: u8, value: u8) -> (u8, u8, u8) {
+    let hue = (hue % 360) as u32;
+    let sector = hue / 60;
+    let remainder = hue % 60;
+
+    let s = saturation as u32;
+    let v = value as u32;
+    let p = (v * (255 - s) / 255) as u8;
+    let q = (v * (255 - s * remainder / 60) / 255) as u8;
+    let t = (v * (255 - s * (60 - remainder) / 60) / 255) as u8;
+    let v = value;
+
+    match sector {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use embassy_futures::block_on;
+    use std::vec::Vec;
+
+    /// Records colors written
+    #[derive(Default)]
+    struct MockLed(Vec<(u8, u8, u8)>);
+
+    impl RgbLedAsync for &mut MockLed {
+        async fn set_color(&mut self, r: u8, g: u8, b: u8) {
+            self.0.push((r, g, b));
+        }
+    }
+
+    fn shown(led: SmoothLed<&mut MockLed>, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        led.corrected(color)
+    }
+
+    #[test]
+    fn hsv_primaries_and_wrap() {
+        assert_eq!(hsv_to_rgb(0, 255, 255), (255, 0, 0));
+        assert_eq!(hsv_to_rgb(60, 255, 255), (255, 255, 0));
+        assert_eq!(hsv_to_rgb(120, 255, 255), (0, 255, 0));
+        assert_eq!(hsv_to_rgb(180, 255, 255), (0, 255, 255));
+        assert_eq!(hsv_to_rgb(240, 255, 255), (0, 0, 255));
+        assert_eq!(hsv_to_rgb(300, 255, 255), (255, 0, 255));
+        assert_eq!(hsv_to_rgb(360, 255, 255), hsv_to_rgb(0, 255, 255));
+        assert_eq!(hsv_to_rgb(30, 255, 255), (255, 128, 0));
+    }
+
+    #[test]
+    fn hsv_without_saturation_or_value() {
+        assert_eq!(hsv_to_rgb(200, 0, 128), (128, 128, 128));
+        assert_eq!(hsv_to_rgb(200, 255, 0), (0, 0, 0));
+        assert_eq!(hsv_to_rgb(0, 128, 255), (255, 127, 127));
+    }
+
+    #[test]
+    fn gamma_follows_curve() {
+        assert_eq!(GAMMA_2_2[0], 0);
+        assert_eq!(GAMMA_2_2[255], 255);
+        for (index, pair) in GAMMA_2_2.windows(2).enumerate() {
+            assert!(pair[0] <= pair[1], "decreases at {index}");
+        }
+        for (index, duty) in GAMMA_2_2.into_iter().enumerate() {
+            let expected = libm::powf(index as f32 / 255.0, 2.2) * 255.0;
+            assert!(
+                (duty as f32 - expected).abs() <= 1.0,
+                "{duty} at {index}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn corrections_scale_brightness_then_gamma() {
+        let mut mock = MockLed::default();
+        let linear = SmoothLed::new(&mut mock).with_gamma(None);
+        assert_eq!(shown(linear, (255, 128, 0)), (255, 128, 0));
+
+        let dimmed = SmoothLed::new(&mut mock)
+            .with_gamma(None)
+            .with_brightness(128);
+        assert_eq!(shown(dimmed, (255, 128, 0)), (128, 64, 0));
+
+        let gamma = SmoothLed::new(&mut mock).with_brightness(128);
+        assert_eq!(
+            shown(gamma, (255, 128, 0)),
+            (GAMMA_2_2[128], GAMMA_2_2[64], 0)
+        );
+    }
+
+    #[test]
+    fn current_limit_scales_channels_down() {
+        let mut mock = MockLed::default();
+        let limited = SmoothLed::new(&mut mock)
+            .with_gamma(None)
+            .with_current_limit(255);
+        assert_eq!(shown(limited, (255, 255, 255)), (85, 85, 85));
+
+        let mut limited = SmoothLed::new(&mut mock).with_gamma(None);
+        limited.set_current_limit(50);
+        // Below limit is untouched, above it keeps hue
+        assert_eq!(limited.corrected((255, 127, 0)), (255, 127, 0));
+        assert_eq!(limited.corrected((255, 255, 0)), (191, 191, 0));
+
+        limited.set_current_limit(200);
+        assert_eq!(limited.corrected((255, 255, 255)), (255, 255, 255));
+    }
+
+    #[test]
+    fn fade_steps_to_color() {
+        let mut mock = MockLed::default();
+        let mut led = SmoothLed::new(&mut mock).with_gamma(None);
+        block_on(led.fade_to((200, 100, 0), Duration::from_millis(80)));
+        assert_eq!(led.color(), (200, 100, 0));
+        drop(led);
+
+        assert_eq!(
+            mock.0,
+            [(50, 25, 0), (100, 50, 0), (150, 75, 0), (200, 100, 0)]
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use core::future::Future;
+
+pub trait LedStripAsync {
+    /// Count of pixels in strip
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Shows pixels, starting from the first one. Extra pixels are ignored, missing ones are off
+    fn write(&mut self, pixels: &[(u8, u8, u8)]) -> impl Future<Output = ()>;
+}
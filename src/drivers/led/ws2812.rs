@@ -1,59 +1,171 @@
+//!
+//! WS2812-compatible LEDs over RMT
+//!
+//! Channels are used in blocking mode: the driver refills channel memory while it
+//! sends, so a whole strip goes out as one transmission, however long it is.
+//!
+
 use defmt::error;
 use esp_hal::{
     gpio::Level,
-    rmt::{PulseCode, TxChannelAsync},
+    rmt::{Channel, Error, PulseCode, TxChannel, TxChannelInternal},
     time::Rate,
+    Blocking,
 };
 
-use crate::drivers::led::RgbLedAsync;
+use crate::drivers::led::{LedStripAsync, RgbLedAsync};
 
-pub struct WS2812<Chan> {
-    chan: Chan,
-    rate: Rate,
+/// Count of pulse codes to encode one pixel
+pub const CODES_PER_PIXEL: usize = 24;
+
+/// Longest strip, frames are sent with interrupts disabled for about 30 µs per pixel.
+/// 32 pixels keep it below 1 ms, so WiFi interrupts and embassy timers are not held
+/// back longer than that
+pub const MAX_STRIP_LEN: usize = 32;
+
+/// Bit timings in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Timing {
+    pub t0h_ns: u32,
+    pub t0l_ns: u32,
+    pub t1h_ns: u32,
+    pub t1l_ns: u32,
 }
 
-impl<Chan> WS2812<Chan>
-where
-    Chan: TxChannelAsync,
-{
-    fn get_length(&self, ns: u32) -> u16 {
-        let mhz = self.rate.as_mhz();
+impl Timing {
+    pub const WS2812: Timing = Timing {
+        t0h_ns: 350,
+        t0l_ns: 800,
+        t1h_ns: 700,
+        t1l_ns: 600,
+    };
+
+    pub const WS2812B: Timing = Timing {
+        t0h_ns: 400,
+        t0l_ns: 850,
+        t1h_ns: 800,
+        t1l_ns: 450,
+    };
+
+    pub const SK6812: Timing = Timing {
+        t0h_ns: 300,
+        t0l_ns: 900,
+        t1h_ns: 600,
+        t1l_ns: 600,
+    };
+}
+
+/// Order of color bytes on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ColorOrder {
+    Rgb,
+    Rbg,
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+impl ColorOrder {
+    fn arrange(&self, r: u8, g: u8, b: u8) -> [u8; 3] {
+        match self {
+            ColorOrder::Rgb => [r, g, b],
+            ColorOrder::Rbg => [r, b, g],
+            ColorOrder::Grb => [g, r, b],
+            ColorOrder::Gbr => [g, b, r],
+            ColorOrder::Brg => [b, r, g],
+            ColorOrder::Bgr => [b, g, r],
+        }
+    }
+}
+
+/// Converts colors into RMT pulse codes
+#[derive(Debug, Clone, Copy)]
+struct Encoder {
+    t0: u32,
+    t1: u32,
+    order: ColorOrder,
+}
+
+impl Encoder {
+    fn new(timing: Timing, order: ColorOrder, rate: Rate) -> Self {
+        let mhz = rate.as_mhz();
+        let length = |ns: u32| (ns * mhz / 1000) as u16;
+
+        Self {
+            t0: PulseCode::new(
+                Level::High,
+                length(timing.t0h_ns),
+                Level::Low,
+                length(timing.t0l_ns),
+            ),
+            t1: PulseCode::new(
+                Level::High,
+                length(timing.t1h_ns),
+                Level::Low,
+                length(timing.t1l_ns),
+            ),
+            order,
+        }
+    }
+
+    /// Writes [`CODES_PER_PIXEL`] codes of color into `out`
+    fn encode(&self, (r, g, b): (u8, u8, u8), out: &mut [u32]) {
+        let color = self
+            .order
+            .arrange(r, g, b)
+            .into_iter()
+            .fold(0_u32, |acc, byte| (acc << 8) | byte as u32);
+
+        for (i, code) in out[..CODES_PER_PIXEL].iter_mut().enumerate() {
+            let bit = color & (1 << (CODES_PER_PIXEL - 1 - i)) != 0;
+            *code = if bit { self.t1 } else { self.t0 };
+        }
+    }
+}
+
+/// Turns the last code into end marker, keeping its high level
+///
+/// Line stays low after the end, so the low level of the last bit is not needed.
+fn mark_end(code: &mut u32) {
+    *code = PulseCode::new(code.level1(), code.length1(), Level::Low, 0);
+}
+
+/// RMT channel, taken only while transmitting
+struct Transmitter<Raw: TxChannelInternal> {
+    /// Lost if transmission does not start, it starts for any data with end marker
+    chan: Option<Channel<Blocking, Raw>>,
+}
 
-        (ns * mhz / 1000) as u16
+impl<Raw: TxChannelInternal> Transmitter<Raw> {
+    /// Sends codes, returning when the last one is out
+    fn transmit(&mut self, data: &[u32]) -> Result<(), Error> {
+        let chan = self.chan.take().ok_or(Error::InvalidArgument)?;
+        let (result, chan) = match chan.transmit(data)?.wait() {
+            Ok(chan) => (Ok(()), chan),
+            Err((err, chan)) => (Err(err), chan),
+        };
+        self.chan = Some(chan);
+        result
     }
 }
 
-impl<Chan> RgbLedAsync for WS2812<Chan>
+pub struct WS2812<Raw: TxChannelInternal> {
+    chan: Transmitter<Raw>,
+    encoder: Encoder,
+}
+
+impl<Raw> RgbLedAsync for WS2812<Raw>
 where
-    Chan: TxChannelAsync,
+    Raw: TxChannelInternal,
 {
     async fn set_color(&mut self, r: u8, g: u8, b: u8) {
-        let t0: u32 = PulseCode::new(
-            Level::High,
-            self.get_length(350),
-            Level::Low,
-            self.get_length(800),
-        );
-        let t1: u32 = PulseCode::new(
-            Level::High,
-            self.get_length(700),
-            Level::Low,
-            self.get_length(600),
-        );
-
-        let mut data = [0_u32; 25];
-
-        let color: u32 = ((g as u32) << 16) | ((r as u32) << 8) | b as u32;
-        for i in (0..24).rev() {
-            let p = 2_u32.pow(i);
-            let bit = p & color != 0;
-            let pulse = if bit { t1 } else { t0 };
-            data[23 - i as usize] = pulse;
-        }
+        let mut data = [0_u32; CODES_PER_PIXEL];
 
-        data[24] = PulseCode::empty();
+        self.encoder.encode((r, g, b), &mut data);
+        mark_end(&mut data[CODES_PER_PIXEL - 1]);
 
-        if let Err(err) = self.chan.transmit(&data).await {
+        if let Err(err) = self.chan.transmit(&data) {
             error!("failed to set: {}", err);
         }
     }
@@ -65,12 +177,82 @@ where
 /// - `channel` - RMT channel to use
 /// - `rate` - base rate of rmt channel input_freq/prescaler_from_tx_cfg
 ///
-pub fn init<C>(channel: C, rate: Rate) -> WS2812<C>
+pub fn init<Raw>(channel: Channel<Blocking, Raw>, rate: Rate) -> WS2812<Raw>
 where
-    C: TxChannelAsync,
+    Raw: TxChannelInternal,
 {
     WS2812 {
-        chan: channel,
-        rate,
+        chan: Transmitter {
+            chan: Some(channel),
+        },
+        encoder: Encoder::new(Timing::WS2812, ColorOrder::Grb, rate),
+    }
+}
+
+/// Strip of `N` WS2812-compatible LEDs
+///
+/// Any gap in the data longer than strip reset time (50 µs for WS2812, 280 µs for
+/// WS2812B) latches a partial frame. The whole frame is sent in one transmission within
+/// critical section, so neither other tasks nor interrupts delay refills of channel
+/// memory. It blocks for 30 µs per pixel, so `N` is capped by [`MAX_STRIP_LEN`].
+pub struct WS2812Strip<Raw: TxChannelInternal, const N: usize> {
+    chan: Transmitter<Raw>,
+    encoder: Encoder,
+}
+
+impl<Raw, const N: usize> LedStripAsync for WS2812Strip<Raw, N>
+where
+    Raw: TxChannelInternal,
+{
+    fn len(&self) -> usize {
+        N
+    }
+
+    async fn write(&mut self, pixels: &[(u8, u8, u8)]) {
+        let mut data = [[0_u32; CODES_PER_PIXEL]; N];
+
+        // Pixels not given are off
+        let given = pixels.iter().copied().chain(core::iter::repeat((0, 0, 0)));
+        for (pixel, out) in given.zip(data.iter_mut()) {
+            self.encoder.encode(pixel, out);
+        }
+
+        let data = data.as_flattened_mut();
+        let Some(last) = data.last_mut() else {
+            return;
+        };
+        mark_end(last);
+
+        if let Err(err) = critical_section::with(|_| self.chan.transmit(data)) {
+            error!("strip: failed to write: {}", err);
+        }
+    }
+}
+
+/// Initializes strip of `N` WS2812-compatible LEDs
+///
+/// # Arguments
+/// - `channel` - RMT channel to use, the more memory blocks it has, the less often
+///   memory is refilled
+/// - `rate` - base rate of rmt channel input_freq/prescaler_from_tx_cfg
+/// - `timing` - bit timings of LEDs
+/// - `order` - color order of LEDs
+///
+pub fn init_strip<Raw, const N: usize>(
+    channel: Channel<Blocking, Raw>,
+    rate: Rate,
+    timing: Timing,
+    order: ColorOrder,
+) -> WS2812Strip<Raw, N>
+where
+    Raw: TxChannelInternal,
+{
+    const { assert!(N <= MAX_STRIP_LEN, "LED strip is longer than MAX_STRIP_LEN") };
+
+    WS2812Strip {
+        chan: Transmitter {
+            chan: Some(channel),
+        },
+        encoder: Encoder::new(timing, order, rate),
     }
 }
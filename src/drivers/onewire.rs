@@ -0,0 +1,514 @@
+//!
+//! Bit-banged 1-Wire bus at standard speed
+//!
+//! Every time slot runs in critical section, as timing is in microseconds.
+//! Bytes, addressing and ROM search are built on time slots of [`OneWire`],
+//! so they are tested against a simulated bus.
+//!
+
+use core::fmt::Write as _;
+
+#[cfg(target_arch = "riscv32")]
+use esp_hal::{
+    delay::Delay,
+    gpio::{DriveMode, Flex, InputConfig, OutputConfig, Pull},
+};
+use serde::Serialize;
+
+const CMD_SEARCH_ROM: u8 = 0xF0;
+const CMD_MATCH_ROM: u8 = 0x55;
+const CMD_SKIP_ROM: u8 = 0xCC;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// No device answered reset
+    NoPresence,
+    /// Line is held low
+    BusShorted,
+    /// Received data does not match its CRC
+    Crc,
+}
+
+/// 64-bit ROM id: family code in the lowest byte, 48-bit serial and CRC in the highest byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rom(pub u64);
+
+impl Rom {
+    pub fn family(&self) -> u8 {
+        self.0 as u8
+    }
+
+    /// Bytes in order of transmission
+    pub fn to_bytes(&self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    /// Formats as 16 hex digits, CRC first
+    pub fn to_hex(&self) -> heapless::String<16> {
+        let mut hex = heapless::String::new();
+        write!(hex, "{:016x}", self.0).ok();
+        hex
+    }
+}
+
+impl defmt::Format for Rom {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "{=u64:016x}", self.0)
+    }
+}
+
+impl Serialize for Rom {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+/// Calculates Dallas/Maxim CRC-8, zero over data with its CRC means valid
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0_u8;
+    for byte in data {
+        let mut byte = *byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 0x01;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            byte >>= 1;
+        }
+    }
+
+    crc
+}
+
+/// Progress of ROM search between calls of [`OneWireBus::search_next`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SearchState {
+    rom: u64,
+    /// Bit position of the last taken zero branch, counting from 1
+    last_discrepancy: u8,
+    finished: bool,
+}
+
+/// Time slots of 1-Wire bus, everything else is built on them
+pub trait OneWire {
+    /// Sends reset pulse
+    ///
+    /// # Returns
+    /// - Err(NoPresence), if no device answered
+    fn reset(&mut self) -> Result<(), Error>;
+
+    /// Drives line high with push-pull to power parasite devices during conversion
+    ///
+    /// Turned off by the next reset
+    fn strong_pullup(&mut self, on: bool);
+
+    fn write_bit(&mut self, bit: bool);
+
+    fn read_bit(&mut self) -> bool;
+
+    /// Writes byte, the least significant bit first
+    fn write_byte(&mut self, byte: u8) {
+        for bit in 0..8 {
+            self.write_bit(byte & (1 << bit) != 0);
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, bit| byte | (u8::from(self.read_bit()) << bit))
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_byte(*byte);
+        }
+    }
+
+    fn read_bytes(&mut self, bytes: &mut [u8]) {
+        for byte in bytes {
+            *byte = self.read_byte();
+        }
+    }
+
+    /// Resets bus and addresses one device, or all of them if `rom` is None
+    fn select(&mut self, rom: Option<Rom>) -> Result<(), Error> {
+        self.reset()?;
+        match rom {
+            Some(rom) => {
+                self.write_byte(CMD_MATCH_ROM);
+                self.write_bytes(&rom.to_bytes());
+            }
+            None => self.write_byte(CMD_SKIP_ROM),
+        }
+
+        Ok(())
+    }
+
+    /// Finds next device on bus, see Maxim application note 187
+    ///
+    /// # Returns
+    /// - None, when all devices found
+    fn search_next(&mut self, state: &mut SearchState) -> Result<Option<Rom>, Error> {
+        if state.finished {
+            return Ok(None);
+        }
+
+        match self.reset() {
+            Ok(()) => {}
+            Err(Error::NoPresence) => {
+                state.finished = true;
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        }
+        self.write_byte(CMD_SEARCH_ROM);
+
+        let mut last_zero = 0;
+        for bit in 1..=64_u8 {
+            let id_bit = self.read_bit();
+            let complement_bit = self.read_bit();
+
+            let direction = match (id_bit, complement_bit) {
+                // Nobody answered, e.g. device gone during search
+                (true, true) => {
+                    state.finished = true;
+                    return Ok(None);
+                }
+                // Discrepancy, devices with both values present
+                (false, false) => {
+                    let direction = if bit < state.last_discrepancy {
+                        state.rom & (1 << (bit - 1)) != 0
+                    } else {
+                        bit == state.last_discrepancy
+                    };
+                    if !direction {
+                        last_zero = bit;
+                    }
+                    direction
+                }
+                // All devices have the same bit
+                (id_bit, _) => id_bit,
+            };
+
+            if direction {
+                state.rom |= 1 << (bit - 1);
+            } else {
+                state.rom &= !(1 << (bit - 1));
+            }
+            self.write_bit(direction);
+        }
+
+        state.last_discrepancy = last_zero;
+        state.finished = last_zero == 0;
+
+        let rom = Rom(state.rom);
+        if crc8(&rom.to_bytes()) != 0 {
+            return Err(Error::Crc);
+        }
+
+        Ok(Some(rom))
+    }
+
+    /// Finds devices on bus, the ones over capacity are ignored
+    fn search<const N: usize>(&mut self) -> Result<heapless::Vec<Rom, N>, Error> {
+        let mut state = SearchState::default();
+        let mut roms = heapless::Vec::new();
+        while let Some(rom) = self.search_next(&mut state)? {
+            if roms.push(rom).is_err() {
+                break;
+            }
+        }
+
+        Ok(roms)
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+pub struct OneWireBus {
+    pin: Flex<'static>,
+    delay: Delay,
+    out_config: OutputConfig,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl OneWireBus {
+    /// Configures pin as open-drain with pull-up, external 4.7k pull-up is still needed
+    pub fn new(mut pin: Flex<'static>) -> Self {
+        let out_config = OutputConfig::default()
+            .with_drive_mode(DriveMode::OpenDrain)
+            .with_pull(Pull::Up);
+        pin.apply_output_config(&out_config);
+        pin.apply_input_config(&InputConfig::default().with_pull(Pull::Up));
+        pin.set_high();
+        pin.set_output_enable(true);
+        pin.set_input_enable(true);
+
+        Self {
+            pin,
+            delay: Delay::new(),
+            out_config,
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl OneWire for OneWireBus {
+    fn reset(&mut self) -> Result<(), Error> {
+        self.strong_pullup(false);
+        if self.pin.is_low() {
+            return Err(Error::BusShorted);
+        }
+
+        let presence = critical_section::with(|_| {
+            self.pin.set_low();
+            self.delay.delay_micros(480);
+            self.pin.set_high();
+            self.delay.delay_micros(70);
+            self.pin.is_low()
+        });
+        self.delay.delay_micros(410);
+
+        if presence {
+            Ok(())
+        } else {
+            Err(Error::NoPresence)
+        }
+    }
+
+    fn strong_pullup(&mut self, on: bool) {
+        let drive_mode = if on {
+            DriveMode::PushPull
+        } else {
+            DriveMode::OpenDrain
+        };
+        self.pin
+            .apply_output_config(&self.out_config.with_drive_mode(drive_mode));
+        self.pin.set_high();
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        critical_section::with(|_| {
+            self.pin.set_low();
+            if bit {
+                self.delay.delay_micros(6);
+                self.pin.set_high();
+                self.delay.delay_micros(64);
+            } else {
+                self.delay.delay_micros(60);
+                self.pin.set_high();
+                self.delay.delay_micros(10);
+            }
+        })
+    }
+
+    fn read_bit(&mut self) -> bool {
+        let bit = critical_section::with(|_| {
+            self.pin.set_low();
+            self.delay.delay_micros(6);
+            self.pin.set_high();
+            self.delay.delay_micros(9);
+            self.pin.is_high()
+        });
+        self.delay.delay_micros(55);
+
+        bit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// Maxim application note 27 example ROM
+    const ROM: Rom = Rom(0xA200_0000_01B8_1C02);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Phase {
+        /// Receiving command byte
+        Command {
+            bits: u8,
+            byte: u8,
+        },
+        /// Search of ROM bit, devices send it, then its complement, then master
+        /// writes direction
+        Search {
+            bit: u8,
+            step: u8,
+        },
+        Other,
+    }
+
+    /// Open-drain bus with devices answering ROM search
+    struct SimulatedBus {
+        roms: Vec<u64>,
+        /// Devices still taking part in search
+        taking_part: Vec<bool>,
+        phase: Phase,
+        written: Vec<bool>,
+    }
+
+    impl SimulatedBus {
+        fn new(roms: &[u64]) -> Self {
+            Self {
+                roms: roms.to_vec(),
+                taking_part: Vec::new(),
+                phase: Phase::Other,
+                written: Vec::new(),
+            }
+        }
+
+        /// Wired-AND of bits sent by devices, line stays high if nobody pulls it
+        fn line(&self, bit: u8, complement: bool) -> bool {
+            self.roms
+                .iter()
+                .zip(&self.taking_part)
+                .filter(|(_, taking_part)| **taking_part)
+                .all(|(rom, _)| (rom & (1 << bit) != 0) != complement)
+        }
+
+        fn written_bytes(&self) -> Vec<u8> {
+            self.written
+                .chunks(8)
+                .map(|bits| {
+                    bits.iter()
+                        .enumerate()
+                        .fold(0, |byte, (idx, bit)| byte | (u8::from(*bit) << idx))
+                })
+                .collect()
+        }
+    }
+
+    impl OneWire for SimulatedBus {
+        fn reset(&mut self) -> Result<(), Error> {
+            self.taking_part = std::vec![true; self.roms.len()];
+            self.phase = Phase::Command { bits: 0, byte: 0 };
+            self.written.clear();
+            if self.roms.is_empty() {
+                Err(Error::NoPresence)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn strong_pullup(&mut self, _on: bool) {}
+
+        fn write_bit(&mut self, value: bool) {
+            self.written.push(value);
+            self.phase = match self.phase {
+                Phase::Command { bits: 7, byte } => {
+                    if byte | (u8::from(value) << 7) == CMD_SEARCH_ROM {
+                        Phase::Search { bit: 0, step: 0 }
+                    } else {
+                        Phase::Other
+                    }
+                }
+                Phase::Command { bits, byte } => Phase::Command {
+                    bits: bits + 1,
+                    byte: byte | (u8::from(value) << bits),
+                },
+                Phase::Search { bit, step: 2 } => {
+                    for (rom, taking_part) in self.roms.iter().zip(&mut self.taking_part) {
+                        *taking_part &= (rom & (1 << bit) != 0) == value;
+                    }
+                    Phase::Search {
+                        bit: bit + 1,
+                        step: 0,
+                    }
+                }
+                phase => phase,
+            };
+        }
+
+        fn read_bit(&mut self) -> bool {
+            match self.phase {
+                Phase::Search { bit, step } if step < 2 => {
+                    self.phase = Phase::Search {
+                        bit,
+                        step: step + 1,
+                    };
+                    self.line(bit, step == 1)
+                }
+                _ => true,
+            }
+        }
+    }
+
+    /// ROM with serial number given and valid CRC
+    fn rom(family: u8, serial: u64) -> u64 {
+        let bytes = (serial << 8 | family as u64).to_le_bytes();
+        (crc8(&bytes[..7]) as u64) << 56 | serial << 8 | family as u64
+    }
+
+    #[test]
+    fn crc8_matches_maxim_example() {
+        let bytes = ROM.to_bytes();
+        assert_eq!(crc8(&bytes[..7]), 0xA2);
+        assert_eq!(crc8(&bytes), 0);
+        assert_eq!(crc8(&[]), 0);
+
+        let mut corrupted = bytes;
+        corrupted[3] ^= 0x10;
+        assert_ne!(crc8(&corrupted), 0);
+    }
+
+    #[test]
+    fn rom_parts_and_hex() {
+        assert_eq!(ROM.family(), 0x02);
+        assert_eq!(ROM.to_bytes(), [0x02, 0x1C, 0xB8, 0x01, 0, 0, 0, 0xA2]);
+        assert_eq!(ROM.to_hex(), "a200000001b81c02");
+    }
+
+    #[test]
+    fn search_finds_every_device() {
+        let roms = [
+            rom(0x28, 0x0000_0000_0001),
+            rom(0x28, 0x0000_0000_0002),
+            rom(0x28, 0x8000_0000_0003),
+            rom(0x10, 0x1234_5678_9ABC),
+            ROM.0,
+        ];
+        let mut bus = SimulatedBus::new(&roms);
+
+        let mut found: Vec<u64> = bus.search::<8>().unwrap().iter().map(|rom| rom.0).collect();
+        found.sort();
+        let mut expected = roms.to_vec();
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn search_stops_at_capacity() {
+        let roms = [rom(0x28, 1), rom(0x28, 2), rom(0x28, 3)];
+        let mut bus = SimulatedBus::new(&roms);
+        assert_eq!(bus.search::<2>().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn search_of_empty_bus_finds_nothing() {
+        let mut bus = SimulatedBus::new(&[]);
+        assert!(bus.search::<4>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_rejects_rom_with_bad_crc() {
+        let mut bus = SimulatedBus::new(&[ROM.0 ^ 0x0100]);
+        assert_eq!(bus.search::<4>(), Err(Error::Crc));
+    }
+
+    #[test]
+    fn select_addresses_one_or_all() {
+        let mut bus = SimulatedBus::new(&[ROM.0]);
+        bus.select(Some(ROM)).unwrap();
+        let mut expected = std::vec![CMD_MATCH_ROM];
+        expected.extend(ROM.to_bytes());
+        assert_eq!(bus.written_bytes(), expected);
+
+        bus.select(None).unwrap();
+        assert_eq!(bus.written_bytes(), [CMD_SKIP_ROM]);
+
+        assert_eq!(SimulatedBus::new(&[]).select(None), Err(Error::NoPresence));
+    }
+}
@@ -1,4 +1,13 @@
 pub mod temperature;
 
+pub mod bme280;
+#[cfg(target_arch = "riscv32")]
 pub mod dht22;
+pub mod ds18b20;
 pub mod lm75b;
+pub mod scd4x;
+pub mod sensirion;
+pub mod sht3x;
+pub mod sht4x;
+#[cfg(target_arch = "riscv32")]
+pub mod tsens;
@@ -0,0 +1,601 @@
+//!
+//! Bosch BME280 humidity, pressure and temperature sensor, and BMP280 without humidity
+//!
+//! Compensation uses integer formulas from BME280 datasheet, section 4.2.3
+//!
+
+use embassy_time::Timer;
+
+use crate::drivers::sensors::temperature::TemperatureSensorAsync;
+
+/// Address with SDO pin low, 0x77 if high
+pub const DEFAULT_ADDRESS: u8 = 0x76;
+
+const REG_CALIBRATION_TP: u8 = 0x88;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_RESET: u8 = 0xE0;
+const REG_CALIBRATION_H: u8 = 0xE1;
+const REG_CTRL_HUM: u8 = 0xF2;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_CONFIG: u8 = 0xF5;
+const REG_DATA: u8 = 0xF7;
+
+const RESET_COMMAND: u8 = 0xB6;
+
+/// Raw value of skipped measurement
+const SKIPPED: i32 = 0x80000;
+const SKIPPED_HUMIDITY: i32 = 0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error<E> {
+    I2c(E),
+    /// Chip ID is not of BME280 or BMP280
+    UnknownChip(u8),
+    /// Calibration not read yet, see [`Bme280::init`]
+    NotInitialized,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(value: E) -> Self {
+        Error::I2c(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Chip {
+    Bmp280,
+    Bme280,
+}
+
+impl Chip {
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            // Samples and mass production
+            0x56..=0x58 => Some(Chip::Bmp280),
+            0x60 => Some(Chip::Bme280),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Oversampling {
+    /// Measurement is not done
+    Skip = 0,
+    #[default]
+    X1 = 1,
+    X2 = 2,
+    X4 = 3,
+    X8 = 4,
+    X16 = 5,
+}
+
+impl Oversampling {
+    fn samples(&self) -> u32 {
+        match self {
+            Oversampling::Skip => 0,
+            other => 1 << (*other as u32 - 1),
+        }
+    }
+}
+
+/// IIR filter coefficient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Filter {
+    #[default]
+    Off = 0,
+    X2 = 1,
+    X4 = 2,
+    X8 = 3,
+    X16 = 4,
+}
+
+/// Inactive time between measurements in normal mode
+///
+/// Values 10ms and 20ms are 2000ms and 4000ms on BMP280
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Standby {
+    Ms0_5 = 0,
+    Ms62_5 = 1,
+    Ms125 = 2,
+    Ms250 = 3,
+    Ms500 = 4,
+    #[default]
+    Ms1000 = 5,
+    Ms10 = 6,
+    Ms20 = 7,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Mode {
+    #[default]
+    Sleep = 0b00,
+    /// One measurement, then back to sleep
+    Forced = 0b01,
+    /// Continuous measurements with standby between them
+    Normal = 0b11,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Config {
+    pub temperature: Oversampling,
+    pub pressure: Oversampling,
+    /// Ignored by BMP280
+    pub humidity: Oversampling,
+    pub filter: Filter,
+    pub standby: Standby,
+}
+
+impl Config {
+    /// Max duration of one measurement in µs, datasheet appendix B
+    pub fn measurement_time_us(&self) -> u32 {
+        let mut time = 1250 + 2300 * self.temperature.samples();
+        if self.pressure != Oversampling::Skip {
+            time += 2300 * self.pressure.samples() + 575;
+        }
+        if self.humidity != Oversampling::Skip {
+            time += 2300 * self.humidity.samples() + 575;
+        }
+
+        time
+    }
+}
+
+/// Factory calibration stored in sensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Calibration {
+    pub t1: u16,
+    pub t2: i16,
+    pub t3: i16,
+    pub p1: u16,
+    pub p2: i16,
+    pub p3: i16,
+    pub p4: i16,
+    pub p5: i16,
+    pub p6: i16,
+    pub p7: i16,
+    pub p8: i16,
+    pub p9: i16,
+    pub h1: u8,
+    pub h2: i16,
+    pub h3: u8,
+    pub h4: i16,
+    pub h5: i16,
+    pub h6: i8,
+}
+
+impl Calibration {
+    /// Parses calibration registers
+    ///
+    /// # Arguments
+    /// - `tp` - registers 0x88..=0xA1
+    /// - `h` - registers 0xE1..=0xE7, zeros for BMP280
+    pub fn parse(tp: &[u8; 26], h: &[u8; 7]) -> Self {
+        let u16_at = |idx: usize| u16::from_le_bytes([tp[idx], tp[idx + 1]]);
+        let i16_at = |idx: usize| i16::from_le_bytes([tp[idx], tp[idx + 1]]);
+
+        Self {
+            t1: u16_at(0),
+            t2: i16_at(2),
+            t3: i16_at(4),
+            p1: u16_at(6),
+            p2: i16_at(8),
+            p3: i16_at(10),
+            p4: i16_at(12),
+            p5: i16_at(14),
+            p6: i16_at(16),
+            p7: i16_at(18),
+            p8: i16_at(20),
+            p9: i16_at(22),
+            h1: tp[25],
+            h2: i16::from_le_bytes([h[0], h[1]]),
+            h3: h[2],
+            // 12-bit values sharing byte 0xE5
+            h4: (i16::from(h[3] as i8) << 4) | i16::from(h[4] & 0x0F),
+            h5: (i16::from(h[5] as i8) << 4) | i16::from(h[4] >> 4),
+            h6: h[6] as i8,
+        }
+    }
+
+    /// Compensates raw temperature
+    ///
+    /// # Returns
+    /// Temperature in 0.01 °C and fine temperature used by other compensations
+    pub fn temperature(&self, adc: i32) -> (i32, i32) {
+        let t1 = i32::from(self.t1);
+        let var1 = (((adc >> 3) - (t1 << 1)) * i32::from(self.t2)) >> 11;
+        let var2 = (((((adc >> 4) - t1) * ((adc >> 4) - t1)) >> 12) * i32::from(self.t3)) >> 14;
+        let t_fine = var1 + var2;
+
+        ((t_fine * 5 + 128) >> 8, t_fine)
+    }
+
+    /// Compensates raw pressure
+    ///
+    /// # Returns
+    /// Pressure in Pa as Q24.8 fixed point, e.g. 24674867 is 96386.2 Pa
+    pub fn pressure(&self, adc: i32, t_fine: i32) -> u32 {
+        let mut var1 = i64::from(t_fine) - 128000;
+        let mut var2 = var1 * var1 * i64::from(self.p6);
+        var2 += (var1 * i64::from(self.p5)) << 17;
+        var2 += i64::from(self.p4) << 35;
+        var1 = ((var1 * var1 * i64::from(self.p3)) >> 8) + ((var1 * i64::from(self.p2)) << 12);
+        var1 = (((1_i64 << 47) + var1) * i64::from(self.p1)) >> 33;
+        if var1 == 0 {
+            // Avoid division by zero
+            return 0;
+        }
+
+        let mut p = 1048576 - i64::from(adc);
+        p = (((p << 31) - var2) * 3125) / var1;
+        var1 = (i64::from(self.p9) * (p >> 13) * (p >> 13)) >> 25;
+        var2 = (i64::from(self.p8) * p) >> 19;
+        p = ((p + var1 + var2) >> 8) + (i64::from(self.p7) << 4);
+
+        p as u32
+    }
+
+    /// Compensates raw humidity
+    ///
+    /// # Returns
+    /// Humidity in % as Q22.10 fixed point, e.g. 47445 is 46.333 %
+    pub fn humidity(&self, adc: i32, t_fine: i32) -> u32 {
+        let mut v = t_fine - 76800;
+        v = ((((adc << 14) - (i32::from(self.h4) << 20) - (i32::from(self.h5) * v)) + 16384) >> 15)
+            * (((((((v * i32::from(self.h6)) >> 10)
+                * (((v * i32::from(self.h3)) >> 11) + 32768))
+                >> 10)
+                + 2097152)
+                * i32::from(self.h2)
+                + 8192)
+                >> 14);
+        v -= ((((v >> 15) * (v >> 15)) >> 7) * i32::from(self.h1)) >> 4;
+        v = v.clamp(0, 419430400);
+
+        (v >> 12) as u32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Measurement {
+    /// In °C
+    pub temperature: f32,
+    /// In hPa, None if skipped
+    pub pressure: Option<f32>,
+    /// In %, None if skipped or BMP280
+    pub humidity: Option<f32>,
+}
+
+impl Measurement {
+    /// Compensates raw data registers 0xF7..=0xFE, BMP280 has no humidity registers
+    ///
+    /// # Returns
+    /// None, if temperature is skipped, as other compensations depend on it
+    pub fn compensate(calibration: &Calibration, data: &[u8; 8]) -> Option<Self> {
+        let raw20 = |idx: usize| {
+            (i32::from(data[idx]) << 12)
+                | (i32::from(data[idx + 1]) << 4)
+                | (i32::from(data[idx + 2]) >> 4)
+        };
+        let adc_p = raw20(0);
+        let adc_t = raw20(3);
+        let adc_h = (i32::from(data[6]) << 8) | i32::from(data[7]);
+
+        if adc_t == SKIPPED {
+            return None;
+        }
+
+        let (temperature, t_fine) = calibration.temperature(adc_t);
+        let pressure =
+            (adc_p != SKIPPED).then(|| calibration.pressure(adc_p, t_fine) as f32 / 256.0 / 100.0);
+        let humidity = (adc_h != SKIPPED_HUMIDITY)
+            .then(|| calibration.humidity(adc_h, t_fine) as f32 / 1024.0);
+
+        Some(Self {
+            temperature: temperature as f32 / 100.0,
+            pressure,
+            humidity,
+        })
+    }
+}
+
+pub struct Bme280<I2C> {
+    i2c: I2C,
+    address: u8,
+    chip: Chip,
+    config: Config,
+    calibration: Option<Calibration>,
+}
+
+impl<I2C> Bme280<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Self {
+            i2c,
+            address,
+            chip: Chip::Bme280,
+            config: Config::default(),
+            calibration: None,
+        }
+    }
+
+    /// Detected chip, valid after [`Self::init`]
+    pub fn chip(&self) -> Chip {
+        self.chip
+    }
+}
+
+impl<I2C> Bme280<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    /// Resets sensor, detects chip and reads calibration
+    ///
+    /// Sensor stays in sleep mode with skipped measurements until [`Self::configure`]
+    pub async fn init(&mut self) -> Result<Chip, Error<I2C::Error>> {
+        let id = self.read_register(REG_CHIP_ID).await?;
+        self.chip = Chip::from_id(id).ok_or(Error::UnknownChip(id))?;
+
+        self.write_register(REG_RESET, RESET_COMMAND).await?;
+        // Start-up time
+        Timer::after_millis(2).await;
+
+        let mut tp = [0_u8; 26];
+        self.i2c
+            .write_read(self.address, &[REG_CALIBRATION_TP], &mut tp)
+            .await?;
+        let mut h = [0_u8; 7];
+        if self.chip == Chip::Bme280 {
+            self.i2c
+                .write_read(self.address, &[REG_CALIBRATION_H], &mut h)
+                .await?;
+        }
+
+        self.calibration = Some(Calibration::parse(&tp, &h));
+        self.config = Config {
+            temperature: Oversampling::Skip,
+            pressure: Oversampling::Skip,
+            humidity: Oversampling::Skip,
+            ..Default::default()
+        };
+
+        Ok(self.chip)
+    }
+
+    /// Applies configuration and mode
+    pub async fn configure(&mut self, config: Config, mode: Mode) -> Result<(), Error<I2C::Error>> {
+        self.config = config;
+
+        // Config is written in sleep mode only, otherwise it can be ignored
+        self.set_mode(Mode::Sleep).await?;
+        self.write_register(
+            REG_CONFIG,
+            ((config.standby as u8) << 5) | ((config.filter as u8) << 2),
+        )
+        .await?;
+        if self.chip == Chip::Bme280 {
+            // Takes effect after ctrl_meas write
+            self.write_register(REG_CTRL_HUM, config.humidity as u8)
+                .await?;
+        }
+        self.set_mode(mode).await
+    }
+
+    pub async fn set_mode(&mut self, mode: Mode) -> Result<(), Error<I2C::Error>> {
+        let ctrl_meas = ((self.config.temperature as u8) << 5)
+            | ((self.config.pressure as u8) << 2)
+            | mode as u8;
+        self.write_register(REG_CTRL_MEAS, ctrl_meas).await
+    }
+
+    /// Triggers forced measurement, waits for it and reads result
+    pub async fn measure(&mut self) -> Result<Option<Measurement>, Error<I2C::Error>> {
+        self.set_mode(Mode::Forced).await?;
+        Timer::after_micros(u64::from(self.config.measurement_time_us())).await;
+        self.read().await
+    }
+
+    /// Reads the latest measurement, e.g. in normal mode
+    ///
+    /// # Returns
+    /// - None, if temperature measurement is skipped
+    pub async fn read(&mut self) -> Result<Option<Measurement>, Error<I2C::Error>> {
+        let calibration = self.calibration.ok_or(Error::NotInitialized)?;
+
+        let mut data = [0_u8; 8];
+        self.i2c
+            .write_read(self.address, &[REG_DATA], &mut data)
+            .await?;
+
+        let measurement = Measurement::compensate(&calibration, &data);
+        Ok(measurement.map(|measurement| match self.chip {
+            Chip::Bme280 => measurement,
+            Chip::Bmp280 => Measurement {
+                humidity: None,
+                ..measurement
+            },
+        }))
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, I2C::Error> {
+        let mut value = [0_u8];
+        self.i2c
+            .write_read(self.address, &[register], &mut value)
+            .await?;
+        Ok(value[0])
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), Error<I2C::Error>> {
+        self.i2c.write(self.address, &[register, value]).await?;
+        Ok(())
+    }
+}
+
+impl<I2C> TemperatureSensorAsync for Bme280<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    type ReadTemp = Result<Option<f32>, Error<I2C::Error>>;
+
+    async fn read_temperature(&mut self) -> Self::ReadTemp {
+        Ok(self
+            .measure()
+            .await?
+            .map(|measurement| measurement.temperature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::drivers::i2c::mock::{MockI2c, Transaction};
+
+    /// Registers 0x88..=0xA1 of the BMP280 datasheet example, section 3.12, and H1 = 75
+    const TP: [u8; 26] = [
+        0x70, 0x6B, 0x43, 0x67, 0x18, 0xFC, 0x7D, 0x8E, 0x43, 0xD6, 0xD0, 0x0B, 0x27, 0x0B, 0x8C,
+        0x00, 0xF9, 0xFF, 0x8C, 0x3C, 0xF8, 0xC6, 0x70, 0x17, 0x00, 0x4B,
+    ];
+    /// Registers 0xE1..=0xE7 with H2 = 362, H3 = 0, H4 = 313, H5 = 50, H6 = 30
+    const H: [u8; 7] = [0x6A, 0x01, 0x00, 0x13, 0x29, 0x03, 0x1E];
+    /// adc_P = 415148, adc_T = 519888, adc_H = 30000
+    const DATA: [u8; 8] = [0x65, 0x5A, 0xC0, 0x7E, 0xED, 0x00, 0x75, 0x30];
+
+    fn calibration() -> Calibration {
+        Calibration::parse(&TP, &H)
+    }
+
+    #[test]
+    fn parses_calibration() {
+        let calibration = calibration();
+        assert_eq!(
+            (calibration.t1, calibration.t2, calibration.t3),
+            (27504, 26435, -1000)
+        );
+        assert_eq!(
+            [
+                calibration.p2,
+                calibration.p3,
+                calibration.p4,
+                calibration.p5,
+                calibration.p6,
+                calibration.p7,
+                calibration.p8,
+                calibration.p9
+            ],
+            [-10685, 3024, 2855, 140, -7, 15500, -14600, 6000]
+        );
+        assert_eq!(calibration.p1, 36477);
+        assert_eq!(
+            (
+                calibration.h1,
+                calibration.h2,
+                calibration.h3,
+                calibration.h4,
+                calibration.h5,
+                calibration.h6
+            ),
+            (75, 362, 0, 313, 50, 30)
+        );
+    }
+
+    #[test]
+    fn parses_negative_humidity_calibration() {
+        // H4 = -100 and H5 = -200 share the nibbles of 0xE5
+        let calibration = Calibration::parse(&TP, &[0, 0, 0, 0xF9, 0x8C, 0xF3, 0xF6]);
+        assert_eq!(calibration.h4, -100);
+        assert_eq!(calibration.h5, -200);
+        assert_eq!(calibration.h6, -10);
+    }
+
+    #[test]
+    fn compensates_temperature_of_datasheet() {
+        assert_eq!(calibration().temperature(519888), (2508, 128422));
+    }
+
+    #[test]
+    fn compensates_pressure_of_datasheet() {
+        // 100653.27 Pa by floating point formula
+        let pressure = calibration().pressure(415148, 128422) as f32 / 256.0;
+        assert!((pressure - 100653.27).abs() < 0.5, "{pressure}");
+    }
+
+    #[test]
+    fn compensates_humidity() {
+        // 55.0007 % by floating point formula of datasheet, section 8.1
+        let humidity = calibration().humidity(30000, 128422) as f32 / 1024.0;
+        assert!((humidity - 55.0).abs() < 0.01, "{humidity}");
+    }
+
+    #[test]
+    fn humidity_stays_in_range() {
+        let calibration = calibration();
+        assert_eq!(calibration.humidity(0, 128422), 0);
+        assert_eq!(calibration.humidity(0xFFFF, 128422), 100 * 1024);
+    }
+
+    #[test]
+    fn compensates_data_registers() {
+        let measurement = Measurement::compensate(&calibration(), &DATA).unwrap();
+        assert_eq!(measurement.temperature, 25.08);
+        assert!((measurement.pressure.unwrap() - 1006.53).abs() < 0.01);
+        assert!((measurement.humidity.unwrap() - 55.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn skipped_measurements_are_none() {
+        let calibration = calibration();
+        let skipped = [0x80, 0x00, 0x00, 0x7E, 0xED, 0x00, 0x80, 0x00];
+        let measurement = Measurement::compensate(&calibration, &skipped).unwrap();
+        assert_eq!(measurement.pressure, None);
+        assert_eq!(measurement.humidity, None);
+
+        let no_temperature = [0x65, 0x5A, 0xC0, 0x80, 0x00, 0x00, 0x75, 0x30];
+        assert_eq!(Measurement::compensate(&calibration, &no_temperature), None);
+    }
+
+    #[test]
+    fn measurement_time_of_datasheet() {
+        let config = Config {
+            temperature: Oversampling::X1,
+            pressure: Oversampling::X1,
+            humidity: Oversampling::X1,
+            ..Default::default()
+        };
+        // 9.3 ms max for weather monitoring, datasheet section 9.1
+        assert_eq!(config.measurement_time_us(), 9300);
+    }
+
+    #[test]
+    fn bmp280_reads_without_humidity() {
+        let script = [
+            Transaction::write_read(DEFAULT_ADDRESS, &[0xD0], &[0x58]),
+            Transaction::write(DEFAULT_ADDRESS, &[0xE0, 0xB6]),
+            Transaction::write_read(DEFAULT_ADDRESS, &[0x88], &TP),
+            Transaction::write_read(DEFAULT_ADDRESS, &[0xF7], &DATA),
+        ];
+        let mut sensor = Bme280::new(MockI2c::new(&script), DEFAULT_ADDRESS);
+
+        let measurement = block_on(async {
+            assert_eq!(sensor.init().await, Ok(Chip::Bmp280));
+            sensor.read().await
+        })
+        .unwrap()
+        .unwrap();
+        sensor.i2c.done();
+
+        assert_eq!(measurement.temperature, 25.08);
+        assert!(measurement.pressure.is_some());
+        assert_eq!(measurement.humidity, None);
+    }
+
+    #[test]
+    fn rejects_unknown_chip() {
+        let script = [Transaction::write_read(DEFAULT_ADDRESS, &[0xD0], &[0x61])];
+        let mut sensor = Bme280::new(MockI2c::new(&script), DEFAULT_ADDRESS);
+
+        assert_eq!(block_on(sensor.init()), Err(Error::UnknownChip(0x61)));
+        assert_eq!(block_on(sensor.read()), Err(Error::NotInitialized));
+        sensor.i2c.done();
+    }
+}
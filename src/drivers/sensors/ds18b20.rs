@@ -0,0 +1,290 @@
+//!
+//! Maxim DS18B20 1-Wire temperature probe
+//!
+//! Many probes share one bus, so the bus is passed to every call
+//!
+
+use embassy_time::Timer;
+
+use crate::drivers::onewire::{crc8, Error, OneWire, Rom};
+
+pub const FAMILY_CODE: u8 = 0x28;
+
+const CMD_CONVERT_T: u8 = 0x44;
+const CMD_WRITE_SCRATCHPAD: u8 = 0x4E;
+const CMD_READ_SCRATCHPAD: u8 = 0xBE;
+const CMD_COPY_SCRATCHPAD: u8 = 0x48;
+const CMD_READ_POWER_SUPPLY: u8 = 0xB4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Resolution {
+    /// 0.5 °C
+    Bits9,
+    /// 0.25 °C
+    Bits10,
+    /// 0.125 °C
+    Bits11,
+    /// 0.0625 °C, power-up default
+    #[default]
+    Bits12,
+}
+
+impl Resolution {
+    fn from_config(config: u8) -> Self {
+        match (config >> 5) & 0b11 {
+            0 => Resolution::Bits9,
+            1 => Resolution::Bits10,
+            2 => Resolution::Bits11,
+            _ => Resolution::Bits12,
+        }
+    }
+
+    fn config(&self) -> u8 {
+        ((*self as u8) << 5) | 0x1F
+    }
+
+    /// Max conversion time in ms
+    pub fn conversion_ms(&self) -> u64 {
+        match self {
+            Resolution::Bits9 => 94,
+            Resolution::Bits10 => 188,
+            Resolution::Bits11 => 375,
+            Resolution::Bits12 => 750,
+        }
+    }
+
+    /// Mask of defined bits of temperature register
+    fn mask(&self) -> i16 {
+        !((1 << (3 - *self as i16)) - 1)
+    }
+}
+
+/// Decoded scratchpad memory
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Scratchpad {
+    /// In °C
+    pub temperature: f32,
+    /// Alarm high threshold in °C, or user byte
+    pub alarm_high: i8,
+    /// Alarm low threshold in °C, or user byte
+    pub alarm_low: i8,
+    pub resolution: Resolution,
+}
+
+impl Scratchpad {
+    /// Decodes scratchpad with its CRC
+    pub fn decode(data: &[u8; 9]) -> Result<Self, Error> {
+        if crc8(data) != 0 {
+            return Err(Error::Crc);
+        }
+
+        let resolution = Resolution::from_config(data[4]);
+        let raw = i16::from_le_bytes([data[0], data[1]]) & resolution.mask();
+
+        Ok(Self {
+            temperature: f32::from(raw) / 16.0,
+            alarm_high: data[2] as i8,
+            alarm_low: data[3] as i8,
+            resolution,
+        })
+    }
+}
+
+/// Checks whatever any device on bus is parasite powered
+pub fn any_parasite_powered(bus: &mut impl OneWire) -> Result<bool, Error> {
+    bus.select(None)?;
+    bus.write_byte(CMD_READ_POWER_SUPPLY);
+    // Parasite powered devices pull the line low
+    Ok(!bus.read_bit())
+}
+
+/// Starts conversion on all probes at once
+///
+/// # Arguments
+/// - `parasite` - hold line high during conversion to power parasite devices
+pub fn start_conversion_all(bus: &mut impl OneWire, parasite: bool) -> Result<(), Error> {
+    bus.select(None)?;
+    bus.write_byte(CMD_CONVERT_T);
+    if parasite {
+        bus.strong_pullup(true);
+    }
+
+    Ok(())
+}
+
+pub struct Ds18b20 {
+    rom: Rom,
+    resolution: Resolution,
+    parasite: bool,
+}
+
+impl Ds18b20 {
+    /// # Arguments
+    /// - `parasite` - probe is powered from data line, see [`any_parasite_powered`]
+    pub fn new(rom: Rom, parasite: bool) -> Self {
+        Self {
+            rom,
+            resolution: Resolution::default(),
+            parasite,
+        }
+    }
+
+    pub fn rom(&self) -> Rom {
+        self.rom
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Starts conversion and waits for it
+    pub async fn measure(&mut self, bus: &mut impl OneWire) -> Result<f32, Error> {
+        self.start_conversion(bus)?;
+        Timer::after_millis(self.resolution.conversion_ms()).await;
+        self.read_temperature(bus)
+    }
+
+    pub fn start_conversion(&mut self, bus: &mut impl OneWire) -> Result<(), Error> {
+        bus.select(Some(self.rom))?;
+        bus.write_byte(CMD_CONVERT_T);
+        if self.parasite {
+            bus.strong_pullup(true);
+        }
+
+        Ok(())
+    }
+
+    /// Reads result of the last conversion
+    pub fn read_temperature(&mut self, bus: &mut impl OneWire) -> Result<f32, Error> {
+        Ok(self.read_scratchpad(bus)?.temperature)
+    }
+
+    pub fn read_scratchpad(&mut self, bus: &mut impl OneWire) -> Result<Scratchpad, Error> {
+        bus.select(Some(self.rom))?;
+        bus.write_byte(CMD_READ_SCRATCHPAD);
+
+        let mut data = [0_u8; 9];
+        bus.read_bytes(&mut data);
+        let scratchpad = Scratchpad::decode(&data)?;
+        self.resolution = scratchpad.resolution;
+
+        Ok(scratchpad)
+    }
+
+    /// Sets resolution and alarm thresholds
+    ///
+    /// # Arguments
+    /// - `persist` - copy settings to EEPROM, so they survive power loss
+    pub async fn configure(
+        &mut self,
+        bus: &mut impl OneWire,
+        resolution: Resolution,
+        alarm_high: i8,
+        alarm_low: i8,
+        persist: bool,
+    ) -> Result<(), Error> {
+        bus.select(Some(self.rom))?;
+        bus.write_byte(CMD_WRITE_SCRATCHPAD);
+        bus.write_bytes(&[alarm_high as u8, alarm_low as u8, resolution.config()]);
+        self.resolution = resolution;
+
+        if persist {
+            bus.select(Some(self.rom))?;
+            bus.write_byte(CMD_COPY_SCRATCHPAD);
+            if self.parasite {
+                bus.strong_pullup(true);
+            }
+            // EEPROM write time
+            Timer::after_millis(10).await;
+            bus.strong_pullup(false);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scratchpad with temperature register, config register and valid CRC
+    fn scratchpad(raw: u16, config: u8) -> [u8; 9] {
+        let [lsb, msb] = raw.to_le_bytes();
+        let mut data = [lsb, msb, 0x4B, 0x46, config, 0xFF, 0x0C, 0x10, 0];
+        data[8] = crc8(&data[..8]);
+        data
+    }
+
+    fn temperature(raw: u16, resolution: Resolution) -> f32 {
+        Scratchpad::decode(&scratchpad(raw, resolution.config()))
+            .unwrap()
+            .temperature
+    }
+
+    #[test]
+    fn decodes_datasheet_temperatures() {
+        for (raw, celsius) in [
+            (0x07D0, 125.0),
+            (0x0550, 85.0),
+            (0x0191, 25.0625),
+            (0x00A2, 10.125),
+            (0x0008, 0.5),
+            (0x0000, 0.0),
+            (0xFFF8, -0.5),
+            (0xFF5E, -10.125),
+            (0xFE6F, -25.0625),
+            (0xFC90, -55.0),
+        ] {
+            assert_eq!(temperature(raw, Resolution::Bits12), celsius, "{raw:04x}");
+        }
+    }
+
+    #[test]
+    fn lower_resolution_ignores_undefined_bits() {
+        assert_eq!(temperature(0x0191, Resolution::Bits9), 25.0);
+        assert_eq!(temperature(0x0197, Resolution::Bits10), 25.25);
+        assert_eq!(temperature(0x0197, Resolution::Bits11), 25.375);
+        // Rounded down, towards colder
+        assert_eq!(temperature(0xFE6F, Resolution::Bits9), -25.5);
+    }
+
+    #[test]
+    fn decodes_alarms_and_resolution() {
+        let decoded = Scratchpad::decode(&scratchpad(0x0550, 0x3F)).unwrap();
+        assert_eq!(
+            decoded,
+            Scratchpad {
+                temperature: 85.0,
+                alarm_high: 75,
+                alarm_low: 70,
+                resolution: Resolution::Bits10,
+            }
+        );
+
+        let mut negative = scratchpad(0, Resolution::Bits12.config());
+        negative[2] = -10_i8 as u8;
+        negative[8] = crc8(&negative[..8]);
+        assert_eq!(Scratchpad::decode(&negative).unwrap().alarm_high, -10);
+    }
+
+    #[test]
+    fn resolution_config_round_trips() {
+        for resolution in [
+            Resolution::Bits9,
+            Resolution::Bits10,
+            Resolution::Bits11,
+            Resolution::Bits12,
+        ] {
+            assert_eq!(Resolution::from_config(resolution.config()), resolution);
+        }
+        assert_eq!(Resolution::Bits9.config(), 0x1F);
+        assert_eq!(Resolution::Bits12.config(), 0x7F);
+    }
+
+    #[test]
+    fn rejects_corrupted_scratchpad() {
+        let mut data = scratchpad(0x0191, Resolution::Bits12.config());
+        data[0] ^= 0x01;
+        assert_eq!(Scratchpad::decode(&data), Err(Error::Crc));
+    }
+}
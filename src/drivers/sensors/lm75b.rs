@@ -1,5 +1,89 @@
+//!
+//! NXP LM75B temperature sensor with thermostat output
+//!
+
 use crate::drivers::sensors::temperature::TemperatureSensorAsync;
-use defmt::error;
+
+/// Address with A0-A2 pins low, up to 0x4F with them
+pub const DEFAULT_ADDRESS: u8 = 0x48;
+
+const REG_TEMPERATURE: u8 = 0x00;
+const REG_CONFIGURATION: u8 = 0x01;
+const REG_HYSTERESIS: u8 = 0x02;
+const REG_OVERTEMPERATURE: u8 = 0x03;
+
+/// Behaviour of OS output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum OsMode {
+    /// Active while temperature is above Tos, until it falls below Thyst
+    #[default]
+    Comparator,
+    /// Activated by crossing Tos or Thyst, cleared by reading any register
+    Interrupt,
+}
+
+/// Count of consecutive faults to activate OS output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum FaultQueue {
+    #[default]
+    One,
+    Two,
+    Four,
+    Six,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub struct Configuration {
+    /// Stops measurements to save power, registers stay accessible
+    pub shutdown: bool,
+    pub os_mode: OsMode,
+    /// OS output is active high instead of active low
+    pub os_active_high: bool,
+    pub fault_queue: FaultQueue,
+}
+
+impl Configuration {
+    pub fn from_register(value: u8) -> Self {
+        Self {
+            shutdown: value & 0b1 != 0,
+            os_mode: if value & 0b10 != 0 {
+                OsMode::Interrupt
+            } else {
+                OsMode::Comparator
+            },
+            os_active_high: value & 0b100 != 0,
+            fault_queue: match (value >> 3) & 0b11 {
+                0 => FaultQueue::One,
+                1 => FaultQueue::Two,
+                2 => FaultQueue::Four,
+                _ => FaultQueue::Six,
+            },
+        }
+    }
+
+    pub fn to_register(&self) -> u8 {
+        u8::from(self.shutdown)
+            | (u8::from(self.os_mode == OsMode::Interrupt) << 1)
+            | (u8::from(self.os_active_high) << 2)
+            | ((self.fault_queue as u8) << 3)
+    }
+}
+
+/// Decodes 11-bit temperature register in 0.125 °C steps
+pub fn decode_temperature(data: [u8; 2]) -> f32 {
+    f32::from(i16::from_be_bytes(data) >> 5) * 0.125
+}
+
+/// Decodes 9-bit threshold register in 0.5 °C steps
+pub fn decode_threshold(data: [u8; 2]) -> f32 {
+    f32::from(i16::from_be_bytes(data) >> 7) * 0.5
+}
+
+/// Encodes threshold register, temperature is truncated to 0.5 °C steps and limited to -55..=125 °C
+pub fn encode_threshold(temperature: f32) -> [u8; 2] {
+    let steps = (temperature.clamp(-55.0, 125.0) * 2.0) as i16;
+    (steps << 7).to_be_bytes()
+}
 
 pub struct Lm75B<I2C> {
     i2c: I2C,
@@ -12,23 +96,189 @@ impl<I2C> Lm75B<I2C> {
     }
 }
 
+impl<I2C> Lm75B<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    pub async fn configuration(&mut self) -> Result<Configuration, I2C::Error> {
+        let mut data = [0_u8];
+        self.i2c
+            .write_read(self.address, &[REG_CONFIGURATION], &mut data)
+            .await?;
+        Ok(Configuration::from_register(data[0]))
+    }
+
+    pub async fn set_configuration(
+        &mut self,
+        configuration: &Configuration,
+    ) -> Result<(), I2C::Error> {
+        self.i2c
+            .write(
+                self.address,
+                &[REG_CONFIGURATION, configuration.to_register()],
+            )
+            .await
+    }
+
+    /// Turns measurements off or on, keeping other configuration
+    pub async fn set_shutdown(&mut self, shutdown: bool) -> Result<(), I2C::Error> {
+        let configuration = Configuration {
+            shutdown,
+            ..self.configuration().await?
+        };
+        self.set_configuration(&configuration).await
+    }
+
+    /// Reads overtemperature threshold Tos in °C
+    pub async fn overtemperature(&mut self) -> Result<f32, I2C::Error> {
+        Ok(decode_threshold(self.read_word(REG_OVERTEMPERATURE).await?))
+    }
+
+    /// Sets overtemperature threshold Tos in °C, OS output activates above it
+    pub async fn set_overtemperature(&mut self, temperature: f32) -> Result<(), I2C::Error> {
+        self.write_word(REG_OVERTEMPERATURE, encode_threshold(temperature))
+            .await
+    }
+
+    /// Reads hysteresis threshold Thyst in °C
+    pub async fn hysteresis(&mut self) -> Result<f32, I2C::Error> {
+        Ok(decode_threshold(self.read_word(REG_HYSTERESIS).await?))
+    }
+
+    /// Sets hysteresis threshold Thyst in °C, OS output deactivates below it
+    pub async fn set_hysteresis(&mut self, temperature: f32) -> Result<(), I2C::Error> {
+        self.write_word(REG_HYSTERESIS, encode_threshold(temperature))
+            .await
+    }
+
+    async fn read_word(&mut self, register: u8) -> Result<[u8; 2], I2C::Error> {
+        let mut data = [0_u8; 2];
+        self.i2c
+            .write_read(self.address, &[register], &mut data)
+            .await?;
+        Ok(data)
+    }
+
+    async fn write_word(&mut self, register: u8, data: [u8; 2]) -> Result<(), I2C::Error> {
+        self.i2c
+            .write(self.address, &[register, data[0], data[1]])
+            .await
+    }
+}
+
 impl<I2C> TemperatureSensorAsync for Lm75B<I2C>
 where
     I2C: embedded_hal_async::i2c::I2c,
 {
-    type ReadTemp = f32;
+    type ReadTemp = Result<f32, I2C::Error>;
+
     async fn read_temperature(&mut self) -> Self::ReadTemp {
-        let mut data = [0_u8; 2];
-        // 0x00 - Temperature register
-        if let Err(_) = self.i2c.write_read(self.address, &[0x00], &mut data).await {
-            error!("lm75b: read_temperature failed");
+        Ok(decode_temperature(self.read_word(REG_TEMPERATURE).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::drivers::i2c::mock::{MockI2c, Transaction};
+
+    fn run<T>(script: &[Transaction], f: impl AsyncFnOnce(&mut Lm75B<MockI2c<'_>>) -> T) -> T {
+        let mut sensor = Lm75B::new(MockI2c::new(script), DEFAULT_ADDRESS);
+        let result = block_on(f(&mut sensor));
+        sensor.i2c.done();
+        result
+    }
+
+    #[test]
+    fn decodes_temperature_of_datasheet() {
+        assert_eq!(decode_temperature([0x7F, 0x00]), 127.0);
+        assert_eq!(decode_temperature([0x19, 0x00]), 25.0);
+        assert_eq!(decode_temperature([0x00, 0x20]), 0.125);
+        assert_eq!(decode_temperature([0x00, 0x00]), 0.0);
+        assert_eq!(decode_temperature([0xFF, 0xE0]), -0.125);
+        assert_eq!(decode_temperature([0xE7, 0x00]), -25.0);
+        assert_eq!(decode_temperature([0xC9, 0x00]), -55.0);
+    }
+
+    #[test]
+    fn ignores_unused_low_bits() {
+        assert_eq!(decode_temperature([0x19, 0x1F]), 25.0);
+        assert_eq!(decode_threshold([0x50, 0x7F]), 80.0);
+    }
+
+    #[test]
+    fn encodes_threshold_in_half_degrees() {
+        assert_eq!(encode_threshold(80.0), [0x50, 0x00]);
+        assert_eq!(encode_threshold(75.5), [0x4B, 0x80]);
+        assert_eq!(encode_threshold(-25.5), [0xE6, 0x80]);
+        assert_eq!(encode_threshold(75.7), [0x4B, 0x80]);
+        assert_eq!(encode_threshold(200.0), encode_threshold(125.0));
+        assert_eq!(encode_threshold(-80.0), encode_threshold(-55.0));
+    }
+
+    #[test]
+    fn threshold_survives_encoding() {
+        for half_degrees in -110..=250 {
+            let temperature = half_degrees as f32 * 0.5;
+            assert_eq!(decode_threshold(encode_threshold(temperature)), temperature);
         }
+    }
+
+    #[test]
+    fn reads_temperature() {
+        let temperature = run(
+            &[Transaction::write_read(
+                DEFAULT_ADDRESS,
+                &[0x00],
+                &[0xE7, 0x00],
+            )],
+            async |sensor| sensor.read_temperature().await,
+        );
+
+        assert_eq!(temperature, Ok(-25.0));
+    }
+
+    #[test]
+    fn threshold_registers_round_trip() {
+        let thresholds = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0x03, 0x4B, 0x80]),
+                Transaction::write(DEFAULT_ADDRESS, &[0x02, 0xE6, 0x80]),
+                Transaction::write_read(DEFAULT_ADDRESS, &[0x03], &[0x4B, 0x80]),
+                Transaction::write_read(DEFAULT_ADDRESS, &[0x02], &[0xE6, 0x80]),
+            ],
+            async |sensor| {
+                sensor.set_overtemperature(75.5).await?;
+                sensor.set_hysteresis(-25.5).await?;
+                Ok::<_, embedded_hal_async::i2c::ErrorKind>((
+                    sensor.overtemperature().await?,
+                    sensor.hysteresis().await?,
+                ))
+            },
+        );
+
+        assert_eq!(thresholds, Ok((75.5, -25.5)));
+    }
+
+    #[test]
+    fn configuration_register_round_trip() {
+        for value in 0..0b100000 {
+            assert_eq!(Configuration::from_register(value).to_register(), value);
+        }
+    }
 
-        let msb = data[0];
-        let lsb = data[1];
+    #[test]
+    fn shutdown_keeps_configuration() {
+        let result = run(
+            &[
+                Transaction::write_read(DEFAULT_ADDRESS, &[0x01], &[0b10110]),
+                Transaction::write(DEFAULT_ADDRESS, &[0x01, 0b10111]),
+            ],
+            async |sensor| sensor.set_shutdown(true).await,
+        );
 
-        let msb = f32::from(msb as i8);
-        let decimal = f32::from((lsb & 0b1111_1000 as u8) >> 5) * 0.125;
-        msb + decimal
+        assert_eq!(result, Ok(()));
     }
 }
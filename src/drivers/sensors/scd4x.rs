@@ -0,0 +1,378 @@
+//!
+//! Sensirion SCD40/SCD41 CO2 sensor
+//!
+//! Most commands are accepted only while periodic measurement is stopped, except
+//! reading data, data-ready status and ambient pressure.
+//!
+
+use embassy_time::Timer;
+
+use crate::drivers::sensors::sensirion::{self, Error};
+
+pub const DEFAULT_ADDRESS: u8 = 0x62;
+
+const CMD_START_PERIODIC: u16 = 0x21B1;
+const CMD_START_LOW_POWER_PERIODIC: u16 = 0x21AC;
+const CMD_READ_MEASUREMENT: u16 = 0xEC05;
+const CMD_STOP_PERIODIC: u16 = 0x3F86;
+const CMD_SET_TEMPERATURE_OFFSET: u16 = 0x241D;
+const CMD_GET_TEMPERATURE_OFFSET: u16 = 0x2318;
+const CMD_SET_ALTITUDE: u16 = 0x2427;
+const CMD_GET_ALTITUDE: u16 = 0x2322;
+const CMD_SET_AMBIENT_PRESSURE: u16 = 0xE000;
+const CMD_FORCED_RECALIBRATION: u16 = 0x362F;
+const CMD_SET_AUTO_CALIBRATION: u16 = 0x2416;
+const CMD_GET_AUTO_CALIBRATION: u16 = 0x2313;
+const CMD_GET_DATA_READY: u16 = 0xE4B8;
+const CMD_PERSIST_SETTINGS: u16 = 0x3615;
+const CMD_GET_SERIAL: u16 = 0x3682;
+const CMD_REINIT: u16 = 0x3646;
+const CMD_MEASURE_SINGLE_SHOT: u16 = 0x219D;
+const CMD_MEASURE_SINGLE_SHOT_RHT: u16 = 0x2196;
+
+/// Returned by forced recalibration if it failed
+const RECALIBRATION_FAILED: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Measurement {
+    /// In ppm, 0 after RHT only single shot
+    pub co2: u16,
+    /// In °C
+    pub temperature: f32,
+    /// In %
+    pub humidity: f32,
+}
+
+pub struct Scd4x<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C> Scd4x<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I2C> Scd4x<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    /// Starts measurements every 5 seconds
+    pub async fn start_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_START_PERIODIC, None, 0).await
+    }
+
+    /// Starts measurements every 30 seconds
+    pub async fn start_low_power_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_START_LOW_POWER_PERIODIC, None, 0).await
+    }
+
+    pub async fn stop_periodic_measurement(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_STOP_PERIODIC, None, 500).await
+    }
+
+    /// Checks whatever new measurement can be read
+    pub async fn data_ready(&mut self) -> Result<bool, Error<I2C::Error>> {
+        let [status] = self.read(CMD_GET_DATA_READY, 1).await?;
+        Ok(status & 0x07FF != 0)
+    }
+
+    /// Reads the latest measurement, wait for [`Self::data_ready`] before
+    pub async fn read_measurement(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        let [co2, temperature, humidity] = self.read(CMD_READ_MEASUREMENT, 1).await?;
+
+        Ok(Measurement {
+            co2,
+            temperature: sensirion::temperature(temperature),
+            humidity: 100.0 * f32::from(humidity) / 65535.0,
+        })
+    }
+
+    /// Measures once and reads result, SCD41 only
+    ///
+    /// # Arguments
+    /// - `co2` - false to measure temperature and humidity only, which is much faster
+    pub async fn measure_single_shot(
+        &mut self,
+        co2: bool,
+    ) -> Result<Measurement, Error<I2C::Error>> {
+        if co2 {
+            self.command(CMD_MEASURE_SINGLE_SHOT, None, 5000).await?;
+        } else {
+            self.command(CMD_MEASURE_SINGLE_SHOT_RHT, None, 50).await?;
+        }
+
+        self.read_measurement().await
+    }
+
+    /// Sets offset subtracted from measured temperature, e.g. self heating of device
+    pub async fn set_temperature_offset(&mut self, offset: f32) -> Result<(), Error<I2C::Error>> {
+        let word = (offset.clamp(0.0, 175.0) * 65535.0 / 175.0) as u16;
+        self.command(CMD_SET_TEMPERATURE_OFFSET, Some(word), 1)
+            .await
+    }
+
+    pub async fn temperature_offset(&mut self) -> Result<f32, Error<I2C::Error>> {
+        let [word] = self.read(CMD_GET_TEMPERATURE_OFFSET, 1).await?;
+        Ok(f32::from(word) * 175.0 / 65535.0)
+    }
+
+    /// Sets altitude in meters above sea level for pressure compensation
+    pub async fn set_altitude(&mut self, altitude: u16) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_SET_ALTITUDE, Some(altitude), 1).await
+    }
+
+    pub async fn altitude(&mut self) -> Result<u16, Error<I2C::Error>> {
+        let [altitude] = self.read(CMD_GET_ALTITUDE, 1).await?;
+        Ok(altitude)
+    }
+
+    /// Sets ambient pressure in Pa for compensation, overrides altitude
+    ///
+    /// Can be sent during periodic measurement
+    pub async fn set_ambient_pressure(&mut self, pressure: u32) -> Result<(), Error<I2C::Error>> {
+        let word = (pressure / 100).min(u32::from(u16::MAX)) as u16;
+        self.command(CMD_SET_AMBIENT_PRESSURE, Some(word), 1).await
+    }
+
+    /// Recalibrates sensor to known CO2 concentration
+    ///
+    /// Sensor must work in periodic mode for at least 3 minutes in the reference
+    /// environment before, and periodic measurement must be stopped.
+    ///
+    /// # Returns
+    /// Applied correction in ppm
+    pub async fn forced_recalibration(&mut self, co2: u16) -> Result<i16, Error<I2C::Error>> {
+        self.command(CMD_FORCED_RECALIBRATION, Some(co2), 400)
+            .await?;
+        let [correction] = self.read_response::<1>().await?;
+        if correction == RECALIBRATION_FAILED {
+            return Err(Error::Failed);
+        }
+
+        Ok((i32::from(correction) - 0x8000) as i16)
+    }
+
+    /// Enables or disables automatic self calibration, enabled by default
+    ///
+    /// It assumes that sensor sees fresh air (about 400 ppm) at least once a week
+    pub async fn set_automatic_self_calibration(
+        &mut self,
+        enabled: bool,
+    ) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_SET_AUTO_CALIBRATION, Some(u16::from(enabled)), 1)
+            .await
+    }
+
+    pub async fn automatic_self_calibration(&mut self) -> Result<bool, Error<I2C::Error>> {
+        let [enabled] = self.read(CMD_GET_AUTO_CALIBRATION, 1).await?;
+        Ok(enabled != 0)
+    }
+
+    /// Stores settings in EEPROM, otherwise they are lost on power cycle
+    pub async fn persist_settings(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_PERSIST_SETTINGS, None, 800).await
+    }
+
+    /// Reloads settings from EEPROM
+    pub async fn reinit(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_REINIT, None, 30).await
+    }
+
+    /// Reads 48-bit serial number
+    pub async fn serial_number(&mut self) -> Result<u64, Error<I2C::Error>> {
+        let words = self.read::<3>(CMD_GET_SERIAL, 1).await?;
+        Ok(sensirion::serial_number(&words))
+    }
+
+    /// Sends command with optional argument and waits for its execution
+    async fn command(
+        &mut self,
+        command: u16,
+        argument: Option<u16>,
+        execution_ms: u64,
+    ) -> Result<(), Error<I2C::Error>> {
+        let mut data = [0_u8; 5];
+        data[..2].copy_from_slice(&command.to_be_bytes());
+        let len = match argument {
+            Some(argument) => {
+                data[2..].copy_from_slice(&sensirion::encode_word(argument));
+                5
+            }
+            None => 2,
+        };
+
+        self.i2c.write(self.address, &data[..len]).await?;
+        if 0 < execution_ms {
+            Timer::after_millis(execution_ms).await;
+        }
+
+        Ok(())
+    }
+
+    async fn read<const N: usize>(
+        &mut self,
+        command: u16,
+        execution_ms: u64,
+    ) -> Result<[u16; N], Error<I2C::Error>> {
+        self.command(command, None, execution_ms).await?;
+        self.read_response().await
+    }
+
+    async fn read_response<const N: usize>(&mut self) -> Result<[u16; N], Error<I2C::Error>> {
+        let mut data = [0_u8; 9];
+        self.i2c.read(self.address, &mut data[..N * 3]).await?;
+        sensirion::decode_words(&data[..N * 3])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::drivers::i2c::mock::{MockI2c, Transaction};
+
+    const READ_MEASUREMENT: Transaction = Transaction::write(DEFAULT_ADDRESS, &[0xEC, 0x05]);
+    const DATA_READY: Transaction = Transaction::write(DEFAULT_ADDRESS, &[0xE4, 0xB8]);
+
+    fn run<T>(script: &[Transaction], f: impl AsyncFnOnce(&mut Scd4x<MockI2c<'_>>) -> T) -> T {
+        let mut sensor = Scd4x::new(MockI2c::new(script), DEFAULT_ADDRESS);
+        let result = block_on(f(&mut sensor));
+        sensor.i2c.done();
+        result
+    }
+
+    #[test]
+    fn decodes_datasheet_measurement() {
+        let measurement = run(
+            &[
+                READ_MEASUREMENT,
+                Transaction::read(
+                    DEFAULT_ADDRESS,
+                    &[0x01, 0xF4, 0x33, 0x66, 0x67, 0xA2, 0x5E, 0xB9, 0x3C],
+                ),
+            ],
+            async |sensor| sensor.read_measurement().await,
+        )
+        .unwrap();
+
+        assert_eq!(measurement.co2, 500);
+        assert!((measurement.temperature - 25.0).abs() < 0.01);
+        assert!((measurement.humidity - 37.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn rejects_corrupted_measurement() {
+        let result = run(
+            &[
+                READ_MEASUREMENT,
+                Transaction::read(
+                    DEFAULT_ADDRESS,
+                    &[0x01, 0xF4, 0x33, 0x66, 0x67, 0xA2, 0x5E, 0xB8, 0x3C],
+                ),
+            ],
+            async |sensor| sensor.read_measurement().await,
+        );
+
+        assert_eq!(result, Err(Error::Crc));
+    }
+
+    #[test]
+    fn checks_data_ready_bits_only() {
+        let ready = run(
+            &[
+                DATA_READY,
+                Transaction::read(DEFAULT_ADDRESS, &[0x80, 0x06, 0x04]),
+            ],
+            async |sensor| sensor.data_ready().await,
+        );
+        let not_ready = run(
+            &[
+                DATA_READY,
+                Transaction::read(DEFAULT_ADDRESS, &[0x80, 0x00, 0xA2]),
+            ],
+            async |sensor| sensor.data_ready().await,
+        );
+
+        assert_eq!(ready, Ok(true));
+        assert_eq!(not_ready, Ok(false));
+    }
+
+    #[test]
+    fn encodes_commands() {
+        run(
+            &[Transaction::write(DEFAULT_ADDRESS, &[0x21, 0xB1])],
+            async |sensor| sensor.start_periodic_measurement().await,
+        )
+        .unwrap();
+        run(
+            &[Transaction::write(DEFAULT_ADDRESS, &[0x21, 0xAC])],
+            async |sensor| sensor.start_low_power_periodic_measurement().await,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn encodes_arguments_with_crc() {
+        run(
+            &[Transaction::write(
+                DEFAULT_ADDRESS,
+                &[0x24, 0x1D, 0x07, 0xE6, 0x48],
+            )],
+            async |sensor| sensor.set_temperature_offset(5.4).await,
+        )
+        .unwrap();
+        run(
+            &[Transaction::write(
+                DEFAULT_ADDRESS,
+                &[0xE0, 0x00, 0x03, 0xE8, 0xD4],
+            )],
+            async |sensor| sensor.set_ambient_pressure(100_000).await,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn applies_forced_recalibration() {
+        let correction = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0x36, 0x2F, 0x01, 0xE0, 0xB4]),
+                Transaction::read(DEFAULT_ADDRESS, &[0x7F, 0xCE, 0x7B]),
+            ],
+            async |sensor| sensor.forced_recalibration(480).await,
+        );
+
+        assert_eq!(correction, Ok(-50));
+    }
+
+    #[test]
+    fn reports_failed_recalibration() {
+        let correction = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0x36, 0x2F, 0x01, 0xE0, 0xB4]),
+                Transaction::read(DEFAULT_ADDRESS, &[0xFF, 0xFF, 0xAC]),
+            ],
+            async |sensor| sensor.forced_recalibration(480).await,
+        );
+
+        assert_eq!(correction, Err(Error::Failed));
+    }
+
+    #[test]
+    fn reads_serial_number() {
+        let serial = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0x36, 0x82]),
+                Transaction::read(
+                    DEFAULT_ADDRESS,
+                    &[0xBE, 0xEF, 0x92, 0x80, 0x00, 0xA2, 0x03, 0xE8, 0xD4],
+                ),
+            ],
+            async |sensor| sensor.serial_number().await,
+        );
+
+        assert_eq!(serial, Ok(0xBEEF_8000_03E8));
+    }
+}
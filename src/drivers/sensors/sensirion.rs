@@ -0,0 +1,117 @@
+//!
+//! Common parts of Sensirion I2C sensors
+//!
+//! Sensirion sensors transfer data in 16-bit big-endian words, each followed by CRC-8
+//!
+
+/// Error of Sensirion sensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error<E> {
+    I2c(E),
+    /// Received word does not match its CRC
+    Crc,
+    /// Sensor reported that command failed
+    Failed,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(value: E) -> Self {
+        Error::I2c(value)
+    }
+}
+
+/// Temperature and relative humidity
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Measurement {
+    /// In °C
+    pub temperature: f32,
+    /// In %
+    pub humidity: f32,
+}
+
+/// Calculates CRC-8 with polynomial 0x31 and initial value 0xFF
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFF_u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Checks CRC of received words and extracts them
+///
+/// # Arguments
+/// - `data` - words with CRC, 3 bytes per word
+pub fn decode_words<E, const N: usize>(data: &[u8]) -> Result<[u16; N], Error<E>> {
+    let mut words = [0; N];
+    for (word, chunk) in words.iter_mut().zip(data.chunks_exact(3)) {
+        if crc8(&chunk[..2]) != chunk[2] {
+            return Err(Error::Crc);
+        }
+        *word = u16::from_be_bytes([chunk[0], chunk[1]]);
+    }
+
+    Ok(words)
+}
+
+/// Appends CRC to word to send it
+pub fn encode_word(word: u16) -> [u8; 3] {
+    let [msb, lsb] = word.to_be_bytes();
+    [msb, lsb, crc8(&[msb, lsb])]
+}
+
+/// Converts raw temperature to °C, same formula for SHT3x, SHT4x and SCD4x
+pub fn temperature(raw: u16) -> f32 {
+    -45.0 + 175.0 * f32::from(raw) / 65535.0
+}
+
+/// Combines serial number from words, the most significant first
+pub fn serial_number(words: &[u16]) -> u64 {
+    words
+        .iter()
+        .fold(0, |serial, word| (serial << 16) | u64::from(*word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_of_datasheet_example() {
+        assert_eq!(crc8(&[0xBE, 0xEF]), 0x92);
+        assert_eq!(encode_word(0xBEEF), [0xBE, 0xEF, 0x92]);
+    }
+
+    #[test]
+    fn decodes_words_with_valid_crc() {
+        let words = decode_words::<(), 2>(&[0x12, 0x34, 0x37, 0x56, 0x78, 0x7D]);
+        assert_eq!(words, Ok([0x1234, 0x5678]));
+    }
+
+    #[test]
+    fn rejects_word_with_wrong_crc() {
+        let words = decode_words::<(), 2>(&[0x12, 0x34, 0x37, 0x56, 0x78, 0x7E]);
+        assert_eq!(words, Err(Error::Crc));
+    }
+
+    #[test]
+    fn converts_temperature() {
+        assert_eq!(temperature(0x0000), -45.0);
+        assert_eq!(temperature(0xFFFF), 130.0);
+        assert!((temperature(0x6666) - 25.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn combines_serial_number() {
+        assert_eq!(serial_number(&[0x1234, 0x5678]), 0x1234_5678);
+        assert_eq!(serial_number(&[0xAAAA, 0xBBBB, 0xCCCC]), 0xAAAA_BBBB_CCCC);
+    }
+}
@@ -0,0 +1,259 @@
+//!
+//! Sensirion SHT30/SHT31/SHT35 humidity and temperature sensor
+//!
+
+use embassy_time::Timer;
+
+use crate::drivers::sensors::{
+    sensirion::{self, Error, Measurement},
+    temperature::TemperatureSensorAsync,
+};
+
+/// Address with ADDR pin low, 0x45 if high
+pub const DEFAULT_ADDRESS: u8 = 0x44;
+
+const CMD_SOFT_RESET: u16 = 0x30A2;
+const CMD_HEATER_ENABLE: u16 = 0x306D;
+const CMD_HEATER_DISABLE: u16 = 0x3066;
+const CMD_READ_STATUS: u16 = 0xF32D;
+const CMD_CLEAR_STATUS: u16 = 0x3041;
+const CMD_READ_SERIAL: u16 = 0x3780;
+
+/// Status register bit: heater is on
+pub const STATUS_HEATER: u16 = 1 << 13;
+/// Status register bit: reset detected since status cleared
+pub const STATUS_RESET: u16 = 1 << 4;
+
+/// Measurement repeatability, higher is less noisy but slower
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Repeatability {
+    #[default]
+    High,
+    Medium,
+    Low,
+}
+
+impl Repeatability {
+    /// Single shot command without clock stretching
+    fn command(&self) -> u16 {
+        match self {
+            Repeatability::High => 0x2400,
+            Repeatability::Medium => 0x240B,
+            Repeatability::Low => 0x2416,
+        }
+    }
+
+    /// Max measurement duration in ms
+    fn duration_ms(&self) -> u64 {
+        match self {
+            Repeatability::High => 16,
+            Repeatability::Medium => 7,
+            Repeatability::Low => 5,
+        }
+    }
+}
+
+pub struct Sht3x<I2C> {
+    i2c: I2C,
+    address: u8,
+    repeatability: Repeatability,
+}
+
+impl<I2C> Sht3x<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Self {
+            i2c,
+            address,
+            repeatability: Repeatability::default(),
+        }
+    }
+
+    pub fn set_repeatability(&mut self, repeatability: Repeatability) {
+        self.repeatability = repeatability;
+    }
+}
+
+impl<I2C> Sht3x<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    /// Runs single shot measurement
+    pub async fn measure(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        self.command(self.repeatability.command()).await?;
+        Timer::after_millis(self.repeatability.duration_ms()).await;
+
+        let [temperature, humidity] = self.read_words::<2>().await?;
+        Ok(Measurement {
+            temperature: sensirion::temperature(temperature),
+            humidity: 100.0 * f32::from(humidity) / 65535.0,
+        })
+    }
+
+    /// Turns heater on or off
+    ///
+    /// Heater evaporates condensation, readings are not accurate while it is on
+    pub async fn set_heater(&mut self, on: bool) -> Result<(), Error<I2C::Error>> {
+        self.command(if on {
+            CMD_HEATER_ENABLE
+        } else {
+            CMD_HEATER_DISABLE
+        })
+        .await
+    }
+
+    /// Reads status register, see `STATUS_*` bits
+    pub async fn status(&mut self) -> Result<u16, Error<I2C::Error>> {
+        self.command(CMD_READ_STATUS).await?;
+        let [status] = self.read_words::<1>().await?;
+        Ok(status)
+    }
+
+    pub async fn clear_status(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_CLEAR_STATUS).await
+    }
+
+    pub async fn serial_number(&mut self) -> Result<u32, Error<I2C::Error>> {
+        self.command(CMD_READ_SERIAL).await?;
+        let words = self.read_words::<2>().await?;
+        Ok(sensirion::serial_number(&words) as u32)
+    }
+
+    pub async fn soft_reset(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_SOFT_RESET).await?;
+        Timer::after_millis(2).await;
+        Ok(())
+    }
+
+    async fn command(&mut self, command: u16) -> Result<(), Error<I2C::Error>> {
+        self.i2c.write(self.address, &command.to_be_bytes()).await?;
+        Ok(())
+    }
+
+    async fn read_words<const N: usize>(&mut self) -> Result<[u16; N], Error<I2C::Error>> {
+        let mut data = [0_u8; 6];
+        self.i2c.read(self.address, &mut data[..N * 3]).await?;
+        sensirion::decode_words(&data[..N * 3])
+    }
+}
+
+impl<I2C> TemperatureSensorAsync for Sht3x<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    type ReadTemp = Result<f32, Error<I2C::Error>>;
+
+    async fn read_temperature(&mut self) -> Self::ReadTemp {
+        Ok(self.measure().await?.temperature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embedded_hal_async::i2c::{ErrorKind, NoAcknowledgeSource};
+
+    use super::*;
+    use crate::drivers::i2c::mock::{MockI2c, Transaction};
+
+    const MEASURE: Transaction = Transaction::write(DEFAULT_ADDRESS, &[0x24, 0x00]);
+
+    fn run<T>(script: &[Transaction], f: impl AsyncFnOnce(&mut Sht3x<MockI2c<'_>>) -> T) -> T {
+        let mut sensor = Sht3x::new(MockI2c::new(script), DEFAULT_ADDRESS);
+        let result = block_on(f(&mut sensor));
+        sensor.i2c.done();
+        result
+    }
+
+    #[test]
+    fn converts_measurement() {
+        let measurement = run(
+            &[
+                MEASURE,
+                Transaction::read(DEFAULT_ADDRESS, &[0x66, 0x66, 0x93, 0x80, 0x00, 0xA2]),
+            ],
+            async |sensor| sensor.measure().await,
+        )
+        .unwrap();
+
+        assert!((measurement.temperature - 25.0).abs() < 0.001);
+        assert!((measurement.humidity - 50.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn measures_with_repeatability() {
+        let result = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0x24, 0x16]),
+                Transaction::read(DEFAULT_ADDRESS, &[0x00, 0x00, 0x81, 0xFF, 0xFF, 0xAC]),
+            ],
+            async |sensor| {
+                sensor.set_repeatability(Repeatability::Low);
+                sensor.measure().await
+            },
+        );
+
+        assert_eq!(
+            result,
+            Ok(Measurement {
+                temperature: -45.0,
+                humidity: 100.0,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_humidity() {
+        let result = run(
+            &[
+                MEASURE,
+                Transaction::read(DEFAULT_ADDRESS, &[0x66, 0x66, 0x93, 0x80, 0x00, 0xA3]),
+            ],
+            async |sensor| sensor.measure().await,
+        );
+
+        assert_eq!(result, Err(Error::Crc));
+    }
+
+    #[test]
+    fn reports_nack_while_measuring() {
+        let result = run(
+            &[MEASURE, Transaction::read(DEFAULT_ADDRESS, &[]).nack()],
+            async |sensor| sensor.measure().await,
+        );
+
+        assert_eq!(
+            result,
+            Err(Error::I2c(ErrorKind::NoAcknowledge(
+                NoAcknowledgeSource::Address
+            )))
+        );
+    }
+
+    #[test]
+    fn reads_status() {
+        let status = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0xF3, 0x2D]),
+                Transaction::read(DEFAULT_ADDRESS, &[0x20, 0x00, 0x5D]),
+            ],
+            async |sensor| sensor.status().await,
+        )
+        .unwrap();
+
+        assert_ne!(status & STATUS_HEATER, 0);
+        assert_eq!(status & STATUS_RESET, 0);
+    }
+
+    #[test]
+    fn reads_serial_number() {
+        let serial = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0x37, 0x80]),
+                Transaction::read(DEFAULT_ADDRESS, &[0x12, 0x34, 0x37, 0x56, 0x78, 0x7D]),
+            ],
+            async |sensor| sensor.serial_number().await,
+        );
+
+        assert_eq!(serial, Ok(0x1234_5678));
+    }
+}
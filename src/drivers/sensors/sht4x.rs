@@ -0,0 +1,275 @@
+//!
+//! Sensirion SHT40/SHT41/SHT45 humidity and temperature sensor
+//!
+
+use embassy_time::Timer;
+
+use crate::drivers::sensors::{
+    sensirion::{self, Error, Measurement},
+    temperature::TemperatureSensorAsync,
+};
+
+/// Address of SHT4x-A variants, B variants use 0x45
+pub const DEFAULT_ADDRESS: u8 = 0x44;
+
+const CMD_SOFT_RESET: u8 = 0x94;
+const CMD_READ_SERIAL: u8 = 0x89;
+
+/// Measurement precision, higher is less noisy but slower
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, defmt::Format)]
+pub enum Precision {
+    #[default]
+    High,
+    Medium,
+    Low,
+}
+
+impl Precision {
+    fn command(&self) -> u8 {
+        match self {
+            Precision::High => 0xFD,
+            Precision::Medium => 0xF6,
+            Precision::Low => 0xE0,
+        }
+    }
+
+    /// Max measurement duration in ms
+    fn duration_ms(&self) -> u64 {
+        match self {
+            Precision::High => 9,
+            Precision::Medium => 5,
+            Precision::Low => 2,
+        }
+    }
+}
+
+/// Heater pulse, measurement with high precision is done at its end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum HeaterPulse {
+    /// 200mW for 1s
+    High1s,
+    /// 200mW for 0.1s
+    High100ms,
+    /// 110mW for 1s
+    Medium1s,
+    /// 110mW for 0.1s
+    Medium100ms,
+    /// 20mW for 1s
+    Low1s,
+    /// 20mW for 0.1s
+    Low100ms,
+}
+
+impl HeaterPulse {
+    fn command(&self) -> u8 {
+        match self {
+            HeaterPulse::High1s => 0x39,
+            HeaterPulse::High100ms => 0x32,
+            HeaterPulse::Medium1s => 0x2F,
+            HeaterPulse::Medium100ms => 0x24,
+            HeaterPulse::Low1s => 0x1E,
+            HeaterPulse::Low100ms => 0x15,
+        }
+    }
+
+    /// Max duration of pulse with measurement in ms
+    fn duration_ms(&self) -> u64 {
+        match self {
+            HeaterPulse::High1s | HeaterPulse::Medium1s | HeaterPulse::Low1s => 1100,
+            HeaterPulse::High100ms | HeaterPulse::Medium100ms | HeaterPulse::Low100ms => 110,
+        }
+    }
+}
+
+pub struct Sht4x<I2C> {
+    i2c: I2C,
+    address: u8,
+    precision: Precision,
+}
+
+impl<I2C> Sht4x<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Self {
+            i2c,
+            address,
+            precision: Precision::default(),
+        }
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+    }
+}
+
+impl<I2C> Sht4x<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    pub async fn measure(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        self.command(self.precision.command()).await?;
+        Timer::after_millis(self.precision.duration_ms()).await;
+        self.read_measurement().await
+    }
+
+    /// Heats sensor to evaporate condensation
+    ///
+    /// # Returns
+    /// Measurement done at the end of pulse, temperature is affected by heating
+    pub async fn heat(&mut self, pulse: HeaterPulse) -> Result<Measurement, Error<I2C::Error>> {
+        self.command(pulse.command()).await?;
+        Timer::after_millis(pulse.duration_ms()).await;
+        self.read_measurement().await
+    }
+
+    pub async fn serial_number(&mut self) -> Result<u32, Error<I2C::Error>> {
+        self.command(CMD_READ_SERIAL).await?;
+        Timer::after_millis(1).await;
+
+        let mut data = [0_u8; 6];
+        self.i2c.read(self.address, &mut data).await?;
+        let words = sensirion::decode_words::<_, 2>(&data)?;
+        Ok(sensirion::serial_number(&words) as u32)
+    }
+
+    pub async fn soft_reset(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.command(CMD_SOFT_RESET).await?;
+        Timer::after_millis(1).await;
+        Ok(())
+    }
+
+    async fn command(&mut self, command: u8) -> Result<(), Error<I2C::Error>> {
+        self.i2c.write(self.address, &[command]).await?;
+        Ok(())
+    }
+
+    async fn read_measurement(&mut self) -> Result<Measurement, Error<I2C::Error>> {
+        let mut data = [0_u8; 6];
+        self.i2c.read(self.address, &mut data).await?;
+        let [temperature, humidity] = sensirion::decode_words(&data)?;
+
+        Ok(Measurement {
+            temperature: sensirion::temperature(temperature),
+            // Formula gives values slightly out of physical range
+            humidity: (-6.0 + 125.0 * f32::from(humidity) / 65535.0).clamp(0.0, 100.0),
+        })
+    }
+}
+
+impl<I2C> TemperatureSensorAsync for Sht4x<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    type ReadTemp = Result<f32, Error<I2C::Error>>;
+
+    async fn read_temperature(&mut self) -> Self::ReadTemp {
+        Ok(self.measure().await?.temperature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embedded_hal_async::i2c::{ErrorKind, NoAcknowledgeSource};
+
+    use super::*;
+    use crate::drivers::i2c::mock::{MockI2c, Transaction};
+
+    const MEASURE: Transaction = Transaction::write(DEFAULT_ADDRESS, &[0xFD]);
+
+    fn run<T>(script: &[Transaction], f: impl AsyncFnOnce(&mut Sht4x<MockI2c<'_>>) -> T) -> T {
+        let mut sensor = Sht4x::new(MockI2c::new(script), DEFAULT_ADDRESS);
+        let result = block_on(f(&mut sensor));
+        sensor.i2c.done();
+        result
+    }
+
+    #[test]
+    fn converts_measurement() {
+        let measurement = run(
+            &[
+                MEASURE,
+                Transaction::read(DEFAULT_ADDRESS, &[0x66, 0x66, 0x93, 0x80, 0x00, 0xA2]),
+            ],
+            async |sensor| sensor.measure().await,
+        )
+        .unwrap();
+
+        assert!((measurement.temperature - 25.0).abs() < 0.001);
+        assert!((measurement.humidity - 56.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn clamps_humidity_to_physical_range() {
+        let dry = run(
+            &[
+                MEASURE,
+                Transaction::read(DEFAULT_ADDRESS, &[0x66, 0x66, 0x93, 0x00, 0x00, 0x81]),
+            ],
+            async |sensor| sensor.measure().await,
+        );
+        let wet = run(
+            &[
+                MEASURE,
+                Transaction::read(DEFAULT_ADDRESS, &[0x66, 0x66, 0x93, 0xFF, 0xFF, 0xAC]),
+            ],
+            async |sensor| sensor.measure().await,
+        );
+
+        assert_eq!(dry.unwrap().humidity, 0.0);
+        assert_eq!(wet.unwrap().humidity, 100.0);
+    }
+
+    #[test]
+    fn rejects_corrupted_temperature() {
+        let result = run(
+            &[
+                MEASURE,
+                Transaction::read(DEFAULT_ADDRESS, &[0x66, 0x67, 0x93, 0x80, 0x00, 0xA2]),
+            ],
+            async |sensor| sensor.measure().await,
+        );
+
+        assert_eq!(result, Err(Error::Crc));
+    }
+
+    #[test]
+    fn reports_nack_while_measuring() {
+        let result = run(
+            &[MEASURE, Transaction::read(DEFAULT_ADDRESS, &[]).nack()],
+            async |sensor| sensor.measure().await,
+        );
+
+        assert_eq!(
+            result,
+            Err(Error::I2c(ErrorKind::NoAcknowledge(
+                NoAcknowledgeSource::Address
+            )))
+        );
+    }
+
+    #[test]
+    fn measures_after_heater_pulse() {
+        let result = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0x15]),
+                Transaction::read(DEFAULT_ADDRESS, &[0x66, 0x66, 0x93, 0x80, 0x00, 0xA2]),
+            ],
+            async |sensor| sensor.heat(HeaterPulse::Low100ms).await,
+        );
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn reads_serial_number() {
+        let serial = run(
+            &[
+                Transaction::write(DEFAULT_ADDRESS, &[0x89]),
+                Transaction::read(DEFAULT_ADDRESS, &[0x12, 0x34, 0x37, 0x56, 0x78, 0x7D]),
+            ],
+            async |sensor| sensor.serial_number().await,
+        );
+
+        assert_eq!(serial, Ok(0x1234_5678));
+    }
+}
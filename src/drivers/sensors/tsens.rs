@@ -0,0 +1,44 @@
+//!
+//! ESP32-C6 on-die temperature sensor
+//!
+//! Measures the chip itself, which runs warmer than ambient, especially under WiFi load
+//!
+
+use embassy_time::Timer;
+use esp_hal::{
+    peripherals::TSENS,
+    tsens::{Config, TemperatureSensor},
+};
+
+use crate::drivers::sensors::temperature::TemperatureSensorAsync;
+
+/// Count of averaged samples, single readings jitter by a degree
+const SAMPLES: u8 = 8;
+
+pub struct Tsens {
+    sensor: TemperatureSensor<'static>,
+}
+
+impl Tsens {
+    /// Powers sensor up, it needs a few hundred microseconds to settle
+    pub fn new(tsens: TSENS<'static>) -> Self {
+        let sensor =
+            TemperatureSensor::new(tsens, Config::default()).expect("failed to init TSENS");
+
+        Self { sensor }
+    }
+}
+
+impl TemperatureSensorAsync for Tsens {
+    type ReadTemp = f32;
+
+    async fn read_temperature(&mut self) -> Self::ReadTemp {
+        let mut sum = 0.0;
+        for _ in 0..SAMPLES {
+            Timer::after_micros(300).await;
+            sum += self.sensor.get_temperature().to_celsius();
+        }
+
+        sum / f32::from(SAMPLES)
+    }
+}
@@ -0,0 +1,407 @@
+//!
+//! Duty-cycled battery mode
+//!
+//! Device wakes on RTC timer, buffers one reading and goes back to deep sleep.
+//! WiFi is brought up only to upload the buffer every few wakes, when the buffer
+//! is full or when alarm state changes. Nothing here touches hardware, the state
+//! lives in RTC memory owned by the board.
+//!
+//! The buffer is uploaded in chunks of [`UPLOAD_CHUNK`] samples, so the request body
+//! stays small and every accepted chunk leaves the buffer even if a later one fails.
+//!
+
+use heapless::Vec;
+use serde::Serialize;
+
+use crate::{battery::BatteryStatus, units::TemperatureUnit};
+
+/// Count of buffered readings, the oldest is dropped on overflow
+pub const BUFFER_CAP: usize = 32;
+
+/// Count of samples uploaded in one request
+pub const UPLOAD_CHUNK: usize = 8;
+/// Max length of [`Batch`] device name
+pub const MAX_DEVICE_LEN: usize = 32;
+/// Max length of one serialized [`Sample`], floats take up to 15 characters
+const MAX_SAMPLE_JSON_LEN: usize = 80;
+/// Max length of serialized [`Batch`] without samples
+const MAX_HEADER_JSON_LEN: usize = 160 + MAX_DEVICE_LEN;
+/// Request body fitting any [`RtcState::next_batch`]
+pub const MAX_BATCH_LEN: usize = MAX_HEADER_JSON_LEN + UPLOAD_CHUNK * (MAX_SAMPLE_JSON_LEN + 1);
+
+/// Marks initialized state, RTC memory is random after power loss
+const MAGIC: u32 = 0x5445_4D50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, defmt::Format)]
+#[repr(C)]
+pub struct Sample {
+    /// Number of wake the sample was taken on
+    pub wake: u32,
+    /// In °C
+    pub temperature: f32,
+    /// In %
+    pub humidity: f32,
+}
+
+/// State kept in RTC memory over deep sleep
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct RtcState {
+    magic: u32,
+    checksum: u32,
+    /// Count of wakes since power-up
+    wakes: u32,
+    /// Wake the buffer was uploaded on last time
+    last_upload: u32,
+    /// Non-zero if any alarm threshold was exceeded by the last sample
+    alarm: u32,
+    len: u32,
+    samples: [Sample; BUFFER_CAP],
+}
+
+impl Default for RtcState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtcState {
+    pub const fn new() -> Self {
+        Self {
+            magic: MAGIC,
+            checksum: 0,
+            wakes: 0,
+            last_upload: 0,
+            alarm: 0,
+            len: 0,
+            samples: [Sample {
+                wake: 0,
+                temperature: 0.0,
+                humidity: 0.0,
+            }; BUFFER_CAP],
+        }
+    }
+
+    /// Resets state, if it is not sealed by [`Self::seal`] before sleep
+    ///
+    /// # Returns
+    /// - true, if state was valid
+    pub fn validate(&mut self) -> bool {
+        let valid = self.magic == MAGIC
+            && self.len as usize <= BUFFER_CAP
+            && self.checksum == self.calculate_checksum();
+        if !valid {
+            *self = Self::new();
+        }
+
+        valid
+    }
+
+    /// Protects state with checksum, must be called before sleep
+    pub fn seal(&mut self) {
+        self.checksum = self.calculate_checksum();
+    }
+
+    /// Counts new wake
+    ///
+    /// # Returns
+    /// Number of this wake, starting from 1
+    pub fn wake(&mut self) -> u32 {
+        self.wakes = self.wakes.wrapping_add(1);
+        self.wakes
+    }
+
+    pub fn wakes(&self) -> u32 {
+        self.wakes
+    }
+
+    /// Count of wakes since the last upload
+    pub fn wakes_since_upload(&self) -> u32 {
+        self.wakes.wrapping_sub(self.last_upload)
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        if self.is_full() {
+            self.samples.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.samples[self.len as usize] = sample;
+        self.len += 1;
+    }
+
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples[..self.len as usize]
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len as usize == BUFFER_CAP
+    }
+
+    /// Oldest samples to upload in one request, may have no samples
+    ///
+    /// # Arguments
+    /// - `device` - up to [`MAX_DEVICE_LEN`] characters
+    /// - `unit` - temperatures of samples are converted to
+    pub fn next_batch(
+        &self,
+        device: &'static str,
+        interval_s: u64,
+        battery: Option<BatteryStatus>,
+        unit: TemperatureUnit,
+    ) -> Batch {
+        let samples = self
+            .samples()
+            .iter()
+            .take(UPLOAD_CHUNK)
+            .map(|sample| Sample {
+                temperature: unit.from_celsius(sample.temperature),
+                ..*sample
+            })
+            .collect();
+
+        Batch {
+            device,
+            wake: self.wakes,
+            interval_s,
+            battery,
+            unit,
+            samples,
+        }
+    }
+
+    /// Drops the oldest samples after they were uploaded or rejected by server
+    ///
+    /// Upload counts as done once the buffer is empty, otherwise it is retried next wake.
+    pub fn remove_uploaded(&mut self, count: usize) {
+        let count = count.min(self.len as usize);
+        self.samples.copy_within(count..self.len as usize, 0);
+        self.len -= count as u32;
+        if self.len == 0 {
+            self.last_upload = self.wakes;
+        }
+    }
+
+    /// Stores alarm state of the latest sample
+    ///
+    /// # Returns
+    /// - true, if alarm state changed
+    pub fn set_alarm(&mut self, active: bool) -> bool {
+        let changed = (self.alarm != 0) != active;
+        self.alarm = u32::from(active);
+        changed
+    }
+
+    /// FNV-1a over all fields except checksum
+    fn calculate_checksum(&self) -> u32 {
+        let words = [
+            self.magic,
+            self.wakes,
+            self.last_upload,
+            self.alarm,
+            self.len,
+        ]
+        .into_iter()
+        .chain(self.samples.iter().flat_map(|sample| {
+            [
+                sample.wake,
+                sample.temperature.to_bits(),
+                sample.humidity.to_bits(),
+            ]
+        }));
+
+        words
+            .flat_map(u32::to_le_bytes)
+            .fold(0x811C_9DC5, |hash, byte| {
+                (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+            })
+    }
+}
+
+/// Decides when to bring WiFi up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// Upload every this count of wakes
+    pub upload_every: u32,
+}
+
+impl Schedule {
+    /// # Arguments
+    /// - `alarm_changed` - alarm triggered or cleared on this wake
+    pub fn should_upload(&self, state: &RtcState, alarm_changed: bool) -> bool {
+        alarm_changed || state.is_full() || self.upload_every <= state.wakes_since_upload()
+    }
+}
+
+/// Uploaded buffer
+#[derive(Debug, Serialize)]
+pub struct Batch {
+    pub device: &'static str,
+    /// Number of current wake, sample age is `(wake - sample.wake) * interval_s`
+    pub wake: u32,
+    /// Sleep interval between wakes
+    pub interval_s: u64,
+    /// Measured on this wake, None if battery is not monitored
+    pub battery: Option<BatteryStatus>,
+    /// Of sample temperatures
+    pub unit: TemperatureUnit,
+    pub samples: Vec<Sample, UPLOAD_CHUNK>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(wake: u32) -> Sample {
+        Sample {
+            wake,
+            temperature: 21.5,
+            humidity: 40.0,
+        }
+    }
+
+    /// Wakes once and buffers a sample like battery mode does
+    fn wake(state: &mut RtcState) -> u32 {
+        let wake = state.wake();
+        state.push(sample(wake));
+        wake
+    }
+
+    #[test]
+    fn uploads_every_n_wakes() {
+        let schedule = Schedule { upload_every: 3 };
+        let mut state = RtcState::new();
+
+        let mut uploads = [false; 6];
+        for upload in &mut uploads {
+            wake(&mut state);
+            *upload = schedule.should_upload(&state, false);
+            if *upload {
+                state.remove_uploaded(BUFFER_CAP);
+            }
+        }
+
+        assert_eq!(uploads, [false, false, true, false, false, true]);
+        assert!(state.samples().is_empty());
+    }
+
+    #[test]
+    fn uploads_on_alarm_change() {
+        let schedule = Schedule { upload_every: 12 };
+        let mut state = RtcState::new();
+        wake(&mut state);
+
+        assert!(!state.set_alarm(false));
+        assert!(!schedule.should_upload(&state, false));
+        assert!(state.set_alarm(true));
+        assert!(schedule.should_upload(&state, true));
+        assert!(!state.set_alarm(true));
+        assert!(state.set_alarm(false));
+    }
+
+    #[test]
+    fn full_buffer_drops_oldest_and_uploads() {
+        let schedule = Schedule {
+            upload_every: u32::MAX,
+        };
+        let mut state = RtcState::new();
+        for _ in 0..BUFFER_CAP + 3 {
+            wake(&mut state);
+        }
+
+        assert!(state.is_full());
+        assert!(schedule.should_upload(&state, false));
+        let wakes: [u32; BUFFER_CAP] = core::array::from_fn(|index| index as u32 + 4);
+        assert!(state
+            .samples()
+            .iter()
+            .map(|sample| sample.wake)
+            .eq(wakes.into_iter()));
+    }
+
+    #[test]
+    fn wakes_since_upload_survive_wrap() {
+        let mut state = RtcState::new();
+        state.wakes = u32::MAX - 1;
+        state.last_upload = u32::MAX - 1;
+        state.wake();
+        state.wake();
+        assert_eq!(state.wakes(), 0);
+        assert_eq!(state.wakes_since_upload(), 2);
+    }
+
+    #[test]
+    fn partial_upload_keeps_rest_for_next_wake() {
+        let mut state = RtcState::new();
+        for _ in 0..UPLOAD_CHUNK + 2 {
+            wake(&mut state);
+        }
+
+        let batch = state.next_batch("device", 300, None, TemperatureUnit::Celsius);
+        assert_eq!(batch.samples.len(), UPLOAD_CHUNK);
+        assert_eq!(batch.samples[0].wake, 1);
+
+        state.remove_uploaded(UPLOAD_CHUNK);
+        assert_eq!(state.samples(), &[sample(9), sample(10)]);
+        assert_eq!(state.wakes_since_upload(), 10);
+
+        state.remove_uploaded(UPLOAD_CHUNK);
+        assert!(state.samples().is_empty());
+        assert_eq!(state.wakes_since_upload(), 0);
+    }
+
+    #[test]
+    fn worst_case_batch_fits_body() {
+        let mut state = RtcState::new();
+        state.wakes = u32::MAX;
+        for _ in 0..BUFFER_CAP {
+            state.push(Sample {
+                wake: u32::MAX,
+                temperature: -1.1754944e-38,
+                humidity: -3.4028235e38,
+            });
+        }
+        let device = "device-name-of-max-length-is-32c";
+        assert_eq!(device.len(), MAX_DEVICE_LEN);
+        let battery = BatteryStatus {
+            voltage: -1.1754944e-38,
+            percentage: u8::MAX,
+            low: false,
+        };
+
+        let mut body = [0_u8; MAX_BATCH_LEN];
+        let batch = state.next_batch(device, u64::MAX, Some(battery), TemperatureUnit::Fahrenheit);
+        assert!(serde_json_core::to_slice(&batch, &mut body).is_ok());
+    }
+
+    #[test]
+    fn batch_is_in_settings_unit() {
+        let mut state = RtcState::new();
+        wake(&mut state);
+
+        let mut body = [0_u8; MAX_BATCH_LEN];
+        let batch = state.next_batch("device", 300, None, TemperatureUnit::Fahrenheit);
+        let len = serde_json_core::to_slice(&batch, &mut body).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&body[..len]).unwrap(),
+            r#"{"device":"device","wake":1,"interval_s":300,"battery":null,"unit":"fahrenheit","samples":[{"wake":1,"temperature":70.7,"humidity":40.0}]}"#
+        );
+        // Buffer stays in °C
+        assert_eq!(state.samples(), &[sample(1)]);
+    }
+
+    #[test]
+    fn checksum_detects_corruption() {
+        let mut state = RtcState::new();
+        wake(&mut state);
+        state.seal();
+
+        let mut copy = state;
+        assert!(copy.validate());
+        assert_eq!(copy.samples(), state.samples());
+
+        copy.samples[0].temperature = 99.0;
+        assert!(!copy.validate());
+        assert!(copy.samples().is_empty());
+        assert_eq!(copy.wakes(), 0);
+    }
+}
@@ -0,0 +1,123 @@
+//!
+//! Device-wide events bus
+//!
+
+use serde::{Deserialize, Serialize};
+
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    pubsub::{ImmediatePublisher, PubSubChannel, Subscriber},
+};
+
+/// Count of events stored for lagging subscribers
+pub const EVENTS_CAP: usize = 8;
+/// Max count of simultaneous subscribers
+pub const EVENTS_SUBS: usize = 4;
+/// Max count of simultaneous awaiting publishers
+pub const EVENTS_PUBS: usize = 4;
+
+pub type EventBus =
+    PubSubChannel<CriticalSectionRawMutex, Event, EVENTS_CAP, EVENTS_SUBS, EVENTS_PUBS>;
+
+pub type EventPublisher = ImmediatePublisher<
+    'static,
+    CriticalSectionRawMutex,
+    Event,
+    EVENTS_CAP,
+    EVENTS_SUBS,
+    EVENTS_PUBS,
+>;
+
+pub type EventSubscriber =
+    Subscriber<'static, CriticalSectionRawMutex, Event, EVENTS_CAP, EVENTS_SUBS, EVENTS_PUBS>;
+
+/// Measured quantity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantity {
+    Temperature,
+    Humidity,
+    /// Battery state of charge in %
+    Battery,
+    /// CO2 concentration in ppm
+    Co2,
+}
+
+impl Quantity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Quantity::Temperature => "temperature",
+            Quantity::Humidity => "humidity",
+            Quantity::Battery => "battery",
+            Quantity::Co2 => "co2",
+        }
+    }
+}
+
+/// Which threshold was crossed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum AlarmKind {
+    High,
+    Low,
+}
+
+impl AlarmKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlarmKind::High => "high",
+            AlarmKind::Low => "low",
+        }
+    }
+}
+
+/// Alarm details attached to alarm events
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Alarm {
+    pub quantity: Quantity,
+    pub kind: AlarmKind,
+    /// The value caused the event
+    pub value: f32,
+    /// The threshold crossed
+    pub threshold: f32,
+}
+
+/// Heating of ambient sensor by the chip, attached to self-heating events
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Heating {
+    pub sensor: &'static str,
+    /// Chip temperature above ambient in °C
+    pub difference: f32,
+    /// Warning level in °C
+    pub warning: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Event {
+    /// Device started
+    Boot,
+    AlarmTriggered(Alarm),
+    AlarmCleared(Alarm),
+    /// Sensor with name given stopped responding
+    SensorFault(&'static str),
+    /// Sensor with name given responds again after fault
+    SensorRecovered(&'static str),
+    /// Chip heats ambient sensor above warning level, its readings are too high
+    SelfHeatingStarted(Heating),
+    /// Difference of chip and ambient sensor is back below warning level
+    SelfHeatingStopped(Heating),
+}
+
+impl Event {
+    /// Name of event used in external notifications
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Boot => "boot",
+            Event::AlarmTriggered(_) => "alarm_triggered",
+            Event::AlarmCleared(_) => "alarm_cleared",
+            Event::SensorFault(_) => "sensor_fault",
+            Event::SensorRecovered(_) => "sensor_recovered",
+            Event::SelfHeatingStarted(_) => "self_heating_started",
+            Event::SelfHeatingStopped(_) => "self_heating_stopped",
+        }
+    }
+}
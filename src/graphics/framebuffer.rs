@@ -0,0 +1,378 @@
+use crate::graphics::font;
+
+/// Monochrome framebuffer in SSD1306 memory layout
+///
+/// Memory split into pages of 8 rows, each byte is a column of one page with
+/// the least significant bit at the top.
+///
+/// Changed columns of every page are tracked, so only they can be sent to display
+pub struct Framebuffer<const WIDTH: usize, const PAGES: usize> {
+    pages: [[u8; WIDTH]; PAGES],
+    /// Changed columns `start..end` of each page since [`Self::clear_dirty`]
+    dirty: [Option<(usize, usize)>; PAGES],
+}
+
+impl<const WIDTH: usize, const PAGES: usize> Default for Framebuffer<WIDTH, PAGES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const WIDTH: usize, const PAGES: usize> Framebuffer<WIDTH, PAGES> {
+    pub const WIDTH: usize = WIDTH;
+    pub const HEIGHT: usize = PAGES * 8;
+
+    /// Creates empty framebuffer with everything marked dirty
+    pub const fn new() -> Self {
+        Self {
+            pages: [[0; WIDTH]; PAGES],
+            dirty: [Some((0, WIDTH)); PAGES],
+        }
+    }
+
+    /// Gets raw bytes of page
+    pub fn page(&self, page: usize) -> &[u8; WIDTH] {
+        &self.pages[page]
+    }
+
+    /// Gets changed columns `start..end` of page
+    pub fn dirty(&self, page: usize) -> Option<(usize, usize)> {
+        self.dirty[page]
+    }
+
+    /// Forgets changes, e.g. after they sent to display
+    pub fn clear_dirty(&mut self) {
+        self.dirty = [None; PAGES];
+    }
+
+    /// Marks everything changed, e.g. when display content is unknown
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty = [Some((0, WIDTH)); PAGES];
+    }
+
+    pub fn clear(&mut self) {
+        for page in 0..PAGES {
+            for x in 0..WIDTH {
+                self.write(page, x, 0);
+            }
+        }
+    }
+
+    /// Takes content of other framebuffer, marking only columns that differ dirty
+    pub fn copy_from(&mut self, other: &Self) {
+        for (page, bytes) in other.pages.iter().enumerate() {
+            for (x, byte) in bytes.iter().enumerate() {
+                self.write(page, x, *byte);
+            }
+        }
+    }
+
+    /// Sets pixel, pixels outside of buffer are ignored
+    pub fn set_pixel(&mut self, x: i32, y: i32, on: bool) {
+        if x < 0 || y < 0 || WIDTH as i32 <= x || Self::HEIGHT as i32 <= y {
+            return;
+        }
+
+        let (x, y) = (x as usize, y as usize);
+        let mask = 1 << (y % 8);
+        let byte = self.pages[y / 8][x];
+        self.write(y / 8, x, if on { byte | mask } else { byte & !mask });
+    }
+
+    /// Writes byte, marking column dirty if it changed
+    fn write(&mut self, page: usize, x: usize, byte: u8) {
+        if self.pages[page][x] == byte {
+            return;
+        }
+
+        self.pages[page][x] = byte;
+        self.dirty[page] = Some(match self.dirty[page] {
+            Some((start, end)) => (start.min(x), end.max(x + 1)),
+            None => (x, x + 1),
+        });
+    }
+
+    /// Gets pixel, pixels outside of buffer are off
+    pub fn pixel(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || WIDTH as i32 <= x || Self::HEIGHT as i32 <= y {
+            return false;
+        }
+
+        let (x, y) = (x as usize, y as usize);
+        self.pages[y / 8][x] & (1 << (y % 8)) != 0
+    }
+
+    pub fn hline(&mut self, x: i32, y: i32, len: i32) {
+        for dx in 0..len {
+            self.set_pixel(x + dx, y, true);
+        }
+    }
+
+    pub fn vline(&mut self, x: i32, y: i32, len: i32) {
+        for dy in 0..len {
+            self.set_pixel(x, y + dy, true);
+        }
+    }
+
+    /// Draws line with Bresenham's algorithm
+    pub fn line(&mut self, (x0, y0): (i32, i32), (x1, y1): (i32, i32)) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+
+        let (mut x, mut y) = (x0, y0);
+        let mut err = dx + dy;
+        loop {
+            self.set_pixel(x, y, true);
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            let e2 = 2 * err;
+            if dy <= e2 {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Draws rectangle outline
+    pub fn rect(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+
+        self.hline(x, y, width);
+        self.hline(x, y + height - 1, width);
+        self.vline(x, y, height);
+        self.vline(x + width - 1, y, height);
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, on: bool) {
+        for dy in 0..height {
+            for dx in 0..width {
+                self.set_pixel(x + dx, y + dy, on);
+            }
+        }
+    }
+
+    /// Draws character with top-left corner at position
+    ///
+    /// # Arguments
+    /// - `scale` - size multiplier, 1 means [`font::GLYPH_WIDTH`] x [`font::GLYPH_HEIGHT`]
+    pub fn draw_char(&mut self, x: i32, y: i32, c: char, scale: i32) {
+        for (col, bits) in font::glyph(c).iter().enumerate() {
+            for row in 0..font::GLYPH_HEIGHT {
+                if bits & (1 << row) != 0 {
+                    self.fill_rect(x + col as i32 * scale, y + row * scale, scale, scale, true);
+                }
+            }
+        }
+    }
+
+    /// Draws text in one line with top-left corner at position
+    ///
+    /// # Returns
+    /// x coordinate after the last character
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, scale: i32) -> i32 {
+        let mut x = x;
+        for c in text.chars() {
+            self.draw_char(x, y, c, scale);
+            x += font::ADVANCE * scale;
+        }
+
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Small = Framebuffer<16, 2>;
+
+    /// Asserts area with top-left corner at origin against rows of `#` for on and `.` for off pixels
+    fn assert_bitmap(fb: &Small, rows: &[&str]) {
+        for (y, row) in rows.iter().enumerate() {
+            for (x, pixel) in row.chars().enumerate() {
+                assert_eq!(
+                    fb.pixel(x as i32, y as i32),
+                    pixel == '#',
+                    "pixel at {x}, {y}"
+                );
+            }
+        }
+    }
+
+    fn blank() -> Small {
+        let mut fb = Small::new();
+        fb.clear_dirty();
+        fb
+    }
+
+    #[test]
+    fn pixels_in_page_layout() {
+        let mut fb = blank();
+        fb.set_pixel(0, 0, true);
+        fb.set_pixel(0, 7, true);
+        fb.set_pixel(3, 9, true);
+        assert_eq!(fb.page(0)[0], 0x81);
+        assert_eq!(fb.page(1)[3], 0x02);
+
+        fb.set_pixel(0, 0, false);
+        assert_eq!(fb.page(0)[0], 0x80);
+    }
+
+    #[test]
+    fn pixels_outside_are_clipped() {
+        let mut fb = blank();
+        fb.set_pixel(-1, 0, true);
+        fb.set_pixel(16, 0, true);
+        fb.set_pixel(0, 16, true);
+        assert!(!fb.pixel(-1, 0));
+        assert!(!fb.pixel(16, 0));
+        assert!((0..2).all(|page| fb.dirty(page).is_none()));
+    }
+
+    #[test]
+    fn rectangles() {
+        let mut fb = blank();
+        fb.rect(1, 1, 5, 4);
+        fb.fill_rect(8, 2, 2, 2, true);
+        assert_bitmap(
+            &fb,
+            &[
+                "...........", //
+                ".#####.....", //
+                ".#...#..##.", //
+                ".#...#..##.", //
+                ".#####.....", //
+                "...........", //
+            ],
+        );
+    }
+
+    #[test]
+    fn empty_rectangle_draws_nothing() {
+        let mut fb = blank();
+        fb.rect(1, 1, 0, 4);
+        fb.rect(1, 1, 4, -1);
+        assert!((0..2).all(|page| fb.dirty(page).is_none()));
+    }
+
+    #[test]
+    fn lines() {
+        let mut fb = blank();
+        fb.line((0, 0), (4, 2));
+        fb.line((6, 4), (6, 1));
+        fb.line((10, 4), (7, 4));
+        assert_bitmap(
+            &fb,
+            &[
+                "#..........", //
+                ".##...#....", //
+                "...##.#....", //
+                "......#....", //
+                "......#####", //
+            ],
+        );
+    }
+
+    #[test]
+    fn scaled_character() {
+        let mut fb = blank();
+        fb.draw_char(1, 0, '-', 2);
+        assert_bitmap(
+            &fb,
+            &[
+                "............", //
+                "............", //
+                "............", //
+                "............", //
+                "............", //
+                "............", //
+                ".##########.", //
+                ".##########.", //
+                "............", //
+            ],
+        );
+    }
+
+    #[test]
+    fn text_advances_by_glyph_and_gap() {
+        let mut fb = blank();
+        assert_eq!(fb.draw_text(1, 1, "::", 1), 1 + 2 * font::ADVANCE);
+        assert_bitmap(
+            &fb,
+            &[
+                "...............", //
+                "...............", //
+                "..##....##.....", //
+                "..##....##.....", //
+                "...............", //
+                "..##....##.....", //
+                "..##....##.....", //
+                "...............", //
+            ],
+        );
+    }
+
+    #[test]
+    fn new_buffer_is_dirty() {
+        let fb = Small::new();
+        assert_eq!(fb.dirty(0), Some((0, 16)));
+        assert_eq!(fb.dirty(1), Some((0, 16)));
+    }
+
+    #[test]
+    fn dirty_spans_changed_columns_of_page() {
+        let mut fb = blank();
+        fb.set_pixel(5, 9, true);
+        fb.set_pixel(2, 10, true);
+        assert_eq!(fb.dirty(0), None);
+        assert_eq!(fb.dirty(1), Some((2, 6)));
+    }
+
+    #[test]
+    fn unchanged_pixels_stay_clean() {
+        let mut fb = blank();
+        fb.set_pixel(5, 9, true);
+        fb.clear_dirty();
+
+        fb.set_pixel(5, 9, true);
+        fb.set_pixel(6, 9, false);
+        assert_eq!(fb.dirty(1), None);
+
+        fb.clear();
+        assert_eq!(fb.dirty(0), None);
+        assert_eq!(fb.dirty(1), Some((5, 6)));
+    }
+
+    #[test]
+    fn copy_marks_differences_only() {
+        let mut fb = blank();
+        fb.set_pixel(3, 3, true);
+        fb.clear_dirty();
+
+        let mut next = Small::new();
+        next.set_pixel(3, 3, true);
+        next.set_pixel(12, 14, true);
+        fb.copy_from(&next);
+
+        assert!(fb.pixel(12, 14));
+        assert_eq!(fb.dirty(0), None);
+        assert_eq!(fb.dirty(1), Some((12, 13)));
+    }
+
+    #[test]
+    fn mark_all_dirty() {
+        let mut fb = blank();
+        fb.mark_all_dirty();
+        assert_eq!(fb.dirty(1), Some((0, 16)));
+    }
+}
@@ -0,0 +1,209 @@
+//!
+//! Effects rendered on LED strip
+//!
+
+use crate::{color_gradient::Gradient, status_indicator::Pattern};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// All pixels with one color
+    Solid((u8, u8, u8)),
+    /// Value shown as bar from the first pixel, each pixel colored by gradient at its position
+    BarGraph {
+        gradient: Gradient,
+        min: f32,
+        max: f32,
+    },
+    /// All pixels go brighter and dimmer within period
+    Breathe { color: (u8, u8, u8), period_ms: u32 },
+    /// Dot running along strip with fading tail
+    Chase {
+        color: (u8, u8, u8),
+        /// Time for dot to pass one pixel
+        step_ms: u32,
+        /// Count of fading pixels behind dot
+        tail: usize,
+    },
+}
+
+impl Effect {
+    /// Renders effect frame into `pixels`
+    ///
+    /// # Arguments
+    /// - `elapsed_ms` - time from effect start used to animate
+    /// - `value` - sensor value used by [`Effect::BarGraph`]
+    pub fn render(&self, elapsed_ms: u64, value: f32, pixels: &mut [(u8, u8, u8)]) {
+        match *self {
+            Effect::Solid(color) => pixels.fill(color),
+            Effect::BarGraph { gradient, min, max } => {
+                render_bar(pixels, &gradient, min, max, value)
+            }
+            Effect::Breathe { color, period_ms } => {
+                let brightness = Pattern::Breathe { period_ms }.brightness(elapsed_ms);
+                pixels.fill(dim(color, brightness));
+            }
+            Effect::Chase {
+                color,
+                step_ms,
+                tail,
+            } => {
+                let len = pixels.len();
+                if len == 0 {
+                    return;
+                }
+
+                let head = (elapsed_ms / step_ms.max(1) as u64 % len as u64) as usize;
+                for (idx, pixel) in pixels.iter_mut().enumerate() {
+                    // Distance behind head, wrapping around strip end
+                    let behind = (head + len - idx) % len;
+                    *pixel = if behind <= tail {
+                        let brightness = 255 - (behind * 255 / (tail + 1)) as u8;
+                        dim(color, brightness)
+                    } else {
+                        (0, 0, 0)
+                    };
+                }
+            }
+        }
+    }
+}
+
+fn render_bar(pixels: &mut [(u8, u8, u8)], gradient: &Gradient, min: f32, max: f32, value: f32) {
+    let len = pixels.len();
+    if len == 0 {
+        return;
+    }
+
+    let range = max - min;
+    let filled = if range <= 0.0 || value.is_nan() {
+        0.0
+    } else {
+        ((value - min) / range).clamp(0.0, 1.0) * len as f32
+    };
+
+    for (idx, pixel) in pixels.iter_mut().enumerate() {
+        // Value represented by the pixel center
+        let position = min + range * (idx as f32 + 0.5) / len as f32;
+        let color = gradient.color_at(position);
+
+        let fill = (filled - idx as f32).clamp(0.0, 1.0);
+        *pixel = dim(color, (fill * 255.0) as u8);
+    }
+}
+
+fn dim((r, g, b): (u8, u8, u8), brightness: u8) -> (u8, u8, u8) {
+    let scale = |channel: u8| ((channel as u16) * (brightness as u16) / 255) as u8;
+    (scale(r), scale(g), scale(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_gradient::GradientStop;
+
+    const OFF: (u8, u8, u8) = (0, 0, 0);
+    const BAR: Effect = Effect::BarGraph {
+        gradient: Gradient::Stops {
+            stops: &[
+                GradientStop::new(0.0, 0, 0, 255),
+                GradientStop::new(1.0, 255, 0, 0),
+            ],
+            low: 0.0,
+            high: 40.0,
+        },
+        min: 0.0,
+        max: 40.0,
+    };
+    /// Colors of bar pixels at 5, 15, 25 and 35
+    const BAR_COLORS: [(u8, u8, u8); 4] = [(32, 0, 223), (96, 0, 159), (159, 0, 96), (223, 0, 32)];
+
+    fn render<const N: usize>(effect: Effect, elapsed_ms: u64, value: f32) -> [(u8, u8, u8); N] {
+        let mut pixels = [(1, 1, 1); N];
+        effect.render(elapsed_ms, value, &mut pixels);
+        pixels
+    }
+
+    #[test]
+    fn solid_fills_strip() {
+        assert_eq!(
+            render::<3>(Effect::Solid((1, 2, 3)), 100, 0.0),
+            [(1, 2, 3); 3]
+        );
+    }
+
+    #[test]
+    fn bar_fills_up_to_value() {
+        assert_eq!(render::<4>(BAR, 0, 40.0), BAR_COLORS);
+        assert_eq!(
+            render::<4>(BAR, 0, 20.0),
+            [BAR_COLORS[0], BAR_COLORS[1], OFF, OFF]
+        );
+        // Pixel filled halfway is half bright
+        assert_eq!(
+            render::<4>(BAR, 0, 25.0),
+            [BAR_COLORS[0], BAR_COLORS[1], (79, 0, 47), OFF]
+        );
+    }
+
+    #[test]
+    fn bar_clamps_and_hides_missing_value() {
+        assert_eq!(render::<4>(BAR, 0, 100.0), BAR_COLORS);
+        assert_eq!(render::<4>(BAR, 0, -10.0), [OFF; 4]);
+        assert_eq!(render::<4>(BAR, 0, f32::NAN), [OFF; 4]);
+
+        let empty = Effect::BarGraph {
+            gradient: Gradient::Kelvin {
+                cold: 10.0,
+                warm: 30.0,
+            },
+            min: 20.0,
+            max: 20.0,
+        };
+        assert_eq!(render::<4>(empty, 0, 25.0), [OFF; 4]);
+    }
+
+    #[test]
+    fn breathe_follows_period() {
+        let breathe = Effect::Breathe {
+            color: (255, 128, 0),
+            period_ms: 1000,
+        };
+        assert_eq!(render::<2>(breathe, 0, 0.0), [OFF; 2]);
+        assert_eq!(render::<2>(breathe, 250, 0.0), [(127, 63, 0); 2]);
+        assert_eq!(render::<2>(breathe, 500, 0.0), [(255, 128, 0); 2]);
+        assert_eq!(render::<2>(breathe, 1250, 0.0), [(127, 63, 0); 2]);
+    }
+
+    #[test]
+    fn chase_moves_with_fading_tail() {
+        let chase = Effect::Chase {
+            color: (255, 0, 0),
+            step_ms: 100,
+            tail: 2,
+        };
+        // Tail wraps around strip end
+        assert_eq!(
+            render::<5>(chase, 0, 0.0),
+            [(255, 0, 0), OFF, OFF, (85, 0, 0), (170, 0, 0)]
+        );
+        assert_eq!(
+            render::<5>(chase, 250, 0.0),
+            [(85, 0, 0), (170, 0, 0), (255, 0, 0), OFF, OFF]
+        );
+        assert_eq!(render::<5>(chase, 500, 0.0), render::<5>(chase, 0, 0.0));
+    }
+
+    #[test]
+    fn empty_strip_is_fine() {
+        for effect in [
+            BAR,
+            Effect::Chase {
+                color: (255, 0, 0),
+                step_ms: 0,
+                tail: 2,
+            },
+        ] {
+            assert_eq!(render::<0>(effect, 100, 20.0), []);
+        }
+    }
+}
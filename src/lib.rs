@@ -1,14 +1,43 @@
 #![no_std]
 #![feature(impl_trait_in_assoc_type)]
+// Web router type nests one level per route
+#![recursion_limit = "256"]
 
+pub mod alarm;
+pub mod auth;
+pub mod battery;
+#[cfg(target_arch = "riscv32")]
 pub mod boards;
+pub mod calibration;
+pub mod color_gradient;
 pub mod color_temp;
+pub mod discovery;
 pub mod drivers;
+pub mod duty_cycle;
+pub mod events;
+pub mod graphics;
+pub mod led_effects;
+#[cfg(target_arch = "riscv32")]
 pub mod load_indicator;
+pub mod net;
+pub mod ota;
+pub mod partition;
+pub mod psychrometrics;
+pub mod self_heating;
 pub mod sensor_data;
+pub mod settings;
+pub mod sha256;
+pub mod status_indicator;
 pub mod sync;
+#[cfg(test)]
+mod test_time;
+pub mod tls;
+pub mod ui;
+pub mod units;
+#[cfg(target_arch = "riscv32")]
 pub mod web;
 
+#[cfg(target_arch = "riscv32")]
 macro_rules! mk_static {
     ($t:ty,$val:expr) => {{
         static STATIC_CELL: static_cell::StaticCell<$t> = static_cell::StaticCell::new();
@@ -18,4 +47,5 @@ macro_rules! mk_static {
     }};
 }
 
+#[cfg(target_arch = "riscv32")]
 pub(crate) use mk_static;
@@ -0,0 +1,263 @@
+//!
+//! Minimal HTTP/1.1 client over embassy-net TCP sockets
+//!
+
+use core::fmt::Write as _;
+
+use embassy_net::{dns::DnsQueryType, tcp::TcpSocket, Stack};
+use embassy_time::Duration;
+use embedded_io_async::Write as _;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    InvalidUrl,
+    /// Only plain `http://` is supported
+    UnsupportedScheme,
+    Dns,
+    Connect,
+    Io,
+    InvalidResponse,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::InvalidUrl => "invalid url",
+            Error::UnsupportedScheme => "unsupported scheme",
+            Error::Dns => "dns",
+            Error::Connect => "connect",
+            Error::Io => "io",
+            Error::InvalidResponse => "invalid response",
+        }
+    }
+}
+
+/// Parsed `http://host[:port][/path]` URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Url<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub path: &'a str,
+}
+
+impl<'a> Url<'a> {
+    pub fn parse(url: &'a str) -> Result<Self, Error> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            return Err(if url.contains("://") {
+                Error::UnsupportedScheme
+            } else {
+                Error::InvalidUrl
+            });
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| Error::InvalidUrl)?),
+            None => (authority, 80),
+        };
+
+        if host.is_empty() {
+            return Err(Error::InvalidUrl);
+        }
+
+        Ok(Self { host, port, path })
+    }
+}
+
+pub struct HttpClient<'a> {
+    stack: Stack<'a>,
+    rx_buffer: &'a mut [u8],
+    tx_buffer: &'a mut [u8],
+    timeout: Duration,
+}
+
+impl<'a> HttpClient<'a> {
+    /// Creates client
+    ///
+    /// # Arguments
+    /// - `stack` - network stack to use
+    /// - `rx_buffer`, `tx_buffer` - TCP socket buffers, reused between requests
+    /// - `timeout` - socket inactivity timeout
+    pub fn new(
+        stack: Stack<'a>,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            stack,
+            rx_buffer,
+            tx_buffer,
+            timeout,
+        }
+    }
+
+    /// Sends `body` with POST request as JSON
+    ///
+    /// # Returns
+    /// HTTP status code of response
+    pub async fn post_json(&mut self, url: &Url<'_>, body: &[u8]) -> Result<u16, Error> {
+        let address = self
+            .stack
+            .dns_query(url.host, DnsQueryType::A)
+            .await
+            .map_err(|_| Error::Dns)?
+            .first()
+            .copied()
+            .ok_or(Error::Dns)?;
+
+        let mut socket = TcpSocket::new(self.stack, self.rx_buffer, self.tx_buffer);
+        socket.set_timeout(Some(self.timeout));
+        socket
+            .connect((address, url.port))
+            .await
+            .map_err(|_| Error::Connect)?;
+
+        let mut content_length = heapless::String::<8>::new();
+        write!(content_length, "{}", body.len()).map_err(|_| Error::Io)?;
+
+        for part in [
+            "POST ",
+            url.path,
+            " HTTP/1.1\r\nHost: ",
+            url.host,
+            "\r\nContent-Type: application/json\r\nContent-Length: ",
+            content_length.as_str(),
+            "\r\nConnection: close\r\n\r\n",
+        ] {
+            socket
+                .write_all(part.as_bytes())
+                .await
+                .map_err(|_| Error::Io)?;
+        }
+        socket.write_all(body).await.map_err(|_| Error::Io)?;
+        socket.flush().await.map_err(|_| Error::Io)?;
+
+        let mut status_line = [0_u8; 32];
+        let mut len = 0;
+        while !status_line[..len].contains(&b'\n') && len < status_line.len() {
+            let read = socket
+                .read(&mut status_line[len..])
+                .await
+                .map_err(|_| Error::Io)?;
+            if read == 0 {
+                break;
+            }
+            len += read;
+        }
+
+        socket.close();
+        // Give the FIN a chance to be sent, the response body is not interesting
+        socket.flush().await.ok();
+
+        parse_status(&status_line[..len])
+    }
+}
+
+/// Parses status code from `HTTP/1.x NNN ...` line
+fn parse_status(data: &[u8]) -> Result<u16, Error> {
+    let line = data.split(|byte| *byte == b'\n').next().unwrap_or_default();
+    let line = core::str::from_utf8(line).map_err(|_| Error::InvalidResponse)?;
+    let mut parts = line.split(' ');
+
+    if !parts
+        .next()
+        .is_some_and(|version| version.starts_with("HTTP/1."))
+    {
+        return Err(Error::InvalidResponse);
+    }
+
+    parts
+        .next()
+        .and_then(|code| code.trim_end().parse().ok())
+        .ok_or(Error::InvalidResponse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_url() {
+        assert_eq!(
+            Url::parse("http://192.168.1.10:8080/hook/a?b=c"),
+            Ok(Url {
+                host: "192.168.1.10",
+                port: 8080,
+                path: "/hook/a?b=c",
+            })
+        );
+        assert_eq!(
+            Url::parse("http://example.com"),
+            Ok(Url {
+                host: "example.com",
+                port: 80,
+                path: "/",
+            })
+        );
+        assert_eq!(
+            Url::parse("http://example.com/"),
+            Ok(Url {
+                host: "example.com",
+                port: 80,
+                path: "/",
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_url() {
+        assert_eq!(Url::parse("example.com/hook"), Err(Error::InvalidUrl));
+        assert_eq!(Url::parse(""), Err(Error::InvalidUrl));
+        assert_eq!(Url::parse("http://"), Err(Error::InvalidUrl));
+        assert_eq!(Url::parse("http://:8080/"), Err(Error::InvalidUrl));
+        assert_eq!(Url::parse("http://host:port/"), Err(Error::InvalidUrl));
+        assert_eq!(Url::parse("http://host:65536/"), Err(Error::InvalidUrl));
+    }
+
+    #[test]
+    fn rejects_other_schemes() {
+        assert_eq!(
+            Url::parse("https://example.com/"),
+            Err(Error::UnsupportedScheme)
+        );
+        assert_eq!(
+            Url::parse("ftp://example.com/"),
+            Err(Error::UnsupportedScheme)
+        );
+    }
+
+    #[test]
+    fn parses_status_line() {
+        assert_eq!(parse_status(b"HTTP/1.1 200 OK\r\nServer: x\r\n"), Ok(200));
+        assert_eq!(parse_status(b"HTTP/1.0 404 Not Found\r\n"), Ok(404));
+        assert_eq!(parse_status(b"HTTP/1.1 204\r\n"), Ok(204));
+        // Read stopped before the end of line
+        assert_eq!(parse_status(b"HTTP/1.1 500 Internal"), Ok(500));
+    }
+
+    #[test]
+    fn rejects_invalid_status_line() {
+        assert_eq!(parse_status(b""), Err(Error::InvalidResponse));
+        assert_eq!(
+            parse_status(b"HTTP/2 200 OK\r\n"),
+            Err(Error::InvalidResponse)
+        );
+        assert_eq!(
+            parse_status(b"SSH-2.0-OpenSSH\r\n"),
+            Err(Error::InvalidResponse)
+        );
+        assert_eq!(
+            parse_status(b"HTTP/1.1 OK\r\n"),
+            Err(Error::InvalidResponse)
+        );
+        assert_eq!(
+            parse_status(b"HTTP/1.1 \xff\r\n"),
+            Err(Error::InvalidResponse)
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//!
+//! Redirect of plain HTTP to HTTPS
+//!
+//! Browsers go to port 80 when only the device address is typed, so when the web
+//! interface is on HTTPS every request there is sent to the same host and path on it.
+//! Temporary, so browsers do not remember it after HTTPS is disabled.
+//!
+
+use core::fmt::Write as _;
+
+use defmt::warn;
+use embassy_net::{tcp::TcpSocket, Stack};
+use embassy_time::Duration;
+use embedded_io_async::Write as _;
+use heapless::String;
+
+/// Longest redirect target, longer requests get 400
+pub const MAX_LOCATION_LEN: usize = 256;
+/// Longest request head read, the rest is ignored
+const MAX_HEAD_LEN: usize = 1024;
+
+/// HTTPS URL of request head
+///
+/// # Returns
+/// None, if head has no `Host` header or an invalid target
+pub fn https_location(head: &[u8]) -> Option<String<MAX_LOCATION_LEN>> {
+    let head = core::str::from_utf8(head).ok()?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let _method = request_line.next()?;
+    let target = request_line.next()?;
+    if !target.starts_with('/') || target.chars().any(|c| c.is_ascii_control()) {
+        return None;
+    }
+
+    let host = lines.take_while(|line| !line.is_empty()).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("host").then_some(value.trim())
+    })?;
+    // Port of plain HTTP does not apply to HTTPS, brackets of IPv6 address stay
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '[' | ']' | ':');
+    if host.is_empty() || !host.chars().all(valid) {
+        return None;
+    }
+
+    let mut location = String::new();
+    write!(location, "https://{host}{target}").ok()?;
+    Some(location)
+}
+
+/// Answers requests on port 80 with redirect to HTTPS
+#[embassy_executor::task]
+pub async fn redirect_task(stack: Stack<'static>) -> ! {
+    let mut rx_buffer = [0; MAX_HEAD_LEN];
+    let mut tx_buffer = [0; 512];
+    let mut head = [0_u8; MAX_HEAD_LEN];
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(5)));
+        if let Err(err) = socket.accept(80).await {
+            warn!("redirect: accept failed: {}", err);
+            continue;
+        }
+
+        let len = read_head(&mut socket, &mut head).await;
+        let mut response = String::<{ MAX_LOCATION_LEN + 128 }>::new();
+        let written = match https_location(&head[..len]) {
+            Some(location) => write!(
+                response,
+                "HTTP/1.1 307 Temporary Redirect\r\nLocation: {location}\r\n\
+                 Content-Length: 0\r\nConnection: close\r\n\r\n"
+            ),
+            None => write!(
+                response,
+                "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            ),
+        };
+        if written.is_ok() && socket.write_all(response.as_bytes()).await.is_ok() {
+            socket.close();
+            socket.flush().await.ok();
+        }
+    }
+}
+
+/// Reads request up to the end of its head, or as much of it as fits
+///
+/// # Returns
+/// Length read
+async fn read_head(socket: &mut TcpSocket<'_>, head: &mut [u8]) -> usize {
+    let mut len = 0;
+    while len < head.len() && !head[..len].ends_with(b"\r\n\r\n") {
+        match socket.read(&mut head[len..]).await {
+            Ok(0) | Err(_) => break,
+            Ok(count) => len += count,
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirects_to_same_host_and_path() {
+        let head =
+            b"GET /settings?unit=kelvin HTTP/1.1\r\nUser-Agent: test\r\nHost: sensor.local\r\n\r\n";
+        assert_eq!(
+            https_location(head).unwrap(),
+            "https://sensor.local/settings?unit=kelvin"
+        );
+    }
+
+    #[test]
+    fn drops_port_of_plain_http() {
+        let head = b"GET / HTTP/1.1\r\nhost: 192.168.1.20:80\r\n\r\n";
+        assert_eq!(https_location(head).unwrap(), "https://192.168.1.20/");
+        let head = b"GET / HTTP/1.1\r\nHost: [fe80::1]:80\r\n\r\n";
+        assert_eq!(https_location(head).unwrap(), "https://[fe80::1]/");
+        let head = b"GET / HTTP/1.1\r\nHost: [fe80::1]\r\n\r\n";
+        assert_eq!(https_location(head).unwrap(), "https://[fe80::1]/");
+    }
+
+    #[test]
+    fn rejects_requests_it_cannot_redirect() {
+        // No host
+        assert_eq!(https_location(b"GET / HTTP/1.0\r\n\r\n"), None);
+        // Host after the end of head
+        assert_eq!(https_location(b"GET / HTTP/1.1\r\n\r\nHost: a\r\n"), None);
+        // Absolute form target
+        assert_eq!(
+            https_location(b"GET http://a/ HTTP/1.1\r\nHost: a\r\n\r\n"),
+            None
+        );
+        // Would inject into response
+        assert_eq!(
+            https_location(b"GET / HTTP/1.1\r\nHost: a\tSet-Cookie\r\n\r\n"),
+            None
+        );
+        assert_eq!(https_location(b"GET / HTTP/1.1\r\nHost: a/b\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn rejects_too_long_location() {
+        let mut head = String::<512>::new();
+        write!(head, "GET /{:0300} HTTP/1.1\r\nHost: a\r\n\r\n", 0).unwrap();
+        assert_eq!(https_location(head.as_bytes()), None);
+    }
+}
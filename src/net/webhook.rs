@@ -0,0 +1,431 @@
+//!
+//! Webhook notifications about device events
+//!
+//! Every event is POSTed as JSON to each webhook URL of settings. The URLs
+//! default to `WEBHOOK_URLS` (comma-separated, set at build time like `SSID`).
+//! Only plain `http://` is supported, e.g. `WEBHOOK_URLS=http://192.168.1.10:8080/hook`
+//!
+//! Temperatures are sent in °C, payloads carrying them name the unit.
+//!
+
+use defmt::{error, info, warn};
+use embassy_futures::select::{select, Either};
+use embassy_net::Stack;
+use embassy_time::{Duration, Instant};
+use heapless::{Deque, String, Vec};
+use serde::Serialize;
+
+use crate::{
+    events::{Event, EventSubscriber, Quantity},
+    net::http::{HttpClient, Url},
+    settings::MAX_URL_LEN,
+    sync::mutex::AtomicMutex,
+    units::TemperatureUnit,
+};
+
+pub const MAX_WEBHOOKS: usize = 4;
+/// Count of deliveries waiting for (re)try. The oldest is dropped on overflow
+const QUEUE_CAP: usize = 8;
+const MAX_ATTEMPTS: u8 = 5;
+/// Delay before first retry, doubled on every next one
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivery statistics
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct WebhookStatus {
+    /// Count of configured URLs
+    pub targets: usize,
+    /// Deliveries waiting in retry queue
+    pub pending: usize,
+    pub delivered: u32,
+    /// Deliveries failed after all attempts
+    pub failed: u32,
+    /// Deliveries dropped because queue overflowed
+    pub dropped: u32,
+    pub retries: u32,
+    /// HTTP status of last response
+    pub last_status: Option<u16>,
+    pub last_error: Option<&'static str>,
+}
+
+#[derive(Clone)]
+pub struct SharedWebhookStatus(&'static AtomicMutex<WebhookStatus>);
+
+impl SharedWebhookStatus {
+    pub fn new(m: &'static AtomicMutex<WebhookStatus>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> WebhookStatus {
+        *self.0.lock().await
+    }
+
+    async fn update(&self, f: impl FnOnce(&mut WebhookStatus)) {
+        f(&mut *self.0.lock().await)
+    }
+}
+
+#[derive(Serialize)]
+struct Payload {
+    device: &'static str,
+    event: &'static str,
+    /// Device uptime when event happened
+    uptime_s: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quantity: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    threshold: Option<f32>,
+    /// Of temperature value and threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<TemperatureUnit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sensor: Option<&'static str>,
+}
+
+impl Payload {
+    fn new(event: &Event, happened: Instant) -> Self {
+        let mut payload = Self {
+            device: env!("CARGO_PKG_NAME"),
+            event: event.name(),
+            uptime_s: happened.as_secs(),
+            quantity: None,
+            kind: None,
+            value: None,
+            threshold: None,
+            unit: None,
+            sensor: None,
+        };
+
+        match event {
+            Event::Boot => {}
+            Event::AlarmTriggered(alarm) | Event::AlarmCleared(alarm) => {
+                payload.quantity = Some(alarm.quantity.as_str());
+                payload.kind = Some(alarm.kind.as_str());
+                payload.value = Some(alarm.value);
+                payload.threshold = Some(alarm.threshold);
+                if alarm.quantity == Quantity::Temperature {
+                    payload.unit = Some(TemperatureUnit::Celsius);
+                }
+            }
+            Event::SensorFault(sensor) | Event::SensorRecovered(sensor) => {
+                payload.sensor = Some(sensor);
+            }
+            Event::SelfHeatingStarted(heating) | Event::SelfHeatingStopped(heating) => {
+                payload.sensor = Some(heating.sensor);
+                payload.value = Some(heating.difference);
+                payload.threshold = Some(heating.warning);
+                payload.unit = Some(TemperatureUnit::Celsius);
+            }
+        }
+
+        payload
+    }
+}
+
+struct Delivery {
+    event: Event,
+    happened: Instant,
+    /// Index of target URL
+    target: usize,
+    attempts: u8,
+    next_attempt: Instant,
+}
+
+impl Delivery {
+    /// Schedules retry after failed attempt
+    ///
+    /// # Returns
+    /// false, if all attempts are used up
+    fn retry(&mut self, now: Instant) -> bool {
+        self.attempts += 1;
+        if MAX_ATTEMPTS <= self.attempts {
+            return false;
+        }
+        self.next_attempt = now + RETRY_DELAY * (1 << (self.attempts - 1));
+        true
+    }
+}
+
+/// Queues delivery, dropping the oldest one on overflow
+///
+/// # Returns
+/// true, if a delivery was dropped
+fn enqueue(queue: &mut Deque<Delivery, QUEUE_CAP>, delivery: Delivery) -> bool {
+    match queue.push_back(delivery) {
+        Ok(()) => false,
+        Err(delivery) => {
+            queue.pop_front();
+            queue.push_back(delivery).ok();
+            true
+        }
+    }
+}
+
+/// Splits comma-separated URLs, skipping too long ones
+pub fn split_urls(urls: &str) -> Vec<String<MAX_URL_LEN>, MAX_WEBHOOKS> {
+    let mut split = Vec::new();
+
+    for url in urls.split(',').map(str::trim).filter(|url| !url.is_empty()) {
+        match String::try_from(url) {
+            Ok(url) => {
+                if split.push(url).is_err() {
+                    warn!("webhook: too many targets, rest ignored");
+                    break;
+                }
+            }
+            Err(_) => error!("webhook: url too long {}", url),
+        }
+    }
+
+    split
+}
+
+/// Parses configured URLs, skipping invalid ones
+fn configured_targets(urls: &'static [String<MAX_URL_LEN>]) -> Vec<Url<'static>, MAX_WEBHOOKS> {
+    let mut targets = Vec::new();
+
+    for url in urls {
+        match Url::parse(url) {
+            Ok(parsed) => {
+                if targets.push(parsed).is_err() {
+                    warn!("webhook: too many targets, {} ignored", url.as_str());
+                }
+            }
+            Err(err) => error!("webhook: invalid url {}: {}", url.as_str(), err),
+        }
+    }
+
+    targets
+}
+
+/// Delivers events from bus to configured webhooks
+#[embassy_executor::task]
+pub async fn webhook_task(
+    stack: Stack<'static>,
+    mut events: EventSubscriber,
+    status: SharedWebhookStatus,
+    urls: &'static [String<MAX_URL_LEN>],
+) {
+    let targets = configured_targets(urls);
+    status.update(|s| s.targets = targets.len()).await;
+
+    if targets.is_empty() {
+        info!("webhook: no targets configured");
+        return;
+    }
+
+    let mut rx_buffer = [0_u8; 256];
+    let mut tx_buffer = [0_u8; 512];
+    let mut body = [0_u8; 256];
+    let mut client = HttpClient::new(stack, &mut rx_buffer, &mut tx_buffer, REQUEST_TIMEOUT);
+
+    let mut queue: Deque<Delivery, QUEUE_CAP> = Deque::new();
+
+    loop {
+        // Wait for new event, but not longer than nearest retry. Offline, retries are
+        // pointless until network configuration is up again
+        let next_attempt = queue.iter().map(|delivery| delivery.next_attempt).min();
+        let event = if !stack.is_config_up() {
+            match select(stack.wait_config_up(), events.next_message_pure()).await {
+                Either::First(()) => None,
+                Either::Second(event) => Some(event),
+            }
+        } else {
+            match next_attempt {
+                Some(at) => embassy_time::with_deadline(at, events.next_message_pure())
+                    .await
+                    .ok(),
+                None => Some(events.next_message_pure().await),
+            }
+        };
+
+        if let Some(event) = event {
+            let now = Instant::now();
+            for target in 0..targets.len() {
+                let delivery = Delivery {
+                    event,
+                    happened: now,
+                    target,
+                    attempts: 0,
+                    next_attempt: now,
+                };
+
+                if enqueue(&mut queue, delivery) {
+                    warn!("webhook: queue overflow, oldest delivery dropped");
+                    status.update(|s| s.dropped += 1).await;
+                }
+            }
+        }
+
+        if !stack.is_config_up() {
+            // No reason to waste attempts, queued deliveries wait for network
+            status.update(|s| s.pending = queue.len()).await;
+            continue;
+        }
+
+        for _ in 0..queue.len() {
+            let Some(mut delivery) = queue.pop_front() else {
+                break;
+            };
+
+            if Instant::now() < delivery.next_attempt {
+                queue.push_back(delivery).ok();
+                continue;
+            }
+
+            let payload = Payload::new(&delivery.event, delivery.happened);
+            let Ok(len) = serde_json_core::to_slice(&payload, &mut body) else {
+                error!("webhook: payload too big for {}", delivery.event);
+                status.update(|s| s.failed += 1).await;
+                continue;
+            };
+
+            let url = &targets[delivery.target];
+            let result = client.post_json(url, &body[..len]).await;
+
+            match result {
+                Ok(code) if (200..300).contains(&code) => {
+                    info!(
+                        "webhook: {} delivered to {}",
+                        delivery.event.name(),
+                        url.host
+                    );
+                    status
+                        .update(|s| {
+                            s.delivered += 1;
+                            s.last_status = Some(code);
+                        })
+                        .await;
+                    continue;
+                }
+                Ok(code) => {
+                    warn!("webhook: {} responded {}", url.host, code);
+                    status.update(|s| s.last_status = Some(code)).await;
+                }
+                Err(err) => {
+                    warn!("webhook: {} failed: {}", url.host, err);
+                    status.update(|s| s.last_error = Some(err.as_str())).await;
+                }
+            }
+
+            if !delivery.retry(Instant::now()) {
+                error!(
+                    "webhook: {} to {} given up",
+                    delivery.event.name(),
+                    url.host
+                );
+                status.update(|s| s.failed += 1).await;
+                continue;
+            }
+
+            queue.push_back(delivery).ok();
+            status.update(|s| s.retries += 1).await;
+        }
+
+        status.update(|s| s.pending = queue.len()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivery(target: usize) -> Delivery {
+        Delivery {
+            event: Event::Boot,
+            happened: Instant::from_secs(0),
+            target,
+            attempts: 0,
+            next_attempt: Instant::from_secs(0),
+        }
+    }
+
+    #[test]
+    fn retries_back_off_until_given_up() {
+        let mut delivery = delivery(0);
+        let now = Instant::from_secs(100);
+
+        let mut delays = Vec::<u64, 8>::new();
+        while delivery.retry(now) {
+            delays
+                .push((delivery.next_attempt - now).as_secs())
+                .unwrap();
+        }
+
+        assert_eq!(delays, [5, 10, 20, 40]);
+        assert_eq!(delivery.attempts, MAX_ATTEMPTS);
+    }
+
+    #[test]
+    fn queue_overflow_drops_oldest() {
+        let mut queue = Deque::new();
+        for target in 0..QUEUE_CAP {
+            assert!(!enqueue(&mut queue, delivery(target)));
+        }
+        assert!(enqueue(&mut queue, delivery(100)));
+        assert!(enqueue(&mut queue, delivery(101)));
+
+        let targets: Vec<usize, QUEUE_CAP> = queue.iter().map(|d| d.target).collect();
+        assert_eq!(targets, [2, 3, 4, 5, 6, 7, 100, 101]);
+    }
+
+    #[test]
+    fn splits_urls() {
+        let urls = split_urls(" http://a/hook , ,http://b:8080/ ");
+        assert_eq!(urls, ["http://a/hook", "http://b:8080/"]);
+
+        let urls = split_urls("http://1,http://2,http://3,http://4,http://5");
+        assert_eq!(urls.len(), MAX_WEBHOOKS);
+    }
+
+    #[test]
+    fn payload_names_unit_of_temperatures_only() {
+        use crate::events::{Alarm, AlarmKind, Heating};
+
+        let humidity = Event::AlarmCleared(Alarm {
+            quantity: Quantity::Humidity,
+            kind: AlarmKind::Low,
+            value: 35.0,
+            threshold: 30.0,
+        });
+        let heating = Event::SelfHeatingStarted(Heating {
+            sensor: "sht4x",
+            difference: 26.0,
+            warning: 25.0,
+        });
+        let at = Instant::from_secs(0);
+
+        assert_eq!(Payload::new(&humidity, at).unit, None);
+        assert_eq!(Payload::new(&Event::Boot, at).unit, None);
+        assert_eq!(
+            Payload::new(&heating, at).unit,
+            Some(TemperatureUnit::Celsius)
+        );
+    }
+
+    #[test]
+    fn payload_of_alarm() {
+        use crate::events::{Alarm, AlarmKind};
+
+        let event = Event::AlarmTriggered(Alarm {
+            quantity: Quantity::Temperature,
+            kind: AlarmKind::High,
+            value: 31.5,
+            threshold: 30.0,
+        });
+        let mut body = [0_u8; 256];
+        let len =
+            serde_json_core::to_slice(&Payload::new(&event, Instant::from_secs(42)), &mut body)
+                .unwrap();
+
+        assert_eq!(
+            core::str::from_utf8(&body[..len]).unwrap(),
+            r#"{"device":"esp-temperature","event":"alarm_triggered","uptime_s":42,"quantity":"temperature","kind":"high","value":31.5,"threshold":30.0,"unit":"celsius"}"#
+        );
+    }
+}
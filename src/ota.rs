@@ -0,0 +1,630 @@
+//!
+//! Firmware update over the air
+//!
+//! Image is streamed into the OTA slot which is not running, verified by SHA-256
+//! and optional HMAC signature, and selected for the next boot. New image has to
+//! confirm itself healthy, otherwise it is marked invalid and the bootloader
+//! falls back to the previous one.
+//!
+//! Image writing and verification work over `embedded-storage` traits and are tested
+//! against a flash mock. Slot selection and image states live in OTA data of
+//! `esp-bootloader-esp-idf`, which builds only for the chip.
+//!
+
+#[cfg(target_arch = "riscv32")]
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+#[cfg(target_arch = "riscv32")]
+use embedded_storage::Storage;
+#[cfg(target_arch = "riscv32")]
+use esp_bootloader_esp_idf::{
+    ota::{Ota, OtaImageState, Slot},
+    partitions::{
+        self, read_partition_table, AppPartitionSubType, DataPartitionSubType, PartitionType,
+        PARTITION_TABLE_MAX_LEN,
+    },
+};
+use serde::Serialize;
+
+#[cfg(target_arch = "riscv32")]
+use crate::partition;
+use crate::{
+    partition::Partition,
+    sha256::{digest_eq, hmac_sha256, Digest, Sha256},
+    sync::mutex::AtomicMutex,
+};
+
+/// The first byte of ESP application image
+const IMAGE_MAGIC: u8 = 0xE9;
+
+/// Image is written by pages, so the 2 KB HTTP buffer is never copied whole
+const PAGE_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+#[serde(rename_all = "snake_case")]
+pub enum OtaError {
+    /// Partition table has no OTA data or OTA app partitions
+    NoOtaPartitions,
+    /// Partition table or OTA data could not be read or written
+    Partition,
+    /// Flash read, write or erase failed
+    Storage,
+    /// Image does not fit into the slot
+    TooLarge,
+    /// Image does not start with ESP image magic
+    NotAnImage,
+    /// Connection closed before the whole image was received
+    Incomplete,
+    /// SHA-256 of received or written image does not match
+    DigestMismatch,
+    /// Signature is missing or does not match
+    SignatureMismatch,
+    /// Another update is in progress
+    Busy,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl From<partitions::Error> for OtaError {
+    fn from(_: partitions::Error) -> Self {
+        Self::Partition
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+/// OTA slot selected in OTA data and its image state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootState {
+    pub selected: Slot,
+    /// None if no slot is selected and factory or OTA-0 image boots
+    pub state: Option<OtaImageState>,
+    pub has_factory: bool,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl BootState {
+    /// Reads OTA data
+    pub fn read<F: Storage>(flash: &mut F) -> Result<Self, OtaError> {
+        let has_factory =
+            find_partition(flash, PartitionType::App(AppPartitionSubType::Factory)).is_ok();
+        with_ota_data(flash, |ota| {
+            let selected = ota.current_slot()?;
+            let state = match selected {
+                Slot::None => None,
+                _ => Some(ota.current_ota_state()?),
+            };
+            Ok(Self {
+                selected,
+                state,
+                has_factory,
+            })
+        })
+    }
+
+    /// Slot to write update into, the one not running
+    pub fn target_slot(&self) -> Slot {
+        match (self.selected, self.state) {
+            // Factory image runs, or OTA-0 if there is no factory partition
+            (Slot::None, _) if self.has_factory => Slot::Slot0,
+            (Slot::None, _) => Slot::Slot1,
+            // Bootloader rejected the selected slot and runs the other one
+            (slot, Some(OtaImageState::Invalid | OtaImageState::Aborted)) => slot,
+            (slot, _) => slot.next(),
+        }
+    }
+
+    /// Running image was just installed and has not confirmed itself yet
+    pub fn needs_confirmation(&self) -> bool {
+        matches!(
+            self.state,
+            Some(OtaImageState::New | OtaImageState::PendingVerify)
+        )
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+/// Marks running image as working, so the bootloader keeps it
+pub fn confirm<F: Storage>(flash: &mut F) -> Result<(), OtaError> {
+    with_ota_data(flash, |ota| ota.set_current_ota_state(OtaImageState::Valid))
+}
+
+#[cfg(target_arch = "riscv32")]
+/// Marks running image as broken, the bootloader boots the previous image after reset
+pub fn reject<F: Storage>(flash: &mut F) -> Result<(), OtaError> {
+    with_ota_data(flash, |ota| {
+        ota.set_current_ota_state(OtaImageState::Invalid)
+    })
+}
+
+#[cfg(target_arch = "riscv32")]
+/// Selects slot for the next boot, new image has to confirm itself by [`confirm`]
+pub fn activate<F: Storage>(flash: &mut F, slot: Slot) -> Result<(), OtaError> {
+    with_ota_data(flash, |ota| {
+        ota.set_current_slot(slot)?;
+        ota.set_current_ota_state(OtaImageState::New)
+    })
+}
+
+#[cfg(target_arch = "riscv32")]
+fn with_ota_data<F, R>(
+    flash: &mut F,
+    f: impl FnOnce(&mut Ota<'_, F>) -> Result<R, partitions::Error>,
+) -> Result<R, OtaError>
+where
+    F: Storage,
+{
+    let mut table = [0_u8; PARTITION_TABLE_MAX_LEN];
+    let partitions = read_partition_table(flash, &mut table)?;
+    let entry = partitions
+        .find_partition(PartitionType::Data(DataPartitionSubType::Ota))?
+        .ok_or(OtaError::NoOtaPartitions)?;
+
+    let mut region = entry.as_embedded_storage(flash);
+    let mut ota = Ota::new(&mut region)?;
+    Ok(f(&mut ota)?)
+}
+
+#[cfg(target_arch = "riscv32")]
+fn find_partition<F: Storage>(
+    flash: &mut F,
+    partition_type: PartitionType,
+) -> Result<Partition, OtaError> {
+    partition::find_partition(flash, partition_type)?.ok_or(OtaError::NoOtaPartitions)
+}
+
+/// Expected digest of image and its signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    pub sha256: Digest,
+    /// HMAC-SHA256 of `sha256` by shared key
+    pub signature: Option<Digest>,
+}
+
+impl Verification {
+    /// # Arguments
+    /// - `key` - signature is required if set, and ignored otherwise
+    fn check(&self, digest: &Digest, key: Option<&[u8]>) -> Result<(), OtaError> {
+        if !digest_eq(&self.sha256, digest) {
+            return Err(OtaError::DigestMismatch);
+        }
+
+        match (key, &self.signature) {
+            (None, _) => Ok(()),
+            (Some(key), Some(signature)) if digest_eq(&hmac_sha256(key, digest), signature) => {
+                Ok(())
+            }
+            (Some(_), _) => Err(OtaError::SignatureMismatch),
+        }
+    }
+}
+
+/// Slot which is not running and its partition, where an update goes
+#[cfg(target_arch = "riscv32")]
+pub fn target_partition<F: Storage>(flash: &mut F) -> Result<(Slot, Partition), OtaError> {
+    let slot = BootState::read(flash)?.target_slot();
+    let subtype = match slot {
+        Slot::Slot1 => AppPartitionSubType::Ota1,
+        _ => AppPartitionSubType::Ota0,
+    };
+    Ok((slot, find_partition(flash, PartitionType::App(subtype))?))
+}
+
+/// Streams image into partition
+///
+/// Sectors are erased as the image reaches them, so a small image does not
+/// wear out the whole slot. Flash is passed to every call, so others can use it
+/// between chunks of a slow upload.
+pub struct ImageWriter {
+    partition: Partition,
+    /// Announced image size
+    len: u32,
+    /// Count of received bytes
+    received: u32,
+    /// Count of bytes written to flash
+    flushed: u32,
+    page: [u8; PAGE_SIZE],
+    page_len: usize,
+    hasher: Sha256,
+}
+
+impl ImageWriter {
+    /// # Arguments
+    /// - `len` - image size, rejected early if it does not fit
+    pub fn new(partition: Partition, len: u32) -> Result<Self, OtaError> {
+        if partition.len < len {
+            return Err(OtaError::TooLarge);
+        }
+
+        Ok(Self {
+            partition,
+            len,
+            received: 0,
+            flushed: 0,
+            page: [0; PAGE_SIZE],
+            page_len: 0,
+            hasher: Sha256::new(),
+        })
+    }
+
+    /// Count of received bytes
+    pub fn received(&self) -> u32 {
+        self.received
+    }
+
+    pub fn write<F: NorFlash>(&mut self, flash: &mut F, mut data: &[u8]) -> Result<(), OtaError> {
+        if self.received == 0 && data.first().is_some_and(|byte| *byte != IMAGE_MAGIC) {
+            return Err(OtaError::NotAnImage);
+        }
+        if self.len - self.received < data.len() as u32 {
+            return Err(OtaError::TooLarge);
+        }
+        self.hasher.update(data);
+        self.received += data.len() as u32;
+
+        while !data.is_empty() {
+            let count = (PAGE_SIZE - self.page_len).min(data.len());
+            self.page[self.page_len..self.page_len + count].copy_from_slice(&data[..count]);
+            self.page_len += count;
+            data = &data[count..];
+
+            if self.page_len == PAGE_SIZE {
+                self.flush(flash)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verifies written image, it is ready to be activated by [`activate`]
+    ///
+    /// # Arguments
+    /// - `key` - shared key of signature, signature is not checked if None
+    pub fn finish<F: NorFlash>(
+        mut self,
+        flash: &mut F,
+        verification: &Verification,
+        key: Option<&[u8]>,
+    ) -> Result<(), OtaError> {
+        if self.received < self.len {
+            return Err(OtaError::Incomplete);
+        }
+        self.flush(flash)?;
+
+        let digest = self.hasher.clone().finalize();
+        verification.check(&digest, key)?;
+        // Read back, so a flash write failure is not activated
+        if !digest_eq(&self.written_digest(flash)?, &digest) {
+            return Err(OtaError::DigestMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Writes buffered page, padding it to write size
+    fn flush<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), OtaError> {
+        if self.page_len == 0 {
+            return Ok(());
+        }
+
+        let address = self.partition.offset + self.flushed;
+        let erase_size = F::ERASE_SIZE as u32;
+        if self.flushed % erase_size == 0 {
+            flash
+                .erase(address, address + erase_size)
+                .map_err(|_| OtaError::Storage)?;
+        }
+
+        let len = self.page_len.next_multiple_of(F::WRITE_SIZE);
+        self.page[self.page_len..len].fill(0xFF);
+        flash
+            .write(address, &self.page[..len])
+            .map_err(|_| OtaError::Storage)?;
+
+        self.flushed += len as u32;
+        self.page_len = 0;
+        Ok(())
+    }
+
+    fn written_digest<F: ReadNorFlash>(&mut self, flash: &mut F) -> Result<Digest, OtaError> {
+        let mut hasher = Sha256::new();
+        let mut offset = 0;
+        while offset < self.received {
+            let count = (PAGE_SIZE as u32).min(self.received - offset) as usize;
+            let len = count.next_multiple_of(F::READ_SIZE);
+            flash
+                .read(self.partition.offset + offset, &mut self.page[..len])
+                .map_err(|_| OtaError::Storage)?;
+            hasher.update(&self.page[..count]);
+            offset += count as u32;
+        }
+        Ok(hasher.finalize())
+    }
+}
+
+/// Phase of update shown by API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, defmt::Format)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    #[default]
+    Idle,
+    Receiving,
+    /// Image is activated and device reboots into it
+    Rebooting,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, defmt::Format)]
+pub struct OtaStatus {
+    pub phase: Phase,
+    /// Slot number being written or activated
+    pub slot: Option<u8>,
+    pub received: u32,
+    pub size: u32,
+    /// Why the last update failed
+    pub error: Option<OtaError>,
+}
+
+/// Update being received, only one can run at a time
+///
+/// Dropped while still receiving, e.g. when the connection breaks and the upload
+/// handler is cancelled mid-body, it marks the update failed. Otherwise every
+/// later upload would be refused as busy until reboot
+pub struct Claim<'a> {
+    status: &'a AtomicMutex<OtaStatus>,
+}
+
+impl<'a> Claim<'a> {
+    /// # Returns
+    /// - None, if another update is being received
+    pub async fn take(status: &'a AtomicMutex<OtaStatus>, size: u32) -> Option<Self> {
+        let mut current = status.lock().await;
+        if current.phase == Phase::Receiving {
+            return None;
+        }
+        *current = OtaStatus {
+            phase: Phase::Receiving,
+            size,
+            ..Default::default()
+        };
+        Some(Self { status })
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        // Status is never locked across an await, so the lock is free whenever
+        // the task owning the claim runs
+        if let Some(mut status) = self.status.try_lock() {
+            if status.phase == Phase::Receiving {
+                status.phase = Phase::Failed;
+                status.error = Some(OtaError::Incomplete);
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+/// Flash used for updates, update status and reboot request
+pub struct SharedOta<F: 'static> {
+    flash: &'static AtomicMutex<F>,
+    status: &'static AtomicMutex<OtaStatus>,
+    activated: &'static Signal<CriticalSectionRawMutex, Slot>,
+    key: Option<&'static [u8]>,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<F> Clone for SharedOta<F> {
+    fn clone(&self) -> Self {
+        Self {
+            flash: self.flash,
+            status: self.status,
+            activated: self.activated,
+            key: self.key,
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<F> SharedOta<F> {
+    /// # Arguments
+    /// - `key` - shared key of image signatures, unsigned images are accepted if None
+    pub fn new(
+        flash: &'static AtomicMutex<F>,
+        status: &'static AtomicMutex<OtaStatus>,
+        activated: &'static Signal<CriticalSectionRawMutex, Slot>,
+        key: Option<&'static [u8]>,
+    ) -> Self {
+        Self {
+            flash,
+            status,
+            activated,
+            key,
+        }
+    }
+
+    pub fn flash(&self) -> &'static AtomicMutex<F> {
+        self.flash
+    }
+
+    pub fn key(&self) -> Option<&'static [u8]> {
+        self.key
+    }
+
+    pub async fn status(&self) -> OtaStatus {
+        *self.status.lock().await
+    }
+
+    pub async fn set_status(&self, status: OtaStatus) {
+        *self.status.lock().await = status;
+    }
+
+    /// Claims update, it has to be held until the phase is set to the result
+    ///
+    /// # Returns
+    /// - None, if another update is being received
+    pub async fn start(&self, size: u32) -> Option<Claim<'static>> {
+        Claim::take(self.status, size).await
+    }
+
+    /// Requests reboot into activated image
+    pub fn request_reboot(&self, slot: Slot) {
+        self.activated.signal(slot);
+    }
+
+    /// Waits for an image to be activated
+    pub async fn wait_reboot(&self) -> Slot {
+        self.activated.wait().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partition::mock::MockFlash;
+    use crate::sha256::digest;
+    use embassy_futures::block_on;
+
+    const SLOT: Partition = Partition {
+        offset: 0x1000,
+        len: 0x3000,
+    };
+    const KEY: &[u8] = b"update key";
+
+    fn image<const N: usize>() -> [u8; N] {
+        let mut image = core::array::from_fn(|index| (index * 7) as u8);
+        image[0] = IMAGE_MAGIC;
+        image
+    }
+
+    fn verification(image: &[u8]) -> Verification {
+        let sha256 = digest(image);
+        Verification {
+            sha256,
+            signature: Some(hmac_sha256(KEY, &sha256)),
+        }
+    }
+
+    /// Writes image in odd chunks, like they come from network
+    fn write_all<const N: usize>(
+        flash: &mut MockFlash<N>,
+        image: &[u8],
+        len: u32,
+    ) -> Result<ImageWriter, OtaError> {
+        let mut writer = ImageWriter::new(SLOT, len)?;
+        for chunk in image.chunks(333) {
+            writer.write(flash, chunk)?;
+        }
+        Ok(writer)
+    }
+
+    #[test]
+    fn writes_image_and_erases_only_its_sectors() {
+        let mut flash = MockFlash::<0x4000>::new();
+        flash.data[0x3000..].fill(0x55);
+        let image = image::<5000>();
+
+        let writer = write_all(&mut flash, &image, image.len() as u32).unwrap();
+        assert_eq!(writer.received(), 5000);
+        writer
+            .finish(&mut flash, &verification(&image), Some(KEY))
+            .unwrap();
+
+        assert_eq!(&flash.data[0x1000..0x1000 + image.len()], &image);
+        assert_eq!(flash.erased, 2);
+        assert!(flash.data[0x3000..].iter().all(|byte| *byte == 0x55));
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_image() {
+        let mut flash = MockFlash::<0x4000>::new();
+        let mut image = image::<600>();
+        image[0] = 0x7F;
+
+        let result = write_all(&mut flash, &image, image.len() as u32);
+        assert_eq!(result.err(), Some(OtaError::NotAnImage));
+        assert_eq!(flash.erased, 0);
+    }
+
+    #[test]
+    fn rejects_image_larger_than_slot_or_announced() {
+        assert_eq!(
+            ImageWriter::new(SLOT, SLOT.len + 1).err(),
+            Some(OtaError::TooLarge)
+        );
+
+        let mut flash = MockFlash::<0x4000>::new();
+        let image = image::<600>();
+        let result = write_all(&mut flash, &image, 500);
+        assert_eq!(result.err(), Some(OtaError::TooLarge));
+    }
+
+    #[test]
+    fn truncated_image_is_incomplete() {
+        let mut flash = MockFlash::<0x4000>::new();
+        let image = image::<600>();
+
+        let writer = write_all(&mut flash, &image[..400], image.len() as u32).unwrap();
+        let result = writer.finish(&mut flash, &verification(&image), None);
+        assert_eq!(result, Err(OtaError::Incomplete));
+    }
+
+    #[test]
+    fn rejects_wrong_digest_and_signature() {
+        let image = image::<600>();
+        let mut other = image;
+        other[100] ^= 1;
+
+        let mut flash = MockFlash::<0x4000>::new();
+        let writer = write_all(&mut flash, &other, other.len() as u32).unwrap();
+        let result = writer.finish(&mut flash, &verification(&image), None);
+        assert_eq!(result, Err(OtaError::DigestMismatch));
+
+        let unsigned = Verification {
+            signature: None,
+            ..verification(&image)
+        };
+        let writer = write_all(&mut flash, &image, image.len() as u32).unwrap();
+        let result = writer.finish(&mut flash, &unsigned, Some(KEY));
+        assert_eq!(result, Err(OtaError::SignatureMismatch));
+
+        let writer = write_all(&mut flash, &image, image.len() as u32).unwrap();
+        let result = writer.finish(&mut flash, &verification(&image), Some(b"other key"));
+        assert_eq!(result, Err(OtaError::SignatureMismatch));
+
+        // Signature is not required without key
+        let writer = write_all(&mut flash, &image, image.len() as u32).unwrap();
+        assert_eq!(writer.finish(&mut flash, &unsigned, None), Ok(()));
+    }
+
+    #[test]
+    fn dropped_claim_fails_update_so_next_can_start() {
+        let status = AtomicMutex::new(OtaStatus::default());
+
+        let claim = block_on(Claim::take(&status, 1000)).unwrap();
+        assert_eq!(block_on(status.lock()).phase, Phase::Receiving);
+        assert!(block_on(Claim::take(&status, 1000)).is_none());
+
+        // Connection broke mid-body
+        drop(claim);
+        let current = *block_on(status.lock());
+        assert_eq!(current.phase, Phase::Failed);
+        assert_eq!(current.error, Some(OtaError::Incomplete));
+
+        let claim = block_on(Claim::take(&status, 2000)).unwrap();
+        assert_eq!(block_on(status.lock()).size, 2000);
+
+        // Finished update keeps its result
+        block_on(status.lock()).phase = Phase::Rebooting;
+        drop(claim);
+        assert_eq!(block_on(status.lock()).phase, Phase::Rebooting);
+    }
+
+    #[test]
+    fn failed_flash_write_is_caught_by_read_back() {
+        let mut flash = MockFlash::<0x4000>::new().stuck_at(SLOT.offset + 300);
+        let image = image::<600>();
+
+        let writer = write_all(&mut flash, &image, image.len() as u32).unwrap();
+        let result = writer.finish(&mut flash, &verification(&image), Some(KEY));
+        assert_eq!(result, Err(OtaError::DigestMismatch));
+    }
+}
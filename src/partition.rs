@@ -0,0 +1,65 @@
+//!
+//! Partitions of flash
+//!
+//! Looked up in the partition table of `esp-bootloader-esp-idf`, so updates
+//! and settings land where `partitions.csv` puts them
+//!
+
+#[cfg(target_arch = "riscv32")]
+use embedded_storage::Storage;
+#[cfg(target_arch = "riscv32")]
+use esp_bootloader_esp_idf::partitions::{
+    read_partition_table, Error, PartitionType, PARTITION_TABLE_MAX_LEN,
+};
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+/// Location of partition in flash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Partition {
+    pub offset: u32,
+    pub len: u32,
+}
+
+/// Finds the first partition of type
+///
+/// # Returns
+/// None, if partition table has no such partition
+#[cfg(target_arch = "riscv32")]
+pub fn find_partition<F: Storage>(
+    flash: &mut F,
+    partition_type: PartitionType,
+) -> Result<Option<Partition>, Error> {
+    let mut table = [0_u8; PARTITION_TABLE_MAX_LEN];
+    let partitions = read_partition_table(flash, &mut table)?;
+    Ok(partitions
+        .find_partition(partition_type)?
+        .map(|entry| Partition {
+            offset: entry.offset(),
+            len: entry.len(),
+        }))
+}
+
+/// Finds partition by its name in `partitions.csv`, for data partitions sharing a type
+///
+/// # Returns
+/// None, if partition table has no such partition
+#[cfg(target_arch = "riscv32")]
+pub fn find_partition_by_label<F: Storage>(
+    flash: &mut F,
+    label: &str,
+) -> Result<Option<Partition>, Error> {
+    let mut table = [0_u8; PARTITION_TABLE_MAX_LEN];
+    let partitions = read_partition_table(flash, &mut table)?;
+    for index in 0..partitions.len() {
+        let entry = partitions.get_partition(index)?;
+        if entry.label_as_str() == label {
+            return Ok(Some(Partition {
+                offset: entry.offset(),
+                len: entry.len(),
+            }));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,87 @@
+//!
+//! RAM flash for host tests
+//!
+//! Behaves like NOR flash of the chip: erase sets sectors to `0xFF`, writes can only
+//! clear bits, and accesses must be aligned. A stuck byte reads back as zero, like
+//! a worn out cell.
+//!
+
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash,
+};
+
+pub struct MockFlash<const N: usize> {
+    pub data: [u8; N],
+    /// Count of erased sectors
+    pub erased: usize,
+    stuck: Option<u32>,
+}
+
+impl<const N: usize> Default for MockFlash<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MockFlash<N> {
+    /// Erased flash
+    pub const fn new() -> Self {
+        Self {
+            data: [0xFF; N],
+            erased: 0,
+            stuck: None,
+        }
+    }
+
+    /// Byte at `address` is written as zero whatever is written to it
+    pub fn stuck_at(self, address: u32) -> Self {
+        Self {
+            stuck: Some(address),
+            ..self
+        }
+    }
+}
+
+impl<const N: usize> ErrorType for MockFlash<N> {
+    type Error = NorFlashErrorKind;
+}
+
+impl<const N: usize> ReadNorFlash for MockFlash<N> {
+    const READ_SIZE: usize = 4;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> NorFlash for MockFlash<N> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 4096;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        self.data[from as usize..to as usize].fill(0xFF);
+        self.erased += (to - from) as usize / Self::ERASE_SIZE;
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        for (address, byte) in (offset..).zip(bytes) {
+            let cell = &mut self.data[address as usize];
+            *cell &= if self.stuck == Some(address) {
+                0
+            } else {
+                *byte
+            };
+        }
+        Ok(())
+    }
+}
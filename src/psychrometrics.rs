@@ -0,0 +1,209 @@
+//!
+//! Psychrometric properties of moist air
+//!
+//! Derived from temperature in °C and relative humidity in %. Vapour pressure
+//! uses the Magnus formula with Sonntag 1990 constants, over water for dew point
+//! and over ice for frost point.
+//!
+
+use libm::{atanf, expf, logf, sqrtf};
+use serde::Serialize;
+
+use crate::units::TemperatureUnit;
+
+/// Standard sea level pressure in hPa
+pub const STANDARD_PRESSURE: f32 = 1013.25;
+
+/// Magnus constants over water
+const WATER: (f32, f32) = (17.62, 243.12);
+/// Magnus constants over ice
+const ICE: (f32, f32) = (22.46, 272.62);
+/// Saturation vapour pressure at 0 °C in hPa
+const E0: f32 = 6.112;
+/// Ratio of molar masses of water vapour and dry air, in g/kg
+const EPSILON: f32 = 621.97;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Psychrometrics {
+    /// In °C
+    pub dew_point: f32,
+    /// In °C, None if it would not be below freezing
+    pub frost_point: Option<f32>,
+    /// Water vapour in g/m³
+    pub absolute_humidity: f32,
+    /// Water vapour per dry air in g/kg
+    pub mixing_ratio: f32,
+    /// Apparent temperature by NWS in °C
+    pub heat_index: f32,
+    /// Apparent temperature by Environment Canada, in °C
+    pub humidex: f32,
+    /// In °C, by Stull 2011, valid for humidity 5-99 % and temperature -20-50 °C
+    pub wet_bulb: f32,
+    /// Vapour-pressure deficit in kPa
+    pub vpd: f32,
+}
+
+impl Psychrometrics {
+    /// # Arguments
+    /// - `temperature` - in °C
+    /// - `humidity` - relative humidity in %
+    /// - `pressure` - air pressure in hPa, [`STANDARD_PRESSURE`] if not measured
+    ///
+    /// # Returns
+    /// None, if humidity is not above zero or values are not finite
+    pub fn new(temperature: f32, humidity: f32, pressure: f32) -> Option<Self> {
+        if !temperature.is_finite() || !pressure.is_finite() || humidity.is_nan() || humidity <= 0.0
+        {
+            return None;
+        }
+        let humidity = humidity.min(100.0);
+
+        let saturation = saturation_vapour_pressure(temperature);
+        let vapour = saturation * humidity / 100.0;
+        let frost_point = magnus_inverse(vapour, ICE);
+
+        Some(Self {
+            dew_point: magnus_inverse(vapour, WATER),
+            frost_point: (frost_point < 0.0).then_some(frost_point),
+            absolute_humidity: 216.7 * vapour / (temperature + 273.15),
+            mixing_ratio: EPSILON * vapour / (pressure - vapour),
+            heat_index: heat_index(temperature, humidity),
+            humidex: temperature + 0.5555 * (vapour - 10.0),
+            wet_bulb: wet_bulb(temperature, humidity),
+            vpd: (saturation - vapour) / 10.0,
+        })
+    }
+
+    /// Converts temperatures from °C
+    pub fn in_unit(self, unit: TemperatureUnit) -> Self {
+        let convert = |celsius| unit.from_celsius(celsius);
+        Self {
+            dew_point: convert(self.dew_point),
+            frost_point: self.frost_point.map(convert),
+            heat_index: convert(self.heat_index),
+            humidex: convert(self.humidex),
+            wet_bulb: convert(self.wet_bulb),
+            ..self
+        }
+    }
+}
+
+/// Over water, in hPa
+pub fn saturation_vapour_pressure(temperature: f32) -> f32 {
+    let (a, b) = WATER;
+    E0 * expf(a * temperature / (b + temperature))
+}
+
+/// Temperature at which vapour pressure saturates
+fn magnus_inverse(vapour: f32, (a, b): (f32, f32)) -> f32 {
+    let gamma = logf(vapour / E0);
+    b * gamma / (a - gamma)
+}
+
+/// Rothfusz regression with NWS adjustments, Steadman approximation below 80 °F
+fn heat_index(temperature: f32, humidity: f32) -> f32 {
+    let t = temperature * 9.0 / 5.0 + 32.0;
+    let rh = humidity;
+
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let fahrenheit = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut index = -42.379 + 2.049_015_2 * t + 10.143_331 * rh
+            - 0.224_755_4 * t * rh
+            - 6.837_83e-3 * t * t
+            - 5.481_717e-2 * rh * rh
+            + 1.228_74e-3 * t * t * rh
+            + 8.5282e-4 * t * rh * rh
+            - 1.99e-6 * t * t * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            index -= (13.0 - rh) / 4.0 * sqrtf((17.0 - (t - 95.0).abs()) / 17.0);
+        } else if 85.0 < rh && (80.0..=87.0).contains(&t) {
+            index += (rh - 85.0) / 10.0 * (87.0 - t) / 5.0;
+        }
+        index
+    };
+
+    (fahrenheit - 32.0) * 5.0 / 9.0
+}
+
+/// Stull 2011 empirical fit at standard pressure
+fn wet_bulb(temperature: f32, humidity: f32) -> f32 {
+    let t = temperature;
+    let rh = humidity;
+    t * atanf(0.151_977 * sqrtf(rh + 8.313_659)) + atanf(t + rh) - atanf(rh - 1.676_331)
+        + 0.003_918_38 * rh * sqrtf(rh) * atanf(0.023_101 * rh)
+        - 4.686_035
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn room_air() {
+        let air = Psychrometrics::new(25.0, 50.0, STANDARD_PRESSURE).unwrap();
+        assert_close(air.dew_point, 13.9, 0.05);
+        assert_close(air.absolute_humidity, 11.5, 0.05);
+        assert_close(air.wet_bulb, 18.0, 0.05);
+        assert_close(air.vpd, 1.58, 0.005);
+        assert_close(air.mixing_ratio, 9.85, 0.05);
+        assert_eq!(air.frost_point, None);
+    }
+
+    #[test]
+    fn frost_point_below_freezing() {
+        let air = Psychrometrics::new(-5.0, 80.0, STANDARD_PRESSURE).unwrap();
+        assert_close(air.dew_point, -7.92, 0.05);
+        assert_close(air.frost_point.unwrap(), -7.01, 0.05);
+        assert_close(air.absolute_humidity, 2.73, 0.05);
+    }
+
+    #[test]
+    fn heat_index_below_regression() {
+        let air = Psychrometrics::new(25.0, 50.0, STANDARD_PRESSURE).unwrap();
+        assert_close(air.heat_index, 24.86, 0.05);
+    }
+
+    #[test]
+    fn heat_index_by_regression() {
+        let air = Psychrometrics::new(32.0, 70.0, STANDARD_PRESSURE).unwrap();
+        assert_close(air.heat_index, 40.41, 0.1);
+        assert_close(air.humidex, 44.9, 0.1);
+
+        let air = Psychrometrics::new(35.0, 40.0, STANDARD_PRESSURE).unwrap();
+        assert_close(air.heat_index, 37.22, 0.1);
+    }
+
+    #[test]
+    fn mixing_ratio_rises_with_altitude() {
+        let sea_level = Psychrometrics::new(20.0, 60.0, STANDARD_PRESSURE).unwrap();
+        let mountain = Psychrometrics::new(20.0, 60.0, 800.0).unwrap();
+        assert!(mountain.mixing_ratio > sea_level.mixing_ratio);
+        assert_eq!(mountain.dew_point, sea_level.dew_point);
+    }
+
+    #[test]
+    fn rejects_dry_or_invalid_air() {
+        assert_eq!(Psychrometrics::new(25.0, 0.0, STANDARD_PRESSURE), None);
+        assert_eq!(Psychrometrics::new(25.0, f32::NAN, STANDARD_PRESSURE), None);
+        assert_eq!(Psychrometrics::new(f32::NAN, 50.0, STANDARD_PRESSURE), None);
+        assert_eq!(Psychrometrics::new(25.0, 50.0, f32::INFINITY), None);
+    }
+
+    #[test]
+    fn converts_temperatures_only() {
+        let celsius = Psychrometrics::new(25.0, 50.0, STANDARD_PRESSURE).unwrap();
+        let fahrenheit = celsius.in_unit(TemperatureUnit::Fahrenheit);
+        assert_close(fahrenheit.dew_point, celsius.dew_point * 1.8 + 32.0, 0.01);
+        assert_eq!(fahrenheit.vpd, celsius.vpd);
+        assert_eq!(fahrenheit.absolute_humidity, celsius.absolute_humidity);
+    }
+}
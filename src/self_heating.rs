@@ -0,0 +1,99 @@
+//!
+//! Self-heating of the board
+//!
+//! Chip temperature far above ambient means the enclosure traps heat, so ambient
+//! sensors near the chip read high
+//!
+
+/// Watches difference of chip and ambient temperatures
+pub struct SelfHeating {
+    /// Difference in °C to warn about
+    warning: f32,
+    /// Share of the difference subtracted from ambient temperature
+    compensation: f32,
+    warned: bool,
+}
+
+impl SelfHeating {
+    /// # Arguments
+    /// - `warning` - difference of chip and ambient temperatures in °C to warn about
+    /// - `compensation` - share of the difference subtracted from ambient, 0 disables it
+    pub fn new(warning: f32, compensation: f32) -> Self {
+        Self {
+            warning,
+            compensation,
+            warned: false,
+        }
+    }
+
+    /// Feeds new temperatures, both in °C
+    ///
+    /// # Returns
+    /// - Some(true), if difference has exceeded warning level
+    /// - Some(false), if difference has dropped 2 °C below warning level
+    /// - None, if warning state not changed
+    pub fn update(&mut self, chip: f32, ambient: f32) -> Option<bool> {
+        let difference = chip - ambient;
+        if !self.warned && self.warning < difference {
+            self.warned = true;
+            Some(true)
+        } else if self.warned && difference < self.warning - 2.0 {
+            self.warned = false;
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Changes levels, warning state is kept until the next update
+    pub fn set_levels(&mut self, warning: f32, compensation: f32) {
+        self.warning = warning;
+        self.compensation = compensation;
+    }
+
+    pub fn is_warned(&self) -> bool {
+        self.warned
+    }
+
+    /// Estimates ambient temperature without heat from the chip
+    ///
+    /// # Returns
+    /// Ambient temperature as is, if chip is not warmer
+    pub fn compensate(&self, chip: f32, ambient: f32) -> f32 {
+        ambient - self.compensation * (chip - ambient).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_once_and_clears_below_hysteresis() {
+        let mut heating = SelfHeating::new(10.0, 0.0);
+        assert_eq!(heating.update(30.0, 21.0), None);
+        assert_eq!(heating.update(32.0, 21.0), Some(true));
+        assert_eq!(heating.update(33.0, 21.0), None);
+        assert_eq!(heating.update(29.5, 21.0), None);
+        assert!(heating.is_warned());
+        assert_eq!(heating.update(28.5, 21.0), Some(false));
+        assert!(!heating.is_warned());
+    }
+
+    #[test]
+    fn new_levels_apply_to_next_update() {
+        let mut heating = SelfHeating::new(25.0, 0.0);
+        assert_eq!(heating.update(35.0, 21.0), None);
+        heating.set_levels(10.0, 0.5);
+        assert_eq!(heating.update(35.0, 21.0), Some(true));
+        assert_eq!(heating.compensate(35.0, 21.0), 14.0);
+    }
+
+    #[test]
+    fn compensates_only_warmer_chip() {
+        let heating = SelfHeating::new(25.0, 0.25);
+        assert_eq!(heating.compensate(29.0, 21.0), 19.0);
+        assert_eq!(heating.compensate(18.0, 21.0), 21.0);
+        assert_eq!(SelfHeating::new(25.0, 0.0).compensate(29.0, 21.0), 21.0);
+    }
+}
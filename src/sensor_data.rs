@@ -102,4 +102,9 @@ where
     pub fn last(&self) -> Option<&TimedSensorData<T>> {
         self.buffer.back()
     }
+
+    /// Iterates over stored data from the oldest to the newest
+    pub fn iter(&self) -> impl Iterator<Item = &TimedSensorData<T>> {
+        self.buffer.iter()
+    }
 }
@@ -0,0 +1,1275 @@
+//!
+//! Settings changed on a running device
+//!
+//! Submitted settings are validated as a whole, persisted and only then applied,
+//! so the device never runs a half-applied or unsaved configuration. Persisted
+//! copies alternate between two sectors of the `settings` partition, the newest
+//! valid one wins, so power loss during save keeps the previous settings.
+//!
+
+#[cfg(target_arch = "riscv32")]
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+#[cfg(target_arch = "riscv32")]
+use embedded_storage::Storage;
+#[cfg(target_arch = "riscv32")]
+use esp_bootloader_esp_idf::partitions::{DataPartitionSubType, PartitionType};
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_arch = "riscv32")]
+use crate::sync::mutex::AtomicMutex;
+use crate::{
+    calibration::{self, Calibrations},
+    net::{http::Url, webhook::MAX_WEBHOOKS},
+    partition::Partition,
+    sha256::SaltedDigest,
+    units::TemperatureUnit,
+};
+
+pub const MAX_SSID_LEN: usize = 32;
+pub const MAX_PASSWORD_LEN: usize = 64;
+pub const MAX_URL_LEN: usize = 128;
+pub const MAX_OTA_KEY_LEN: usize = 64;
+
+/// Sensors are not read faster, DHT22 needs 2 s between readings
+const MIN_SAMPLE_INTERVAL_S: u32 = 2;
+const MAX_SAMPLE_INTERVAL_S: u32 = 3600;
+const TEMPERATURE_RANGE: (f32, f32) = (-40.0, 125.0);
+const HUMIDITY_RANGE: (f32, f32) = (0.0, 100.0);
+/// Chip-ambient difference in °C
+const SELF_HEATING_WARNING_RANGE: (f32, f32) = (1.0, 100.0);
+const SELF_HEATING_COMPENSATION_RANGE: (f32, f32) = (0.0, 1.0);
+/// WPA2 passphrase length
+const PASSWORD_LEN_RANGE: (usize, usize) = (8, 63);
+/// State of charge in %
+const BATTERY_RANGE: (f32, f32) = (0.0, 100.0);
+/// Battery voltage divided by ADC pin voltage
+const BATTERY_DIVIDER_RANGE: (f32, f32) = (1.0, 20.0);
+const SLEEP_INTERVAL_RANGE_S: (u32, u32) = (10, 86400);
+const UPLOAD_EVERY_RANGE: (u32, u32) = (1, 1000);
+/// Sum of RGB Led channels in % of full white
+const LED_CURRENT_LIMIT_RANGE: (u32, u32) = (10, 100);
+/// Time new firmware has to prove itself healthy in
+const HEALTH_TIMEOUT_RANGE_S: (u32, u32) = (30, 3600);
+
+/// Marks persisted record
+const MAGIC: u32 = 0x5345_5454;
+const HEADER_LEN: usize = 16;
+/// Settings persist in two alternating sectors
+const SLOT_SIZE: u32 = 4096;
+/// Longest persisted JSON, the rest of sector after header
+const MAX_RECORD_LEN: usize = SLOT_SIZE as usize - HEADER_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum LedMode {
+    /// Device status
+    #[default]
+    Status,
+    /// CPU load instead of device status
+    Load,
+    /// Gradient color of temperature instead of normal status
+    Temperature,
+    /// Gradient color of humidity instead of normal status
+    Humidity,
+}
+
+/// Colors of gradient LED modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientKind {
+    /// Cold-warm and dry-wet color stops
+    #[default]
+    Colors,
+    /// Through color temperatures
+    Kelvin,
+}
+
+/// Effect of LED strip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum StripEffect {
+    Solid,
+    /// Temperature as bar of gradient colors
+    Bar,
+    Breathe,
+    Chase,
+}
+
+/// LEDs of strip, they differ in bit timings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum StripChip {
+    Ws2812,
+    #[default]
+    Ws2812b,
+    Sk6812,
+}
+
+/// Order of color bytes expected by LEDs of strip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum StripColorOrder {
+    Rgb,
+    Rbg,
+    #[default]
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayController {
+    #[default]
+    Ssd1306,
+    Sh1106,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerMode {
+    /// Always on, serving web and display
+    #[default]
+    Live,
+    /// Sleeps between measurements and uploads them in batches
+    Battery,
+}
+
+/// Temperatures are in °C inside, but in settings unit in API
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct AlarmSettings {
+    pub temperature_high: Option<f32>,
+    pub temperature_low: Option<f32>,
+    /// In %
+    pub humidity_high: Option<f32>,
+    /// In %
+    pub humidity_low: Option<f32>,
+    /// State of charge in %
+    #[serde(default = "default_battery_low")]
+    pub battery_low: Option<f32>,
+}
+
+fn default_battery_low() -> Option<f32> {
+    Some(15.0)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct NetworkSettings {
+    pub ssid: String<MAX_SSID_LEN>,
+    /// Never sent by API. None in submitted settings keeps the current password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String<MAX_PASSWORD_LEN>>,
+    /// Web interface on HTTPS, plain HTTP redirects to it
+    #[serde(default)]
+    pub https: bool,
+}
+
+/// Values shown by the ends of LED gradient
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GradientRange {
+    pub low: f32,
+    pub high: f32,
+}
+
+impl GradientRange {
+    pub const TEMPERATURE: GradientRange = GradientRange {
+        low: 10.0,
+        high: 30.0,
+    };
+    pub const HUMIDITY: GradientRange = GradientRange {
+        low: 20.0,
+        high: 80.0,
+    };
+}
+
+fn default_current_limit() -> u8 {
+    100
+}
+
+fn default_temperature_range() -> GradientRange {
+    GradientRange::TEMPERATURE
+}
+
+fn default_humidity_range() -> GradientRange {
+    GradientRange::HUMIDITY
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LedSettings {
+    pub mode: LedMode,
+    /// 0-255
+    pub brightness: u8,
+    /// Max sum of RGB Led channels in % of full white, roughly proportional to its current
+    #[serde(default = "default_current_limit")]
+    pub current_limit: u8,
+    #[serde(default)]
+    pub gradient: GradientKind,
+    /// Temperatures shown by gradient, °C inside, but settings unit in API
+    #[serde(default = "default_temperature_range")]
+    pub temperature_range: GradientRange,
+    /// Humidities in % shown by gradient
+    #[serde(default = "default_humidity_range")]
+    pub humidity_range: GradientRange,
+    /// Strip is not driven if None, applied after restart
+    #[serde(default)]
+    pub strip: Option<StripEffect>,
+    /// Applied after restart
+    #[serde(default)]
+    pub strip_chip: StripChip,
+    /// Applied after restart
+    #[serde(default)]
+    pub strip_order: StripColorOrder,
+}
+
+/// Attached hardware, applied after restart
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct HardwareSettings {
+    pub display: DisplayController,
+    /// Battery voltage divided by voltage of GPIO2, e.g. 2 for two equal resistors.
+    /// Battery is not monitored if None
+    pub battery_divider: Option<f32>,
+}
+
+/// Applied after restart
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerSettings {
+    pub mode: PowerMode,
+    /// Where battery mode POSTs buffered readings, plain `http://` only
+    pub upload_url: Option<String<MAX_URL_LEN>>,
+    /// Seconds of deep sleep between measurements in battery mode
+    pub sleep_interval_s: u32,
+    /// Battery mode uploads every this count of wakes
+    pub upload_every: u32,
+}
+
+impl Default for PowerSettings {
+    fn default() -> Self {
+        Self {
+            mode: PowerMode::Live,
+            upload_url: None,
+            sleep_interval_s: 300,
+            upload_every: 12,
+        }
+    }
+}
+
+/// Firmware updates, applied after restart
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OtaSettings {
+    /// Shared key of HMAC-SHA256 image signatures, unsigned images are accepted
+    /// if None or empty. Never sent by API, None in submitted settings keeps the current key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String<MAX_OTA_KEY_LEN>>,
+    /// Seconds new firmware has to get network and sensor reading in, or it is rolled back
+    pub health_timeout_s: u32,
+}
+
+impl Default for OtaSettings {
+    fn default() -> Self {
+        Self {
+            key: None,
+            health_timeout_s: 120,
+        }
+    }
+}
+
+/// Heating of ambient sensors by the chip, in °C regardless of settings unit
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SelfHeatingSettings {
+    /// Difference of chip and ambient temperatures to warn about
+    pub warning: f32,
+    /// Share of the difference subtracted from ambient temperature, 0 disables it
+    pub compensation: f32,
+}
+
+impl Default for SelfHeatingSettings {
+    fn default() -> Self {
+        Self {
+            warning: 25.0,
+            compensation: 0.0,
+        }
+    }
+}
+
+/// Salted digests of admin credentials, never sent by API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Credentials {
+    /// Of `user:password` of HTTP Basic authentication
+    pub password: Option<SaltedDigest>,
+    /// Of bearer token
+    pub token: Option<SaltedDigest>,
+}
+
+impl Credentials {
+    /// Admin access is denied to everyone if there are no credentials
+    pub fn is_configured(&self) -> bool {
+        self.password.is_some() || self.token.is_some()
+    }
+
+    fn is_unset(&self) -> bool {
+        !self.is_configured()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Seconds between environment readings
+    pub sample_interval_s: u32,
+    /// Unit of temperatures shown by dashboard, display and API
+    pub unit: TemperatureUnit,
+    pub alarms: AlarmSettings,
+    pub network: NetworkSettings,
+    /// Plain `http://` URLs receiving events
+    pub webhooks: Vec<String<MAX_URL_LEN>, MAX_WEBHOOKS>,
+    pub led: LedSettings,
+    /// Corrections of sensor readings, missing is no calibration
+    #[serde(default)]
+    pub calibration: Calibrations,
+    #[serde(default)]
+    pub self_heating: SelfHeatingSettings,
+    #[serde(default)]
+    pub hardware: HardwareSettings,
+    #[serde(default)]
+    pub power: PowerSettings,
+    #[serde(default)]
+    pub ota: OtaSettings,
+    /// Changed by their own route only, submitted ones are ignored
+    #[serde(default, skip_serializing_if = "Credentials::is_unset")]
+    pub credentials: Credentials,
+}
+
+/// Why submitted settings were rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    OutOfRange,
+    NotFinite,
+    /// Low threshold or end of range is not below high one
+    LowAboveHigh,
+    Empty,
+    /// WPA2 passphrase must be 8-63 characters, or empty for open network
+    InvalidPassword,
+    /// Not a plain `http://` URL
+    InvalidUrl,
+    /// Correction is not finite, points are too close or sensor quantity is calibrated twice
+    InvalidCalibration,
+    /// User contains `:`, or user and password are not given together
+    InvalidCredentials,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+pub struct SettingsError {
+    /// Path of rejected field, e.g. `alarms.temperature_low`
+    pub field: &'static str,
+    pub reason: Reason,
+}
+
+impl SettingsError {
+    pub(crate) fn new(field: &'static str, reason: Reason) -> Self {
+        Self { field, reason }
+    }
+}
+
+impl Settings {
+    /// Checks every field, the first invalid one is reported
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if !(MIN_SAMPLE_INTERVAL_S..=MAX_SAMPLE_INTERVAL_S).contains(&self.sample_interval_s) {
+            return Err(SettingsError::new("sample_interval_s", Reason::OutOfRange));
+        }
+
+        let alarms = &self.alarms;
+        validate_thresholds(
+            ("alarms.temperature_high", alarms.temperature_high),
+            ("alarms.temperature_low", alarms.temperature_low),
+            TEMPERATURE_RANGE,
+        )?;
+        validate_thresholds(
+            ("alarms.humidity_high", alarms.humidity_high),
+            ("alarms.humidity_low", alarms.humidity_low),
+            HUMIDITY_RANGE,
+        )?;
+        if let Some(battery_low) = alarms.battery_low {
+            validate_value("alarms.battery_low", battery_low, BATTERY_RANGE)?;
+        }
+
+        if self.network.ssid.is_empty() {
+            return Err(SettingsError::new("network.ssid", Reason::Empty));
+        }
+        if let Some(password) = &self.network.password {
+            let (min, max) = PASSWORD_LEN_RANGE;
+            if !password.is_empty() && !(min..=max).contains(&password.len()) {
+                return Err(SettingsError::new(
+                    "network.password",
+                    Reason::InvalidPassword,
+                ));
+            }
+        }
+
+        let led = &self.led;
+        validate_count(
+            "led.current_limit",
+            led.current_limit as u32,
+            LED_CURRENT_LIMIT_RANGE,
+        )?;
+        validate_range(
+            ("led.temperature_range.low", "led.temperature_range.high"),
+            led.temperature_range,
+            TEMPERATURE_RANGE,
+        )?;
+        validate_range(
+            ("led.humidity_range.low", "led.humidity_range.high"),
+            led.humidity_range,
+            HUMIDITY_RANGE,
+        )?;
+
+        if self.webhooks.iter().any(|url| Url::parse(url).is_err()) {
+            return Err(SettingsError::new("webhooks", Reason::InvalidUrl));
+        }
+
+        if calibration::validate(&self.calibration).is_err() {
+            return Err(SettingsError::new(
+                "calibration",
+                Reason::InvalidCalibration,
+            ));
+        }
+
+        let self_heating = &self.self_heating;
+        validate_value(
+            "self_heating.warning",
+            self_heating.warning,
+            SELF_HEATING_WARNING_RANGE,
+        )?;
+        validate_value(
+            "self_heating.compensation",
+            self_heating.compensation,
+            SELF_HEATING_COMPENSATION_RANGE,
+        )?;
+
+        if let Some(divider) = self.hardware.battery_divider {
+            validate_value("hardware.battery_divider", divider, BATTERY_DIVIDER_RANGE)?;
+        }
+
+        let power = &self.power;
+        match &power.upload_url {
+            Some(url) if Url::parse(url).is_err() => {
+                return Err(SettingsError::new("power.upload_url", Reason::InvalidUrl));
+            }
+            None if power.mode == PowerMode::Battery => {
+                return Err(SettingsError::new("power.upload_url", Reason::Empty));
+            }
+            _ => {}
+        }
+        validate_count(
+            "power.sleep_interval_s",
+            power.sleep_interval_s,
+            SLEEP_INTERVAL_RANGE_S,
+        )?;
+        validate_count("power.upload_every", power.upload_every, UPLOAD_EVERY_RANGE)?;
+
+        validate_count(
+            "ota.health_timeout_s",
+            self.ota.health_timeout_s,
+            HEALTH_TIMEOUT_RANGE_S,
+        )?;
+
+        Ok(())
+    }
+
+    /// Merges submitted settings into current ones
+    ///
+    /// # Returns
+    /// Settings to apply, missing password and OTA key are taken from current settings,
+    /// credentials are kept
+    pub fn merge(&self, mut submitted: Settings) -> Settings {
+        if submitted.network.password.is_none() {
+            submitted.network.password = self.network.password.clone();
+        }
+        if submitted.ota.key.is_none() {
+            submitted.ota.key = self.ota.key.clone();
+        }
+        submitted.credentials = self.credentials;
+        submitted
+    }
+
+    /// Network, webhooks, hardware, power mode, updates and LED strip are set up at boot only
+    pub fn requires_restart(&self, new: &Settings) -> bool {
+        self.network != new.network
+            || self.webhooks != new.webhooks
+            || self.hardware != new.hardware
+            || self.power != new.power
+            || self.ota != new.ota
+            || self.led.strip != new.led.strip
+            || self.led.strip_chip != new.led.strip_chip
+            || self.led.strip_order != new.led.strip_order
+    }
+
+    /// Copy sent by API, without secrets and with temperatures in settings unit
+    pub fn public(&self) -> Settings {
+        let mut settings = self.clone();
+        settings.network.password = None;
+        settings.ota.key = None;
+        settings.credentials = Credentials::default();
+        settings.convert_temperatures(|celsius| self.unit.from_celsius(celsius));
+        settings
+    }
+
+    /// Converts temperatures submitted in settings unit to °C
+    pub fn into_celsius(mut self) -> Settings {
+        let unit = self.unit;
+        self.convert_temperatures(|value| unit.to_celsius(value));
+        self
+    }
+
+    fn convert_temperatures(&mut self, convert: impl Fn(f32) -> f32) {
+        let alarms = &mut self.alarms;
+        alarms.temperature_high = alarms.temperature_high.map(&convert);
+        alarms.temperature_low = alarms.temperature_low.map(&convert);
+
+        let range = &mut self.led.temperature_range;
+        range.low = convert(range.low);
+        range.high = convert(range.high);
+    }
+}
+
+fn validate_value(
+    field: &'static str,
+    value: f32,
+    (min, max): (f32, f32),
+) -> Result<(), SettingsError> {
+    if !value.is_finite() {
+        Err(SettingsError::new(field, Reason::NotFinite))
+    } else if !(min..=max).contains(&value) {
+        Err(SettingsError::new(field, Reason::OutOfRange))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_count(
+    field: &'static str,
+    value: u32,
+    (min, max): (u32, u32),
+) -> Result<(), SettingsError> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(SettingsError::new(field, Reason::OutOfRange))
+    }
+}
+
+fn validate_range(
+    (low_field, high_field): (&'static str, &'static str),
+    GradientRange { low, high }: GradientRange,
+    range: (f32, f32),
+) -> Result<(), SettingsError> {
+    validate_value(low_field, low, range)?;
+    validate_value(high_field, high, range)?;
+    if high <= low {
+        return Err(SettingsError::new(low_field, Reason::LowAboveHigh));
+    }
+    Ok(())
+}
+
+fn validate_thresholds(
+    high: (&'static str, Option<f32>),
+    low: (&'static str, Option<f32>),
+    range: (f32, f32),
+) -> Result<(), SettingsError> {
+    for (field, value) in [high, low] {
+        if let Some(value) = value {
+            validate_value(field, value, range)?;
+        }
+    }
+
+    match (high.1, low) {
+        (Some(high), (field, Some(low))) if high <= low => {
+            Err(SettingsError::new(field, Reason::LowAboveHigh))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreError {
+    /// Partition table has no partition of the store
+    NoPartition,
+    Storage,
+    /// Settings do not fit into record
+    TooLarge,
+}
+
+/// Persisted settings in two alternating sectors
+///
+/// Record is a header of magic, sequence, length and FNV-1a checksum followed by JSON
+pub struct SettingsStore {
+    partition: Partition,
+}
+
+impl SettingsStore {
+    /// Finds `settings` partition, a data partition of undefined subtype
+    #[cfg(target_arch = "riscv32")]
+    pub fn open<F: Storage>(flash: &mut F) -> Result<Self, StoreError> {
+        let partition = crate::partition::find_partition(
+            flash,
+            PartitionType::Data(DataPartitionSubType::Undefined),
+        )
+        .map_err(|_| StoreError::Storage)?
+        .ok_or(StoreError::NoPartition)?;
+        if partition.len < 2 * SLOT_SIZE {
+            return Err(StoreError::NoPartition);
+        }
+        Ok(Self { partition })
+    }
+
+    /// Reads the newest valid record
+    ///
+    /// # Returns
+    /// None, if nothing valid is persisted
+    pub fn load<F: ReadNorFlash>(&self, flash: &mut F) -> Option<Settings> {
+        let mut buffer = [0_u8; MAX_RECORD_LEN];
+        let (slot, _) = self.newest(flash, &mut buffer)?;
+        let len = self.read_record(flash, slot, &mut buffer)?;
+        let mut unescape = [0_u8; MAX_URL_LEN];
+        serde_json_core::from_slice_escaped(&buffer[..len], &mut unescape)
+            .ok()
+            .map(|(settings, _)| settings)
+    }
+
+    /// Writes settings over the older record
+    pub fn save<F: ReadNorFlash + NorFlash>(
+        &self,
+        flash: &mut F,
+        settings: &Settings,
+    ) -> Result<(), StoreError> {
+        let mut buffer = [0_u8; HEADER_LEN + MAX_RECORD_LEN];
+        let len = serde_json_core::to_slice(settings, &mut buffer[HEADER_LEN..])
+            .map_err(|_| StoreError::TooLarge)?;
+
+        let (slot, sequence) = match self.newest(flash, &mut [0; MAX_RECORD_LEN]) {
+            Some((slot, sequence)) => (1 - slot, sequence.wrapping_add(1)),
+            None => (0, 1),
+        };
+
+        let payload = &buffer[HEADER_LEN..HEADER_LEN + len];
+        let header = [MAGIC, sequence, len as u32, checksum(payload)];
+        for (bytes, word) in buffer.chunks_exact_mut(4).zip(header) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+
+        let offset = self.partition.offset + slot * SLOT_SIZE;
+        let total = (HEADER_LEN + len).next_multiple_of(F::WRITE_SIZE);
+        buffer[HEADER_LEN + len..total].fill(0xFF);
+        flash
+            .erase(offset, offset + SLOT_SIZE)
+            .map_err(|_| StoreError::Storage)?;
+        // Header goes last, so interrupted write leaves no valid record
+        flash
+            .write(offset + HEADER_LEN as u32, &buffer[HEADER_LEN..total])
+            .map_err(|_| StoreError::Storage)?;
+        flash
+            .write(offset, &buffer[..HEADER_LEN])
+            .map_err(|_| StoreError::Storage)
+    }
+
+    /// Finds slot of valid record with the highest sequence
+    fn newest<F: ReadNorFlash>(&self, flash: &mut F, buffer: &mut [u8]) -> Option<(u32, u32)> {
+        (0..2)
+            .filter_map(|slot| {
+                self.read_record(flash, slot, buffer)?;
+                let header = self.read_header(flash, slot)?;
+                Some((slot, header[1]))
+            })
+            // Sequence wraps, the newer one is less than half the range ahead
+            .reduce(|a, b| {
+                if b.1.wrapping_sub(a.1) < u32::MAX / 2 {
+                    b
+                } else {
+                    a
+                }
+            })
+    }
+
+    fn read_header<F: ReadNorFlash>(&self, flash: &mut F, slot: u32) -> Option<[u32; 4]> {
+        let mut bytes = [0_u8; HEADER_LEN];
+        flash
+            .read(self.partition.offset + slot * SLOT_SIZE, &mut bytes)
+            .ok()?;
+
+        let mut header = [0_u32; 4];
+        for (word, bytes) in header.iter_mut().zip(bytes.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        (header[0] == MAGIC && header[2] as usize <= MAX_RECORD_LEN).then_some(header)
+    }
+
+    /// Reads JSON of valid record into buffer
+    ///
+    /// # Returns
+    /// JSON length, None if record is missing or corrupted
+    fn read_record<F: ReadNorFlash>(
+        &self,
+        flash: &mut F,
+        slot: u32,
+        buffer: &mut [u8],
+    ) -> Option<usize> {
+        let [_, _, len, expected] = self.read_header(flash, slot)?;
+        let len = len as usize;
+        let read_len = len.next_multiple_of(F::READ_SIZE);
+        let offset = self.partition.offset + slot * SLOT_SIZE + HEADER_LEN as u32;
+        flash.read(offset, buffer.get_mut(..read_len)?).ok()?;
+        (checksum(&buffer[..len]) == expected).then_some(len)
+    }
+}
+
+/// FNV-1a
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811C_9DC5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Outcome of submitted settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Applied {
+    /// Settings set up at boot changed, they take effect after restart
+    pub restart_required: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+#[serde(untagged)]
+pub enum ApplyError {
+    Invalid(SettingsError),
+    Store(StoreError),
+}
+
+/// Current settings, flash they persist in and notification of changes
+#[cfg(target_arch = "riscv32")]
+pub struct SharedSettings<F: 'static> {
+    settings: &'static AtomicMutex<Settings>,
+    flash: &'static AtomicMutex<F>,
+    changed: &'static Signal<CriticalSectionRawMutex, ()>,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<F> Clone for SharedSettings<F> {
+    fn clone(&self) -> Self {
+        Self {
+            settings: self.settings,
+            flash: self.flash,
+            changed: self.changed,
+        }
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<F> SharedSettings<F>
+where
+    F: Storage + NorFlash,
+{
+    pub fn new(
+        settings: &'static AtomicMutex<Settings>,
+        flash: &'static AtomicMutex<F>,
+        changed: &'static Signal<CriticalSectionRawMutex, ()>,
+    ) -> Self {
+        Self {
+            settings,
+            flash,
+            changed,
+        }
+    }
+
+    pub async fn get(&self) -> Settings {
+        self.settings.lock().await.clone()
+    }
+
+    /// Validates, persists and applies submitted settings, nothing changes on error
+    ///
+    /// # Arguments
+    /// - `submitted` - settings as API exchanges them, thresholds in their unit
+    pub async fn apply(&self, submitted: Settings) -> Result<Applied, ApplyError> {
+        self.update(|current| current.merge(submitted.into_celsius()))
+            .await
+    }
+
+    /// Replaces calibrations, keeping other settings
+    pub async fn set_calibration(&self, calibration: Calibrations) -> Result<Applied, ApplyError> {
+        self.update(|current| Settings {
+            calibration,
+            ..current.clone()
+        })
+        .await
+    }
+
+    async fn update(&self, f: impl FnOnce(&Settings) -> Settings) -> Result<Applied, ApplyError> {
+        let mut settings = self.settings.lock().await;
+        let new = f(&settings);
+        new.validate().map_err(ApplyError::Invalid)?;
+
+        {
+            let mut flash = self.flash.lock().await;
+            let store = SettingsStore::open(&mut *flash).map_err(ApplyError::Store)?;
+            store.save(&mut *flash, &new).map_err(ApplyError::Store)?;
+        }
+
+        let applied = Applied {
+            restart_required: settings.requires_restart(&new),
+        };
+        *settings = new;
+        self.changed.signal(());
+        Ok(applied)
+    }
+
+    /// Replaces admin credentials, keeping other settings
+    pub async fn set_credentials(&self, credentials: Credentials) -> Result<Applied, ApplyError> {
+        self.update(|current| Settings {
+            credentials,
+            ..current.clone()
+        })
+        .await
+    }
+
+    /// Waits for settings to be applied
+    pub async fn wait_changed(&self) {
+        self.changed.wait().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::{Calibration, Correction, MAX_CALIBRATIONS};
+    use crate::events::Quantity;
+    use crate::partition::mock::MockFlash;
+    use crate::sha256::SALT_LEN;
+
+    type Flash = MockFlash<0x3000>;
+
+    fn store() -> SettingsStore {
+        SettingsStore {
+            partition: Partition {
+                offset: 0x1000,
+                len: 2 * SLOT_SIZE,
+            },
+        }
+    }
+
+    /// `prefix` followed by `fill` up to `len` characters
+    fn text<const N: usize>(prefix: &str, fill: char, len: usize) -> String<N> {
+        let mut text = String::try_from(prefix).unwrap();
+        while text.len() < len {
+            text.push(fill).unwrap();
+        }
+        text
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            sample_interval_s: 2,
+            unit: TemperatureUnit::Celsius,
+            alarms: AlarmSettings {
+                temperature_high: Some(30.0),
+                temperature_low: Some(10.0),
+                humidity_high: None,
+                humidity_low: None,
+                battery_low: Some(15.0),
+            },
+            network: NetworkSettings {
+                ssid: String::try_from("home").unwrap(),
+                password: Some(String::try_from("password").unwrap()),
+                https: false,
+            },
+            webhooks: Vec::new(),
+            led: LedSettings {
+                mode: LedMode::Status,
+                brightness: 255,
+                current_limit: 100,
+                gradient: GradientKind::Colors,
+                temperature_range: GradientRange::TEMPERATURE,
+                humidity_range: GradientRange::HUMIDITY,
+                strip: None,
+                strip_chip: StripChip::Ws2812b,
+                strip_order: StripColorOrder::Grb,
+            },
+            calibration: Vec::new(),
+            self_heating: SelfHeatingSettings::default(),
+            hardware: HardwareSettings::default(),
+            power: PowerSettings::default(),
+            ota: OtaSettings::default(),
+            credentials: Credentials::default(),
+        }
+    }
+
+    /// Every string and list at its limit, with characters which are escaped in JSON
+    fn largest() -> Settings {
+        let url = text("http://example.com:65535/", 'p', MAX_URL_LEN);
+        let calibration = (0..MAX_CALIBRATIONS)
+            .map(|index| Calibration {
+                sensor: text("sensor-number-", char::from(b'0' + index as u8), 16),
+                quantity: Quantity::Temperature,
+                correction: Correction::TwoPoint {
+                    raw_low: -1.2345678e30,
+                    reference_low: -1.2345679e30,
+                    raw_high: 1.2345678e30,
+                    reference_high: 1.2345679e30,
+                },
+            })
+            .collect();
+
+        Settings {
+            sample_interval_s: MAX_SAMPLE_INTERVAL_S,
+            unit: TemperatureUnit::Fahrenheit,
+            alarms: AlarmSettings {
+                temperature_high: Some(123.45679),
+                temperature_low: Some(-39.876543),
+                humidity_high: Some(99.876543),
+                humidity_low: Some(0.12345679),
+                battery_low: Some(12.345679),
+            },
+            network: NetworkSettings {
+                ssid: text("", '\\', MAX_SSID_LEN),
+                password: Some(text("", '"', PASSWORD_LEN_RANGE.1)),
+                https: true,
+            },
+            webhooks: (0..MAX_WEBHOOKS).map(|_| url.clone()).collect(),
+            led: LedSettings {
+                mode: LedMode::Humidity,
+                brightness: 255,
+                current_limit: 10,
+                gradient: GradientKind::Kelvin,
+                temperature_range: GradientRange {
+                    low: -39.876543,
+                    high: 123.45679,
+                },
+                humidity_range: GradientRange {
+                    low: 0.12345679,
+                    high: 99.876543,
+                },
+                strip: Some(StripEffect::Breathe),
+                strip_chip: StripChip::Sk6812,
+                strip_order: StripColorOrder::Bgr,
+            },
+            calibration,
+            self_heating: SelfHeatingSettings {
+                warning: 99.876543,
+                compensation: 0.12345679,
+            },
+            hardware: HardwareSettings {
+                display: DisplayController::Sh1106,
+                battery_divider: Some(19.876543),
+            },
+            power: PowerSettings {
+                mode: PowerMode::Battery,
+                upload_url: Some(url),
+                sleep_interval_s: SLEEP_INTERVAL_RANGE_S.1,
+                upload_every: UPLOAD_EVERY_RANGE.1,
+            },
+            ota: OtaSettings {
+                key: Some(text("", '"', MAX_OTA_KEY_LEN)),
+                health_timeout_s: HEALTH_TIMEOUT_RANGE_S.1,
+            },
+            credentials: Credentials {
+                password: Some(SaltedDigest::new([0xA5; SALT_LEN], b"admin:secret")),
+                token: Some(SaltedDigest::new([0x5A; SALT_LEN], b"token")),
+            },
+        }
+    }
+
+    fn rejected(change: impl FnOnce(&mut Settings)) -> Option<(&'static str, Reason)> {
+        let mut settings = settings();
+        change(&mut settings);
+        settings.validate().err().map(|err| (err.field, err.reason))
+    }
+
+    /// Overwrites sequence in header of slot, it is not covered by checksum
+    fn set_sequence(flash: &mut Flash, slot: u32, sequence: u32) {
+        let offset = (store().partition.offset + slot * SLOT_SIZE) as usize + 4;
+        flash.data[offset..offset + 4].copy_from_slice(&sequence.to_le_bytes());
+    }
+
+    #[test]
+    fn validate_accepts_defaults_and_limits() {
+        assert_eq!(settings().validate(), Ok(()));
+        assert_eq!(largest().validate(), Ok(()));
+        // Open network
+        assert_eq!(rejected(|s| s.network.password = Some(String::new())), None);
+        // Current password is kept
+        assert_eq!(rejected(|s| s.network.password = None), None);
+    }
+
+    #[test]
+    fn validate_rejects_invalid_fields() {
+        let duplicated = Calibration {
+            sensor: String::try_from("sht4x").unwrap(),
+            quantity: Quantity::Humidity,
+            correction: Correction::Offset { offset: 1.0 },
+        };
+
+        let cases: [(fn(&mut Settings), &str, Reason); 21] = [
+            (
+                |s| s.sample_interval_s = 1,
+                "sample_interval_s",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.alarms.temperature_high = Some(f32::NAN),
+                "alarms.temperature_high",
+                Reason::NotFinite,
+            ),
+            (
+                |s| s.alarms.temperature_low = Some(30.0),
+                "alarms.temperature_low",
+                Reason::LowAboveHigh,
+            ),
+            (
+                |s| s.alarms.humidity_high = Some(101.0),
+                "alarms.humidity_high",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.alarms.battery_low = Some(-1.0),
+                "alarms.battery_low",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.led.current_limit = 101,
+                "led.current_limit",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.led.temperature_range.high = -50.0,
+                "led.temperature_range.high",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.led.temperature_range.low = 30.0,
+                "led.temperature_range.low",
+                Reason::LowAboveHigh,
+            ),
+            (
+                |s| s.led.humidity_range.low = f32::INFINITY,
+                "led.humidity_range.low",
+                Reason::NotFinite,
+            ),
+            (|s| s.network.ssid.clear(), "network.ssid", Reason::Empty),
+            (
+                |s| s.network.password = Some(String::try_from("short").unwrap()),
+                "network.password",
+                Reason::InvalidPassword,
+            ),
+            (
+                |s| s.network.password = Some(text("", 'x', MAX_PASSWORD_LEN)),
+                "network.password",
+                Reason::InvalidPassword,
+            ),
+            (
+                |s| {
+                    s.webhooks
+                        .push(String::try_from("https://example.com").unwrap())
+                        .unwrap()
+                },
+                "webhooks",
+                Reason::InvalidUrl,
+            ),
+            (
+                |s| s.self_heating.compensation = 1.5,
+                "self_heating.compensation",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.hardware.battery_divider = Some(0.5),
+                "hardware.battery_divider",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.power.mode = PowerMode::Battery,
+                "power.upload_url",
+                Reason::Empty,
+            ),
+            (
+                |s| s.power.upload_url = Some(String::try_from("example.com").unwrap()),
+                "power.upload_url",
+                Reason::InvalidUrl,
+            ),
+            (
+                |s| s.power.sleep_interval_s = 5,
+                "power.sleep_interval_s",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.power.upload_every = 0,
+                "power.upload_every",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.ota.health_timeout_s = 10,
+                "ota.health_timeout_s",
+                Reason::OutOfRange,
+            ),
+            (
+                |s| s.sample_interval_s = MAX_SAMPLE_INTERVAL_S + 1,
+                "sample_interval_s",
+                Reason::OutOfRange,
+            ),
+        ];
+        for (change, field, reason) in cases {
+            assert_eq!(rejected(change), Some((field, reason)));
+        }
+
+        assert_eq!(
+            rejected(|s| {
+                s.calibration.push(duplicated.clone()).unwrap();
+                s.calibration.push(duplicated).unwrap();
+            }),
+            Some(("calibration", Reason::InvalidCalibration))
+        );
+    }
+
+    #[test]
+    fn merge_keeps_secrets_and_public_strips_them() {
+        let current = largest();
+        let mut submitted = current.public();
+        submitted.credentials = Credentials::default();
+        submitted.sample_interval_s = 10;
+
+        let merged = current.merge(submitted.into_celsius());
+        assert_eq!(merged.network.password, current.network.password);
+        assert_eq!(merged.ota.key, current.ota.key);
+        assert_eq!(merged.credentials, current.credentials);
+        assert_eq!(merged.sample_interval_s, 10);
+        assert!(!current.requires_restart(&merged));
+
+        let public = current.public();
+        assert_eq!(public.network.password, None);
+        assert_eq!(public.ota.key, None);
+        assert!(!public.credentials.is_configured());
+    }
+
+    #[test]
+    fn api_exchanges_temperatures_in_settings_unit() {
+        let mut current = settings();
+        current.unit = TemperatureUnit::Fahrenheit;
+
+        let public = current.public();
+        assert_eq!(public.alarms.temperature_high, Some(86.0));
+        assert_eq!(public.alarms.temperature_low, Some(50.0));
+        assert_eq!(
+            public.led.temperature_range,
+            GradientRange {
+                low: 50.0,
+                high: 86.0
+            }
+        );
+        assert_eq!(public.led.humidity_range, GradientRange::HUMIDITY);
+
+        let submitted = public.into_celsius();
+        assert_eq!(submitted.alarms, current.alarms);
+        assert_eq!(submitted.led, current.led);
+    }
+
+    #[test]
+    fn requires_restart_for_boot_settings_only() {
+        let current = settings();
+        let mut new = current.clone();
+        new.led.brightness = 10;
+        new.alarms.temperature_high = None;
+        assert!(!current.requires_restart(&new));
+
+        new.led.strip = Some(StripEffect::Chase);
+        assert!(current.requires_restart(&new));
+
+        let mut new = current.clone();
+        new.led.strip_order = StripColorOrder::Rgb;
+        assert!(current.requires_restart(&new));
+
+        let mut new = current.clone();
+        new.power.sleep_interval_s = 60;
+        assert!(current.requires_restart(&new));
+    }
+
+    #[test]
+    fn largest_settings_persist() {
+        let mut flash = Flash::new();
+        let settings = largest();
+
+        store().save(&mut flash, &settings).unwrap();
+        assert_eq!(store().load(&mut flash), Some(settings));
+    }
+
+    #[test]
+    fn load_of_empty_flash_gives_nothing() {
+        assert_eq!(store().load(&mut Flash::new()), None);
+    }
+
+    #[test]
+    fn saves_alternate_sectors_and_newest_wins() {
+        let mut flash = Flash::new();
+        let mut settings = settings();
+
+        for interval in 2..6 {
+            settings.sample_interval_s = interval;
+            store().save(&mut flash, &settings).unwrap();
+            assert_eq!(store().load(&mut flash), Some(settings.clone()));
+        }
+        // Every save erased only its own sector
+        assert_eq!(flash.erased, 4);
+        assert_eq!(store().read_header(&mut flash, 0).unwrap()[1], 3);
+        assert_eq!(store().read_header(&mut flash, 1).unwrap()[1], 4);
+    }
+
+    #[test]
+    fn corrupted_newest_falls_back_to_previous() {
+        let mut flash = Flash::new();
+        let old = settings();
+        let mut new = settings();
+        new.sample_interval_s = 60;
+        store().save(&mut flash, &old).unwrap();
+        store().save(&mut flash, &new).unwrap();
+
+        // Flipped bit in JSON of the newest record fails its checksum
+        let payload = (store().partition.offset + SLOT_SIZE) as usize + HEADER_LEN;
+        flash.data[payload + 5] ^= 0x01;
+        assert_eq!(store().load(&mut flash), Some(old.clone()));
+
+        // The next save replaces the corrupted record, not the valid one
+        store().save(&mut flash, &new).unwrap();
+        assert_eq!(store().load(&mut flash), Some(new));
+        assert_eq!(store().read_header(&mut flash, 1).unwrap()[1], 2);
+    }
+
+    #[test]
+    fn interrupted_save_keeps_previous() {
+        let mut flash = Flash::new();
+        let old = settings();
+        let mut new = settings();
+        new.sample_interval_s = 60;
+        store().save(&mut flash, &old).unwrap();
+        store().save(&mut flash, &new).unwrap();
+
+        // Power lost before header of the newest record was written
+        let header = (store().partition.offset + SLOT_SIZE) as usize;
+        flash.data[header..header + HEADER_LEN].fill(0xFF);
+        assert_eq!(store().load(&mut flash), Some(old));
+    }
+
+    #[test]
+    fn newest_follows_sequence_wrap() {
+        let mut flash = Flash::new();
+        let first = settings();
+        let mut second = settings();
+        second.sample_interval_s = 60;
+        store().save(&mut flash, &first).unwrap();
+        store().save(&mut flash, &second).unwrap();
+
+        set_sequence(&mut flash, 0, u32::MAX);
+        set_sequence(&mut flash, 1, 0);
+        assert_eq!(store().load(&mut flash), Some(second));
+
+        set_sequence(&mut flash, 0, 0);
+        set_sequence(&mut flash, 1, u32::MAX);
+        assert_eq!(store().load(&mut flash), Some(first));
+    }
+}
@@ -0,0 +1,291 @@
+//!
+//! SHA-256 and HMAC-SHA256
+//!
+//! Software implementation, used for OTA images and web credentials. Images are
+//! hashed while they are streamed, so speed is limited by network, not hashing
+//!
+
+use core::fmt::Write;
+
+use heapless::String;
+use serde::{Deserialize, Serialize};
+
+/// SHA-256 digest
+pub type Digest = [u8; 32];
+
+/// Length of salt of [`SaltedDigest`]
+pub const SALT_LEN: usize = 16;
+/// Length of [`SaltedDigest`] as hex, salt first
+const SALTED_HEX_LEN: usize = 2 * (SALT_LEN + 32);
+
+const BLOCK_SIZE: usize = 64;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+#[derive(Debug, Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; BLOCK_SIZE],
+    block_len: usize,
+    /// Count of hashed bytes
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    pub const fn new() -> Self {
+        Self {
+            state: H0,
+            block: [0; BLOCK_SIZE],
+            block_len: 0,
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+
+        while !data.is_empty() {
+            let count = (BLOCK_SIZE - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + count].copy_from_slice(&data[..count]);
+            self.block_len += count;
+            data = &data[count..];
+
+            if self.block_len == BLOCK_SIZE {
+                let block = self.block;
+                self.compress(&block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    pub fn finalize(mut self) -> Digest {
+        let bits = self.len.wrapping_mul(8);
+
+        // Padding is a single set bit, zeroes and 64-bit length in the end of the last block
+        self.update(&[0x80]);
+        while self.block_len != BLOCK_SIZE - 8 {
+            self.update(&[0x00]);
+        }
+        self.update(&bits.to_be_bytes());
+
+        let mut digest = [0; 32];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; BLOCK_SIZE]) {
+        let mut w = [0_u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (k, w) in K.iter().zip(w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(*k)
+                .wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+/// SHA-256 of the whole message at once
+pub fn digest(message: &[u8]) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(message);
+    hasher.finalize()
+}
+
+/// HMAC-SHA256 of message authenticated by shared key
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> Digest {
+    // Keys longer than block are hashed first
+    let mut padded_key = [0_u8; BLOCK_SIZE];
+    if BLOCK_SIZE < key.len() {
+        padded_key[..32].copy_from_slice(&digest(key));
+    } else {
+        padded_key[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(&padded_key.map(|byte| byte ^ 0x36));
+    inner.update(message);
+    let inner = inner.finalize();
+
+    let mut outer = Sha256::new();
+    outer.update(&padded_key.map(|byte| byte ^ 0x5c));
+    outer.update(&inner);
+    outer.finalize()
+}
+
+/// Compares digests in constant time, so timing does not leak matching prefix
+pub fn digest_eq(a: &Digest, b: &Digest) -> bool {
+    a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Parses digest from 64 hex digits
+pub fn parse_digest(hex: &str) -> Option<Digest> {
+    parse_hex(hex)
+}
+
+fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.as_bytes();
+    if hex.len() != 2 * N {
+        return None;
+    }
+
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.chunks_exact(2)) {
+        let high = char::from(pair[0]).to_digit(16)?;
+        let low = char::from(pair[1]).to_digit(16)?;
+        *byte = (high << 4 | low) as u8;
+    }
+    Some(bytes)
+}
+
+/// HMAC-SHA256 of secret keyed by random salt, so equal secrets have different digests
+///
+/// Persisted as hex of salt followed by digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String<SALTED_HEX_LEN>", into = "String<SALTED_HEX_LEN>")]
+pub struct SaltedDigest {
+    pub salt: [u8; SALT_LEN],
+    pub digest: Digest,
+}
+
+impl SaltedDigest {
+    pub fn new(salt: [u8; SALT_LEN], secret: &[u8]) -> Self {
+        Self {
+            salt,
+            digest: hmac_sha256(&salt, secret),
+        }
+    }
+
+    /// Checks secret in constant time
+    pub fn matches(&self, secret: &[u8]) -> bool {
+        digest_eq(&self.digest, &hmac_sha256(&self.salt, secret))
+    }
+}
+
+impl From<SaltedDigest> for String<SALTED_HEX_LEN> {
+    fn from(salted: SaltedDigest) -> Self {
+        let mut hex = String::new();
+        for byte in salted.salt.iter().chain(&salted.digest) {
+            write!(hex, "{byte:02x}").ok();
+        }
+        hex
+    }
+}
+
+impl TryFrom<String<SALTED_HEX_LEN>> for SaltedDigest {
+    type Error = &'static str;
+
+    fn try_from(hex: String<SALTED_HEX_LEN>) -> Result<Self, Self::Error> {
+        const ERROR: &str = "not a hex salted digest";
+        let (salt, digest) = hex.split_at_checked(2 * SALT_LEN).ok_or(ERROR)?;
+        Ok(Self {
+            salt: parse_hex(salt).ok_or(ERROR)?,
+            digest: parse_hex(digest).ok_or(ERROR)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_fips_vectors() {
+        assert_eq!(
+            digest(b"abc"),
+            parse_digest("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                .unwrap()
+        );
+        assert_eq!(
+            digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            parse_digest("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        assert_eq!(
+            hmac_sha256(b"Jefe", b"what do ya want for nothing?"),
+            parse_digest("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn salted_digest_matches_only_its_secret() {
+        let salted = SaltedDigest::new([7; SALT_LEN], b"admin:secret");
+        assert!(salted.matches(b"admin:secret"));
+        assert!(!salted.matches(b"admin:Secret"));
+        assert_ne!(
+            SaltedDigest::new([8; SALT_LEN], b"admin:secret").digest,
+            salted.digest
+        );
+    }
+
+    #[test]
+    fn salted_digest_round_trips_as_hex() {
+        let salted = SaltedDigest::new([0xA5; SALT_LEN], b"token");
+        let mut json = [0_u8; 128];
+        let len = serde_json_core::to_slice(&salted, &mut json).unwrap();
+        assert_eq!(len, SALTED_HEX_LEN + 2);
+        assert!(json[1..33].iter().all(|&c| c == b'a' || c == b'5'));
+
+        let (parsed, _): (SaltedDigest, _) = serde_json_core::from_slice(&json[..len]).unwrap();
+        assert_eq!(parsed, salted);
+
+        assert!(serde_json_core::from_str::<SaltedDigest>("\"a5a5\"").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//!
+//! Device status shown by the RGB Led
+//!
+//! Tasks raise or drop status flags, the indicator shows the one with the highest
+//! priority using its own color and blink pattern
+//!
+
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+use crate::color_gradient::Gradient;
+
+/// Device status, ordered by priority: the first has the highest one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum Status {
+    Booting = 0,
+    WifiConnecting = 1,
+    NoIp = 2,
+    SensorFault = 3,
+    AlarmActive = 4,
+    /// Nothing to report, shown when no flags raised
+    Normal = 5,
+}
+
+impl Status {
+    const FLAGGED: [Status; 5] = [
+        Status::Booting,
+        Status::WifiConnecting,
+        Status::NoIp,
+        Status::SensorFault,
+        Status::AlarmActive,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+
+    /// Color and pattern to show this status with
+    pub fn appearance(&self) -> ((u8, u8, u8), Pattern) {
+        match self {
+            Status::Booting => ((255, 255, 255), Pattern::Breathe { period_ms: 2000 }),
+            Status::WifiConnecting => (
+                (0, 0, 255),
+                Pattern::Blink {
+                    on_ms: 250,
+                    off_ms: 250,
+                },
+            ),
+            Status::NoIp => (
+                (0, 255, 255),
+                Pattern::Blink {
+                    on_ms: 1000,
+                    off_ms: 1000,
+                },
+            ),
+            Status::SensorFault => (
+                (255, 96, 0),
+                Pattern::Blink {
+                    on_ms: 500,
+                    off_ms: 500,
+                },
+            ),
+            Status::AlarmActive => (
+                (255, 0, 0),
+                Pattern::Blink {
+                    on_ms: 150,
+                    off_ms: 150,
+                },
+            ),
+            Status::Normal => ((0, 255, 0), Pattern::Solid),
+        }
+    }
+}
+
+/// How color changes over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Pattern {
+    Solid,
+    Blink {
+        on_ms: u32,
+        off_ms: u32,
+    },
+    /// Brightness goes up and down linearly within period
+    Breathe {
+        period_ms: u32,
+    },
+}
+
+impl Pattern {
+    /// Brightness of pattern at given time
+    ///
+    /// # Returns
+    /// brightness, 0..=255
+    pub fn brightness(&self, elapsed_ms: u64) -> u8 {
+        match *self {
+            Pattern::Solid => 255,
+            Pattern::Blink { on_ms, off_ms } => {
+                let period = (on_ms + off_ms).max(1) as u64;
+                if elapsed_ms % period < on_ms as u64 {
+                    255
+                } else {
+                    0
+                }
+            }
+            Pattern::Breathe { period_ms } => {
+                let period = (period_ms as u64).max(2);
+                let half = period / 2;
+                let phase = elapsed_ms % period;
+                let level = if phase < half { phase } else { period - phase };
+                (level * 255 / half) as u8
+            }
+        }
+    }
+}
+
+/// What the LED shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum IndicatorMode {
+    Status = 0,
+    /// CPU load shown as white brightness
+    CpuLoad = 1,
+    /// Gradient value shown with gradient color instead of [`Status::Normal`].
+    /// Other statuses are still shown
+    Gradient = 2,
+}
+
+impl IndicatorMode {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => IndicatorMode::CpuLoad,
+            2 => IndicatorMode::Gradient,
+            _ => IndicatorMode::Status,
+        }
+    }
+}
+
+pub struct StatusIndicator {
+    flags: AtomicU8,
+    mode: AtomicU8,
+    /// Global Led brightness
+    brightness: AtomicU8,
+    /// f32 bits of value to show in [`IndicatorMode::Gradient`]
+    gradient_value: AtomicU32,
+}
+
+impl Default for StatusIndicator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusIndicator {
+    /// Creates indicator in booting state
+    pub const fn new() -> Self {
+        Self {
+            flags: AtomicU8::new(1 << (Status::Booting as u8)),
+            mode: AtomicU8::new(IndicatorMode::Status as u8),
+            brightness: AtomicU8::new(255),
+            gradient_value: AtomicU32::new(0),
+        }
+    }
+
+    /// Raises or drops status flag. [`Status::Normal`] cannot be set, it is shown when nothing raised
+    pub fn set(&self, status: Status, active: bool) {
+        if status == Status::Normal {
+            return;
+        }
+
+        if active {
+            self.flags.fetch_or(status.bit(), Ordering::SeqCst);
+        } else {
+            self.flags.fetch_and(!status.bit(), Ordering::SeqCst);
+        }
+    }
+
+    /// Checks whatever status flag raised
+    pub fn is_set(&self, status: Status) -> bool {
+        match status {
+            Status::Normal => self.current() == Status::Normal,
+            _ => self.flags.load(Ordering::SeqCst) & status.bit() != 0,
+        }
+    }
+
+    /// Gets status with the highest priority
+    pub fn current(&self) -> Status {
+        let flags = self.flags.load(Ordering::SeqCst);
+
+        Status::FLAGGED
+            .into_iter()
+            .find(|status| flags & status.bit() != 0)
+            .unwrap_or(Status::Normal)
+    }
+
+    pub fn mode(&self) -> IndicatorMode {
+        IndicatorMode::from_u8(self.mode.load(Ordering::SeqCst))
+    }
+
+    pub fn set_mode(&self, mode: IndicatorMode) {
+        self.mode.store(mode as u8, Ordering::SeqCst);
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness.load(Ordering::SeqCst)
+    }
+
+    pub fn set_brightness(&self, brightness: u8) {
+        self.brightness.store(brightness, Ordering::SeqCst);
+    }
+
+    /// Sets value shown in [`IndicatorMode::Gradient`]
+    pub fn set_gradient_value(&self, value: f32) {
+        self.gradient_value.store(value.to_bits(), Ordering::SeqCst);
+    }
+
+    pub fn gradient_value(&self) -> f32 {
+        f32::from_bits(self.gradient_value.load(Ordering::SeqCst))
+    }
+
+    /// Color to show at given time from indicator start
+    ///
+    /// # Arguments
+    /// - `elapsed_ms` - time used to animate patterns
+    /// - `load` - CPU load in percents, used in [`IndicatorMode::CpuLoad`]
+    /// - `gradient` - gradient used in [`IndicatorMode::Gradient`]
+    pub fn color(&self, elapsed_ms: u64, load: u8, gradient: &Gradient) -> (u8, u8, u8) {
+        match self.mode() {
+            IndicatorMode::Gradient if self.current() == Status::Normal => {
+                gradient.color_at(self.gradient_value())
+            }
+            IndicatorMode::Status | IndicatorMode::Gradient => {
+                let ((r, g, b), pattern) = self.current().appearance();
+                let brightness = pattern.brightness(elapsed_ms);
+                (
+                    scale(r, brightness),
+                    scale(g, brightness),
+                    scale(b, brightness),
+                )
+            }
+            IndicatorMode::CpuLoad => {
+                let level = ((load.min(100) as u16) * 255 / 100) as u8;
+                (level, level, level)
+            }
+        }
+    }
+}
+
+fn scale(channel: u8, brightness: u8) -> u8 {
+    ((channel as u16) * (brightness as u16) / 255) as u8
+}
@@ -26,6 +26,17 @@ impl<T> AtomicMutex<T> {
         }
     }
 
+    /// Locks without waiting
+    ///
+    /// # Returns
+    /// None, if the mutex is locked already
+    pub fn try_lock(&self) -> Option<AtomicMutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+            .then_some(AtomicMutexGuard { mutex: self })
+    }
+
     pub fn lock(&self) -> impl Future<Output = AtomicMutexGuard<'_, T>> {
         poll_fn(|cx| {
             let locked = self
@@ -0,0 +1,32 @@
+//!
+//! Time driver of host tests
+//!
+//! Follows the host clock. Timers are woken right away and polled until they expire,
+//! which is fine for executors of tests, they poll all the time anyway.
+//!
+
+extern crate std;
+
+use core::task::Waker;
+use std::{sync::OnceLock, time::Instant};
+
+use embassy_time_driver::{Driver, TICK_HZ};
+
+struct HostDriver {
+    start: OnceLock<Instant>,
+}
+
+impl Driver for HostDriver {
+    fn now(&self) -> u64 {
+        let elapsed = self.start.get_or_init(Instant::now).elapsed();
+        elapsed.as_micros() as u64 * TICK_HZ / 1_000_000
+    }
+
+    fn schedule_wake(&self, _at: u64, waker: &Waker) {
+        waker.wake_by_ref();
+    }
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: HostDriver = HostDriver {
+    start: OnceLock::new(),
+});
@@ -0,0 +1,73 @@
+//!
+//! TLS 1.3 server of the web interface
+//!
+//! Deliberately small: one cipher suite, `TLS_CHACHA20_POLY1305_SHA256`, X25519 key
+//! exchange and an ECDSA P-256 certificate, which every current browser offers.
+//! There is no session resumption, early data, client authentication or
+//! HelloRetryRequest, clients without an X25519 key share are turned away.
+//!
+//! Records from peers may be 16 KiB, so each connection needs a receive buffer of
+//! that size, see [`Buffers`].
+//!
+
+pub mod der;
+pub mod identity;
+mod key_schedule;
+mod record;
+mod server;
+
+pub use self::server::{accept, Buffers, TlsSocket};
+
+/// Content type of record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum ContentType {
+    ChangeCipherSpec = 20,
+    Alert = 21,
+    Handshake = 22,
+    ApplicationData = 23,
+}
+
+/// Alert description, they are all fatal except close notify
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum Alert {
+    CloseNotify = 0,
+    UnexpectedMessage = 10,
+    BadRecordMac = 20,
+    RecordOverflow = 22,
+    HandshakeFailure = 40,
+    IllegalParameter = 47,
+    DecodeError = 50,
+    DecryptError = 51,
+    ProtocolVersion = 70,
+    InternalError = 80,
+    MissingExtension = 109,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error<E> {
+    Io(E),
+    /// Connection closed in the middle of record
+    Closed,
+    /// Peer misbehaved, alert was sent to it
+    Alert(Alert),
+    /// Peer sent fatal alert of description
+    PeerAlert(u8),
+}
+
+impl<E> From<Alert> for Error<E> {
+    fn from(alert: Alert) -> Self {
+        Error::Alert(alert)
+    }
+}
+
+impl<E: embedded_io_async::Error> embedded_io_async::Error for Error<E> {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        match self {
+            Error::Io(err) => err.kind(),
+            Error::Closed => embedded_io_async::ErrorKind::BrokenPipe,
+            Error::Alert(_) | Error::PeerAlert(_) => embedded_io_async::ErrorKind::InvalidData,
+        }
+    }
+}
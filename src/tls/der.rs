@@ -0,0 +1,311 @@
+//!
+//! DER encoding of certificates and keys
+//!
+//! Just enough of it for TLS: walking certificates and private keys, and writing
+//! a self-signed certificate and ECDSA signatures. Lengths up to 64 KiB.
+//!
+
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const OID: u8 = 0x06;
+pub const UTF8_STRING: u8 = 0x0C;
+pub const UTC_TIME: u8 = 0x17;
+pub const GENERALIZED_TIME: u8 = 0x18;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+/// Tag of constructed context specific `[n]`
+pub const fn explicit(n: u8) -> u8 {
+    0xA0 | n
+}
+
+/// Tag of primitive context specific `[n]`
+pub const fn implicit(n: u8) -> u8 {
+    0x80 | n
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// Truncated element, unexpected tag or length
+    Malformed,
+    /// Written elements do not fit into buffer
+    TooLarge,
+}
+
+/// Elements one after another
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Reads tag and content of the next element
+    pub fn read_any(&mut self) -> Result<(u8, &'a [u8]), Error> {
+        let element = self.read_element()?;
+        let (tag, header_len, _) = header(element)?;
+        Ok((tag, &element[header_len..]))
+    }
+
+    /// Reads content of the next element, which must have the tag
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        match self.read_any()? {
+            (read, content) if read == tag => Ok(content),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// Reads content of the next element if it has the tag
+    pub fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, Error> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads the next element with its header, e.g. a whole certificate
+    pub fn read_element(&mut self) -> Result<&'a [u8], Error> {
+        let (_, header_len, len) = header(self.data)?;
+        let total = header_len + len;
+        let element = self.data.get(..total).ok_or(Error::Malformed)?;
+        self.data = &self.data[total..];
+        Ok(element)
+    }
+}
+
+/// Tag, header length and content length of element
+fn header(data: &[u8]) -> Result<(u8, usize, usize), Error> {
+    match *data {
+        [tag, len, ..] if len < 0x80 => Ok((tag, 2, usize::from(len))),
+        [tag, 0x81, len, ..] => Ok((tag, 3, usize::from(len))),
+        [tag, 0x82, high, low, ..] => Ok((tag, 4, usize::from(u16::from_be_bytes([high, low])))),
+        _ => Err(Error::Malformed),
+    }
+}
+
+/// Writes elements into buffer, constructed ones get their length when they are complete
+pub struct Writer<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+    overflow: bool,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Length written so far, start of the next element
+    pub fn position(&self) -> usize {
+        self.len
+    }
+
+    /// Written bytes from position
+    pub fn written_since(&self, position: usize) -> &[u8] {
+        &self.buffer[position.min(self.len)..self.len]
+    }
+
+    /// Appends bytes as they are
+    pub fn raw(&mut self, bytes: &[u8]) {
+        match self.buffer.get_mut(self.len..self.len + bytes.len()) {
+            Some(space) => {
+                space.copy_from_slice(bytes);
+                self.len += bytes.len();
+            }
+            None => self.overflow = true,
+        }
+    }
+
+    pub fn element(&mut self, tag: u8, content: &[u8]) {
+        self.raw(&[tag]);
+        self.length(content.len());
+        self.raw(content);
+    }
+
+    /// Constructed element of everything `content` writes
+    pub fn nested(&mut self, tag: u8, content: impl FnOnce(&mut Self)) {
+        let start = self.len;
+        content(self);
+        if self.overflow {
+            return;
+        }
+
+        let content_len = self.len - start;
+        let mut header = [tag, 0, 0, 0];
+        let header_len = 1 + encode_length(content_len, &mut header[1..]);
+        if self.buffer.len() < self.len + header_len {
+            self.overflow = true;
+            return;
+        }
+        self.buffer.copy_within(start..self.len, start + header_len);
+        self.buffer[start..start + header_len].copy_from_slice(&header[..header_len]);
+        self.len += header_len;
+    }
+
+    /// Unsigned integer of big-endian bytes, with leading zeros stripped
+    pub fn integer(&mut self, big_endian: &[u8]) {
+        let first = big_endian
+            .iter()
+            .position(|byte| *byte != 0)
+            .unwrap_or(big_endian.len().saturating_sub(1));
+        let magnitude = &big_endian[first..];
+        // Set high bit would make it negative
+        let sign = magnitude.first().is_none_or(|byte| byte & 0x80 != 0);
+
+        self.raw(&[INTEGER]);
+        self.length(magnitude.len() + usize::from(sign));
+        if sign {
+            self.raw(&[0]);
+        }
+        self.raw(magnitude);
+    }
+
+    /// Bit string of whole bytes
+    pub fn bit_string(&mut self, bytes: &[u8]) {
+        self.raw(&[BIT_STRING]);
+        self.length(bytes.len() + 1);
+        self.raw(&[0]);
+        self.raw(bytes);
+    }
+
+    /// # Returns
+    /// Written length
+    pub fn finish(self) -> Result<usize, Error> {
+        if self.overflow {
+            Err(Error::TooLarge)
+        } else {
+            Ok(self.len)
+        }
+    }
+
+    fn length(&mut self, len: usize) {
+        let mut bytes = [0; 3];
+        let bytes_len = encode_length(len, &mut bytes);
+        self.raw(&bytes[..bytes_len]);
+    }
+}
+
+/// Encodes length in the shortest form
+///
+/// # Returns
+/// Count of bytes used
+fn encode_length(len: usize, out: &mut [u8]) -> usize {
+    match len {
+        0..0x80 => {
+            out[0] = len as u8;
+            1
+        }
+        0x80..0x100 => {
+            out[..2].copy_from_slice(&[0x81, len as u8]);
+            2
+        }
+        _ => {
+            let [high, low] = (len.min(0xFFFF) as u16).to_be_bytes();
+            out[..3].copy_from_slice(&[0x82, high, low]);
+            3
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(f: impl FnOnce(&mut Writer)) -> heapless::Vec<u8, 512> {
+        let mut buffer = [0; 512];
+        let mut writer = Writer::new(&mut buffer);
+        f(&mut writer);
+        let len = writer.finish().unwrap();
+        heapless::Vec::from_slice(&buffer[..len]).unwrap()
+    }
+
+    #[test]
+    fn encodes_lengths_in_shortest_form() {
+        assert_eq!(
+            written(|w| w.element(OCTET_STRING, &[7; 3]))[..2],
+            [0x04, 3]
+        );
+        assert_eq!(
+            written(|w| w.element(OCTET_STRING, &[7; 200]))[..3],
+            [0x04, 0x81, 200]
+        );
+        assert_eq!(
+            written(|w| w.element(OCTET_STRING, &[7; 300]))[..4],
+            [0x04, 0x82, 0x01, 0x2C]
+        );
+    }
+
+    #[test]
+    fn fixes_up_length_of_nested_elements() {
+        let der = written(|w| {
+            w.nested(SEQUENCE, |w| {
+                w.element(OID, &[0x55, 0x04, 0x03]);
+                w.nested(SET, |w| w.element(OCTET_STRING, &[1; 130]));
+            })
+        });
+
+        assert_eq!(der[..2], [SEQUENCE, 0x81]);
+        assert_eq!(usize::from(der[2]), der.len() - 3);
+
+        let mut reader = Reader::new(&der);
+        let mut sequence = Reader::new(reader.read(SEQUENCE).unwrap());
+        assert!(reader.is_empty());
+        assert_eq!(sequence.read(OID).unwrap(), [0x55, 0x04, 0x03]);
+        let mut set = Reader::new(sequence.read(SET).unwrap());
+        assert_eq!(set.read(OCTET_STRING).unwrap(), [1; 130]);
+        assert!(sequence.is_empty());
+    }
+
+    #[test]
+    fn encodes_integers_as_positive_and_minimal() {
+        assert_eq!(written(|w| w.integer(&[0, 0, 0x12])), [INTEGER, 1, 0x12]);
+        assert_eq!(written(|w| w.integer(&[0, 0x80])), [INTEGER, 2, 0, 0x80]);
+        assert_eq!(written(|w| w.integer(&[0, 0])), [INTEGER, 1, 0]);
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let mut buffer = [0; 8];
+        let mut writer = Writer::new(&mut buffer);
+        writer.nested(SEQUENCE, |w| w.element(OCTET_STRING, &[1; 6]));
+        assert_eq!(writer.finish(), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn rejects_truncated_elements() {
+        assert_eq!(
+            Reader::new(&[SEQUENCE, 4, 1, 2]).read_any(),
+            Err(Error::Malformed)
+        );
+        assert_eq!(Reader::new(&[SEQUENCE]).read_any(), Err(Error::Malformed));
+        assert_eq!(
+            Reader::new(&[OID, 1, 2]).read(SEQUENCE),
+            Err(Error::Malformed)
+        );
+    }
+
+    #[test]
+    fn reads_optional_elements() {
+        let mut reader = Reader::new(&[INTEGER, 1, 5]);
+        assert_eq!(reader.read_optional(explicit(0)), Ok(None));
+        assert_eq!(reader.read_optional(INTEGER), Ok(Some(&[5][..])));
+        assert!(reader.is_empty());
+    }
+}
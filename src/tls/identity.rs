@@ -0,0 +1,782 @@
+//!
+//! Certificate chain and private key of the device
+//!
+//! The key is a P-256 scalar, certificates are DER, leaf first. A self-signed
+//! certificate is generated when HTTPS is enabled without one, a certificate
+//! signed by a CA can be uploaded as PEM, certificates followed by the key.
+//!
+//! Persisted in the `tls` partition as one record of magic, chain length and
+//! SHA-256 of key and chain, followed by key and chain. Header goes last, so an
+//! interrupted save leaves no record and the next boot generates a new one.
+//!
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+#[cfg(target_arch = "riscv32")]
+use embedded_storage::Storage;
+use heapless::Vec;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use serde::Serialize;
+
+use super::der::{self, Reader, Writer};
+use crate::{
+    auth::decode_base64,
+    partition::Partition,
+    settings::StoreError,
+    sha256::{self, Digest, Sha256},
+};
+
+/// Marks persisted record
+const MAGIC: u32 = 0x544C_5349;
+/// Magic, chain length and digest
+const HEADER_LEN: usize = 4 + 4 + 32;
+const KEY_LEN: usize = 32;
+/// Record fits into one sector
+const RECORD_LEN: u32 = 4096;
+/// Longest certificate chain, the rest of record after header and key
+pub const MAX_CHAIN_LEN: usize = RECORD_LEN as usize - HEADER_LEN - KEY_LEN;
+/// Longest DER of ECDSA P-256 signature
+pub const MAX_SIGNATURE_LEN: usize = 72;
+
+/// Longest line of uploaded PEM, they are 64 characters
+const MAX_PEM_LINE_LEN: usize = 80;
+/// Longest DER of uploaded private key, PKCS#8 of P-256 is 138 bytes
+const MAX_KEY_DER_LEN: usize = 256;
+
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1D, 0x11];
+
+/// Validity of self-signed certificate, it does not expire
+const NOT_BEFORE: &[u8] = b"250101000000Z";
+const NOT_AFTER: &[u8] = b"99991231235959Z";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, defmt::Format)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityError {
+    /// Not a P-256 private key
+    InvalidKey,
+    /// Not a DER certificate with P-256 public key
+    InvalidCertificate,
+    /// Leaf certificate is not of the private key
+    KeyMismatch,
+    NoCertificate,
+    NoKey,
+    /// Certificates do not fit into record
+    TooLarge,
+    /// Not PEM, or an encrypted key
+    InvalidPem,
+}
+
+impl From<der::Error> for IdentityError {
+    fn from(err: der::Error) -> Self {
+        match err {
+            der::Error::Malformed => IdentityError::InvalidCertificate,
+            der::Error::TooLarge => IdentityError::TooLarge,
+        }
+    }
+}
+
+pub struct Identity {
+    key: [u8; KEY_LEN],
+    /// DER certificates, leaf first
+    chain: Vec<u8, MAX_CHAIN_LEN>,
+}
+
+impl Identity {
+    /// Checks that chain is made of certificates and the leaf one is of key
+    pub fn new(key: [u8; KEY_LEN], chain: &[u8]) -> Result<Self, IdentityError> {
+        let identity = Self {
+            key,
+            chain: Vec::from_slice(chain).map_err(|_| IdentityError::TooLarge)?,
+        };
+        identity.check()?;
+        Ok(identity)
+    }
+
+    /// Generates self-signed certificate for key
+    ///
+    /// # Arguments
+    /// - `key` - random P-256 scalar
+    /// - `serial` - random serial number, browsers reject certificates of reused ones
+    /// - `name` - common name and DNS name of subject
+    pub fn self_signed(
+        key: [u8; KEY_LEN],
+        serial: [u8; 16],
+        name: &str,
+    ) -> Result<Self, IdentityError> {
+        let signing_key = signing_key(&key)?;
+        let public = signing_key.verifying_key().to_encoded_point(false);
+
+        let mut chain = Vec::new();
+        chain
+            .resize(MAX_CHAIN_LEN, 0)
+            .map_err(|_| IdentityError::TooLarge)?;
+        let mut writer = Writer::new(&mut chain);
+        writer.nested(der::SEQUENCE, |w| {
+            let tbs = w.position();
+            w.nested(der::SEQUENCE, |w| {
+                // Version 3 for extensions
+                w.nested(der::explicit(0), |w| w.integer(&[2]));
+                w.integer(&serial);
+                w.nested(der::SEQUENCE, |w| {
+                    w.element(der::OID, OID_ECDSA_WITH_SHA256)
+                });
+                write_name(w, name);
+                w.nested(der::SEQUENCE, |w| {
+                    w.element(der::UTC_TIME, NOT_BEFORE);
+                    w.element(der::GENERALIZED_TIME, NOT_AFTER);
+                });
+                write_name(w, name);
+                w.nested(der::SEQUENCE, |w| {
+                    w.nested(der::SEQUENCE, |w| {
+                        w.element(der::OID, OID_EC_PUBLIC_KEY);
+                        w.element(der::OID, OID_PRIME256V1);
+                    });
+                    w.bit_string(public.as_bytes());
+                });
+                // Browsers match host against subject alternative names only
+                w.nested(der::explicit(3), |w| {
+                    w.nested(der::SEQUENCE, |w| {
+                        w.nested(der::SEQUENCE, |w| {
+                            w.element(der::OID, OID_SUBJECT_ALT_NAME);
+                            w.nested(der::OCTET_STRING, |w| {
+                                w.nested(der::SEQUENCE, |w| {
+                                    w.element(der::implicit(2), name.as_bytes())
+                                })
+                            });
+                        })
+                    })
+                });
+            });
+
+            let mut signature = [0; MAX_SIGNATURE_LEN];
+            let len = sign(&signing_key, w.written_since(tbs), &mut signature);
+            w.nested(der::SEQUENCE, |w| {
+                w.element(der::OID, OID_ECDSA_WITH_SHA256)
+            });
+            w.bit_string(&signature[..len]);
+        });
+        let len = writer.finish()?;
+        chain.truncate(len);
+
+        Ok(Self { key, chain })
+    }
+
+    /// DER certificates, leaf first
+    pub fn certificates(&self) -> impl Iterator<Item = &[u8]> {
+        let mut reader = Reader::new(&self.chain);
+        core::iter::from_fn(move || reader.read_element().ok())
+    }
+
+    /// SHA-256 of leaf certificate, to check self-signed one in browser
+    pub fn fingerprint(&self) -> Digest {
+        sha256::digest(self.certificates().next().unwrap_or_default())
+    }
+
+    /// Signs message with ECDSA and SHA-256
+    ///
+    /// # Returns
+    /// Length of DER signature
+    pub fn sign(&self, message: &[u8], signature: &mut [u8; MAX_SIGNATURE_LEN]) -> usize {
+        match signing_key(&self.key) {
+            Ok(signing_key) => sign(&signing_key, message, signature),
+            // Checked when identity was created
+            Err(_) => 0,
+        }
+    }
+
+    fn check(&self) -> Result<(), IdentityError> {
+        let public = signing_key(&self.key)?
+            .verifying_key()
+            .to_encoded_point(false);
+
+        let mut reader = Reader::new(&self.chain);
+        let leaf = reader
+            .read_element()
+            .map_err(|_| IdentityError::NoCertificate)?;
+        while !reader.is_empty() {
+            Reader::new(reader.read_element()?).read(der::SEQUENCE)?;
+        }
+
+        if leaf_public_key(leaf)? == public.as_bytes() {
+            Ok(())
+        } else {
+            Err(IdentityError::KeyMismatch)
+        }
+    }
+}
+
+fn signing_key(key: &[u8; KEY_LEN]) -> Result<SigningKey, IdentityError> {
+    SigningKey::from_bytes(key.into()).map_err(|_| IdentityError::InvalidKey)
+}
+
+/// DER of ECDSA-Sig-Value
+fn sign(key: &SigningKey, message: &[u8], out: &mut [u8; MAX_SIGNATURE_LEN]) -> usize {
+    let signature: Signature = key.sign(message);
+    let (r, s) = signature.split_bytes();
+
+    let mut writer = Writer::new(out);
+    writer.nested(der::SEQUENCE, |w| {
+        w.integer(&r);
+        w.integer(&s);
+    });
+    // Two 33 byte integers fit
+    writer.finish().unwrap_or(0)
+}
+
+/// Distinguished name of common name only
+fn write_name(writer: &mut Writer, name: &str) {
+    writer.nested(der::SEQUENCE, |w| {
+        w.nested(der::SET, |w| {
+            w.nested(der::SEQUENCE, |w| {
+                w.element(der::OID, OID_COMMON_NAME);
+                w.element(der::UTF8_STRING, name.as_bytes());
+            })
+        })
+    });
+}
+
+/// Uncompressed P-256 point of certificate
+fn leaf_public_key(certificate: &[u8]) -> Result<&[u8], IdentityError> {
+    let mut certificate = Reader::new(Reader::new(certificate).read(der::SEQUENCE)?);
+    let mut tbs = Reader::new(certificate.read(der::SEQUENCE)?);
+    tbs.read_optional(der::explicit(0))?;
+    tbs.read(der::INTEGER)?;
+    // Signature algorithm, issuer, validity and subject
+    for _ in 0..4 {
+        tbs.read(der::SEQUENCE)?;
+    }
+
+    let mut public_key_info = Reader::new(tbs.read(der::SEQUENCE)?);
+    let mut algorithm = Reader::new(public_key_info.read(der::SEQUENCE)?);
+    if algorithm.read(der::OID)? != OID_EC_PUBLIC_KEY || algorithm.read(der::OID)? != OID_PRIME256V1
+    {
+        return Err(IdentityError::InvalidCertificate);
+    }
+    match public_key_info.read(der::BIT_STRING)? {
+        [0, point @ ..] => Ok(point),
+        _ => Err(IdentityError::InvalidCertificate),
+    }
+}
+
+/// Scalar of SEC1 or PKCS#8 DER private key
+pub fn parse_private_key(der: &[u8]) -> Result<[u8; KEY_LEN], IdentityError> {
+    let parse = || -> Result<[u8; KEY_LEN], der::Error> {
+        let mut outer = Reader::new(der);
+        let mut key = Reader::new(outer.read(der::SEQUENCE)?);
+        if !outer.is_empty() {
+            return Err(der::Error::Malformed);
+        }
+
+        match key.read(der::INTEGER)? {
+            // SEC1 ECPrivateKey
+            [1] => ec_private_key(key),
+            // PKCS#8 PrivateKeyInfo wrapping ECPrivateKey
+            [0] => {
+                let mut algorithm = Reader::new(key.read(der::SEQUENCE)?);
+                if algorithm.read(der::OID)? != OID_EC_PUBLIC_KEY
+                    || algorithm.read(der::OID)? != OID_PRIME256V1
+                {
+                    return Err(der::Error::Malformed);
+                }
+                let mut inner =
+                    Reader::new(Reader::new(key.read(der::OCTET_STRING)?).read(der::SEQUENCE)?);
+                match inner.read(der::INTEGER)? {
+                    [1] => ec_private_key(inner),
+                    _ => Err(der::Error::Malformed),
+                }
+            }
+            _ => Err(der::Error::Malformed),
+        }
+    };
+
+    parse().map_err(|_| IdentityError::InvalidKey)
+}
+
+/// Private key and curve of ECPrivateKey after its version
+fn ec_private_key(mut key: Reader) -> Result<[u8; KEY_LEN], der::Error> {
+    let scalar = key.read(der::OCTET_STRING)?;
+    if let Some(parameters) = key.read_optional(der::explicit(0))? {
+        if Reader::new(parameters).read(der::OID)? != OID_PRIME256V1 {
+            return Err(der::Error::Malformed);
+        }
+    }
+    scalar.try_into().map_err(|_| der::Error::Malformed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PemBlock {
+    Certificate,
+    Key,
+    /// E.g. EC parameters written by `openssl ecparam`
+    Other,
+}
+
+/// Decodes uploaded PEM as it streams in, certificates and a private key
+pub struct PemDecoder {
+    line: Vec<u8, MAX_PEM_LINE_LEN>,
+    block: Option<PemBlock>,
+    key: Vec<u8, MAX_KEY_DER_LEN>,
+    identity: Identity,
+    error: Option<IdentityError>,
+}
+
+impl Default for PemDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PemDecoder {
+    pub const fn new() -> Self {
+        Self {
+            line: Vec::new(),
+            block: None,
+            key: Vec::new(),
+            identity: Identity {
+                key: [0; KEY_LEN],
+                chain: Vec::new(),
+            },
+            error: None,
+        }
+    }
+
+    /// Forgets previous upload
+    pub fn reset(&mut self) {
+        self.line.clear();
+        self.block = None;
+        self.key.clear();
+        self.identity.chain.clear();
+        self.error = None;
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        for byte in data {
+            if self.error.is_some() {
+                return;
+            }
+            if *byte == b'\n' {
+                self.end_line();
+            } else if self.line.push(*byte).is_err() {
+                self.error = Some(IdentityError::InvalidPem);
+            }
+        }
+    }
+
+    /// Checks that decoded certificates are of the decoded key
+    pub fn finish(&mut self) -> Result<&Identity, IdentityError> {
+        self.end_line();
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if self.block.is_some() {
+            return Err(IdentityError::InvalidPem);
+        }
+        if self.key.is_empty() {
+            return Err(IdentityError::NoKey);
+        }
+
+        self.identity.key = parse_private_key(&self.key)?;
+        self.identity.check()?;
+        Ok(&self.identity)
+    }
+
+    fn end_line(&mut self) {
+        let mut decoded = [0; MAX_PEM_LINE_LEN / 4 * 3];
+        let result = match decode_line(self.line.trim_ascii(), &mut self.block, &mut decoded) {
+            Ok(Some((PemBlock::Certificate, len))) => self
+                .identity
+                .chain
+                .extend_from_slice(&decoded[..len])
+                .map_err(|_| IdentityError::TooLarge),
+            Ok(Some((PemBlock::Key, len))) => self
+                .key
+                .extend_from_slice(&decoded[..len])
+                .map_err(|_| IdentityError::InvalidKey),
+            Ok(_) => Ok(()),
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            self.error.get_or_insert(err);
+        }
+        self.line.clear();
+    }
+}
+
+/// Tracks block of PEM line and decodes its base64 into `out`
+///
+/// # Returns
+/// Block and decoded length, None for boundaries, text around blocks and ignored blocks
+fn decode_line(
+    line: &[u8],
+    block: &mut Option<PemBlock>,
+    out: &mut [u8],
+) -> Result<Option<(PemBlock, usize)>, IdentityError> {
+    if let Some(label) = line
+        .strip_prefix(b"-----BEGIN ")
+        .and_then(|line| line.strip_suffix(b"-----"))
+    {
+        if block.is_some() {
+            return Err(IdentityError::InvalidPem);
+        }
+        *block = Some(match label {
+            b"CERTIFICATE" => PemBlock::Certificate,
+            b"EC PRIVATE KEY" | b"PRIVATE KEY" => PemBlock::Key,
+            b"ENCRYPTED PRIVATE KEY" => return Err(IdentityError::InvalidPem),
+            _ => PemBlock::Other,
+        });
+        return Ok(None);
+    }
+    if line.starts_with(b"-----END ") {
+        return block.take().map(|_| None).ok_or(IdentityError::InvalidPem);
+    }
+
+    match *block {
+        // E.g. subject and issuer `openssl` prints before certificates
+        None | Some(PemBlock::Other) => Ok(None),
+        // Headers of legacy encrypted keys, like `Proc-Type: 4,ENCRYPTED`
+        Some(_) if line.contains(&b':') => Err(IdentityError::InvalidPem),
+        Some(block) => decode_base64(line, out)
+            .map(|len| Some((block, len)))
+            .ok_or(IdentityError::InvalidPem),
+    }
+}
+
+/// Identity persisted in one sector
+pub struct IdentityStore {
+    partition: Partition,
+}
+
+impl IdentityStore {
+    /// Finds `tls` partition
+    #[cfg(target_arch = "riscv32")]
+    pub fn open<F: Storage>(flash: &mut F) -> Result<Self, StoreError> {
+        let partition = crate::partition::find_partition_by_label(flash, "tls")
+            .map_err(|_| StoreError::Storage)?
+            .ok_or(StoreError::NoPartition)?;
+        if partition.len < RECORD_LEN {
+            return Err(StoreError::NoPartition);
+        }
+        Ok(Self { partition })
+    }
+
+    /// Reads persisted identity
+    ///
+    /// # Returns
+    /// None, if nothing valid is persisted
+    pub fn load<F: ReadNorFlash>(&self, flash: &mut F) -> Option<Identity> {
+        let mut header = [0_u8; HEADER_LEN];
+        flash.read(self.partition.offset, &mut header).ok()?;
+        let [magic, chain_len] = [0, 4].map(|at| {
+            u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+        });
+        let chain_len = chain_len as usize;
+        if magic != MAGIC || MAX_CHAIN_LEN < chain_len {
+            return None;
+        }
+
+        let mut record = [0_u8; RECORD_LEN as usize - HEADER_LEN];
+        let len = (KEY_LEN + chain_len).next_multiple_of(F::READ_SIZE);
+        flash
+            .read(
+                self.partition.offset + HEADER_LEN as u32,
+                &mut record[..len],
+            )
+            .ok()?;
+        let (key, chain) = record[..KEY_LEN + chain_len].split_at(KEY_LEN);
+        let expected = header[8..].try_into().ok()?;
+        if !sha256::digest_eq(&record_digest(key, chain), expected) {
+            return None;
+        }
+        Identity::new(key.try_into().ok()?, chain).ok()
+    }
+
+    /// Writes identity over the persisted one
+    pub fn save<F: ReadNorFlash + NorFlash>(
+        &self,
+        flash: &mut F,
+        identity: &Identity,
+    ) -> Result<(), StoreError> {
+        let mut buffer = [0xFF_u8; RECORD_LEN as usize];
+        let chain_len = identity.chain.len();
+        let (header, record) = buffer.split_at_mut(HEADER_LEN);
+        record[..KEY_LEN].copy_from_slice(&identity.key);
+        record[KEY_LEN..KEY_LEN + chain_len].copy_from_slice(&identity.chain);
+        header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&(chain_len as u32).to_le_bytes());
+        header[8..].copy_from_slice(&record_digest(&identity.key, &identity.chain));
+
+        let offset = self.partition.offset;
+        let total = (HEADER_LEN + KEY_LEN + chain_len).next_multiple_of(F::WRITE_SIZE);
+        flash
+            .erase(offset, offset + RECORD_LEN)
+            .map_err(|_| StoreError::Storage)?;
+        // Header goes last, so interrupted write leaves no valid record
+        flash
+            .write(offset + HEADER_LEN as u32, &buffer[HEADER_LEN..total])
+            .map_err(|_| StoreError::Storage)?;
+        flash
+            .write(offset, &buffer[..HEADER_LEN])
+            .map_err(|_| StoreError::Storage)
+    }
+}
+
+fn record_digest(key: &[u8], chain: &[u8]) -> Digest {
+    let mut sha = Sha256::new();
+    sha.update(key);
+    sha.update(chain);
+    sha.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partition::mock::MockFlash;
+    use p256::ecdsa::{signature::Verifier, VerifyingKey};
+
+    extern crate std;
+    use std::string::String;
+
+    const KEY: [u8; KEY_LEN] = [7; KEY_LEN];
+
+    fn identity() -> Identity {
+        Identity::self_signed(KEY, [0x81; 16], "esp-temperature").unwrap()
+    }
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const SYMBOLS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut encoded = String::new();
+        for chunk in bytes.chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0_u32, |bits, (i, byte)| {
+                bits | u32::from(*byte) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                encoded.push(if i <= chunk.len() {
+                    char::from(SYMBOLS[(bits >> (18 - 6 * i)) as usize & 0x3F])
+                } else {
+                    '='
+                });
+            }
+        }
+        encoded
+    }
+
+    fn pem(label: &str, der: &[u8]) -> String {
+        let encoded = encode_base64(der);
+        let mut pem = std::format!("-----BEGIN {label}-----\r\n");
+        for line in encoded.as_bytes().chunks(64) {
+            pem.push_str(core::str::from_utf8(line).unwrap());
+            pem.push_str("\r\n");
+        }
+        pem + &std::format!("-----END {label}-----\r\n")
+    }
+
+    /// SEC1 ECPrivateKey of key, as `openssl ecparam -genkey` writes it
+    fn sec1(key: &[u8; KEY_LEN]) -> heapless::Vec<u8, 128> {
+        let mut der = [0; 128];
+        let mut writer = Writer::new(&mut der);
+        writer.nested(der::SEQUENCE, |w| {
+            w.integer(&[1]);
+            w.element(der::OCTET_STRING, key);
+            w.nested(der::explicit(0), |w| w.element(der::OID, OID_PRIME256V1));
+        });
+        let len = writer.finish().unwrap();
+        heapless::Vec::from_slice(&der[..len]).unwrap()
+    }
+
+    /// PKCS#8 PrivateKeyInfo of key, as `openssl genpkey` writes it
+    fn pkcs8(key: &[u8; KEY_LEN]) -> heapless::Vec<u8, 160> {
+        let mut der = [0; 160];
+        let mut writer = Writer::new(&mut der);
+        writer.nested(der::SEQUENCE, |w| {
+            w.integer(&[0]);
+            w.nested(der::SEQUENCE, |w| {
+                w.element(der::OID, OID_EC_PUBLIC_KEY);
+                w.element(der::OID, OID_PRIME256V1);
+            });
+            w.nested(der::OCTET_STRING, |w| {
+                w.nested(der::SEQUENCE, |w| {
+                    w.integer(&[1]);
+                    w.element(der::OCTET_STRING, key);
+                })
+            });
+        });
+        let len = writer.finish().unwrap();
+        heapless::Vec::from_slice(&der[..len]).unwrap()
+    }
+
+    fn decode(pem: &str) -> Result<[u8; 32], IdentityError> {
+        let mut decoder = PemDecoder::new();
+        // Uploads arrive in arbitrary pieces
+        for piece in pem.as_bytes().chunks(7) {
+            decoder.push(piece);
+        }
+        decoder.finish().map(|identity| identity.fingerprint())
+    }
+
+    #[test]
+    fn self_signed_certificate_is_of_key_and_verifies() {
+        let identity = identity();
+        let certificate = identity.certificates().next().unwrap();
+        assert!(identity.certificates().nth(1).is_none());
+        assert_eq!(
+            Identity::new(KEY, certificate).unwrap().fingerprint(),
+            identity.fingerprint()
+        );
+
+        let mut reader = Reader::new(Reader::new(certificate).read(der::SEQUENCE).unwrap());
+        let tbs = reader.read_element().unwrap();
+        reader.read(der::SEQUENCE).unwrap();
+        let signature = match reader.read(der::BIT_STRING).unwrap() {
+            [0, signature @ ..] => Signature::from_der(signature).unwrap(),
+            _ => panic!("unused bits"),
+        };
+        let public = VerifyingKey::from_sec1_bytes(leaf_public_key(certificate).unwrap()).unwrap();
+        public.verify(tbs, &signature).unwrap();
+        assert!(certificate
+            .windows(15)
+            .any(|name| name == b"esp-temperature"));
+    }
+
+    #[test]
+    fn signs_message_in_der() {
+        let identity = identity();
+        let mut signature = [0; MAX_SIGNATURE_LEN];
+        let len = identity.sign(b"message", &mut signature);
+
+        let public = VerifyingKey::from(signing_key(&KEY).unwrap());
+        let signature = Signature::from_der(&signature[..len]).unwrap();
+        public.verify(b"message", &signature).unwrap();
+    }
+
+    #[test]
+    fn rejects_certificate_of_other_key() {
+        let other = Identity::self_signed([9; KEY_LEN], [1; 16], "other").unwrap();
+        let certificate = other.certificates().next().unwrap();
+        assert_eq!(
+            Identity::new(KEY, certificate).err(),
+            Some(IdentityError::KeyMismatch)
+        );
+        assert_eq!(
+            Identity::new(KEY, &[]).err(),
+            Some(IdentityError::NoCertificate)
+        );
+        assert_eq!(
+            Identity::new([0; KEY_LEN], certificate).err(),
+            Some(IdentityError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn parses_sec1_and_pkcs8_keys() {
+        assert_eq!(parse_private_key(&sec1(&KEY)), Ok(KEY));
+        assert_eq!(parse_private_key(&pkcs8(&KEY)), Ok(KEY));
+        assert_eq!(
+            parse_private_key(&sec1(&KEY)[..20]),
+            Err(IdentityError::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn decodes_uploaded_pem() {
+        let identity = identity();
+        let certificate = identity.certificates().next().unwrap();
+        let chain = std::format!(
+            "subject=CN=esp-temperature\n{}{}",
+            pem("CERTIFICATE", certificate),
+            pem("CERTIFICATE", certificate)
+        );
+
+        let upload =
+            chain.clone() + &pem("EC PARAMETERS", &[6, 8]) + &pem("EC PRIVATE KEY", &sec1(&KEY));
+        assert_eq!(decode(&upload), Ok(identity.fingerprint()));
+        let upload = chain.clone() + &pem("PRIVATE KEY", &pkcs8(&KEY));
+        assert_eq!(decode(&upload), Ok(identity.fingerprint()));
+    }
+
+    #[test]
+    fn rejects_invalid_pem() {
+        let identity = identity();
+        let certificate = pem("CERTIFICATE", identity.certificates().next().unwrap());
+        let key = pem("EC PRIVATE KEY", &sec1(&KEY));
+
+        assert_eq!(decode(&certificate), Err(IdentityError::NoKey));
+        assert_eq!(decode(&key), Err(IdentityError::NoCertificate));
+        assert_eq!(
+            decode(&(certificate.clone() + &key[..40])),
+            Err(IdentityError::InvalidPem)
+        );
+        assert_eq!(
+            decode(&(certificate.clone() + &pem("EC PRIVATE KEY", &sec1(&[9; KEY_LEN])))),
+            Err(IdentityError::KeyMismatch)
+        );
+        assert_eq!(
+            decode(&(certificate + &pem("ENCRYPTED PRIVATE KEY", &[1, 2, 3]))),
+            Err(IdentityError::InvalidPem)
+        );
+    }
+
+    #[test]
+    fn decoder_forgets_previous_upload() {
+        let identity = identity();
+        let certificate = pem("CERTIFICATE", identity.certificates().next().unwrap());
+        let mut decoder = PemDecoder::new();
+        decoder.push(b"garbage");
+        decoder.push(&[b'x'; MAX_PEM_LINE_LEN]);
+        assert_eq!(decoder.finish().err(), Some(IdentityError::InvalidPem));
+
+        decoder.reset();
+        decoder.push(certificate.as_bytes());
+        decoder.push(pem("EC PRIVATE KEY", &sec1(&KEY)).as_bytes());
+        assert_eq!(
+            decoder.finish().unwrap().fingerprint(),
+            identity.fingerprint()
+        );
+    }
+
+    fn store() -> IdentityStore {
+        IdentityStore {
+            partition: Partition {
+                offset: 0x1000,
+                len: RECORD_LEN,
+            },
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_identity() {
+        let mut flash = MockFlash::<0x2000>::new();
+        let store = store();
+        assert!(store.load(&mut flash).is_none());
+
+        let identity = identity();
+        store.save(&mut flash, &identity).unwrap();
+        let loaded = store.load(&mut flash).unwrap();
+        assert_eq!(loaded.key, identity.key);
+        assert_eq!(loaded.chain, identity.chain);
+        // Nothing written outside partition
+        assert!(flash.data[..0x1000].iter().all(|byte| *byte == 0xFF));
+    }
+
+    #[test]
+    fn ignores_corrupted_record() {
+        let mut flash = MockFlash::<0x2000>::new();
+        let store = store();
+        store.save(&mut flash, &identity()).unwrap();
+        flash.data[0x1000 + HEADER_LEN + 100] ^= 1;
+        assert!(store.load(&mut flash).is_none());
+    }
+
+    #[test]
+    fn interrupted_save_leaves_no_record() {
+        let mut flash = MockFlash::<0x2000>::new();
+        let store = store();
+        store.save(&mut flash, &identity()).unwrap();
+        // Erased header, as if power was lost before it was written
+        flash.data[0x1000..0x1000 + HEADER_LEN].fill(0xFF);
+        assert!(store.load(&mut flash).is_none());
+    }
+}
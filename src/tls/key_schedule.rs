@@ -0,0 +1,163 @@
+//!
+//! Key schedule of TLS 1.3 with SHA-256, RFC 8446 section 7
+//!
+//! HKDF of RFC 5869 over [`hmac_sha256`]. Secrets derive from the X25519 shared
+//! secret alone, there are no pre-shared keys.
+//!
+
+use crate::sha256::{digest, hmac_sha256, Digest};
+
+/// Longest info of HKDF-Expand: length, "tls13 " and label, and a digest as context
+const MAX_INFO_LEN: usize = 2 + 1 + 18 + 1 + 32;
+
+/// Salt of HKDF-Extract, and key material in place of missing pre-shared key
+const ZEROS: Digest = [0; 32];
+
+pub fn extract(salt: &[u8], ikm: &[u8]) -> Digest {
+    hmac_sha256(salt, ikm)
+}
+
+/// Fills `okm` with output key material
+///
+/// # Panics
+/// If `info` is longer than the longest label TLS uses
+pub fn expand(prk: &Digest, info: &[u8], okm: &mut [u8]) {
+    // T(n) = HMAC(PRK, T(n - 1) | info | n)
+    let mut message = [0_u8; 32 + MAX_INFO_LEN + 1];
+    let mut previous_len = 0;
+    for (counter, block) in (1..=u8::MAX).zip(okm.chunks_mut(32)) {
+        let len = previous_len + info.len() + 1;
+        message[previous_len..len - 1].copy_from_slice(info);
+        message[len - 1] = counter;
+
+        let t = hmac_sha256(prk, &message[..len]);
+        block.copy_from_slice(&t[..block.len()]);
+        message[..32].copy_from_slice(&t);
+        previous_len = 32;
+    }
+}
+
+/// HKDF-Expand-Label
+pub fn expand_label(secret: &Digest, label: &str, context: &[u8], okm: &mut [u8]) {
+    let mut info = [0_u8; MAX_INFO_LEN];
+    let label_len = b"tls13 ".len() + label.len();
+    let len = 2 + 1 + label_len + 1 + context.len();
+    info[..2].copy_from_slice(&(okm.len() as u16).to_be_bytes());
+    info[2] = label_len as u8;
+    info[3..9].copy_from_slice(b"tls13 ");
+    info[9..3 + label_len].copy_from_slice(label.as_bytes());
+    info[3 + label_len] = context.len() as u8;
+    info[4 + label_len..len].copy_from_slice(context);
+
+    expand(secret, &info[..len], okm);
+}
+
+/// Derive-Secret of transcript hash
+pub fn derive_secret(secret: &Digest, label: &str, transcript: &Digest) -> Digest {
+    let mut derived = [0; 32];
+    expand_label(secret, label, transcript, &mut derived);
+    derived
+}
+
+/// Handshake Secret of X25519 shared secret
+pub fn handshake_secret(shared: &[u8]) -> Digest {
+    let early = extract(&ZEROS, &ZEROS);
+    extract(&derive_secret(&early, "derived", &digest(&[])), shared)
+}
+
+/// Master Secret application traffic secrets derive from
+pub fn master_secret(handshake: &Digest) -> Digest {
+    extract(&derive_secret(handshake, "derived", &digest(&[])), &ZEROS)
+}
+
+/// Verify data of Finished message
+///
+/// # Arguments
+/// - `base` - handshake traffic secret of sender
+/// - `transcript` - hash of handshake up to the Finished message
+pub fn finished(base: &Digest, transcript: &Digest) -> Digest {
+    let mut key = [0; 32];
+    expand_label(base, "finished", &[], &mut key);
+    hmac_sha256(&key, transcript)
+}
+
+/// Application traffic secret after KeyUpdate
+pub fn next_traffic_secret(secret: &Digest) -> Digest {
+    let mut next = [0; 32];
+    expand_label(secret, "traffic upd", &[], &mut next);
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex<const N: usize>(hex: &str) -> [u8; N] {
+        let mut bytes = [0; N];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+            *byte = u8::from_str_radix(core::str::from_utf8(pair).unwrap(), 16).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn hkdf_of_rfc_5869_case_1() {
+        let prk = extract(&hex::<13>("000102030405060708090a0b0c"), &[0x0b; 22]);
+        assert_eq!(
+            prk,
+            hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+        );
+
+        let mut okm = [0; 42];
+        expand(&prk, &hex::<10>("f0f1f2f3f4f5f6f7f8f9"), &mut okm);
+        assert_eq!(
+            okm,
+            hex::<42>(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+            )
+        );
+    }
+
+    /// Secrets of a handshake logged by OpenSSL, also used by rustls
+    #[test]
+    fn traffic_secrets_of_openssl_handshake() {
+        let shared = hex::<32>("e7b8fef8903b520cb9a18971b69dd45dca53ce2f12bf3bef9315e31271df4b40");
+        let hello_hash = hex("ec147a06dea3c8846c02b2238e41bddc9d89f9aea17b5efd4d7482af75881c0a");
+        let handshake_hash =
+            hex("751a3d4a14dfabeb68e92ca5918e2408b9bcb0748982ec9c3230ac30bbeb23e2");
+
+        let handshake = handshake_secret(&shared);
+        let client = derive_secret(&handshake, "c hs traffic", &hello_hash);
+        let server = derive_secret(&handshake, "s hs traffic", &hello_hash);
+        assert_eq!(
+            client,
+            hex("617b35076b9d0e08cf731d94a86614784109ef255551921dd46e040135cf46ab")
+        );
+        assert_eq!(
+            server,
+            hex("fcf7dfe64fa2c04f6235387f434e01422336d9c039de6847a0b9ddcf29a88759")
+        );
+
+        let mut key = [0; 16];
+        let mut iv = [0; 12];
+        expand_label(&client, "key", &[], &mut key);
+        expand_label(&client, "iv", &[], &mut iv);
+        assert_eq!(key, hex("62d0dd00f69619d3b8193ab4a09585a7"));
+        assert_eq!(iv, hex("fff75df5ad35d5cb3c53f3a9"));
+
+        let master = master_secret(&handshake);
+        assert_eq!(
+            derive_secret(&master, "c ap traffic", &handshake_hash),
+            hex("c14a6d7976d8102b5a0c9951493fee87dcaff82c24cab214e8be71a8206dbda5")
+        );
+        let server = derive_secret(&master, "s ap traffic", &handshake_hash);
+        assert_eq!(
+            server,
+            hex("2c907738d3f83702d1e4598f4848531d9f9365491b9f7f52c822290d4c232192")
+        );
+        expand_label(&server, "key", &[], &mut key);
+        expand_label(&server, "iv", &[], &mut iv);
+        assert_eq!(key, hex("0cb29562d8d88f48b02cbfbed7e62bb3"));
+        assert_eq!(iv, hex("0db28f988586a1b7e4d5c69c"));
+    }
+}
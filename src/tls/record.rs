@@ -0,0 +1,487 @@
+//!
+//! Record layer of TLS 1.3 with ChaCha20-Poly1305
+//!
+//! Received records are read whole into the buffer and decrypted in place, so it
+//! must fit the largest record a peer may send. Sent plaintext is collected in a
+//! smaller buffer and sealed into a record when it is full or flushed.
+//!
+
+use chacha20poly1305::{aead::AeadInPlace, ChaCha20Poly1305, KeyInit, Tag};
+use embedded_io_async::{Read, ReadExactError, Write};
+
+use super::{key_schedule, Alert, ContentType, Error};
+use crate::sha256::Digest;
+
+pub const HEADER_LEN: usize = 5;
+const TAG_LEN: usize = 16;
+/// Longest plaintext of record
+const MAX_PLAINTEXT_LEN: usize = 1 << 14;
+/// Longest body of received record, plaintext with content type, padding and tag
+pub const MAX_CIPHERTEXT_LEN: usize = MAX_PLAINTEXT_LEN + 256;
+/// Legacy version of every record
+const LEGACY_VERSION: [u8; 2] = [0x03, 0x03];
+
+/// Keys of one direction
+pub struct Protection {
+    cipher: ChaCha20Poly1305,
+    iv: [u8; 12],
+    sequence: u64,
+    secret: Digest,
+}
+
+impl Protection {
+    /// # Arguments
+    /// - `secret` - traffic secret of the direction
+    pub fn new(secret: Digest) -> Self {
+        let mut key = [0; 32];
+        let mut iv = [0; 12];
+        key_schedule::expand_label(&secret, "key", &[], &mut key);
+        key_schedule::expand_label(&secret, "iv", &[], &mut iv);
+
+        Self {
+            cipher: ChaCha20Poly1305::new(&key.into()),
+            iv,
+            sequence: 0,
+            secret,
+        }
+    }
+
+    /// Switches to keys of the next traffic secret, after KeyUpdate
+    pub fn update(&mut self) {
+        *self = Self::new(key_schedule::next_traffic_secret(&self.secret));
+    }
+
+    /// Encrypts record in place
+    ///
+    /// # Arguments
+    /// - `record` - room for header, plaintext of `len`, content type and tag
+    ///
+    /// # Returns
+    /// Length of record
+    pub fn seal(&mut self, record: &mut [u8], len: usize, content_type: ContentType) -> usize {
+        let body_len = len + 1 + TAG_LEN;
+        record[HEADER_LEN + len] = content_type as u8;
+        write_header(record, ContentType::ApplicationData, body_len);
+        let (header, body) = record.split_at_mut(HEADER_LEN);
+
+        let nonce = self.nonce();
+        // Fails only for messages longer than 256 GiB
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(&nonce.into(), header, &mut body[..len + 1])
+            .unwrap_or_default();
+        body[len + 1..body_len].copy_from_slice(&tag);
+
+        HEADER_LEN + body_len
+    }
+
+    /// Decrypts record body in place
+    ///
+    /// # Returns
+    /// Inner content type and plaintext length
+    pub fn open(&mut self, header: &[u8], body: &mut [u8]) -> Result<(u8, usize), Alert> {
+        let Some(ciphertext_len) = body.len().checked_sub(TAG_LEN) else {
+            return Err(Alert::DecodeError);
+        };
+        let (ciphertext, tag) = body.split_at_mut(ciphertext_len);
+
+        let nonce = self.nonce();
+        self.cipher
+            .decrypt_in_place_detached(&nonce.into(), header, ciphertext, Tag::from_slice(tag))
+            .map_err(|_| Alert::BadRecordMac)?;
+
+        // Content type is the last byte that is not zero padding
+        let len = ciphertext
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .ok_or(Alert::UnexpectedMessage)?;
+        Ok((ciphertext[len], len))
+    }
+
+    /// Per-record nonce, IV xored with sequence number
+    fn nonce(&mut self) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (byte, sequence) in nonce[4..].iter_mut().zip(self.sequence.to_be_bytes()) {
+            *byte ^= sequence;
+        }
+        self.sequence += 1;
+        nonce
+    }
+}
+
+fn write_header(record: &mut [u8], content_type: ContentType, len: usize) {
+    record[0] = content_type as u8;
+    record[1..3].copy_from_slice(&LEGACY_VERSION);
+    record[3..HEADER_LEN].copy_from_slice(&(len as u16).to_be_bytes());
+}
+
+/// Reads and decrypts records
+pub struct Receiver<'a> {
+    buffer: &'a mut [u8],
+    protection: Option<Protection>,
+    /// Unread plaintext of the last record
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Receiver<'a> {
+    /// # Arguments
+    /// - `buffer` - fits the largest record, [`HEADER_LEN`] + [`MAX_CIPHERTEXT_LEN`]
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            protection: None,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    pub fn protect(&mut self, protection: Protection) {
+        self.protection = Some(protection);
+    }
+
+    pub fn protection(&mut self) -> Option<&mut Protection> {
+        self.protection.as_mut()
+    }
+
+    /// Reads the next record
+    ///
+    /// Records are decrypted once there are keys, except change cipher spec of
+    /// middlebox compatibility, which is always plaintext.
+    ///
+    /// # Returns
+    /// Content type, plaintext is in [`Self::plaintext`]
+    pub async fn read_record<R: Read>(&mut self, reader: &mut R) -> Result<u8, Error<R::Error>> {
+        let (header, body) = self.buffer.split_at_mut(HEADER_LEN);
+        reader.read_exact(header).await.map_err(read_error)?;
+        let len = usize::from(u16::from_be_bytes([header[3], header[4]]));
+        let body = body.get_mut(..len).ok_or(Alert::RecordOverflow)?;
+        reader.read_exact(body).await.map_err(read_error)?;
+
+        let content_type = header[0];
+        let (content_type, len) = match &mut self.protection {
+            Some(protection) if content_type == ContentType::ApplicationData as u8 => {
+                protection.open(header, body)?
+            }
+            Some(_) if content_type != ContentType::ChangeCipherSpec as u8 => {
+                return Err(Alert::UnexpectedMessage.into())
+            }
+            _ => (content_type, len),
+        };
+
+        self.start = HEADER_LEN;
+        self.end = HEADER_LEN + len;
+        Ok(content_type)
+    }
+
+    /// Unread plaintext of the last record
+    pub fn plaintext(&self) -> &[u8] {
+        &self.buffer[self.start..self.end]
+    }
+
+    /// Marks plaintext as read
+    pub fn consume(&mut self, len: usize) {
+        self.start = (self.start + len).min(self.end);
+    }
+}
+
+fn read_error<E>(err: ReadExactError<E>) -> Error<E> {
+    match err {
+        ReadExactError::UnexpectedEof => Error::Closed,
+        ReadExactError::Other(err) => Error::Io(err),
+    }
+}
+
+/// Collects plaintext and writes it in records
+pub struct Sender<'a> {
+    buffer: &'a mut [u8],
+    protection: Option<Protection>,
+    len: usize,
+    content_type: ContentType,
+}
+
+impl<'a> Sender<'a> {
+    /// # Arguments
+    /// - `buffer` - record with plaintext of any length, several hundred bytes at least
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            protection: None,
+            len: 0,
+            content_type: ContentType::Handshake,
+        }
+    }
+
+    /// Protects records after the ones written so far
+    pub async fn protect<W: Write>(
+        &mut self,
+        writer: &mut W,
+        protection: Protection,
+    ) -> Result<(), Error<W::Error>> {
+        self.write_record(writer).await?;
+        self.protection = Some(protection);
+        Ok(())
+    }
+
+    pub fn protection(&mut self) -> Option<&mut Protection> {
+        self.protection.as_mut()
+    }
+
+    /// Longest plaintext of record
+    fn capacity(&self) -> usize {
+        let overhead = match self.protection {
+            Some(_) => 1 + TAG_LEN,
+            None => 0,
+        };
+        (self.buffer.len() - HEADER_LEN - overhead).min(MAX_PLAINTEXT_LEN)
+    }
+
+    /// Writes plaintext, records go out when they are full
+    pub async fn write<W: Write>(
+        &mut self,
+        writer: &mut W,
+        content_type: ContentType,
+        mut data: &[u8],
+    ) -> Result<(), Error<W::Error>> {
+        if self.content_type != content_type {
+            self.write_record(writer).await?;
+            self.content_type = content_type;
+        }
+
+        while !data.is_empty() {
+            if self.len == self.capacity() {
+                self.write_record(writer).await?;
+            }
+            let len = data.len().min(self.capacity() - self.len);
+            let start = HEADER_LEN + self.len;
+            self.buffer[start..start + len].copy_from_slice(&data[..len]);
+            self.len += len;
+            data = &data[len..];
+        }
+
+        Ok(())
+    }
+
+    /// Writes collected plaintext as record
+    pub async fn write_record<W: Write>(&mut self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        if self.len == 0 {
+            return Ok(());
+        }
+
+        let len = match &mut self.protection {
+            Some(protection) => protection.seal(self.buffer, self.len, self.content_type),
+            None => {
+                write_header(self.buffer, self.content_type, self.len);
+                HEADER_LEN + self.len
+            }
+        };
+        self.len = 0;
+        writer
+            .write_all(&self.buffer[..len])
+            .await
+            .map_err(Error::Io)
+    }
+
+    /// Writes collected plaintext and flushes socket
+    pub async fn flush<W: Write>(&mut self, writer: &mut W) -> Result<(), Error<W::Error>> {
+        self.write_record(writer).await?;
+        writer.flush().await.map_err(Error::Io)
+    }
+
+    /// Sends alert in its own record
+    pub async fn alert<W: Write>(
+        &mut self,
+        writer: &mut W,
+        alert: Alert,
+    ) -> Result<(), Error<W::Error>> {
+        let level = match alert {
+            Alert::CloseNotify => 1,
+            _ => 2,
+        };
+        self.write_record(writer).await?;
+        self.write(writer, ContentType::Alert, &[level, alert as u8])
+            .await?;
+        self.flush(writer).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+
+    /// Record written by sender
+    struct Capture(Vec<u8>);
+
+    impl embedded_io_async::ErrorType for Capture {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for Capture {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    const SECRET: Digest = [0x42; 32];
+
+    fn sealed(plaintext: &[u8], content_type: ContentType) -> Vec<u8> {
+        let mut record = [0; 64];
+        record[HEADER_LEN..HEADER_LEN + plaintext.len()].copy_from_slice(plaintext);
+        let len = Protection::new(SECRET).seal(&mut record, plaintext.len(), content_type);
+        record[..len].to_vec()
+    }
+
+    #[test]
+    fn opens_sealed_record() {
+        let mut record = sealed(b"hello", ContentType::Handshake);
+        assert_eq!(record[..HEADER_LEN], [23, 3, 3, 0, 5 + 1 + 16]);
+
+        let (header, body) = record.split_at_mut(HEADER_LEN);
+        let opened = Protection::new(SECRET).open(header, body);
+        assert_eq!(opened, Ok((ContentType::Handshake as u8, 5)));
+        assert_eq!(&body[..5], b"hello");
+    }
+
+    #[test]
+    fn rejects_tampered_record() {
+        let mut record = sealed(b"hello", ContentType::ApplicationData);
+        record[HEADER_LEN] ^= 1;
+        let (header, body) = record.split_at_mut(HEADER_LEN);
+        assert_eq!(
+            Protection::new(SECRET).open(header, body),
+            Err(Alert::BadRecordMac)
+        );
+
+        // Header is authenticated too
+        let mut record = sealed(b"hello", ContentType::ApplicationData);
+        record[1] = 1;
+        let (header, body) = record.split_at_mut(HEADER_LEN);
+        assert_eq!(
+            Protection::new(SECRET).open(header, body),
+            Err(Alert::BadRecordMac)
+        );
+    }
+
+    /// Record of inner plaintext as it is, with content type and padding
+    fn sealed_inner(inner: &[u8]) -> Vec<u8> {
+        let mut record = [0; 64];
+        let body_len = inner.len() + TAG_LEN;
+        write_header(&mut record, ContentType::ApplicationData, body_len);
+        let (header, body) = record.split_at_mut(HEADER_LEN);
+        body[..inner.len()].copy_from_slice(inner);
+
+        let mut protection = Protection::new(SECRET);
+        let nonce = protection.nonce();
+        let tag = protection
+            .cipher
+            .encrypt_in_place_detached(&nonce.into(), header, &mut body[..inner.len()])
+            .unwrap();
+        body[inner.len()..body_len].copy_from_slice(&tag);
+        record[..HEADER_LEN + body_len].to_vec()
+    }
+
+    #[test]
+    fn strips_padding_and_rejects_missing_content_type() {
+        let mut padded = sealed_inner(&[b'a', 23, 0, 0]);
+        let (header, body) = padded.split_at_mut(HEADER_LEN);
+        assert_eq!(Protection::new(SECRET).open(header, body), Ok((23, 1)));
+
+        let mut padding = sealed_inner(&[0, 0]);
+        let (header, body) = padding.split_at_mut(HEADER_LEN);
+        assert_eq!(
+            Protection::new(SECRET).open(header, body),
+            Err(Alert::UnexpectedMessage)
+        );
+    }
+
+    #[test]
+    fn nonce_changes_with_sequence() {
+        let mut protection = Protection::new(SECRET);
+        let first = protection.nonce();
+        let second = protection.nonce();
+        assert_eq!(first[..11], second[..11]);
+        assert_eq!(first[11] ^ second[11], 1);
+    }
+
+    #[test]
+    fn updated_keys_differ() {
+        let mut updated = Protection::new(SECRET);
+        updated.update();
+        let mut record = sealed(b"hello", ContentType::ApplicationData);
+        let (header, body) = record.split_at_mut(HEADER_LEN);
+        assert_eq!(updated.open(header, body), Err(Alert::BadRecordMac));
+    }
+
+    #[test]
+    fn sender_splits_plaintext_into_full_records() {
+        let mut buffer = [0; HEADER_LEN + 8];
+        let mut sender = Sender::new(&mut buffer);
+        let mut capture = Capture(Vec::new());
+
+        block_on(async {
+            sender
+                .write(&mut capture, ContentType::Handshake, b"0123456789")
+                .await
+                .unwrap();
+            // Other content type starts a new record
+            sender
+                .write(&mut capture, ContentType::ChangeCipherSpec, &[1])
+                .await
+                .unwrap();
+            sender.flush(&mut capture).await.unwrap();
+        });
+
+        assert_eq!(
+            capture.0,
+            [
+                &[22, 3, 3, 0, 8][..],
+                b"01234567",
+                &[22, 3, 3, 0, 2],
+                b"89",
+                &[20, 3, 3, 0, 1, 1],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn receiver_decrypts_protected_records() {
+        let mut record = sealed(b"GET /", ContentType::ApplicationData);
+        record.extend_from_slice(&[20, 3, 3, 0, 1, 1]);
+
+        let mut buffer = [0; 128];
+        let mut receiver = Receiver::new(&mut buffer);
+        receiver.protect(Protection::new(SECRET));
+        let mut reader = record.as_slice();
+
+        block_on(async {
+            assert_eq!(receiver.read_record(&mut reader).await, Ok(23));
+            assert_eq!(receiver.plaintext(), b"GET /");
+            receiver.consume(4);
+            assert_eq!(receiver.plaintext(), b"/");
+            assert_eq!(receiver.read_record(&mut reader).await, Ok(20));
+            assert_eq!(receiver.plaintext(), [1]);
+            assert_eq!(receiver.read_record(&mut reader).await, Err(Error::Closed));
+        });
+    }
+
+    #[test]
+    fn receiver_rejects_records_larger_than_buffer() {
+        let mut buffer = [0; 16];
+        let mut receiver = Receiver::new(&mut buffer);
+        let record = [22, 3, 3, 0, 12];
+        let mut reader = &record[..];
+
+        assert_eq!(
+            block_on(receiver.read_record(&mut reader)),
+            Err(Error::Alert(Alert::RecordOverflow))
+        );
+    }
+}
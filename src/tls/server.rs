@@ -0,0 +1,854 @@
+//!
+//! Server handshake and the connection it leaves behind
+//!
+//! The whole server flight goes out at once: ServerHello, EncryptedExtensions,
+//! Certificate, CertificateVerify and Finished. Then the client Finished is checked,
+//! after a change cipher spec of middlebox compatibility if the client sends one.
+//!
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embedded_io_async::{ErrorType, Read, Write};
+use picoserve::{io::Socket, Timeouts, Timer};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::{
+    identity::{Identity, MAX_SIGNATURE_LEN},
+    key_schedule,
+    record::{Protection, Receiver, Sender, HEADER_LEN, MAX_CIPHERTEXT_LEN},
+    Alert, ContentType, Error,
+};
+use crate::sha256::{digest_eq, Digest, Sha256};
+
+const TLS_1_3: u16 = 0x0304;
+const TLS_CHACHA20_POLY1305_SHA256: u16 = 0x1303;
+const X25519: u16 = 0x001D;
+const ECDSA_SECP256R1_SHA256: u16 = 0x0403;
+
+const EXTENSION_SIGNATURE_ALGORITHMS: u16 = 13;
+const EXTENSION_SUPPORTED_VERSIONS: u16 = 43;
+const EXTENSION_KEY_SHARE: u16 = 51;
+
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+const ENCRYPTED_EXTENSIONS: u8 = 8;
+const CERTIFICATE: u8 = 11;
+const CERTIFICATE_VERIFY: u8 = 15;
+const FINISHED: u8 = 20;
+const KEY_UPDATE: u8 = 24;
+
+/// Plaintext of sent records, about a TCP segment
+const TX_BUFFER_LEN: usize = 2048;
+
+/// Buffers of one connection, static as the receive one is large
+pub struct Buffers {
+    rx: [u8; HEADER_LEN + MAX_CIPHERTEXT_LEN],
+    tx: [u8; TX_BUFFER_LEN],
+}
+
+impl Default for Buffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Buffers {
+    pub const fn new() -> Self {
+        Self {
+            rx: [0; HEADER_LEN + MAX_CIPHERTEXT_LEN],
+            tx: [0; TX_BUFFER_LEN],
+        }
+    }
+}
+
+/// Performs server handshake on accepted socket
+///
+/// Fatal alerts are sent to client before the error is returned.
+///
+/// # Arguments
+/// - `random` - random of ServerHello
+/// - `secret` - random X25519 secret of this connection
+pub async fn accept<'a, S: Socket>(
+    mut socket: S,
+    identity: &Identity,
+    buffers: &'a mut Buffers,
+    random: [u8; 32],
+    secret: [u8; 32],
+) -> Result<TlsSocket<'a, S>, Error<S::Error>> {
+    let mut rx = Receiver::new(&mut buffers.rx);
+    let mut tx = Sender::new(&mut buffers.tx);
+
+    {
+        let (mut reader, mut writer) = socket.split();
+        let handshake = Handshake {
+            rx: &mut rx,
+            tx: &mut tx,
+            transcript: Sha256::new(),
+        };
+        let result = handshake
+            .run(&mut reader, &mut writer, identity, random, secret)
+            .await;
+        if let Err(Error::Alert(alert)) = result {
+            // Client learns why, the connection is closed anyway
+            let _ = tx.alert(&mut writer, alert).await;
+        }
+        result?;
+    }
+
+    Ok(TlsSocket {
+        socket,
+        rx,
+        tx,
+        key_update: AtomicBool::new(false),
+    })
+}
+
+struct Handshake<'h, 'a> {
+    rx: &'h mut Receiver<'a>,
+    tx: &'h mut Sender<'a>,
+    /// Hash of handshake messages so far
+    transcript: Sha256,
+}
+
+impl Handshake<'_, '_> {
+    async fn run<R: Read, W: Write<Error = R::Error>>(
+        mut self,
+        reader: &mut R,
+        writer: &mut W,
+        identity: &Identity,
+        random: [u8; 32],
+        secret: [u8; 32],
+    ) -> Result<(), Error<R::Error>> {
+        let hello = self.read_client_hello(reader).await?;
+
+        let secret = StaticSecret::from(secret);
+        let shared = secret.diffie_hellman(&PublicKey::from(hello.key_share));
+        if !shared.was_contributory() {
+            return Err(Alert::IllegalParameter.into());
+        }
+
+        self.write_server_hello(writer, &hello, random, PublicKey::from(&secret).as_bytes())
+            .await?;
+        // Client sent session id for middlebox compatibility, and expects this
+        if !hello.session_id.is_empty() {
+            self.tx
+                .write(writer, ContentType::ChangeCipherSpec, &[1])
+                .await?;
+        }
+
+        let handshake = key_schedule::handshake_secret(shared.as_bytes());
+        let hello_hash = self.transcript.clone().finalize();
+        let client_secret = key_schedule::derive_secret(&handshake, "c hs traffic", &hello_hash);
+        let server_secret = key_schedule::derive_secret(&handshake, "s hs traffic", &hello_hash);
+        self.tx
+            .protect(writer, Protection::new(server_secret))
+            .await?;
+        self.rx.protect(Protection::new(client_secret));
+
+        self.write_message(writer, ENCRYPTED_EXTENSIONS, &[&[0, 0]])
+            .await?;
+        self.write_certificate(writer, identity).await?;
+        self.write_certificate_verify(writer, identity).await?;
+        let verify_data =
+            key_schedule::finished(&server_secret, &self.transcript.clone().finalize());
+        self.write_message(writer, FINISHED, &[&verify_data])
+            .await?;
+        self.tx.flush(writer).await?;
+
+        // Application data follows server Finished, client Finished is not part of it
+        let handshake_hash = self.transcript.clone().finalize();
+        let master = key_schedule::master_secret(&handshake);
+        self.tx
+            .protect(
+                writer,
+                Protection::new(key_schedule::derive_secret(
+                    &master,
+                    "s ap traffic",
+                    &handshake_hash,
+                )),
+            )
+            .await?;
+
+        let expected = key_schedule::finished(&client_secret, &handshake_hash);
+        self.read_client_finished(reader, &expected).await?;
+        self.rx.protect(Protection::new(key_schedule::derive_secret(
+            &master,
+            "c ap traffic",
+            &handshake_hash,
+        )));
+        Ok(())
+    }
+
+    async fn read_client_hello<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<ClientHello, Error<R::Error>> {
+        let content_type = self.rx.read_record(reader).await?;
+        if content_type != ContentType::Handshake as u8 {
+            return Err(Alert::UnexpectedMessage.into());
+        }
+        // Clients send it in one record, fragments are not reassembled
+        let message = self.rx.plaintext();
+        let hello = match parse_message(message)? {
+            (CLIENT_HELLO, body) => ClientHello::parse(body)?,
+            _ => return Err(Alert::UnexpectedMessage.into()),
+        };
+        self.transcript.update(message);
+        self.rx.consume(message.len());
+        Ok(hello)
+    }
+
+    async fn read_client_finished<R: Read>(
+        &mut self,
+        reader: &mut R,
+        expected: &Digest,
+    ) -> Result<(), Error<R::Error>> {
+        let mut change_cipher_spec = false;
+        loop {
+            let content_type = self.rx.read_record(reader).await?;
+            let plaintext = self.rx.plaintext();
+            match content_type {
+                t if t == ContentType::ChangeCipherSpec as u8
+                    && plaintext == [1]
+                    && !change_cipher_spec =>
+                {
+                    change_cipher_spec = true;
+                }
+                t if t == ContentType::Handshake as u8 => {
+                    return match parse_message(plaintext)? {
+                        (FINISHED, verify_data) => {
+                            let verify_data: &Digest =
+                                verify_data.try_into().map_err(|_| Alert::DecodeError)?;
+                            if digest_eq(verify_data, expected) {
+                                let len = plaintext.len();
+                                self.rx.consume(len);
+                                Ok(())
+                            } else {
+                                Err(Alert::DecryptError.into())
+                            }
+                        }
+                        _ => Err(Alert::UnexpectedMessage.into()),
+                    };
+                }
+                t if t == ContentType::Alert as u8 => return Err(peer_alert(plaintext)),
+                _ => return Err(Alert::UnexpectedMessage.into()),
+            }
+        }
+    }
+
+    async fn write_server_hello<W: Write>(
+        &mut self,
+        writer: &mut W,
+        hello: &ClientHello,
+        random: [u8; 32],
+        key_share: &[u8; 32],
+    ) -> Result<(), Error<W::Error>> {
+        let [version_high, version_low] = TLS_1_3.to_be_bytes();
+        let supported_versions = [
+            0,
+            EXTENSION_SUPPORTED_VERSIONS as u8,
+            0,
+            2,
+            version_high,
+            version_low,
+        ];
+        let [group_high, group_low] = X25519.to_be_bytes();
+        let key_share_header = [
+            0,
+            EXTENSION_KEY_SHARE as u8,
+            0,
+            36,
+            group_high,
+            group_low,
+            0,
+            32,
+        ];
+        let extensions_len = supported_versions.len() + key_share_header.len() + key_share.len();
+
+        self.write_message(
+            writer,
+            SERVER_HELLO,
+            &[
+                // Legacy version
+                &[0x03, 0x03],
+                &random,
+                &[hello.session_id.len() as u8],
+                &hello.session_id,
+                &TLS_CHACHA20_POLY1305_SHA256.to_be_bytes(),
+                // No compression
+                &[0],
+                &(extensions_len as u16).to_be_bytes(),
+                &supported_versions,
+                &key_share_header,
+                key_share,
+            ],
+        )
+        .await
+    }
+
+    async fn write_certificate<W: Write>(
+        &mut self,
+        writer: &mut W,
+        identity: &Identity,
+    ) -> Result<(), Error<W::Error>> {
+        // Each entry is length, certificate and no extensions
+        let list_len: usize = identity.certificates().map(|der| 3 + der.len() + 2).sum();
+        self.write_header(writer, CERTIFICATE, 1 + 3 + list_len)
+            .await?;
+        // Empty request context, then the list
+        self.write_handshake(writer, &[0]).await?;
+        self.write_handshake(writer, &u24(list_len)).await?;
+        for der in identity.certificates() {
+            self.write_handshake(writer, &u24(der.len())).await?;
+            self.write_handshake(writer, der).await?;
+            self.write_handshake(writer, &[0, 0]).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_certificate_verify<W: Write>(
+        &mut self,
+        writer: &mut W,
+        identity: &Identity,
+    ) -> Result<(), Error<W::Error>> {
+        const CONTEXT: &[u8] = b"TLS 1.3, server CertificateVerify\0";
+        let mut content = [0x20; 64 + CONTEXT.len() + 32];
+        content[64..64 + CONTEXT.len()].copy_from_slice(CONTEXT);
+        content[64 + CONTEXT.len()..].copy_from_slice(&self.transcript.clone().finalize());
+
+        let mut signature = [0; MAX_SIGNATURE_LEN];
+        let len = identity.sign(&content, &mut signature);
+        self.write_message(
+            writer,
+            CERTIFICATE_VERIFY,
+            &[
+                &ECDSA_SECP256R1_SHA256.to_be_bytes(),
+                &(len as u16).to_be_bytes(),
+                &signature[..len],
+            ],
+        )
+        .await
+    }
+
+    /// Writes handshake message of parts
+    async fn write_message<W: Write>(
+        &mut self,
+        writer: &mut W,
+        message_type: u8,
+        parts: &[&[u8]],
+    ) -> Result<(), Error<W::Error>> {
+        let len = parts.iter().map(|part| part.len()).sum();
+        self.write_header(writer, message_type, len).await?;
+        for part in parts {
+            self.write_handshake(writer, part).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        message_type: u8,
+        len: usize,
+    ) -> Result<(), Error<W::Error>> {
+        let [high, middle, low] = u24(len);
+        self.write_handshake(writer, &[message_type, high, middle, low])
+            .await
+    }
+
+    /// Writes part of handshake message and adds it to transcript
+    async fn write_handshake<W: Write>(
+        &mut self,
+        writer: &mut W,
+        data: &[u8],
+    ) -> Result<(), Error<W::Error>> {
+        self.transcript.update(data);
+        self.tx.write(writer, ContentType::Handshake, data).await
+    }
+}
+
+fn u24(len: usize) -> [u8; 3] {
+    let [_, high, middle, low] = (len as u32).to_be_bytes();
+    [high, middle, low]
+}
+
+/// Type and body of the only handshake message of record
+fn parse_message(plaintext: &[u8]) -> Result<(u8, &[u8]), Alert> {
+    let mut parser = Parser(plaintext);
+    let message_type = parser.u8()?;
+    let body = parser.vec24()?;
+    parser.finish()?;
+    Ok((message_type, body))
+}
+
+/// Error of alert record from peer
+fn peer_alert<E>(plaintext: &[u8]) -> Error<E> {
+    match plaintext {
+        [_, description] => Error::PeerAlert(*description),
+        _ => Alert::DecodeError.into(),
+    }
+}
+
+/// What the server takes from ClientHello
+struct ClientHello {
+    session_id: heapless::Vec<u8, 32>,
+    key_share: [u8; 32],
+}
+
+impl ClientHello {
+    /// Checks that client supports the only suite, version, group and signature
+    fn parse(body: &[u8]) -> Result<Self, Alert> {
+        let mut parser = Parser(body);
+        // Legacy version and random
+        parser.take(2 + 32)?;
+        let session_id =
+            heapless::Vec::from_slice(parser.vec8()?).map_err(|_| Alert::IllegalParameter)?;
+        let suite = contains_u16(parser.vec16()?, TLS_CHACHA20_POLY1305_SHA256);
+        if parser.vec8()? != [0] {
+            return Err(Alert::IllegalParameter);
+        }
+
+        let mut extensions = Parser(parser.vec16()?);
+        parser.finish()?;
+        let mut version = false;
+        let mut signature = false;
+        let mut key_share = None;
+        while !extensions.is_empty() {
+            let extension_type = extensions.u16()?;
+            let mut data = Parser(extensions.vec16()?);
+            match extension_type {
+                EXTENSION_SUPPORTED_VERSIONS => version = contains_u16(data.vec8()?, TLS_1_3),
+                EXTENSION_SIGNATURE_ALGORITHMS => {
+                    signature = contains_u16(data.vec16()?, ECDSA_SECP256R1_SHA256)
+                }
+                EXTENSION_KEY_SHARE => {
+                    let mut shares = Parser(data.vec16()?);
+                    while !shares.is_empty() {
+                        let group = shares.u16()?;
+                        let key = shares.vec16()?;
+                        if group == X25519 {
+                            key_share = Some(key.try_into().map_err(|_| Alert::IllegalParameter)?);
+                        }
+                    }
+                }
+                _ => continue,
+            }
+            data.finish()?;
+        }
+
+        if !version {
+            return Err(Alert::ProtocolVersion);
+        }
+        match key_share {
+            Some(key_share) if suite && signature => Ok(Self {
+                session_id,
+                key_share,
+            }),
+            // There is no HelloRetryRequest for other groups
+            _ => Err(Alert::HandshakeFailure),
+        }
+    }
+}
+
+fn contains_u16(list: &[u8], value: u16) -> bool {
+    list.chunks_exact(2).any(|pair| pair == value.to_be_bytes())
+}
+
+/// Reads fields of handshake messages
+struct Parser<'a>(&'a [u8]);
+
+impl<'a> Parser<'a> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Alert> {
+        if self.0.len() < len {
+            return Err(Alert::DecodeError);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, Alert> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Alert> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn vec8(&mut self) -> Result<&'a [u8], Alert> {
+        let len = self.u8()?;
+        self.take(usize::from(len))
+    }
+
+    fn vec16(&mut self) -> Result<&'a [u8], Alert> {
+        let len = self.u16()?;
+        self.take(usize::from(len))
+    }
+
+    fn vec24(&mut self) -> Result<&'a [u8], Alert> {
+        let bytes = self.take(3)?;
+        self.take(usize::from(bytes[0]) << 16 | usize::from(bytes[1]) << 8 | usize::from(bytes[2]))
+    }
+
+    /// Checks that everything was read
+    fn finish(&self) -> Result<(), Alert> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(Alert::DecodeError)
+        }
+    }
+}
+
+/// Connection after handshake, served by picoserve like a plain socket
+pub struct TlsSocket<'a, S> {
+    socket: S,
+    rx: Receiver<'a>,
+    tx: Sender<'a>,
+    /// Client asked for KeyUpdate, ours goes before the next data
+    key_update: AtomicBool,
+}
+
+impl<'a, S: Socket> Socket for TlsSocket<'a, S> {
+    type Error = Error<S::Error>;
+    type ReadHalf<'b>
+        = TlsReader<'b, 'a, S::ReadHalf<'b>>
+    where
+        Self: 'b;
+    type WriteHalf<'b>
+        = TlsWriter<'b, 'a, S::WriteHalf<'b>>
+    where
+        Self: 'b;
+
+    fn split(&mut self) -> (Self::ReadHalf<'_>, Self::WriteHalf<'_>) {
+        let (reader, writer) = self.socket.split();
+        (
+            TlsReader {
+                reader,
+                rx: &mut self.rx,
+                key_update: &self.key_update,
+                closed: false,
+            },
+            TlsWriter {
+                writer,
+                tx: &mut self.tx,
+                key_update: &self.key_update,
+            },
+        )
+    }
+
+    async fn shutdown<T: Timer>(
+        mut self,
+        timeouts: &Timeouts<T::Duration>,
+        timer: &mut T,
+    ) -> Result<(), picoserve::Error<Self::Error>> {
+        {
+            let (_, mut writer) = self.socket.split();
+            let close_notify = self.tx.alert(&mut writer, Alert::CloseNotify);
+            match timeouts.write.clone() {
+                Some(timeout) => timer
+                    .run_with_timeout(timeout, close_notify)
+                    .await
+                    .map_err(|_| picoserve::Error::WriteTimeout)?,
+                None => close_notify.await,
+            }
+            .map_err(picoserve::Error::Write)?;
+        }
+
+        self.socket
+            .shutdown(timeouts, timer)
+            .await
+            .map_err(|err| match err {
+                picoserve::Error::Read(err) => picoserve::Error::Read(Error::Io(err)),
+                picoserve::Error::ReadTimeout => picoserve::Error::ReadTimeout,
+                picoserve::Error::Write(err) => picoserve::Error::Write(Error::Io(err)),
+                picoserve::Error::WriteTimeout => picoserve::Error::WriteTimeout,
+            })
+    }
+}
+
+pub struct TlsReader<'b, 'a, R> {
+    reader: R,
+    rx: &'b mut Receiver<'a>,
+    key_update: &'b AtomicBool,
+    /// Client sent close notify
+    closed: bool,
+}
+
+impl<R: Read> ErrorType for TlsReader<'_, '_, R> {
+    type Error = Error<R::Error>;
+}
+
+impl<R: Read> Read for TlsReader<'_, '_, R> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // Plaintext left over is always application data
+        while self.rx.plaintext().is_empty() {
+            if self.closed {
+                return Ok(0);
+            }
+
+            let content_type = self.rx.read_record(&mut self.reader).await?;
+            let plaintext = self.rx.plaintext();
+            let len = plaintext.len();
+            match content_type {
+                t if t == ContentType::ApplicationData as u8 => continue,
+                t if t == ContentType::Alert as u8 => match plaintext {
+                    [_, description] if *description == Alert::CloseNotify as u8 => {
+                        self.closed = true
+                    }
+                    _ => return Err(peer_alert(plaintext)),
+                },
+                t if t == ContentType::Handshake as u8 => match parse_message(plaintext)? {
+                    (KEY_UPDATE, [request @ (0 | 1)]) => {
+                        if *request == 1 {
+                            self.key_update.store(true, Ordering::Relaxed);
+                        }
+                        if let Some(protection) = self.rx.protection() {
+                            protection.update();
+                        }
+                    }
+                    _ => return Err(Alert::UnexpectedMessage.into()),
+                },
+                _ => return Err(Alert::UnexpectedMessage.into()),
+            }
+            self.rx.consume(len);
+        }
+
+        let plaintext = self.rx.plaintext();
+        let len = plaintext.len().min(buf.len());
+        buf[..len].copy_from_slice(&plaintext[..len]);
+        self.rx.consume(len);
+        Ok(len)
+    }
+}
+
+pub struct TlsWriter<'b, 'a, W> {
+    writer: W,
+    tx: &'b mut Sender<'a>,
+    key_update: &'b AtomicBool,
+}
+
+impl<W: Write> ErrorType for TlsWriter<'_, '_, W> {
+    type Error = Error<W::Error>;
+}
+
+impl<W: Write> Write for TlsWriter<'_, '_, W> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.key_update.swap(false, Ordering::Relaxed) {
+            // Answer without requesting another update
+            self.tx
+                .write(
+                    &mut self.writer,
+                    ContentType::Handshake,
+                    &[KEY_UPDATE, 0, 0, 1, 0],
+                )
+                .await?;
+            self.tx.write_record(&mut self.writer).await?;
+            if let Some(protection) = self.tx.protection() {
+                protection.update();
+            }
+        }
+
+        self.tx
+            .write(&mut self.writer, ContentType::ApplicationData, buf)
+            .await?;
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.tx.flush(&mut self.writer).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::{boxed::Box, rc::Rc, vec::Vec};
+
+    use embassy_futures::block_on;
+    use embedded_io_async::ErrorKind;
+
+    use super::*;
+
+    /// Records `openssl s_client -groups X25519` sent to [`accept`] of [`identity`],
+    /// with random `[1; 32]` and secret `[2; 32]`: ClientHello, change cipher spec,
+    /// Finished and a request
+    const OPENSSL_FLIGHT: &str = concat!(
+        "16030100d4010000d0030330a0be30d61d4b80f803b7073dc05118c31a56b5e78e3b2f3dc64966f67940522032456175",
+        "1eaa0f5b5f3b4f5dabce8b609811c66a91e3357bcb2480ef30f9ac84000613021303130101000081000b000403000102",
+        "000a00040002001d002300000016000000170000000d002a002809050906090404030503060308070808081a081b081c",
+        "0809080a080b080408050806040105010601002b0003020304002d00020101003300260024001d0020c20957b05b7b53",
+        "80e8c5df487982f9bd8238cbe79d17878cc768d07e2eb4b41a14030300010117030300353332aab3d5820eef104eec79",
+        "36f706e9f1d3d98707f7dc631a93ee5b8bf0e8d20df91605124c25b86a747dff431cf6770f3ff972d417030300235a69",
+        "95cc6ebceb37376295b6d240fe1912d64a8ce33284afcacad715b30aa3b951bddd",
+    );
+    /// Offset of Finished record in flight
+    const FINISHED_OFFSET: usize = 217 + 6;
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        hex.as_bytes()
+            .chunks_exact(2)
+            .map(|pair| u8::from_str_radix(core::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn identity() -> Identity {
+        Identity::self_signed([7; 32], [0x81; 16], "esp-temperature").unwrap()
+    }
+
+    /// Reads client flight, collects what server writes
+    struct MockSocket {
+        input: Vec<u8>,
+        read: usize,
+        output: Rc<RefCell<Vec<u8>>>,
+    }
+
+    struct MockReader<'a> {
+        input: &'a [u8],
+        read: &'a mut usize,
+    }
+
+    impl ErrorType for MockReader<'_> {
+        type Error = ErrorKind;
+    }
+
+    impl Read for MockReader<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+            let unread = &self.input[*self.read..];
+            let len = unread.len().min(buf.len());
+            buf[..len].copy_from_slice(&unread[..len]);
+            *self.read += len;
+            Ok(len)
+        }
+    }
+
+    struct MockWriter<'a>(&'a RefCell<Vec<u8>>);
+
+    impl ErrorType for MockWriter<'_> {
+        type Error = ErrorKind;
+    }
+
+    impl Write for MockWriter<'_> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    impl Socket for MockSocket {
+        type Error = ErrorKind;
+        type ReadHalf<'a> = MockReader<'a>;
+        type WriteHalf<'a> = MockWriter<'a>;
+
+        fn split(&mut self) -> (MockReader<'_>, MockWriter<'_>) {
+            (
+                MockReader {
+                    input: &self.input,
+                    read: &mut self.read,
+                },
+                MockWriter(&self.output),
+            )
+        }
+
+        async fn shutdown<T: Timer>(
+            self,
+            _timeouts: &Timeouts<T::Duration>,
+            _timer: &mut T,
+        ) -> Result<(), picoserve::Error<ErrorKind>> {
+            Ok(())
+        }
+    }
+
+    /// Serves client flight
+    ///
+    /// # Returns
+    /// Application data read until flight ends or error, and records server sent
+    fn serve(flight: &[u8]) -> (Result<Vec<u8>, Error<ErrorKind>>, Vec<u8>) {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let socket = MockSocket {
+            input: flight.to_vec(),
+            read: 0,
+            output: output.clone(),
+        };
+        let mut buffers = Box::new(Buffers::new());
+
+        let result = block_on(async {
+            let mut tls = accept(socket, &identity(), &mut buffers, [1; 32], [2; 32]).await?;
+            let (mut reader, _) = tls.split();
+            let mut data = Vec::new();
+            let mut buf = [0; 7];
+            loop {
+                match reader.read(&mut buf).await {
+                    Ok(len) => data.extend_from_slice(&buf[..len]),
+                    Err(Error::Closed) => return Ok(data),
+                    Err(err) => return Err(err),
+                }
+            }
+        });
+        let output = output.borrow().clone();
+        (result, output)
+    }
+
+    /// Content types of records
+    fn record_types(mut records: &[u8]) -> Vec<u8> {
+        let mut types = Vec::new();
+        while let [content_type, _, _, high, low, rest @ ..] = records {
+            types.push(*content_type);
+            records = &rest[usize::from(u16::from_be_bytes([*high, *low]))..];
+        }
+        types
+    }
+
+    #[test]
+    fn accepts_openssl_handshake_and_reads_request() {
+        let (result, output) = serve(&unhex(OPENSSL_FLIGHT));
+        assert_eq!(result.unwrap(), b"GET / HTTP/1.0\r\n\r\n");
+        // ServerHello, change cipher spec for compatibility, then the protected flight
+        assert_eq!(record_types(&output)[..3], [22, 20, 23]);
+    }
+
+    #[test]
+    fn rejects_tampered_client_finished() {
+        let mut flight = unhex(OPENSSL_FLIGHT);
+        flight[FINISHED_OFFSET + 10] ^= 1;
+
+        let (result, output) = serve(&flight);
+        assert_eq!(result, Err(Error::Alert(Alert::BadRecordMac)));
+        // Alert is protected like the rest of the flight, of level, description, type and tag
+        assert_eq!(output[output.len() - 24..][..5], [23, 3, 3, 0, 19]);
+    }
+
+    #[test]
+    fn turns_away_clients_without_tls_1_3() {
+        // Supported versions offers TLS 1.2 only
+        let flight = unhex(&OPENSSL_FLIGHT.replace("002b0003020304", "002b0003020303"));
+        let (result, output) = serve(&flight);
+        assert_eq!(result, Err(Error::Alert(Alert::ProtocolVersion)));
+        assert_eq!(output, [21, 3, 3, 0, 2, 2, Alert::ProtocolVersion as u8]);
+    }
+
+    #[test]
+    fn turns_away_clients_without_x25519_key_share() {
+        // Key share of secp256r1 instead
+        let flight = unhex(&OPENSSL_FLIGHT.replace("0024001d0020", "002400170020"));
+        let (result, output) = serve(&flight);
+        assert_eq!(result, Err(Error::Alert(Alert::HandshakeFailure)));
+        assert_eq!(output, [21, 3, 3, 0, 2, 2, Alert::HandshakeFailure as u8]);
+    }
+
+    #[test]
+    fn rejects_truncated_client_hello() {
+        let mut flight = unhex(OPENSSL_FLIGHT);
+        // Handshake message longer than record
+        flight[8] += 1;
+        let (result, _) = serve(&flight[..217]);
+        assert_eq!(result, Err(Error::Alert(Alert::DecodeError)));
+    }
+}
@@ -0,0 +1,594 @@
+//!
+//! Pages shown on the display
+//!
+
+use core::fmt::Write as _;
+
+use embassy_net::Ipv4Address;
+
+use crate::{
+    drivers::display::ssd1306::DisplayFramebuffer,
+    events::{Alarm, Quantity},
+    graphics::font,
+    units::TemperatureUnit,
+};
+
+type Line = heapless::String<24>;
+
+/// Height of page title including separator
+const TITLE_HEIGHT: i32 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum WifiState {
+    Connecting,
+    /// Connected, but no address received yet
+    NoIp,
+    Connected,
+}
+
+impl WifiState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WifiState::Connecting => "connecting",
+            WifiState::NoIp => "no IP",
+            WifiState::Connected => "connected",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Page {
+    /// Current values
+    Readings,
+    /// Sparklines of stored values
+    History,
+    Network,
+    Alarms,
+    Diagnostics,
+}
+
+impl Page {
+    /// Next page in rotation
+    pub fn next(&self) -> Self {
+        match self {
+            Page::Readings => Page::History,
+            Page::History => Page::Network,
+            Page::Network => Page::Alarms,
+            Page::Alarms => Page::Diagnostics,
+            Page::Diagnostics => Page::Readings,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Page::Readings => "Readings",
+            Page::History => "History 24h",
+            Page::Network => "Network",
+            Page::Alarms => "Alarms",
+            Page::Diagnostics => "Diagnostics",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Readings {
+    pub temperature: f32,
+    pub humidity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkInfo {
+    pub wifi: WifiState,
+    pub ip: Option<Ipv4Address>,
+    pub gateway: Option<Ipv4Address>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diagnostics {
+    pub uptime_s: u64,
+    /// CPU load in percents
+    pub cpu_load: u8,
+    /// Temperature of the MCU die in °C
+    pub chip_temperature: Option<f32>,
+    pub heap_used: usize,
+    pub heap_free: usize,
+    pub webhooks_delivered: u32,
+    pub webhooks_failed: u32,
+}
+
+/// Everything pages can show, temperatures in °C
+pub struct Screen<'a> {
+    /// Unit temperatures are shown in
+    pub unit: TemperatureUnit,
+    pub readings: Readings,
+    /// Values from the oldest to the newest
+    pub temperature_history: &'a [f32],
+    pub humidity_history: &'a [f32],
+    pub network: NetworkInfo,
+    pub alarms: &'a [Alarm],
+    /// Names of sensors not responding
+    pub faults: &'a [&'static str],
+    pub diagnostics: Diagnostics,
+}
+
+/// Draws page into framebuffer
+///
+/// Framebuffer is redrawn completely, but only really changed areas become dirty
+pub fn render(fb: &mut DisplayFramebuffer, page: Page, screen: &Screen<'_>) {
+    // Drawn aside, so pixels cleared and drawn again don't count as changed
+    let mut next = DisplayFramebuffer::new();
+    draw_page(&mut next, page, screen);
+    fb.copy_from(&next);
+}
+
+fn draw_page(fb: &mut DisplayFramebuffer, page: Page, screen: &Screen<'_>) {
+    match page {
+        Page::Readings => render_readings(fb, &screen.readings, &screen.network, screen.unit),
+        Page::History => {
+            render_title(fb, page.title());
+            render_history(
+                fb,
+                TITLE_HEIGHT,
+                "T",
+                screen.temperature_history,
+                |celsius| screen.unit.from_celsius(celsius),
+            );
+            render_history(
+                fb,
+                TITLE_HEIGHT + 27,
+                "H",
+                screen.humidity_history,
+                |humidity| humidity,
+            );
+        }
+        Page::Network => {
+            render_title(fb, page.title());
+            render_network(fb, &screen.network);
+        }
+        Page::Alarms => {
+            render_title(fb, page.title());
+            render_alarms(fb, screen.alarms, screen.faults, screen.unit);
+        }
+        Page::Diagnostics => {
+            render_title(fb, page.title());
+            render_diagnostics(fb, &screen.diagnostics, screen.unit);
+        }
+    }
+}
+
+fn render_title(fb: &mut DisplayFramebuffer, title: &str) {
+    fb.draw_text(0, 0, title, 1);
+    fb.hline(0, TITLE_HEIGHT - 3, DisplayFramebuffer::WIDTH as i32);
+}
+
+/// Draws lines of text below title
+fn render_lines<'a>(fb: &mut DisplayFramebuffer, lines: impl IntoIterator<Item = &'a str>) {
+    for (idx, line) in lines.into_iter().enumerate() {
+        fb.draw_text(0, TITLE_HEIGHT + idx as i32 * 9, line, 1);
+    }
+}
+
+fn render_readings(
+    fb: &mut DisplayFramebuffer,
+    readings: &Readings,
+    network: &NetworkInfo,
+    unit: TemperatureUnit,
+) {
+    let mut line = Line::new();
+
+    write!(
+        line,
+        "{:.1}{}",
+        unit.from_celsius(readings.temperature),
+        unit.symbol()
+    )
+    .ok();
+    fb.draw_text(0, 0, &line, 2);
+
+    line.clear();
+    write!(line, "{:.1}%", readings.humidity).ok();
+    fb.draw_text(0, 18, &line, 2);
+
+    fb.hline(0, 37, DisplayFramebuffer::WIDTH as i32);
+
+    line.clear();
+    write!(line, "WiFi {}", network.wifi.as_str()).ok();
+    fb.draw_text(0, 42, &line, 1);
+
+    line.clear();
+    write_address(&mut line, "IP ", network.ip);
+    fb.draw_text(0, 54, &line, 1);
+}
+
+/// Draws labeled sparkline with min and max values, 26 pixels high
+///
+/// # Arguments
+/// - `convert` - converts values to shown unit, it must be linear to keep sparkline shape
+fn render_history(
+    fb: &mut DisplayFramebuffer,
+    y: i32,
+    label: &str,
+    values: &[f32],
+    convert: impl Fn(f32) -> f32,
+) {
+    const HEIGHT: i32 = 24;
+    const LABEL_WIDTH: i32 = 40;
+
+    let mut line = Line::new();
+    fb.draw_text(0, y + 1, label, 1);
+
+    let Some((min, max)) = min_max(values) else {
+        fb.draw_text(LABEL_WIDTH, y + HEIGHT / 2 - 3, "no data", 1);
+        return;
+    };
+
+    write!(line, "{:.1}", convert(max)).ok();
+    fb.draw_text(font::ADVANCE + 2, y + 1, &line, 1);
+    line.clear();
+    write!(line, "{:.1}", convert(min)).ok();
+    fb.draw_text(font::ADVANCE + 2, y + HEIGHT - font::GLYPH_HEIGHT, &line, 1);
+
+    draw_sparkline(
+        fb,
+        LABEL_WIDTH,
+        y,
+        DisplayFramebuffer::WIDTH as i32 - LABEL_WIDTH,
+        HEIGHT,
+        values,
+    );
+}
+
+/// Draws values as line graph scaled to fit the area
+pub fn draw_sparkline(
+    fb: &mut DisplayFramebuffer,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    values: &[f32],
+) {
+    let Some((min, max)) = min_max(values) else {
+        return;
+    };
+
+    let point = |idx: usize, value: f32| {
+        let px = if values.len() < 2 {
+            x + width / 2
+        } else {
+            x + idx as i32 * (width - 1) / (values.len() as i32 - 1)
+        };
+        let py = if max <= min {
+            y + height / 2
+        } else {
+            y + height - 1 - ((value - min) / (max - min) * (height - 1) as f32) as i32
+        };
+        (px, py)
+    };
+
+    let mut previous = point(0, values[0]);
+    fb.set_pixel(previous.0, previous.1, true);
+    for (idx, value) in values.iter().enumerate().skip(1) {
+        let current = point(idx, *value);
+        fb.line(previous, current);
+        previous = current;
+    }
+}
+
+fn render_network(fb: &mut DisplayFramebuffer, network: &NetworkInfo) {
+    let mut wifi = Line::new();
+    let mut ip = Line::new();
+    let mut gateway = Line::new();
+
+    write!(wifi, "WiFi {}", network.wifi.as_str()).ok();
+    write_address(&mut ip, "IP ", network.ip);
+    write_address(&mut gateway, "GW ", network.gateway);
+
+    render_lines(fb, [wifi.as_str(), ip.as_str(), gateway.as_str()]);
+}
+
+fn render_alarms(
+    fb: &mut DisplayFramebuffer,
+    alarms: &[Alarm],
+    faults: &[&'static str],
+    unit: TemperatureUnit,
+) {
+    if alarms.is_empty() && faults.is_empty() {
+        render_lines(fb, ["No alarms"]);
+        return;
+    }
+
+    let mut lines: heapless::Vec<Line, 5> = heapless::Vec::new();
+    for alarm in alarms {
+        let mut line = Line::new();
+        write!(
+            line,
+            "{} {} {:.1}",
+            alarm.quantity.as_str(),
+            alarm.kind.as_str(),
+            match alarm.quantity {
+                Quantity::Temperature => unit.from_celsius(alarm.value),
+                _ => alarm.value,
+            }
+        )
+        .ok();
+        if lines.push(line).is_err() {
+            break;
+        }
+    }
+    for sensor in faults {
+        let mut line = Line::new();
+        write!(line, "{} fault", sensor).ok();
+        if lines.push(line).is_err() {
+            break;
+        }
+    }
+
+    render_lines(fb, lines.iter().map(|line| line.as_str()));
+}
+
+fn render_diagnostics(
+    fb: &mut DisplayFramebuffer,
+    diagnostics: &Diagnostics,
+    unit: TemperatureUnit,
+) {
+    let mut uptime = Line::new();
+    let mut load = Line::new();
+    let mut heap = Line::new();
+    let mut webhooks = Line::new();
+
+    let s = diagnostics.uptime_s;
+    write!(
+        uptime,
+        "Up {}d {:02}:{:02}",
+        s / 86400,
+        s / 3600 % 24,
+        s / 60 % 60
+    )
+    .ok();
+    write!(load, "CPU {}%", diagnostics.cpu_load).ok();
+    if let Some(chip_temperature) = diagnostics.chip_temperature {
+        write!(
+            load,
+            " {:.0}{}",
+            unit.from_celsius(chip_temperature),
+            unit.symbol()
+        )
+        .ok();
+    }
+    write!(
+        heap,
+        "Heap {}/{}K",
+        diagnostics.heap_used / 1024,
+        (diagnostics.heap_used + diagnostics.heap_free) / 1024
+    )
+    .ok();
+    write!(
+        webhooks,
+        "Hooks {} ok {} err",
+        diagnostics.webhooks_delivered, diagnostics.webhooks_failed
+    )
+    .ok();
+
+    render_lines(
+        fb,
+        [
+            uptime.as_str(),
+            load.as_str(),
+            heap.as_str(),
+            webhooks.as_str(),
+        ],
+    );
+}
+
+fn write_address(line: &mut Line, label: &str, address: Option<Ipv4Address>) {
+    match address {
+        Some(address) => write!(line, "{}{}", label, address).ok(),
+        None => write!(line, "{}-", label).ok(),
+    };
+}
+
+fn min_max(values: &[f32]) -> Option<(f32, f32)> {
+    values
+        .iter()
+        .filter(|value| !value.is_nan())
+        .fold(None, |acc, value| match acc {
+            None => Some((*value, *value)),
+            Some((min, max)) => Some((min.min(*value), max.max(*value))),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIAGNOSTICS: Diagnostics = Diagnostics {
+        uptime_s: 90_061,
+        cpu_load: 12,
+        chip_temperature: Some(41.0),
+        heap_used: 10 * 1024,
+        heap_free: 54 * 1024,
+        webhooks_delivered: 3,
+        webhooks_failed: 1,
+    };
+
+    fn screen(readings: Readings) -> Screen<'static> {
+        Screen {
+            unit: TemperatureUnit::Celsius,
+            readings,
+            temperature_history: &[],
+            humidity_history: &[],
+            network: NetworkInfo {
+                wifi: WifiState::Connected,
+                ip: Some(Ipv4Address::new(192, 168, 1, 2)),
+                gateway: Some(Ipv4Address::new(192, 168, 1, 1)),
+            },
+            alarms: &[],
+            faults: &[],
+            diagnostics: DIAGNOSTICS,
+        }
+    }
+
+    const READINGS: Readings = Readings {
+        temperature: 21.5,
+        humidity: 45.0,
+    };
+
+    fn rendered(page: Page, screen: &Screen<'_>) -> DisplayFramebuffer {
+        let mut fb = DisplayFramebuffer::new();
+        render(&mut fb, page, screen);
+        fb
+    }
+
+    fn assert_same(actual: &DisplayFramebuffer, expected: &DisplayFramebuffer) {
+        for page in 0..8 {
+            assert_eq!(actual.page(page), expected.page(page), "page {page}");
+        }
+    }
+
+    fn dirty_pages(fb: &DisplayFramebuffer) -> heapless::Vec<usize, 8> {
+        (0..8).filter(|page| fb.dirty(*page).is_some()).collect()
+    }
+
+    #[test]
+    fn pages_rotate() {
+        let mut page = Page::Readings;
+        for expected in [
+            Page::History,
+            Page::Network,
+            Page::Alarms,
+            Page::Diagnostics,
+            Page::Readings,
+        ] {
+            page = page.next();
+            assert_eq!(page, expected);
+        }
+    }
+
+    #[test]
+    fn readings_layout() {
+        let mut expected = DisplayFramebuffer::new();
+        expected.draw_text(0, 0, "21.5°C", 2);
+        expected.draw_text(0, 18, "45.0%", 2);
+        expected.hline(0, 37, 128);
+        expected.draw_text(0, 42, "WiFi connected", 1);
+        expected.draw_text(0, 54, "IP 192.168.1.2", 1);
+
+        assert_same(&rendered(Page::Readings, &screen(READINGS)), &expected);
+    }
+
+    #[test]
+    fn readings_in_unit() {
+        let mut fahrenheit = screen(READINGS);
+        fahrenheit.unit = TemperatureUnit::Fahrenheit;
+
+        let mut expected = rendered(Page::Readings, &screen(READINGS));
+        expected.fill_rect(0, 0, 128, 14, false);
+        expected.draw_text(0, 0, "70.7°F", 2);
+
+        assert_same(&rendered(Page::Readings, &fahrenheit), &expected);
+    }
+
+    #[test]
+    fn titled_page_layout() {
+        let mut expected = DisplayFramebuffer::new();
+        expected.draw_text(0, 0, "Network", 1);
+        expected.hline(0, 8, 128);
+        expected.draw_text(0, 11, "WiFi connected", 1);
+        expected.draw_text(0, 20, "IP 192.168.1.2", 1);
+        expected.draw_text(0, 29, "GW 192.168.1.1", 1);
+
+        assert_same(&rendered(Page::Network, &screen(READINGS)), &expected);
+    }
+
+    #[test]
+    fn diagnostics_layout() {
+        let mut expected = DisplayFramebuffer::new();
+        expected.draw_text(0, 0, "Diagnostics", 1);
+        expected.hline(0, 8, 128);
+        expected.draw_text(0, 11, "Up 1d 01:01", 1);
+        expected.draw_text(0, 20, "CPU 12% 41°C", 1);
+        expected.draw_text(0, 29, "Heap 10/64K", 1);
+        expected.draw_text(0, 38, "Hooks 3 ok 1 err", 1);
+
+        assert_same(&rendered(Page::Diagnostics, &screen(READINGS)), &expected);
+    }
+
+    #[test]
+    fn alarms_without_any() {
+        let mut expected = DisplayFramebuffer::new();
+        expected.draw_text(0, 0, "Alarms", 1);
+        expected.hline(0, 8, 128);
+        expected.draw_text(0, 11, "No alarms", 1);
+
+        assert_same(&rendered(Page::Alarms, &screen(READINGS)), &expected);
+    }
+
+    #[test]
+    fn history_without_data() {
+        let mut expected = DisplayFramebuffer::new();
+        expected.draw_text(0, 0, "History 24h", 1);
+        expected.hline(0, 8, 128);
+        expected.draw_text(0, 12, "T", 1);
+        expected.draw_text(40, 20, "no data", 1);
+        expected.draw_text(0, 39, "H", 1);
+        expected.draw_text(40, 47, "no data", 1);
+
+        assert_same(&rendered(Page::History, &screen(READINGS)), &expected);
+    }
+
+    #[test]
+    fn flat_sparkline_is_centered() {
+        let mut fb = DisplayFramebuffer::new();
+        draw_sparkline(&mut fb, 10, 0, 11, 9, &[5.0, 5.0, 5.0]);
+
+        let mut expected = DisplayFramebuffer::new();
+        expected.hline(10, 4, 11);
+        assert_same(&fb, &expected);
+    }
+
+    #[test]
+    fn sparkline_spans_area() {
+        let mut fb = DisplayFramebuffer::new();
+        draw_sparkline(&mut fb, 0, 0, 5, 5, &[0.0, f32::NAN, 4.0]);
+
+        assert!(fb.pixel(0, 4));
+        assert!(fb.pixel(4, 0));
+        assert!(!fb.pixel(0, 0));
+        assert!(!fb.pixel(4, 4));
+    }
+
+    #[test]
+    fn rendering_same_screen_leaves_nothing_dirty() {
+        let mut fb = rendered(Page::Readings, &screen(READINGS));
+        fb.clear_dirty();
+
+        render(&mut fb, Page::Readings, &screen(READINGS));
+        assert!(dirty_pages(&fb).is_empty());
+    }
+
+    #[test]
+    fn changed_value_dirties_its_pages_only() {
+        let mut fb = rendered(Page::Readings, &screen(READINGS));
+        fb.clear_dirty();
+
+        render(
+            &mut fb,
+            Page::Readings,
+            &screen(Readings {
+                humidity: 46.0,
+                ..READINGS
+            }),
+        );
+        assert_eq!(dirty_pages(&fb), [2, 3]);
+        // Only "6" of "46.0%" differs
+        let (start, end) = fb.dirty(2).unwrap();
+        assert!(12 <= start && end <= 24);
+    }
+
+    #[test]
+    fn min_max_skips_missing() {
+        assert_eq!(min_max(&[]), None);
+        assert_eq!(min_max(&[f32::NAN]), None);
+        assert_eq!(min_max(&[3.0, f32::NAN, -1.0, 2.0]), Some((-1.0, 3.0)));
+    }
+}
@@ -0,0 +1,109 @@
+//!
+//! Temperature units
+//!
+//! Temperatures are kept in °C everywhere inside, they are converted only when
+//! shown or sent.
+//!
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, defmt::Format)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl TemperatureUnit {
+    pub fn from_celsius(&self, celsius: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+            TemperatureUnit::Kelvin => celsius + 273.15,
+        }
+    }
+
+    pub fn to_celsius(&self, value: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+            TemperatureUnit::Kelvin => value - 273.15,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+            TemperatureUnit::Kelvin => "K",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNITS: [TemperatureUnit; 3] = [
+        TemperatureUnit::Celsius,
+        TemperatureUnit::Fahrenheit,
+        TemperatureUnit::Kelvin,
+    ];
+
+    fn assert_near(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 1e-3, "{value} is not {expected}");
+    }
+
+    #[test]
+    fn converts_known_points() {
+        for (celsius, fahrenheit, kelvin) in [
+            (0.0, 32.0, 273.15),
+            (100.0, 212.0, 373.15),
+            (-40.0, -40.0, 233.15),
+            (-273.15, -459.67, 0.0),
+        ] {
+            assert_eq!(TemperatureUnit::Celsius.from_celsius(celsius), celsius);
+            assert_near(
+                TemperatureUnit::Fahrenheit.from_celsius(celsius),
+                fahrenheit,
+            );
+            assert_near(TemperatureUnit::Kelvin.from_celsius(celsius), kelvin);
+
+            assert_eq!(TemperatureUnit::Celsius.to_celsius(celsius), celsius);
+            assert_near(TemperatureUnit::Fahrenheit.to_celsius(fahrenheit), celsius);
+            assert_near(TemperatureUnit::Kelvin.to_celsius(kelvin), celsius);
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for unit in UNITS {
+            for celsius in [-40.0, -12.3, 0.0, 21.7, 36.6, 125.0] {
+                assert_near(unit.to_celsius(unit.from_celsius(celsius)), celsius);
+            }
+        }
+    }
+
+    #[test]
+    fn symbols_and_names() {
+        let mut json = [0_u8; 16];
+        for (unit, symbol, name) in [
+            (TemperatureUnit::Celsius, "°C", r#""celsius""#),
+            (TemperatureUnit::Fahrenheit, "°F", r#""fahrenheit""#),
+            (TemperatureUnit::Kelvin, "K", r#""kelvin""#),
+        ] {
+            assert_eq!(unit.symbol(), symbol);
+
+            let len = serde_json_core::to_slice(&unit, &mut json).unwrap();
+            assert_eq!(&json[..len], name.as_bytes());
+            assert_eq!(
+                serde_json_core::from_str::<TemperatureUnit>(name)
+                    .unwrap()
+                    .0,
+                unit
+            );
+        }
+    }
+}
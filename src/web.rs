@@ -1,11 +1,38 @@
+pub mod auth;
 mod routes;
 
-use embassy_net::Stack;
-use embassy_time::Duration;
+use core::net::IpAddr;
+
+use defmt::{warn, Debug2Format};
+use embassy_net::{tcp::TcpSocket, Stack};
+use embassy_time::{with_timeout, Duration};
 use esp_alloc as _;
+use esp_hal::rng::Rng;
 use picoserve::{response::File, routing, AppRouter, AppWithStateBuilder, Router};
 
-use crate::sync::mutex::AtomicMutex;
+use heapless::Vec;
+use serde::Serialize;
+
+use self::auth::{RequireAuth, SharedAuth};
+use crate::{
+    auth::Policy,
+    battery::BatteryStatus,
+    boards::esp32::esp32_c6::Flash,
+    calibration::SharedCalibratedReadings,
+    discovery::SharedInventory,
+    drivers::{i2c::stats::SharedI2cStats, onewire::Rom},
+    net::webhook::SharedWebhookStatus,
+    ota::SharedOta,
+    settings::SharedSettings,
+    sync::mutex::AtomicMutex,
+    tls::{
+        self,
+        identity::{Identity, PemDecoder},
+    },
+};
+
+/// Max count of 1-Wire temperature probes
+pub const MAX_PROBES: usize = 8;
 
 #[derive(Clone)]
 pub struct SharedTemp(&'static AtomicMutex<f32>);
@@ -40,9 +67,159 @@ impl SharedHumidity {
     }
 }
 
+/// CO2 concentration in ppm, None if there is no CO2 sensor
+#[derive(Clone)]
+pub struct SharedCo2(&'static AtomicMutex<Option<u16>>);
+
+impl SharedCo2 {
+    pub fn new(m: &'static AtomicMutex<Option<u16>>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> Option<u16> {
+        *self.0.lock().await
+    }
+
+    pub async fn set(&self, co2: Option<u16>) {
+        *self.0.lock().await = co2;
+    }
+}
+
+/// Air pressure in hPa, None if there is no barometer
+#[derive(Clone)]
+pub struct SharedPressure(&'static AtomicMutex<Option<f32>>);
+
+impl SharedPressure {
+    pub fn new(m: &'static AtomicMutex<Option<f32>>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> Option<f32> {
+        *self.0.lock().await
+    }
+
+    pub async fn set(&self, pressure: Option<f32>) {
+        *self.0.lock().await = pressure;
+    }
+}
+
+/// Temperature of the MCU die in °C, None until the first measurement
+#[derive(Clone)]
+pub struct SharedChipTemp(&'static AtomicMutex<Option<f32>>);
+
+impl SharedChipTemp {
+    pub fn new(m: &'static AtomicMutex<Option<f32>>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> Option<f32> {
+        *self.0.lock().await
+    }
+
+    pub async fn set(&self, temp: Option<f32>) {
+        *self.0.lock().await = temp;
+    }
+}
+
+/// None if battery is not monitored
+#[derive(Clone)]
+pub struct SharedBattery(&'static AtomicMutex<Option<BatteryStatus>>);
+
+impl SharedBattery {
+    pub fn new(m: &'static AtomicMutex<Option<BatteryStatus>>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> Option<BatteryStatus> {
+        *self.0.lock().await
+    }
+
+    pub async fn set(&self, battery: Option<BatteryStatus>) {
+        *self.0.lock().await = battery;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ProbeReading {
+    pub rom: Rom,
+    /// In °C, None if the last read failed
+    pub temperature: Option<f32>,
+}
+
+#[derive(Clone)]
+pub struct SharedProbes(&'static AtomicMutex<Vec<ProbeReading, MAX_PROBES>>);
+
+impl SharedProbes {
+    pub fn new(m: &'static AtomicMutex<Vec<ProbeReading, MAX_PROBES>>) -> Self {
+        Self(m)
+    }
+
+    pub async fn get(&self) -> Vec<ProbeReading, MAX_PROBES> {
+        self.0.lock().await.clone()
+    }
+
+    pub async fn set(&self, probes: Vec<ProbeReading, MAX_PROBES>) {
+        *self.0.lock().await = probes;
+    }
+}
+
+/// Certificate served on HTTPS, and uploaded one that replaces it after restart
+#[derive(Clone)]
+pub struct SharedTls {
+    identity: Option<&'static Identity>,
+    upload: &'static AtomicMutex<PemDecoder>,
+    flash: &'static AtomicMutex<Flash>,
+}
+
+impl SharedTls {
+    /// # Arguments
+    /// - `identity` - None if HTTPS is disabled
+    pub fn new(
+        identity: Option<&'static Identity>,
+        upload: &'static AtomicMutex<PemDecoder>,
+        flash: &'static AtomicMutex<Flash>,
+    ) -> Self {
+        Self {
+            identity,
+            upload,
+            flash,
+        }
+    }
+
+    pub fn identity(&self) -> Option<&'static Identity> {
+        self.identity
+    }
+
+    pub fn upload(&self) -> &'static AtomicMutex<PemDecoder> {
+        self.upload
+    }
+
+    pub fn flash(&self) -> &'static AtomicMutex<Flash> {
+        self.flash
+    }
+}
+
+#[derive(Clone)]
 pub struct AppState {
     pub temp: SharedTemp,
     pub humidity: SharedHumidity,
+    pub co2: SharedCo2,
+    pub pressure: SharedPressure,
+    pub chip_temp: SharedChipTemp,
+    pub battery: SharedBattery,
+    pub probes: SharedProbes,
+    pub webhooks: SharedWebhookStatus,
+    pub i2c: SharedI2cStats,
+    pub devices: SharedInventory,
+    pub ota: SharedOta<Flash>,
+    pub auth: SharedAuth,
+    pub settings: SharedSettings<Flash>,
+    pub calibrated: SharedCalibratedReadings,
+    pub tls: SharedTls,
+    /// Salts of credentials
+    pub rng: Rng,
+    /// Address of connected client, set for each connection
+    pub client: IpAddr,
 }
 
 impl picoserve::extract::FromRef<AppState> for SharedTemp {
@@ -57,6 +234,90 @@ impl picoserve::extract::FromRef<AppState> for SharedHumidity {
     }
 }
 
+impl picoserve::extract::FromRef<AppState> for SharedCo2 {
+    fn from_ref(state: &AppState) -> Self {
+        state.co2.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedPressure {
+    fn from_ref(state: &AppState) -> Self {
+        state.pressure.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedChipTemp {
+    fn from_ref(state: &AppState) -> Self {
+        state.chip_temp.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedBattery {
+    fn from_ref(state: &AppState) -> Self {
+        state.battery.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedProbes {
+    fn from_ref(state: &AppState) -> Self {
+        state.probes.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedWebhookStatus {
+    fn from_ref(state: &AppState) -> Self {
+        state.webhooks.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedI2cStats {
+    fn from_ref(state: &AppState) -> Self {
+        state.i2c.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedInventory {
+    fn from_ref(state: &AppState) -> Self {
+        state.devices.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedOta<Flash> {
+    fn from_ref(state: &AppState) -> Self {
+        state.ota.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedSettings<Flash> {
+    fn from_ref(state: &AppState) -> Self {
+        state.settings.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedAuth {
+    fn from_ref(state: &AppState) -> Self {
+        state.auth.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for Rng {
+    fn from_ref(state: &AppState) -> Self {
+        state.rng
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedCalibratedReadings {
+    fn from_ref(state: &AppState) -> Self {
+        state.calibrated.clone()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for SharedTls {
+    fn from_ref(state: &AppState) -> Self {
+        state.tls.clone()
+    }
+}
+
 pub struct Application;
 
 impl AppWithStateBuilder for Application {
@@ -77,13 +338,96 @@ impl AppWithStateBuilder for Application {
                 "/index.js",
                 routing::get_service(File::javascript(include_str!("web/data/index.js"))),
             )
+            .route("/unit", routing::get(routes::get_unit))
             .route("/temperature", routing::get(routes::get_temperature))
             .route("/humidity", routing::get(routes::get_humidity))
+            .route("/psychrometrics", routing::get(routes::get_psychrometrics))
+            .route("/co2", routing::get(routes::get_co2))
+            .route("/pressure", routing::get(routes::get_pressure))
+            .route(
+                "/chip-temperature",
+                routing::get(routes::get_chip_temperature),
+            )
+            .route("/battery", routing::get(routes::get_battery))
+            .route("/probes", routing::get(routes::get_probes))
+            .route("/webhooks", routing::get(routes::get_webhooks))
+            .route("/i2c", routing::get(routes::get_i2c))
+            .route("/devices", routing::get(routes::get_devices))
+            .route(
+                "/ota",
+                routing::get(routes::get_ota)
+                    .post_service(routes::OtaUpload)
+                    .layer(RequireAuth(Policy::ADMIN)),
+            )
+            .route(
+                "/settings",
+                routing::get_service(File::html(include_str!("web/data/settings.html")))
+                    .layer(RequireAuth(Policy::ADMIN)),
+            )
+            .route(
+                "/settings.js",
+                routing::get_service(File::javascript(include_str!("web/data/settings.js"))),
+            )
+            .route(
+                "/api/settings",
+                routing::get(routes::get_settings)
+                    .post(routes::post_settings)
+                    .layer(RequireAuth(Policy::ADMIN)),
+            )
+            .route(
+                "/api/calibration",
+                routing::get(routes::get_calibration)
+                    .post(routes::post_calibration)
+                    .layer(RequireAuth(Policy::PUBLIC_READ)),
+            )
+            .route(
+                "/api/credentials",
+                routing::post(routes::post_credentials).layer(RequireAuth(Policy::SETUP)),
+            )
+            .route(
+                "/api/tls",
+                routing::get(routes::get_tls)
+                    .post_service(routes::TlsUpload)
+                    .layer(RequireAuth(Policy::ADMIN)),
+            )
     }
 }
 
 pub const WEB_TASK_POOL_SIZE: usize = 2;
 
+/// Longest TLS handshake, a client that stalls it holds the task
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Buffers of one web task
+///
+/// Static, as the TLS receive buffer alone is over 16 KiB. They would take most of
+/// the task arena, and the heap is left to Wi-Fi.
+pub struct WebBuffers {
+    tcp_rx: [u8; 1024],
+    tcp_tx: [u8; 1024],
+    /// Fits settings with every field at its longest
+    http: [u8; 4096],
+    tls: tls::Buffers,
+}
+
+impl Default for WebBuffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebBuffers {
+    pub const fn new() -> Self {
+        Self {
+            tcp_rx: [0; 1024],
+            tcp_tx: [0; 1024],
+            http: [0; 4096],
+            tls: tls::Buffers::new(),
+        }
+    }
+}
+
+/// Serves web interface on HTTP, or HTTPS if there is a certificate
 #[embassy_executor::task(pool_size = WEB_TASK_POOL_SIZE)]
 pub async fn web_task(
     id: usize,
@@ -91,24 +435,67 @@ pub async fn web_task(
     router: &'static AppRouter<Application>,
     config: &'static picoserve::Config<Duration>,
     state: &'static AppState,
+    buffers: &'static mut WebBuffers,
 ) -> ! {
-    let port = 80;
-    let mut tcp_rx_buffer = [0; 1024];
-    let mut tcp_tx_buffer = [0; 1024];
-    let mut http_buffer = [0; 2048];
-
-    picoserve::listen_and_serve_with_state(
-        id,
-        router,
-        config,
-        stack,
-        port,
-        &mut tcp_rx_buffer,
-        &mut tcp_tx_buffer,
-        &mut http_buffer,
-        state,
-    )
-    .await;
+    let identity = state.tls.identity();
+    let port = if identity.is_some() { 443 } else { 80 };
+    let WebBuffers {
+        tcp_rx,
+        tcp_tx,
+        http,
+        tls: tls_buffers,
+    } = buffers;
+
+    loop {
+        let mut socket = TcpSocket::new(stack, tcp_rx, tcp_tx);
+        if let Err(err) = socket.accept(port).await {
+            warn!("web {}: accept failed: {}", id, err);
+            continue;
+        }
+        let Some(remote) = socket.remote_endpoint() else {
+            continue;
+        };
+
+        // Failed logins are counted per client
+        let state = AppState {
+            client: remote.addr.into(),
+            ..state.clone()
+        };
+
+        let Some(identity) = identity else {
+            if let Err(err) =
+                picoserve::serve_with_state(router, config, http, socket, &state).await
+            {
+                warn!("web {}: {}", id, Debug2Format(&err));
+            }
+            continue;
+        };
+
+        let mut rng = state.rng;
+        let mut random = [0; 32];
+        let mut secret = [0; 32];
+        rng.read(&mut random);
+        rng.read(&mut secret);
+        let socket = match with_timeout(
+            HANDSHAKE_TIMEOUT,
+            tls::accept(socket, identity, tls_buffers, random, secret),
+        )
+        .await
+        {
+            Ok(Ok(socket)) => socket,
+            Ok(Err(err)) => {
+                warn!("web {}: TLS handshake failed: {}", id, err);
+                continue;
+            }
+            Err(_) => {
+                warn!("web {}: TLS handshake timed out", id);
+                continue;
+            }
+        };
+        if let Err(err) = picoserve::serve_with_state(router, config, http, socket, &state).await {
+            warn!("web {}: {}", id, Debug2Format(&err));
+        }
+    }
 }
 
 pub struct WebApp {
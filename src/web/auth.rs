@@ -0,0 +1,113 @@
+//!
+//! Authentication middleware of web UI and API
+//!
+
+use core::net::IpAddr;
+
+use embassy_time::Instant;
+use picoserve::{
+    io::Read,
+    request::RequestParts,
+    response::{IntoResponse, Response, ResponseWriter, StatusCode},
+    routing::{Layer, Next},
+    ResponseSent,
+};
+
+use crate::{
+    auth::{Access, Auth, Policy, Verdict},
+    settings::Credentials,
+    sync::mutex::AtomicMutex,
+    web::AppState,
+};
+
+const REALM: &str = "Basic realm=\"esp-temperature\"";
+
+#[derive(Clone)]
+pub struct SharedAuth(&'static AtomicMutex<Auth>);
+
+impl SharedAuth {
+    pub fn new(m: &'static AtomicMutex<Auth>) -> Self {
+        Self(m)
+    }
+
+    pub async fn check(&self, authorization: Option<&[u8]>, client: IpAddr) -> Verdict {
+        let now_ms = Instant::now().as_millis();
+        self.0.lock().await.check(authorization, client, now_ms)
+    }
+
+    /// Lets anyone set the first credentials for a while
+    ///
+    /// # Returns
+    /// false, if credentials are set already
+    pub async fn open_setup(&self) -> bool {
+        let now_ms = Instant::now().as_millis();
+        self.0.lock().await.open_setup(now_ms)
+    }
+
+    /// Replaces credentials and forgets failed attempts
+    pub async fn set_credentials(&self, credentials: Credentials) {
+        *self.0.lock().await = Auth::new(credentials);
+    }
+}
+
+/// Middleware rejecting requests which do not satisfy policy
+pub struct RequireAuth(pub Policy);
+
+impl<PathParameters> Layer<AppState, PathParameters> for RequireAuth {
+    type NextState = AppState;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &AppState,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let required = self.0.required(request_parts.method());
+        if required == Access::Public {
+            return next.run(state, path_parameters, response_writer).await;
+        }
+
+        let authorization = request_parts.headers().get("Authorization");
+        let verdict = state
+            .auth
+            .check(authorization.map(|value| value.as_raw()), state.client)
+            .await;
+
+        let connection = match (verdict, required) {
+            (Verdict::Granted, _) | (Verdict::Setup, Access::Setup) => {
+                return next.run(state, path_parameters, response_writer).await
+            }
+            _ => next.into_connection().await?,
+        };
+        match verdict {
+            Verdict::Locked => {
+                (StatusCode::TOO_MANY_REQUESTS, "Too many failed attempts\n")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+            Verdict::Disabled | Verdict::Setup => {
+                (
+                    StatusCode::FORBIDDEN,
+                    "No admin credentials are configured, press BOOT button on the device \
+                     and set them at /api/credentials within 5 minutes\n",
+                )
+                    .write_to(connection, response_writer)
+                    .await
+            }
+            _ => {
+                Response::new(StatusCode::UNAUTHORIZED, "Authentication required\n")
+                    .with_headers(("WWW-Authenticate", REALM))
+                    .write_to(connection, response_writer)
+                    .await
+            }
+        }
+    }
+}
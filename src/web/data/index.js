@@ -1,10 +1,32 @@
+const UNIT_SYMBOLS = { celsius: '°C', fahrenheit: '°F', kelvin: 'K' };
+
+// Dashboard URL may select unit, e.g. /?unit=fahrenheit, device settings are used otherwise
+async function temperatureUnit() {
+    const unit = new URLSearchParams(window.location.search).get('unit');
+    if (unit in UNIT_SYMBOLS) {
+        return unit;
+    }
+    const unitResponse = await fetch('/unit');
+    return await unitResponse.json();
+}
+
 async function updateReadings() {
     let temperature = null;
     let humidity = null;
 
+    let unit = 'celsius';
+    try {
+        unit = await temperatureUnit();
+    } catch (error) {
+        console.error('Error fetching temperature unit:', error);
+    }
+    for (const element of document.getElementsByClassName('temperature-unit')) {
+        element.textContent = UNIT_SYMBOLS[unit];
+    }
+
     // Fetch Temperature
     try {
-        const temperatureResponse = await fetch('/temperature');
+        const temperatureResponse = await fetch(`/temperature?unit=${unit}`);
         temperature = parseFloat(await temperatureResponse.text()); // Parse as float
         document.getElementById('temperature-value').textContent = temperature.toFixed(1); // Display with 1 decimal
     } catch (error) {
@@ -22,31 +44,69 @@ async function updateReadings() {
         document.getElementById('humidity-value').textContent = 'Error';
     }
 
-    // Calculate and Display Dew Point if both values were fetched successfully
-    if (temperature !== null && humidity !== null && !isNaN(temperature) && !isNaN(humidity)) {
-        // Calculate Dew Point using an approximation of the Magnus-Tetens equation for better accuracy
-        // Formula: T_dew = T - ((100 - RH) / 5) is a simplification.
-        // A more accurate formula is based on the Clausius-Clapeyron relation.
-        // One common approximation is:
-        // T_dew = T - ((100 - %RH) / 5) <-- Original simplified formula
-        // A more complex but accurate version involves the saturation vapor pressure.
-        // Let's use an established approximation:
-        // e_s(T) = 0.6108 * exp((17.27 * T) / (T + 237.3))  [Saturation vapor pressure in kPa]
-        // e(T, RH) = e_s(T) * (RH / 100)                  [Actual vapor pressure]
-        // T_dew = (237.3 * ln(e(T, RH) / 0.6108)) / (17.27 - ln(e(T, RH) / 0.6108)) [Dew point temperature in °C]
-
-        // For practical purposes in JavaScript, we can use a simplified but more accurate formula
-        // derived from the above:
-        const a = 17.27;
-        const b = 237.7;
-        const gamma = ((humidity / 100) * 6.112 * Math.exp((a * temperature) / (b + temperature))) / 6.112;
-        const dewPoint = (b * Math.log(gamma)) / (a - Math.log(gamma));
-        document.getElementById('dewpoint-value').textContent = dewPoint.toFixed(1); // Display with 1 decimal
-    } else {
-        // If either value failed or is invalid, show N/A or Error for dew point
-        if (document.getElementById('dewpoint-value')) { // Check if element exists before trying to update
-            document.getElementById('dewpoint-value').textContent = 'N/A';
+    // Fetch CO2, shown only if device has CO2 sensor
+    try {
+        const co2Response = await fetch('/co2');
+        const co2 = await co2Response.json();
+        document.getElementById('co2').hidden = co2 === null;
+        if (co2 !== null) {
+            document.getElementById('co2-value').textContent = co2;
+        }
+    } catch (error) {
+        console.error('Error fetching CO2:', error);
+        document.getElementById('co2-value').textContent = 'Error';
+    }
+
+    // Fetch pressure, shown only if device has a barometer
+    try {
+        const pressureResponse = await fetch('/pressure');
+        const pressure = await pressureResponse.json();
+        document.getElementById('pressure').hidden = pressure === null;
+        if (pressure !== null) {
+            document.getElementById('pressure-value').textContent = pressure.toFixed(1);
         }
+    } catch (error) {
+        console.error('Error fetching pressure:', error);
+        document.getElementById('pressure-value').textContent = 'Error';
+    }
+
+    // Fetch battery, shown only if device monitors it
+    try {
+        const batteryResponse = await fetch('/battery');
+        const battery = await batteryResponse.json();
+        document.getElementById('battery').hidden = battery === null;
+        if (battery !== null) {
+            document.getElementById('battery-value').textContent =
+                `${battery.percentage} (${battery.voltage.toFixed(2)} V)${battery.low ? ' LOW' : ''}`;
+        }
+    } catch (error) {
+        console.error('Error fetching battery:', error);
+        document.getElementById('battery-value').textContent = 'Error';
+    }
+
+    // Fetch chip temperature, high values mean the board heats its sensors
+    try {
+        const chipResponse = await fetch(`/chip-temperature?unit=${unit}`);
+        const chipTemperature = await chipResponse.json();
+        document.getElementById('chip-temperature-value').textContent =
+            chipTemperature === null ? 'N/A' : chipTemperature.toFixed(1);
+    } catch (error) {
+        console.error('Error fetching chip temperature:', error);
+        document.getElementById('chip-temperature-value').textContent = 'Error';
+    }
+
+    // Fetch dew point and VPD computed by device
+    try {
+        const psychrometricsResponse = await fetch(`/psychrometrics?unit=${unit}`);
+        const psychrometrics = await psychrometricsResponse.json();
+        document.getElementById('dewpoint-value').textContent =
+            psychrometrics === null ? 'N/A' : psychrometrics.dew_point.toFixed(1);
+        document.getElementById('vpd-value').textContent =
+            psychrometrics === null ? 'N/A' : psychrometrics.vpd.toFixed(2);
+    } catch (error) {
+        console.error('Error fetching psychrometrics:', error);
+        document.getElementById('dewpoint-value').textContent = 'Error';
+        document.getElementById('vpd-value').textContent = 'Error';
     }
 }
 
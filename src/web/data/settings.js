@@ -0,0 +1,250 @@
+const form = document.getElementById('settings-form');
+const result = document.getElementById('settings-result');
+
+// Settings not shown by form, e.g. calibration, are sent back as loaded
+let loaded = {};
+
+const ALARM_FIELDS = ['temperature_high', 'temperature_low', 'humidity_high', 'humidity_low', 'battery_low'];
+const TEMPERATURE_FIELDS = ['temperature_high', 'temperature_low', 'led_temperature_low', 'led_temperature_high'];
+const UNIT_SYMBOLS = { celsius: '°C', fahrenheit: '°F', kelvin: 'K' };
+
+const TO_CELSIUS = {
+    celsius: (value) => value,
+    fahrenheit: (value) => (value - 32) * 5 / 9,
+    kelvin: (value) => value - 273.15,
+};
+const FROM_CELSIUS = {
+    celsius: (value) => value,
+    fahrenheit: (value) => value * 9 / 5 + 32,
+    kelvin: (value) => value + 273.15,
+};
+
+// Thresholds are exchanged in settings unit, shown values follow selected unit
+let shownUnit = 'celsius';
+
+function showUnit(unit) {
+    for (const element of document.getElementsByClassName('temperature-unit')) {
+        element.textContent = UNIT_SYMBOLS[unit];
+    }
+    shownUnit = unit;
+}
+
+function optionalNumber(value) {
+    return value === '' ? null : parseFloat(value);
+}
+
+function optionalText(value) {
+    return value === '' ? null : value;
+}
+
+async function loadSettings() {
+    try {
+        const response = await fetch('/api/settings');
+        const settings = await response.json();
+        loaded = settings;
+
+        form.sample_interval_s.value = settings.sample_interval_s;
+        form.unit.value = settings.unit;
+        showUnit(settings.unit);
+        for (const field of ALARM_FIELDS) {
+            form[field].value = settings.alarms[field] ?? '';
+        }
+        form.ssid.value = settings.network.ssid;
+        form.https.value = settings.network.https;
+        form.webhooks.value = settings.webhooks.join('\n');
+        form.led_mode.value = settings.led.mode;
+        form.led_brightness.value = settings.led.brightness;
+        form.led_current_limit.value = settings.led.current_limit;
+        form.led_gradient.value = settings.led.gradient;
+        form.led_temperature_low.value = settings.led.temperature_range.low;
+        form.led_temperature_high.value = settings.led.temperature_range.high;
+        form.led_humidity_low.value = settings.led.humidity_range.low;
+        form.led_humidity_high.value = settings.led.humidity_range.high;
+        form.led_strip.value = settings.led.strip ?? '';
+        form.led_strip_chip.value = settings.led.strip_chip;
+        form.led_strip_order.value = settings.led.strip_order;
+        form.self_heating_warning.value = settings.self_heating.warning;
+        form.self_heating_compensation.value = settings.self_heating.compensation;
+        form.display.value = settings.hardware.display;
+        form.battery_divider.value = settings.hardware.battery_divider ?? '';
+        form.power_mode.value = settings.power.mode;
+        form.upload_url.value = settings.power.upload_url ?? '';
+        form.sleep_interval_s.value = settings.power.sleep_interval_s;
+        form.upload_every.value = settings.power.upload_every;
+        form.health_timeout_s.value = settings.ota.health_timeout_s;
+    } catch (error) {
+        console.error('Error fetching settings:', error);
+        result.textContent = 'Failed to load settings';
+    }
+}
+
+form.unit.addEventListener('change', () => {
+    const unit = form.unit.value;
+    for (const field of TEMPERATURE_FIELDS) {
+        if (form[field].value !== '') {
+            const celsius = TO_CELSIUS[shownUnit](parseFloat(form[field].value));
+            form[field].value = FROM_CELSIUS[unit](celsius).toFixed(1);
+        }
+    }
+    showUnit(unit);
+});
+
+form.addEventListener('submit', async (event) => {
+    event.preventDefault();
+
+    const alarms = {};
+    for (const field of ALARM_FIELDS) {
+        alarms[field] = optionalNumber(form[field].value);
+    }
+    const network = { ssid: form.ssid.value, https: form.https.value === 'true' };
+    // Missing password keeps the current one
+    if (form.password.value !== '') {
+        network.password = form.password.value;
+    }
+
+    const settings = {
+        ...loaded,
+        sample_interval_s: parseInt(form.sample_interval_s.value, 10),
+        unit: form.unit.value,
+        alarms,
+        network,
+        webhooks: form.webhooks.value.split('\n').map((url) => url.trim()).filter((url) => url !== ''),
+        led: {
+            mode: form.led_mode.value,
+            brightness: parseInt(form.led_brightness.value, 10),
+            current_limit: parseInt(form.led_current_limit.value, 10),
+            gradient: form.led_gradient.value,
+            temperature_range: {
+                low: parseFloat(form.led_temperature_low.value),
+                high: parseFloat(form.led_temperature_high.value),
+            },
+            humidity_range: {
+                low: parseFloat(form.led_humidity_low.value),
+                high: parseFloat(form.led_humidity_high.value),
+            },
+            strip: optionalText(form.led_strip.value),
+            strip_chip: form.led_strip_chip.value,
+            strip_order: form.led_strip_order.value,
+        },
+        self_heating: {
+            warning: parseFloat(form.self_heating_warning.value),
+            compensation: parseFloat(form.self_heating_compensation.value),
+        },
+        hardware: {
+            display: form.display.value,
+            battery_divider: optionalNumber(form.battery_divider.value),
+        },
+        power: {
+            mode: form.power_mode.value,
+            upload_url: optionalText(form.upload_url.value.trim()),
+            sleep_interval_s: parseInt(form.sleep_interval_s.value, 10),
+            upload_every: parseInt(form.upload_every.value, 10),
+        },
+        ota: {
+            health_timeout_s: parseInt(form.health_timeout_s.value, 10),
+        },
+    };
+    // Missing key keeps the current one
+    if (form.ota_key.value !== '') {
+        settings.ota.key = form.ota_key.value;
+    }
+
+    try {
+        const response = await fetch('/api/settings', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(settings),
+        });
+        if (response.ok) {
+            const applied = await response.json();
+            result.textContent = applied.restart_required
+                ? 'Saved, restart the device to apply it'
+                : 'Saved';
+            form.password.value = '';
+            form.ota_key.value = '';
+        } else if (response.status === 422) {
+            const error = await response.json();
+            result.textContent = `Invalid ${error.field}: ${error.reason.replaceAll('_', ' ')}`;
+        } else {
+            result.textContent = `Failed to save settings: ${response.status}`;
+        }
+    } catch (error) {
+        console.error('Error saving settings:', error);
+        result.textContent = 'Failed to save settings';
+    }
+});
+
+const credentialsForm = document.getElementById('credentials-form');
+const credentialsResult = document.getElementById('credentials-result');
+
+// Replaces both login and token, only their salted digests are kept by device
+credentialsForm.addEventListener('submit', async (event) => {
+    event.preventDefault();
+
+    const credentials = {
+        user: optionalText(credentialsForm.user.value),
+        password: optionalText(credentialsForm.password.value),
+        token: optionalText(credentialsForm.token.value),
+    };
+
+    try {
+        const response = await fetch('/api/credentials', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(credentials),
+        });
+        if (response.ok) {
+            credentialsResult.textContent = 'Credentials changed, sign in again';
+            credentialsForm.reset();
+        } else if (response.status === 422) {
+            const error = await response.json();
+            credentialsResult.textContent = `Invalid ${error.field}: ${error.reason.replaceAll('_', ' ')}`;
+        } else {
+            credentialsResult.textContent = `Failed to change credentials: ${response.status}`;
+        }
+    } catch (error) {
+        console.error('Error changing credentials:', error);
+        credentialsResult.textContent = 'Failed to change credentials';
+    }
+});
+
+const certificateForm = document.getElementById('certificate-form');
+const certificateResult = document.getElementById('certificate-result');
+
+async function loadFingerprint() {
+    try {
+        const response = await fetch('/api/tls');
+        const tls = await response.json();
+        document.getElementById('fingerprint').textContent = tls.fingerprint ?? 'none';
+    } catch (error) {
+        console.error('Error fetching certificate:', error);
+    }
+}
+
+// Certificate signed by a CA replaces the self-signed one
+certificateForm.addEventListener('submit', async (event) => {
+    event.preventDefault();
+
+    try {
+        const response = await fetch('/api/tls', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/x-pem-file' },
+            body: certificateForm.pem.value,
+        });
+        if (response.ok) {
+            certificateResult.textContent = 'Uploaded, restart the device to apply it';
+            certificateForm.reset();
+        } else if (response.status === 422) {
+            const error = await response.json();
+            certificateResult.textContent = `Invalid certificate: ${error.replaceAll('_', ' ')}`;
+        } else {
+            certificateResult.textContent = `Failed to upload certificate: ${response.status}`;
+        }
+    } catch (error) {
+        console.error('Error uploading certificate:', error);
+        certificateResult.textContent = 'Failed to upload certificate';
+    }
+});
+
+loadSettings();
+loadFingerprint();
@@ -1,15 +1,69 @@
+use core::fmt::Write;
+
+use defmt::{error, info, warn};
+use embedded_io_async::Read;
+use esp_hal::rng::Rng;
+use heapless::Vec;
 use picoserve::{
-    extract::State,
-    response::{DebugValue, IntoResponseWithState},
+    extract::{FromRequestParts, Query, State},
+    request::Request,
+    response::{DebugValue, IntoResponse, IntoResponseWithState, Json, ResponseWriter, StatusCode},
+    routing::RequestHandlerService,
+    ResponseSent,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::MAX_BASIC_LEN,
+    boards::esp32::esp32_c6::Flash,
+    calibration::{CalibratedReading, Calibrations, SharedCalibratedReadings, MAX_CALIBRATIONS},
+    discovery::SharedInventory,
+    drivers::i2c::stats::SharedI2cStats,
+    net::webhook::SharedWebhookStatus,
+    ota::{self, ImageWriter, OtaError, Phase, SharedOta, Verification},
+    psychrometrics::{Psychrometrics, STANDARD_PRESSURE},
+    settings::{
+        Applied, ApplyError, Credentials, Reason, Settings, SettingsError, SharedSettings,
+        StoreError,
+    },
+    sha256::{parse_digest, SaltedDigest, SALT_LEN},
+    tls::identity::{IdentityError, IdentityStore},
+    units::TemperatureUnit,
+    web::{
+        auth::SharedAuth, AppState, SharedBattery, SharedChipTemp, SharedCo2, SharedHumidity,
+        SharedPressure, SharedProbes, SharedTemp, SharedTls,
+    },
 };
 
-use crate::web::{AppState, SharedHumidity, SharedTemp};
+/// Unit of temperatures in response, settings unit if not set
+#[derive(Deserialize)]
+pub struct UnitQuery {
+    unit: Option<TemperatureUnit>,
+}
+
+impl UnitQuery {
+    async fn unit(&self, settings: &SharedSettings<Flash>) -> TemperatureUnit {
+        match self.unit {
+            Some(unit) => unit,
+            None => settings.get().await.unit,
+        }
+    }
+}
+
+/// Temperature unit of settings, the default of responses
+pub async fn get_unit(
+    State(settings): State<SharedSettings<Flash>>,
+) -> impl IntoResponseWithState<AppState> {
+    Json(settings.get().await.unit)
+}
 
 pub async fn get_temperature(
     State(state): State<SharedTemp>,
+    State(settings): State<SharedSettings<Flash>>,
+    Query(query): Query<UnitQuery>,
 ) -> impl IntoResponseWithState<AppState> {
     let celsius = state.get().await;
-    DebugValue(celsius)
+    DebugValue(query.unit(&settings).await.from_celsius(celsius))
 }
 
 pub async fn get_humidity(
@@ -18,3 +72,466 @@ pub async fn get_humidity(
     let percentage = state.get().await;
     DebugValue(percentage)
 }
+
+/// Dew point, VPD and other properties of air, `null` until the first reading.
+/// Uses measured pressure if there is a barometer
+pub async fn get_psychrometrics(
+    State(temp): State<SharedTemp>,
+    State(humidity): State<SharedHumidity>,
+    State(pressure): State<SharedPressure>,
+    State(settings): State<SharedSettings<Flash>>,
+    Query(query): Query<UnitQuery>,
+) -> impl IntoResponseWithState<AppState> {
+    let unit = query.unit(&settings).await;
+    Json(
+        Psychrometrics::new(
+            temp.get().await,
+            humidity.get().await,
+            pressure.get().await.unwrap_or(STANDARD_PRESSURE),
+        )
+        .map(|psychrometrics| psychrometrics.in_unit(unit)),
+    )
+}
+
+/// CO2 in ppm, `null` if there is no CO2 sensor
+pub async fn get_co2(State(state): State<SharedCo2>) -> impl IntoResponseWithState<AppState> {
+    Json(state.get().await)
+}
+
+/// Air pressure in hPa, `null` if there is no barometer
+pub async fn get_pressure(
+    State(state): State<SharedPressure>,
+) -> impl IntoResponseWithState<AppState> {
+    Json(state.get().await)
+}
+
+/// Temperature of the MCU die, `null` until the first measurement
+pub async fn get_chip_temperature(
+    State(state): State<SharedChipTemp>,
+    State(settings): State<SharedSettings<Flash>>,
+    Query(query): Query<UnitQuery>,
+) -> impl IntoResponseWithState<AppState> {
+    let unit = query.unit(&settings).await;
+    Json(state.get().await.map(|celsius| unit.from_celsius(celsius)))
+}
+
+/// Battery voltage and state of charge, `null` if battery is not monitored
+pub async fn get_battery(
+    State(state): State<SharedBattery>,
+) -> impl IntoResponseWithState<AppState> {
+    Json(state.get().await)
+}
+
+/// Temperatures of 1-Wire probes with their ROM ids
+pub async fn get_probes(
+    State(state): State<SharedProbes>,
+    State(settings): State<SharedSettings<Flash>>,
+    Query(query): Query<UnitQuery>,
+) -> impl IntoResponseWithState<AppState> {
+    let unit = query.unit(&settings).await;
+    let mut probes = state.get().await;
+    for probe in probes.iter_mut() {
+        probe.temperature = probe.temperature.map(|celsius| unit.from_celsius(celsius));
+    }
+    Json(probes)
+}
+
+pub async fn get_webhooks(
+    State(state): State<SharedWebhookStatus>,
+) -> impl IntoResponseWithState<AppState> {
+    Json(state.get().await)
+}
+
+pub async fn get_i2c(State(state): State<SharedI2cStats>) -> impl IntoResponseWithState<AppState> {
+    Json(state.get().await)
+}
+
+/// I2C devices found at boot
+pub async fn get_devices(
+    State(state): State<SharedInventory>,
+) -> impl IntoResponseWithState<AppState> {
+    Json(state.get().await)
+}
+
+/// Current settings, passwords and keys are never sent. Temperature thresholds are in settings unit
+pub async fn get_settings(
+    State(settings): State<SharedSettings<Flash>>,
+) -> impl IntoResponseWithState<AppState> {
+    Json(settings.get().await.public())
+}
+
+/// Validates, persists and applies submitted settings, temperature thresholds in submitted unit
+///
+/// Responds `{"restart_required":bool}`, or 422 with the rejected field and
+/// reason, e.g. `{"field":"alarms.temperature_low","reason":"low_above_high"}`
+pub async fn post_settings(
+    State(settings): State<SharedSettings<Flash>>,
+    Json(submitted): Json<Settings, 128>,
+) -> impl IntoResponseWithState<AppState> {
+    match settings.apply(submitted).await {
+        Ok(applied) => {
+            info!(
+                "settings: applied, restart required {}",
+                applied.restart_required
+            );
+            Ok(Json(applied))
+        }
+        Err(err) => {
+            error!("settings: rejected: {}", err);
+            Err(rejection(err))
+        }
+    }
+}
+
+/// Plain admin credentials, only their salted digests are kept
+#[derive(Deserialize)]
+pub struct SubmittedCredentials {
+    user: Option<heapless::String<MAX_USER_LEN>>,
+    password: Option<heapless::String<MAX_ADMIN_PASSWORD_LEN>>,
+    token: Option<heapless::String<MAX_TOKEN_LEN>>,
+}
+
+const MAX_USER_LEN: usize = 32;
+/// `user:password` fits into Basic authentication
+const MAX_ADMIN_PASSWORD_LEN: usize = MAX_BASIC_LEN - 1 - MAX_USER_LEN;
+const MAX_TOKEN_LEN: usize = 64;
+
+impl SubmittedCredentials {
+    /// Hashes credentials, each with its own random salt
+    fn digest(&self, mut rng: Rng) -> Result<Credentials, SettingsError> {
+        let mut salted = |secret: &[u8]| {
+            let mut salt = [0_u8; SALT_LEN];
+            rng.read(&mut salt);
+            SaltedDigest::new(salt, secret)
+        };
+
+        let password = match (&self.user, &self.password) {
+            (Some(user), Some(password))
+                if !user.is_empty() && !user.contains(':') && !password.is_empty() =>
+            {
+                let mut basic: heapless::String<MAX_BASIC_LEN> = heapless::String::new();
+                // Fits by capacity
+                write!(basic, "{user}:{password}").ok();
+                Some(salted(basic.as_bytes()))
+            }
+            (None, None) => None,
+            _ => {
+                return Err(SettingsError::new(
+                    "credentials.password",
+                    Reason::InvalidCredentials,
+                ))
+            }
+        };
+        let token = match &self.token {
+            Some(token) if token.is_empty() => {
+                return Err(SettingsError::new("credentials.token", Reason::Empty))
+            }
+            Some(token) => Some(salted(token.as_bytes())),
+            None => None,
+        };
+
+        let credentials = Credentials { password, token };
+        if !credentials.is_configured() {
+            // Nobody could change them again
+            return Err(SettingsError::new("credentials", Reason::Empty));
+        }
+        Ok(credentials)
+    }
+}
+
+/// Replaces admin credentials, e.g. `{"user":"admin","password":"secret","token":null}`
+///
+/// The first credentials are accepted from anyone within 5 minutes after BOOT button
+/// is pressed, later ones from admin only.
+/// Responds like settings, 422 if credentials are incomplete
+pub async fn post_credentials(
+    State(settings): State<SharedSettings<Flash>>,
+    State(auth): State<SharedAuth>,
+    State(rng): State<Rng>,
+    Json(submitted): Json<SubmittedCredentials, 32>,
+) -> impl IntoResponseWithState<AppState> {
+    let result = match submitted.digest(rng) {
+        Ok(credentials) => settings
+            .set_credentials(credentials)
+            .await
+            .map(|applied| (applied, credentials)),
+        Err(err) => Err(ApplyError::Invalid(err)),
+    };
+
+    match result {
+        Ok((applied, credentials)) => {
+            warn!("web: admin credentials changed");
+            auth.set_credentials(credentials).await;
+            Ok(Json(applied))
+        }
+        Err(err) => {
+            error!("web: credentials rejected: {}", err);
+            Err(rejection(err))
+        }
+    }
+}
+
+/// 422 for invalid settings, 500 if they failed to persist
+fn rejection(err: ApplyError) -> impl IntoResponse {
+    let code = match err {
+        ApplyError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        ApplyError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    Json(err).into_response().with_status_code(code)
+}
+
+#[derive(Serialize)]
+struct CalibrationReport {
+    calibration: Calibrations,
+    /// The last raw and calibrated values of every sensor quantity
+    readings: Vec<CalibratedReading, MAX_CALIBRATIONS>,
+}
+
+pub async fn get_calibration(
+    State(settings): State<SharedSettings<Flash>>,
+    State(readings): State<SharedCalibratedReadings>,
+) -> impl IntoResponseWithState<AppState> {
+    Json(CalibrationReport {
+        calibration: settings.get().await.calibration,
+        readings: readings.get().await,
+    })
+}
+
+/// Replaces all calibrations with submitted list, other settings are kept
+pub async fn post_calibration(
+    State(settings): State<SharedSettings<Flash>>,
+    Json(calibration): Json<Calibrations, 32>,
+) -> impl IntoResponseWithState<AppState> {
+    match settings.set_calibration(calibration).await {
+        Ok(applied) => Ok(Json(applied)),
+        Err(err) => {
+            error!("calibration: rejected: {}", err);
+            Err(rejection(err))
+        }
+    }
+}
+
+/// Progress of the last firmware update
+pub async fn get_ota(State(ota): State<SharedOta<Flash>>) -> impl IntoResponseWithState<AppState> {
+    Json(ota.status().await)
+}
+
+/// Digest and signature of uploaded image as hex
+#[derive(Deserialize)]
+struct OtaQuery {
+    sha256: heapless::String<64>,
+    signature: Option<heapless::String<64>>,
+}
+
+/// Receives firmware image as raw body of `POST /ota?sha256=<hex>&signature=<hex>`
+///
+/// Body is streamed into flash page by page, the device reboots into new image
+/// after response is sent
+pub struct OtaUpload;
+
+impl RequestHandlerService<AppState> for OtaUpload {
+    async fn call_request_handler_service<R: Read, W: ResponseWriter<Error = R::Error>>(
+        &self,
+        state: &AppState,
+        _path_parameters: (),
+        mut request: Request<'_, R>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let verification = match Query::<OtaQuery>::from_request_parts(state, &request.parts).await
+        {
+            Ok(Query(query)) => parse_digest(&query.sha256).and_then(|sha256| {
+                match query.signature.as_deref().map(parse_digest) {
+                    Some(None) => None,
+                    signature => Some(Verification {
+                        sha256,
+                        signature: signature.flatten(),
+                    }),
+                }
+            }),
+            Err(_) => None,
+        };
+        let Some(verification) = verification else {
+            return (
+                StatusCode::BAD_REQUEST,
+                "sha256 and signature must be hex digests\n",
+            )
+                .write_to(request.body_connection.finalize().await?, response_writer)
+                .await;
+        };
+
+        let ota = &state.ota;
+        let size = request.body_connection.content_length() as u32;
+        let Some(_claim) = ota.start(size).await else {
+            return Json(OtaError::Busy)
+                .into_response()
+                .with_status_code(StatusCode::CONFLICT)
+                .write_to(request.body_connection.finalize().await?, response_writer)
+                .await;
+        };
+
+        let mut reader = request.body_connection.body().reader();
+        let result = receive_image(ota, &mut reader, size, &verification).await;
+
+        let mut status = ota.status().await;
+        match result {
+            Ok(slot) => {
+                info!("ota: image activated in slot {}", slot.number());
+                status.phase = Phase::Rebooting;
+                ota.set_status(status).await;
+                ota.request_reboot(slot);
+            }
+            Err(err) => {
+                error!("ota: update failed: {}", err);
+                status.phase = Phase::Failed;
+                status.error = Some(err);
+                ota.set_status(status).await;
+            }
+        }
+
+        let code = match result {
+            Ok(_) => StatusCode::OK,
+            Err(OtaError::TooLarge) => StatusCode::PAYLOAD_TOO_LARGE,
+            Err(
+                OtaError::NotAnImage
+                | OtaError::Incomplete
+                | OtaError::DigestMismatch
+                | OtaError::SignatureMismatch,
+            ) => StatusCode::UNPROCESSABLE_ENTITY,
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Json(status)
+            .into_response()
+            .with_status_code(code)
+            .write_to(request.body_connection.finalize().await?, response_writer)
+            .await
+    }
+}
+
+/// Certificate served on HTTPS
+#[derive(Serialize)]
+pub struct TlsStatus {
+    /// SHA-256 of leaf certificate as colon separated hex, like browsers show it.
+    /// None if HTTPS is disabled
+    fingerprint: Option<heapless::String<95>>,
+}
+
+pub async fn get_tls(State(tls): State<SharedTls>) -> impl IntoResponseWithState<AppState> {
+    let fingerprint = tls.identity().map(|identity| {
+        let mut hex = heapless::String::new();
+        for (i, byte) in identity.fingerprint().iter().enumerate() {
+            let separator = if i == 0 { "" } else { ":" };
+            write!(hex, "{separator}{byte:02X}").ok();
+        }
+        hex
+    });
+    Json(TlsStatus { fingerprint })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, defmt::Format)]
+#[serde(untagged)]
+enum TlsUploadError {
+    Invalid(IdentityError),
+    Store(StoreError),
+}
+
+/// Receives PEM certificates and private key as raw body of `POST /api/tls`
+///
+/// Saved to flash if the leaf certificate is of the key, served after restart
+pub struct TlsUpload;
+
+impl RequestHandlerService<AppState> for TlsUpload {
+    async fn call_request_handler_service<R: Read, W: ResponseWriter<Error = R::Error>>(
+        &self,
+        state: &AppState,
+        _path_parameters: (),
+        mut request: Request<'_, R>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let tls = &state.tls;
+        // Decoder is static, it is too large for the stack of web tasks
+        let Some(mut decoder) = tls.upload().try_lock() else {
+            return (StatusCode::CONFLICT, "another upload is in progress\n")
+                .write_to(request.body_connection.finalize().await?, response_writer)
+                .await;
+        };
+
+        decoder.reset();
+        let mut reader = request.body_connection.body().reader();
+        let mut buffer = [0_u8; 256];
+        loop {
+            match reader.read(&mut buffer).await? {
+                0 => break,
+                count => decoder.push(&buffer[..count]),
+            }
+        }
+
+        let result = match decoder.finish() {
+            Ok(identity) => {
+                let mut flash = tls.flash().lock().await;
+                IdentityStore::open(&mut *flash)
+                    .and_then(|store| store.save(&mut *flash, identity))
+                    .map_err(TlsUploadError::Store)
+            }
+            Err(err) => Err(TlsUploadError::Invalid(err)),
+        };
+
+        let connection = request.body_connection.finalize().await?;
+        match result {
+            Ok(()) => {
+                info!("tls: certificate uploaded");
+                Json(Applied {
+                    restart_required: true,
+                })
+                .into_response()
+                .write_to(connection, response_writer)
+                .await
+            }
+            Err(err) => {
+                error!("tls: upload rejected: {}", err);
+                let code = match err {
+                    TlsUploadError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                    TlsUploadError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                Json(err)
+                    .into_response()
+                    .with_status_code(code)
+                    .write_to(connection, response_writer)
+                    .await
+            }
+        }
+    }
+}
+
+/// Streams body into inactive slot and activates it
+///
+/// Flash is locked for one chunk at a time, so settings can be saved during a slow upload
+async fn receive_image<R: Read>(
+    ota: &SharedOta<Flash>,
+    reader: &mut R,
+    size: u32,
+    verification: &Verification,
+) -> Result<esp_bootloader_esp_idf::ota::Slot, OtaError> {
+    let (slot, partition) = ota::target_partition(&mut *ota.flash().lock().await)?;
+    let mut writer = ImageWriter::new(partition, size)?;
+
+    let mut status = ota.status().await;
+    status.slot = Some(slot.number() as u8);
+    ota.set_status(status).await;
+
+    let mut buffer = [0_u8; 512];
+    while writer.received() < size {
+        let count = match reader.read(&mut buffer).await {
+            Ok(0) | Err(_) => return Err(OtaError::Incomplete),
+            Ok(count) => count,
+        };
+        writer.write(&mut *ota.flash().lock().await, &buffer[..count])?;
+
+        status.received = writer.received();
+        ota.set_status(status).await;
+    }
+
+    let mut flash = ota.flash().lock().await;
+    writer.finish(&mut *flash, verification, ota.key())?;
+    ota::activate(&mut *flash, slot)?;
+    Ok(slot)
+}